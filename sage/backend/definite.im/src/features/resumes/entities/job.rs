@@ -0,0 +1,176 @@
+use mongodb::{
+    bson::{self, oid::ObjectId, Bson, Document},
+    Client,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::features::resumes::migrations::JOB_MIGRATIONS;
+use crate::shared::ops::{
+    date_ops,
+    migration_ops::{self, Migration, Versioned},
+    repository_ops::{Query, Repository, RepositoryError, SortOrder},
+};
+
+const JOB_COLLECTION: &str = "jobs";
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub enum JobKind {
+    ParseResume,
+    ParseJobDescription,
+    ScoreResume,
+    RewriteWork,
+}
+
+impl JobKind {
+    pub fn label(&self) -> &'static str {
+        match self {
+            JobKind::ParseResume => "Parse résumé",
+            JobKind::ParseJobDescription => "Parse job description",
+            JobKind::ScoreResume => "Score résumé",
+            JobKind::RewriteWork => "Rewrite work experience",
+        }
+    }
+}
+
+impl From<JobKind> for Bson {
+    fn from(val: JobKind) -> Self {
+        bson::to_bson(&val).unwrap_or(Bson::Null)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum JobState {
+    Queued,
+    Running,
+    Succeeded,
+    Failed,
+}
+
+impl From<JobState> for Bson {
+    fn from(val: JobState) -> Self {
+        bson::to_bson(&val).unwrap_or(Bson::Null)
+    }
+}
+
+/// What a finished job made, e.g. the score it saved
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct JobResult {
+    pub id: String,
+    pub url: String,
+}
+
+impl From<JobResult> for Bson {
+    fn from(val: JobResult) -> Self {
+        match bson::to_document(&val) {
+            Ok(d) => Bson::Document(d),
+            Err(_) => Bson::Null,
+        }
+    }
+}
+
+/// An LLM job run by `definite worker`, from the moment it is queued
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct JobEntity {
+    pub _id: ObjectId,
+    pub user_id: String,
+    pub kind: JobKind,
+    pub state: JobState,
+    /// Percent done, a rough guide from the step the job is at
+    pub progress: u32,
+    pub message: String,
+    pub error: Option<String>,
+    pub result: Option<JobResult>,
+    /// Attempts started, a job the provider failed is tried again
+    pub attempts: u32,
+    /// The fragment that replaces the progress once the job is finished
+    pub html: Option<String>,
+    pub created: i64,
+    pub updated: i64,
+    /// Bumped by every `Repository::save`
+    #[serde(default)]
+    pub revision: u32,
+    #[serde(default)]
+    pub schema_version: u32,
+}
+
+impl Default for JobEntity {
+    fn default() -> Self {
+        let now = date_ops::to_timestamp();
+        JobEntity {
+            _id: ObjectId::new(),
+            user_id: "not-set".to_string(),
+            kind: JobKind::ParseResume,
+            state: JobState::Queued,
+            progress: 0,
+            message: "Waiting for a worker".to_string(),
+            error: None,
+            result: None,
+            attempts: 0,
+            html: None,
+            created: now,
+            updated: now,
+            revision: 0,
+            schema_version: Self::SCHEMA_VERSION,
+        }
+    }
+}
+
+impl Versioned for JobEntity {
+    const COLLECTION: &'static str = JOB_COLLECTION;
+    const SCHEMA_VERSION: u32 = 1;
+
+    fn migrations() -> &'static [Migration] {
+        JOB_MIGRATIONS
+    }
+}
+
+impl Repository for JobEntity {
+    const COLLECTION: &'static str = JOB_COLLECTION;
+
+    fn id(&self) -> ObjectId {
+        self._id
+    }
+
+    fn set_id(
+        &mut self,
+        id: ObjectId,
+    ) {
+        self._id = id;
+    }
+
+    fn owner(&self) -> &str {
+        &self.user_id
+    }
+
+    fn revision(&self) -> u32 {
+        self.revision
+    }
+
+    fn set_revision(
+        &mut self,
+        revision: u32,
+    ) {
+        self.revision = revision;
+    }
+
+    fn decode(document: Document) -> Result<Self, String> {
+        migration_ops::try_decode::<Self>(document)
+    }
+}
+
+impl JobEntity {
+    pub fn is_finished(&self) -> bool {
+        matches!(self.state, JobState::Succeeded | JobState::Failed)
+    }
+
+    /// The user's most recent jobs, newest first, without the finished fragment
+    pub async fn recent(
+        mongoc: &Client,
+        user_id: &str,
+        limit: u64,
+    ) -> Result<Vec<JobEntity>, RepositoryError> {
+        let query = Query::new().sort("created", SortOrder::Descending).page(0, limit).exclude("html");
+        Self::list(mongoc, user_id, &query).await
+    }
+}
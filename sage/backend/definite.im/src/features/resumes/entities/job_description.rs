@@ -1,18 +1,23 @@
-use mongodb::{
-    bson::{self, oid::ObjectId},
-    Client,
-};
+use async_trait::async_trait;
+use mongodb::bson::{oid::ObjectId, Document};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 
+use crate::features::resumes::migrations::JOB_DESCRIPTION_MIGRATIONS;
+use crate::shared::entities::llm_usage::LlmOperation;
 use crate::shared::ops::{
+    cache_ops::Cache,
     date_ops,
-    db_ops::Database,
+    migration_ops::{self, Migration, Versioned},
     openai::{
+        completion_cache,
+        completion_error::CompletionError,
         completion_request::{Content, ContentType, Message, ResponseFormat, ResponseFormatType},
-        post_chat_completion,
+        parse_completion, post_chat_completion,
+        provider::LlmProvider,
     },
+    repository_ops::Repository,
     schema_ops,
 };
 
@@ -26,6 +31,11 @@ pub struct JobDescriptionEntity {
     pub parsed_jd: Option<ParsedJobDescription>,
     pub name: String,
     pub timestamp: i64,
+    /// Bumped by every `Repository::save`
+    #[serde(default)]
+    pub revision: u32,
+    #[serde(default)]
+    pub schema_version: u32,
 }
 
 impl Default for JobDescriptionEntity {
@@ -37,50 +47,53 @@ impl Default for JobDescriptionEntity {
             parsed_jd: None,
             name: "not-set".to_string(),
             timestamp: date_ops::to_timestamp(),
+            revision: 0,
+            schema_version: Self::SCHEMA_VERSION,
         }
     }
 }
 
-impl JobDescriptionEntity {
-    pub async fn create(
-        &self,
-        mongoc: &Client,
-    ) -> Option<String> {
-        let resume = Database::get_collection::<JobDescriptionEntity>(mongoc, JD_COLLECTION);
-        Database::create(&resume, self).await
+impl Versioned for JobDescriptionEntity {
+    const COLLECTION: &'static str = JD_COLLECTION;
+    const SCHEMA_VERSION: u32 = 1;
+
+    fn migrations() -> &'static [Migration] {
+        JOB_DESCRIPTION_MIGRATIONS
     }
+}
+
+#[async_trait]
+impl Repository for JobDescriptionEntity {
+    const COLLECTION: &'static str = JD_COLLECTION;
 
-    pub async fn find(
-        &self,
-        mongoc: &Client,
-    ) -> Option<JobDescriptionEntity> {
-        let collection = Database::get_collection::<JobDescriptionEntity>(mongoc, JD_COLLECTION);
-        Database::find(collection, &self._id).await
+    fn id(&self) -> ObjectId {
+        self._id
     }
 
-    pub async fn filter(
-        &self,
-        mongoc: &Client,
-        filter: bson::Document,
-    ) -> Option<Vec<JobDescriptionEntity>> {
-        let collection = Database::get_collection::<JobDescriptionEntity>(mongoc, JD_COLLECTION);
-        let result = match Database::scan::<JobDescriptionEntity>(collection, filter).await {
-            Ok(r) => r,
-            Err(e) => {
-                log::error!("Error filtering job descriptions: {:?}", e);
-                return None;
-            }
-        };
+    fn set_id(
+        &mut self,
+        id: ObjectId,
+    ) {
+        self._id = id;
+    }
+
+    fn owner(&self) -> &str {
+        &self.user_id
+    }
 
-        Some(result)
+    fn revision(&self) -> u32 {
+        self.revision
     }
 
-    pub async fn update(
-        &self,
-        mongoc: &Client,
-    ) -> Option<JobDescriptionEntity> {
-        let collection = Database::get_collection::<JobDescriptionEntity>(mongoc, JD_COLLECTION);
-        Database::find(collection, &self._id).await
+    fn set_revision(
+        &mut self,
+        revision: u32,
+    ) {
+        self.revision = revision;
+    }
+
+    fn decode(document: Document) -> Result<Self, String> {
+        migration_ops::try_decode::<Self>(document)
     }
 }
 
@@ -237,19 +250,38 @@ impl Default for ParsedJobDescription {
     }
 }
 
+/// Instructions the job description is appended to, part of the cache key so rewording them skips stale parses
+const PARSE_PROMPT: &str = "Parse the following job description to conform to the provided schema. Also, generate a name slug (that I can use as an identifier) and keywords: \n";
+
 impl ParsedJobDescription {
+    pub fn cache_key(jd_text: &str) -> String {
+        completion_cache::cache_key::<ParsedJobDescription>("job-description", PARSE_PROMPT, jd_text)
+    }
+
+    /// A previous parse of the same text, if it is still cached
+    pub async fn cached(
+        cache: &Cache,
+        jd_text: &str,
+    ) -> Option<ParsedJobDescription> {
+        completion_cache::get::<ParsedJobDescription>(cache, &Self::cache_key(jd_text)).await
+    }
+
+    /// Identical job descriptions are answered from the cache without calling the provider
     pub async fn parse(
         jd_text: &str,
         user_id: Option<String>,
-    ) -> Option<ParsedJobDescription> {
+        cache: &Cache,
+        provider: &dyn LlmProvider,
+    ) -> Result<ParsedJobDescription, CompletionError> {
+        let cache_key = Self::cache_key(jd_text);
+        if let Some(parsed_jd) = completion_cache::get::<ParsedJobDescription>(cache, &cache_key).await {
+            return Ok(parsed_jd);
+        }
         let messages = vec![Message {
             role: "user".to_string(),
             content: vec![Content {
                 content_type: ContentType::Text,
-                text: Some(format!(
-                    "Parse the following job description to conform to the provided schema. Also, generate a name slug (that I can use as an identifier) and keywords: \n{}",
-                    jd_text
-                )),
+                text: Some(format!("{}{}", PARSE_PROMPT, jd_text)),
                 image_url: None,
             }],
         }];
@@ -258,20 +290,22 @@ impl ParsedJobDescription {
             format_type: ResponseFormatType::JsonSchema,
             json_schema: Some(json!(schema_ops::to_openai_schema::<ParsedJobDescription>().unwrap())),
         };
-        let openai_response = match post_chat_completion(messages, Some(response_format), user_id).await {
-            Some(r) => r,
-            None => {
-                log::error!("Error parsing job description");
-                return None;
-            }
-        };
-        let parsed_jd = match serde_json::from_str(&openai_response) {
+        let openai_response = match post_chat_completion(provider, messages, Some(response_format), user_id, LlmOperation::ParseJobDescription).await {
             Ok(r) => r,
             Err(e) => {
-                log::error!("Error parsing job description: {:?}", e);
-                return None;
+                log::error!("Error parsing job description: {}", e);
+                return Err(e);
             }
         };
-        Some(parsed_jd)
+        match parse_completion::<ParsedJobDescription>(&openai_response) {
+            Ok(r) => {
+                completion_cache::set(cache, &cache_key, &r).await;
+                Ok(r)
+            }
+            Err(e) => {
+                log::error!("Error parsing job description: {}", e);
+                Err(e)
+            }
+        }
     }
 }
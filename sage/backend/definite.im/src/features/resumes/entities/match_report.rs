@@ -0,0 +1,94 @@
+use mongodb::bson::{oid::ObjectId, Document};
+use serde::{Deserialize, Serialize};
+
+use crate::features::resumes::{matching::MatchReport, migrations::MATCH_REPORT_MIGRATIONS};
+use crate::shared::ops::{
+    date_ops,
+    migration_ops::{self, Migration, Versioned},
+    repository_ops::Repository,
+};
+
+const MATCH_REPORT_COLLECTION: &str = "match_reports";
+
+/// How well a résumé meets a job description, as it stood when they were matched
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct MatchReportEntity {
+    pub _id: ObjectId,
+    pub user_id: String,
+    pub resume_id: String,
+    pub jd_id: String,
+    /// The `ResumeVersionEntity` version that was matched, if the résumé had one
+    pub resume_version: Option<u32>,
+    pub resume_name: String,
+    pub job_title: Option<String>,
+    pub company: Option<String>,
+    pub report: MatchReport,
+    pub timestamp: i64,
+    /// Bumped by every `Repository::save`
+    #[serde(default)]
+    pub revision: u32,
+    #[serde(default)]
+    pub schema_version: u32,
+}
+
+impl Default for MatchReportEntity {
+    fn default() -> Self {
+        MatchReportEntity {
+            _id: ObjectId::new(),
+            user_id: "not-set".to_string(),
+            resume_id: "not-set".to_string(),
+            jd_id: "not-set".to_string(),
+            resume_version: None,
+            resume_name: "not-set".to_string(),
+            job_title: None,
+            company: None,
+            report: MatchReport::default(),
+            timestamp: date_ops::to_timestamp(),
+            revision: 0,
+            schema_version: Self::SCHEMA_VERSION,
+        }
+    }
+}
+
+impl Versioned for MatchReportEntity {
+    const COLLECTION: &'static str = MATCH_REPORT_COLLECTION;
+    const SCHEMA_VERSION: u32 = 1;
+
+    fn migrations() -> &'static [Migration] {
+        MATCH_REPORT_MIGRATIONS
+    }
+}
+
+impl Repository for MatchReportEntity {
+    const COLLECTION: &'static str = MATCH_REPORT_COLLECTION;
+
+    fn id(&self) -> ObjectId {
+        self._id
+    }
+
+    fn set_id(
+        &mut self,
+        id: ObjectId,
+    ) {
+        self._id = id;
+    }
+
+    fn owner(&self) -> &str {
+        &self.user_id
+    }
+
+    fn revision(&self) -> u32 {
+        self.revision
+    }
+
+    fn set_revision(
+        &mut self,
+        revision: u32,
+    ) {
+        self.revision = revision;
+    }
+
+    fn decode(document: Document) -> Result<Self, String> {
+        migration_ops::try_decode::<Self>(document)
+    }
+}
@@ -1,3 +1,8 @@
+pub mod job;
 pub mod job_description;
+pub mod match_report;
 pub mod resume;
+pub mod resume_diff;
 pub mod resume_score;
+pub mod resume_version;
+pub mod rubric_version;
@@ -1,22 +1,34 @@
-use log::debug;
-use mongodb::{
-    bson::{self, doc, oid::ObjectId, Bson},
-    Client,
-};
+use async_trait::async_trait;
+use mongodb::bson::{self, oid::ObjectId, Bson, Document};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 
+use crate::features::resumes::migrations::RESUME_MIGRATIONS;
+use crate::shared::entities::llm_usage::LlmOperation;
 use crate::shared::ops::{
+    cache_ops::Cache,
     date_ops,
-    db_ops::Database,
+    extract_ops::DocumentFormat,
+    migration_ops::{self, Migration, Versioned},
     openai::{
+        completion_cache,
+        completion_error::CompletionError,
         completion_request::{Content, ContentType, Message, ResponseFormat, ResponseFormatType},
-        post_chat_completion,
+        parse_completion, post_chat_completion,
+        provider::LlmProvider,
+        stream::DeltaStream,
+        stream_chat_completion,
     },
+    repository_ops::Repository,
     schema_ops,
 };
 
+use super::{
+    job_description::ParsedJobDescription,
+    resume_diff::{BulletAudit, WorkExperienceDiff},
+};
+
 #[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
 pub enum ResumeKind {
     Uploaded,
@@ -34,7 +46,7 @@ impl From<ResumeKind> for Bson {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, PartialOrd, Ord, JsonSchema)]
 pub enum Persona {
     /// Application developer, software developer, etc.
     ApplicationDeveloper,
@@ -54,18 +66,35 @@ pub enum Persona {
     TechProfessional,
 }
 
+impl Persona {
+    pub const ALL: [Persona; 8] = [
+        Persona::ApplicationDeveloper,
+        Persona::SoftwareEngineer,
+        Persona::EngineeringManager,
+        Persona::ProductManager,
+        Persona::ProgramManager,
+        Persona::SolutionArchitect,
+        Persona::SolutionArchitectManager,
+        Persona::TechProfessional,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Persona::ApplicationDeveloper => "Application Developer",
+            Persona::SoftwareEngineer => "Software Engineer",
+            Persona::EngineeringManager => "Engineering Manager",
+            Persona::ProductManager => "Product Manager",
+            Persona::ProgramManager => "Program Manager",
+            Persona::SolutionArchitect => "Solution Architect",
+            Persona::SolutionArchitectManager => "Solution Architect Manager",
+            Persona::TechProfessional => "Tech Professional",
+        }
+    }
+}
+
 impl From<Persona> for Bson {
     fn from(val: Persona) -> Self {
-        match val {
-            Persona::ApplicationDeveloper => Bson::String("Application Developer".to_string()),
-            Persona::SoftwareEngineer => Bson::String("Software Engineer".to_string()),
-            Persona::EngineeringManager => Bson::String("Engineering Manager".to_string()),
-            Persona::ProductManager => Bson::String("Product Manager".to_string()),
-            Persona::ProgramManager => Bson::String("Program Manager".to_string()),
-            Persona::SolutionArchitect => Bson::String("Solution Architect".to_string()),
-            Persona::SolutionArchitectManager => Bson::String("Solution Architect Manager".to_string()),
-            Persona::TechProfessional => Bson::String("Tech Professional".to_string()),
-        }
+        Bson::String(val.label().to_string())
     }
 }
 
@@ -80,6 +109,68 @@ pub struct ResumeEntity {
     pub name: String,
     pub timestamp: i64,
     pub kind: Option<ResumeKind>,
+    /// The uploaded file `resume_text` was extracted from, `None` for pasted text
+    #[serde(default)]
+    pub source_file: Option<SourceFile>,
+    /// The résumé and job description an `Edited` résumé was tailored from, `None` for everything else
+    #[serde(default)]
+    pub tailored_from: Option<TailoredFrom>,
+    /// The résumé whose rewritten bullets were reviewed into this `Edited` one, `None` for everything else
+    #[serde(default)]
+    pub edited_from: Option<EditedFrom>,
+    /// Bumped by every `Repository::save`
+    #[serde(default)]
+    pub revision: u32,
+    #[serde(default)]
+    pub schema_version: u32,
+}
+
+/// An uploaded résumé file, the original bytes are kept in the `UploadConfig.upload_bucket` GridFS bucket
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SourceFile {
+    pub file_id: ObjectId,
+    pub filename: String,
+    pub content_type: String,
+    pub format: DocumentFormat,
+    pub size: u64,
+}
+
+impl From<SourceFile> for Bson {
+    fn from(val: SourceFile) -> Self {
+        Bson::Document(bson::to_document(&val).unwrap())
+    }
+}
+
+/// Where a tailored résumé came from, the source résumé keeps changing so its version is kept too
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TailoredFrom {
+    pub resume_id: String,
+    /// The `ResumeVersionEntity` version that was tailored, if the source had one
+    pub resume_version: Option<u32>,
+    pub jd_id: String,
+    pub job_title: Option<String>,
+    pub company: Option<String>,
+}
+
+impl From<TailoredFrom> for Bson {
+    fn from(val: TailoredFrom) -> Self {
+        Bson::Document(bson::to_document(&val).unwrap())
+    }
+}
+
+/// Where a reviewed résumé came from, and who wrote each of its bullets
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct EditedFrom {
+    pub resume_id: String,
+    /// The `ResumeVersionEntity` version that was reviewed, if the source had one
+    pub resume_version: Option<u32>,
+    pub bullets: Vec<BulletAudit>,
+}
+
+impl From<EditedFrom> for Bson {
+    fn from(val: EditedFrom) -> Self {
+        Bson::Document(bson::to_document(&val).unwrap())
+    }
 }
 
 impl Default for ResumeEntity {
@@ -92,119 +183,56 @@ impl Default for ResumeEntity {
             name: "not-set".to_string(),
             timestamp: date_ops::to_timestamp(),
             kind: Some(ResumeKind::Uploaded),
+            source_file: None,
+            tailored_from: None,
+            edited_from: None,
+            revision: 0,
+            schema_version: Self::SCHEMA_VERSION,
         }
     }
 }
 
-impl ResumeEntity {
-    pub async fn upsert(
-        &self,
-        mongoc: &Client,
-    ) -> Option<String> {
-        match Self::find_by(self, mongoc).await {
-            Some(r) => match r.update(mongoc).await {
-                Some(r) => Some(r._id.to_hex()),
-                None => {
-                    debug!("Error updating resume");
-                    None
-                }
-            },
-            None => match self.create(mongoc).await {
-                Some(r) => Some(r),
-                None => {
-                    debug!("Error creating resume");
-                    None
-                }
-            },
-        }
-    }
+impl Versioned for ResumeEntity {
+    const COLLECTION: &'static str = RESUME_COLLECTION;
+    const SCHEMA_VERSION: u32 = 2;
 
-    pub async fn delete(
-        &self,
-        mongoc: &Client,
-    ) -> Option<String> {
-        let collection = Database::get_collection::<ResumeEntity>(mongoc, RESUME_COLLECTION);
-        match Database::delete(&collection, &self._id).await {
-            Some(_) => Some(self._id.to_hex()),
-            None => {
-                debug!("Error deleting resume");
-                None
-            }
-        }
+    fn migrations() -> &'static [Migration] {
+        RESUME_MIGRATIONS
     }
+}
 
-    pub async fn create(
-        &self,
-        mongoc: &Client,
-    ) -> Option<String> {
-        let resume = Database::get_collection::<ResumeEntity>(mongoc, RESUME_COLLECTION);
-        Database::create(&resume, self).await
+#[async_trait]
+impl Repository for ResumeEntity {
+    const COLLECTION: &'static str = RESUME_COLLECTION;
+
+    fn id(&self) -> ObjectId {
+        self._id
     }
 
-    pub async fn find_by(
-        &self,
-        mongoc: &Client,
-    ) -> Option<ResumeEntity> {
-        let collection = Database::get_collection::<ResumeEntity>(mongoc, RESUME_COLLECTION);
-        Database::filter(collection, doc! {"kind": &self.kind, "user_id": &self.user_id }).await
+    fn set_id(
+        &mut self,
+        id: ObjectId,
+    ) {
+        self._id = id;
     }
 
-    pub async fn find(
-        &self,
-        mongoc: &Client,
-    ) -> Option<ResumeEntity> {
-        let collection = Database::get_collection::<ResumeEntity>(mongoc, RESUME_COLLECTION);
-        Database::find(collection, &self._id).await
+    fn owner(&self) -> &str {
+        &self.user_id
     }
 
-    pub async fn filter(
-        &self,
-        mongoc: &Client,
-        filter: bson::Document,
-    ) -> Option<Vec<ResumeEntity>> {
-        let collection = Database::get_collection::<ResumeEntity>(mongoc, RESUME_COLLECTION);
-        let result = match Database::scan::<ResumeEntity>(collection, filter).await {
-            Ok(r) => r,
-            Err(e) => {
-                log::error!("Error filtering resumes: {:?}", e);
-                return None;
-            }
-        };
+    fn revision(&self) -> u32 {
+        self.revision
+    }
 
-        Some(result)
+    fn set_revision(
+        &mut self,
+        revision: u32,
+    ) {
+        self.revision = revision;
     }
 
-    pub async fn update(
-        &self,
-        mongoc: &Client,
-    ) -> Option<ResumeEntity> {
-        let collection = Database::get_collection::<ResumeEntity>(mongoc, RESUME_COLLECTION);
-        let resume = match Database::find(collection.clone(), &self._id).await {
-            Some(r) => r,
-            None => {
-                log::error!("Error finding resume");
-                return None;
-            }
-        };
-        let update_doc = doc! {
-            "$set": {
-                "_id": self._id,
-                "kind": self.kind.clone(),
-                "name": self.name.clone(),
-                "kind": self.kind.clone(),
-                "timestamp": self.timestamp,
-                "parsed_resume": self.parsed_resume.clone(),
-                "resume_text": self.resume_text.clone(),
-                "user_id": self.user_id.clone(),
-            }
-        };
-        match Database::update::<ResumeEntity>(&collection, &resume._id, update_doc).await {
-            Some(_) => Some(resume),
-            None => {
-                log::error!("Error updating resume");
-                None
-            }
-        }
+    fn decode(document: Document) -> Result<Self, String> {
+        migration_ops::try_decode::<Self>(document)
     }
 }
 
@@ -235,7 +263,10 @@ pub struct ParsedResume {
     /// (Must be provided; if omitted in JSON, deserialization will fail)
     pub work_experience: Vec<WorkExperience>,
 
-    pub work_experience_diff: Option<WorkExDiff>,
+    /// Set by `optimize_work`, never asked of the model
+    #[serde(default)]
+    #[schemars(skip)]
+    pub work_experience_diff: Option<WorkExperienceDiff>,
 
     /// Optional list of projects
     #[serde(skip_serializing_if = "Option::is_none", default)]
@@ -318,12 +349,6 @@ pub struct Education {
     pub dates: String,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
-pub struct WorkExDiff {
-    pub added: Vec<WorkExperience>,
-    pub removed: Vec<WorkExperience>,
-}
-
 /// Information about a work experience entry.
 #[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
 pub struct WorkExperience {
@@ -481,22 +506,19 @@ impl From<ParsedResume> for Bson {
     }
 }
 
+/// Instructions the résumé text is appended to, part of the cache key so rewording them skips stale parses
+const PARSE_PROMPT: &str = "Parse the following resume in the provided schema without missing any text (neither summarize nor reduce). \
+    Each field description in the schema provides its purpose. \
+    Deduce persona from the latest work experience. \
+    Generate a name slug (that I can use as an identifier) and a long-list of keywords: \n";
+
 impl ParsedResume {
-    pub async fn parse(
-        resume_text: &str,
-        user_id: Option<String>,
-    ) -> Option<ParsedResume> {
+    fn parse_request(resume_text: &str) -> (Vec<Message>, ResponseFormat) {
         let messages = vec![Message {
             role: "user".to_string(),
             content: vec![Content {
                 content_type: ContentType::Text,
-                text: Some(format!(
-                    "Parse the following resume in the provided schema without missing any text (neither summarize nor reduce). \
-                    Each field description in the schema provides its purpose. \
-                    Deduce persona from the latest work experience. \
-                    Generate a name slug (that I can use as an identifier) and a long-list of keywords: \n{}",
-                    resume_text
-                )),
+                text: Some(format!("{}{}", PARSE_PROMPT, resume_text)),
                 image_url: None,
             }],
         }];
@@ -505,27 +527,74 @@ impl ParsedResume {
             format_type: ResponseFormatType::JsonSchema,
             json_schema: Some(json!(schema_ops::to_openai_schema::<ParsedResume>().unwrap())),
         };
-        let openai_response = match post_chat_completion(messages, Some(response_format), user_id).await {
-            Some(r) => r,
-            None => {
-                log::error!("Error parsing resume");
-                return None;
-            }
-        };
-        let parsed_resume = match serde_json::from_str(&openai_response) {
+        (messages, response_format)
+    }
+
+    pub fn cache_key(resume_text: &str) -> String {
+        completion_cache::cache_key::<ParsedResume>("resume", PARSE_PROMPT, resume_text)
+    }
+
+    /// A previous parse of the same text, if it is still cached
+    pub async fn cached(
+        cache: &Cache,
+        resume_text: &str,
+    ) -> Option<ParsedResume> {
+        completion_cache::get::<ParsedResume>(cache, &Self::cache_key(resume_text)).await
+    }
+
+    pub async fn cache(
+        &self,
+        cache: &Cache,
+        resume_text: &str,
+    ) {
+        completion_cache::set(cache, &Self::cache_key(resume_text), self).await;
+    }
+
+    pub async fn parse(
+        resume_text: &str,
+        user_id: Option<String>,
+        cache: &Cache,
+        provider: &dyn LlmProvider,
+    ) -> Result<ParsedResume, CompletionError> {
+        if let Some(parsed_resume) = Self::cached(cache, resume_text).await {
+            return Ok(parsed_resume);
+        }
+        let (messages, response_format) = Self::parse_request(resume_text);
+        let openai_response = match post_chat_completion(provider, messages, Some(response_format), user_id, LlmOperation::ParseResume).await {
             Ok(r) => r,
             Err(e) => {
-                log::error!("Error parsing resume: {:?}", e);
-                return None;
+                log::error!("Error parsing resume: {}", e);
+                return Err(e);
             }
         };
-        Some(parsed_resume)
+        match parse_completion::<ParsedResume>(&openai_response) {
+            Ok(r) => {
+                r.cache(cache, resume_text).await;
+                Ok(r)
+            }
+            Err(e) => {
+                log::error!("Error parsing resume: {}", e);
+                Err(e)
+            }
+        }
+    }
+
+    /// Same as `parse` but yields the raw json as it is generated, finish with `parse_completion`.
+    /// Check `cached` first and `cache` the result, streams always call the provider.
+    pub async fn parse_stream(
+        resume_text: &str,
+        user_id: Option<String>,
+        provider: &dyn LlmProvider,
+    ) -> Result<DeltaStream, CompletionError> {
+        let (messages, response_format) = Self::parse_request(resume_text);
+        stream_chat_completion(provider, messages, Some(response_format), user_id, LlmOperation::ParseResume).await
     }
 
     pub async fn optimize_work(
         parsed_resume: &mut ParsedResume,
         user_id: Option<String>,
-    ) -> Option<ParsedResume> {
+        provider: &dyn LlmProvider,
+    ) -> Result<ParsedResume, CompletionError> {
         let messages = vec![Message {
             role: "user".to_string(),
             content: vec![Content {
@@ -536,8 +605,9 @@ impl ParsedResume {
                     Please transform each experience into a short, impactful bullet point following this formula:\
                     [ACTION VERB] [WHAT YOU DID] using [TOOLS / TECHNOLOGY], resulting in [MEASURABLE IMPACT].\
                         - Ensure each bullet includes a clear action verb at the start (e.g., “Developed,” “Led,” “Implemented”). \
-                        - Reference any relevant tools or technologies (e.g., Python, AWS, Docker, agile methodologies). \
-                        - Include a measurable outcome (e.g., saved X amount of time, increased revenue by Y%, improved performance by Z%). \
+                        - Reference the tools or technologies the experience mentions (e.g., Python, AWS, Docker, agile methodologies). \
+                        - Include a measurable outcome (e.g., saved X amount of time, increased revenue by Y%, improved performance by Z%) only when the experience states one. \
+                        - Never add numbers, tools, technologies or employers that are not in the provided experience. \
                     Provided work experience: \n{}\n \
                     Use the provided to schema to return structured json output.",
                     serde_json::to_string(&parsed_resume.work_experience).unwrap()
@@ -550,26 +620,115 @@ impl ParsedResume {
             format_type: ResponseFormatType::JsonSchema,
             json_schema: Some(json!(schema_ops::to_openai_schema::<Vec<WorkExperience>>().unwrap())),
         };
-        let openai_response = match post_chat_completion(messages, Some(response_format), user_id).await {
-            Some(r) => r,
-            None => {
-                log::error!("Error parsing work experience");
-                return None;
+        let openai_response = match post_chat_completion(provider, messages, Some(response_format), user_id, LlmOperation::OptimizeWork).await {
+            Ok(r) => r,
+            Err(e) => {
+                log::error!("Error parsing work experience: {}", e);
+                return Err(e);
             }
         };
-        let edited_experiences = match serde_json::from_str::<Vec<WorkExperience>>(&openai_response) {
+        let edited_experiences = match parse_completion::<Vec<WorkExperience>>(&openai_response) {
             Ok(r) => r,
             Err(e) => {
-                log::error!("Error parsing work experience: {:?}", e);
-                return None;
+                log::error!("Error parsing work experience: {}", e);
+                return Err(e);
             }
         };
         // record diff for display
-        parsed_resume.work_experience_diff = Some(WorkExDiff {
-            added: edited_experiences.clone(),
-            removed: parsed_resume.work_experience.clone(),
-        });
+        parsed_resume.work_experience_diff = Some(WorkExperienceDiff::between(&parsed_resume.work_experience, &edited_experiences));
+
+        Ok(parsed_resume.clone())
+    }
+
+    /// `tailored` with everything tailoring must not touch taken back from this résumé: only the summary,
+    /// the work experience and the order of the skills are the model's
+    pub fn with_tailoring_of(
+        &self,
+        tailored: ParsedResume,
+    ) -> ParsedResume {
+        ParsedResume {
+            summary: tailored.summary,
+            work_experience: tailored.work_experience,
+            work_experience_diff: None,
+            skills: tailored.skills,
+            ..self.clone()
+        }
+    }
+
+    /// A copy of the résumé reworded and reordered for one job description, with the same facts
+    pub async fn align(
+        parsed_resume: &ParsedResume,
+        parsed_jd: &ParsedJobDescription,
+        user_id: Option<String>,
+        provider: &dyn LlmProvider,
+    ) -> Result<ParsedResume, CompletionError> {
+        let messages = vec![Message {
+            role: "user".to_string(),
+            content: vec![Content {
+                content_type: ContentType::Text,
+                text: Some(format!(
+                    "You are an expert resume writer. \
+                    I will provide a candidate's resume and a job description, both as json. \
+                    Tailor the resume to the job description:\
+                        - Rewrite the summary to speak to the role. \
+                        - Reword work experience bullets to use the job description's terms where the candidate's experience supports them. \
+                        - Put the most relevant work experience bullets and skills first. \
+                        - Keep every employer, title, date, degree, certification and metric exactly as given. \
+                        - Never add experience, skills, tools or numbers the resume does not already show. \
+                    Resume: \n{}\n \
+                    Job description: \n{}\n \
+                    Use the provided to schema to return structured json output.",
+                    serde_json::to_string(parsed_resume).unwrap(),
+                    serde_json::to_string(parsed_jd).unwrap()
+                )),
+                image_url: None,
+            }],
+        }];
+
+        let response_format = ResponseFormat {
+            format_type: ResponseFormatType::JsonSchema,
+            json_schema: Some(json!(schema_ops::to_openai_schema::<ParsedResume>().unwrap())),
+        };
+        let openai_response = match post_chat_completion(provider, messages, Some(response_format), user_id, LlmOperation::AlignResume).await {
+            Ok(r) => r,
+            Err(e) => {
+                log::error!("Error aligning resume: {}", e);
+                return Err(e);
+            }
+        };
+        match parse_completion::<ParsedResume>(&openai_response) {
+            Ok(r) => Ok(r),
+            Err(e) => {
+                log::error!("Error parsing aligned resume: {}", e);
+                Err(e)
+            }
+        }
+    }
+}
 
-        Some(parsed_resume.clone())
+#[cfg(test)]
+mod tests {
+    use crate::features::resumes::interchange::full_resume;
+
+    #[test]
+    fn tailoring_only_keeps_the_summary_work_and_skills() {
+        let source = full_resume();
+        let mut tailored = full_resume();
+        tailored.name_slug = "someone-else".to_string();
+        tailored.contact.email = Some("made-up@example.com".to_string());
+        tailored.education.clear();
+        tailored.certifications = None;
+        tailored.summary = Some("Payments engineer.".to_string());
+        tailored.work_experience.reverse();
+        tailored.skills.as_mut().unwrap().technical.reverse();
+
+        let kept = source.with_tailoring_of(tailored.clone());
+        assert_eq!(kept.name_slug, source.name_slug);
+        assert_eq!(kept.contact.email, source.contact.email);
+        assert_eq!(kept.education.len(), source.education.len());
+        assert_eq!(kept.certifications.map(|c| c.len()), source.certifications.map(|c| c.len()));
+        assert_eq!(kept.summary, tailored.summary);
+        assert_eq!(kept.work_experience[0].company, tailored.work_experience[0].company);
+        assert_eq!(kept.skills.unwrap().technical, tailored.skills.unwrap().technical);
     }
 }
@@ -0,0 +1,650 @@
+use serde::{Deserialize, Serialize};
+
+use crate::shared::ops::diff_ops::{diff, diff_words, word_similarity, Change, ChangeKind};
+
+use super::resume::{ParsedResume, WorkExperience};
+
+/// A removed and an added bullet sharing at least this share of words are shown as one rewritten bullet
+const MODIFIED_SIMILARITY: f64 = 0.3;
+
+/// Work experience entries are paired on the first of these that matches: company/title/dates,
+/// company/title, company/dates, title/dates, then company alone
+const ALIGNMENT_LEVELS: usize = 5;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum DiffOp {
+    Unchanged,
+    Inserted,
+    Deleted,
+    Modified,
+}
+
+/// A piece of text before and after. `words` is only filled for modified text.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TextDiff {
+    pub op: DiffOp,
+    pub old: Option<String>,
+    pub new: Option<String>,
+    #[serde(default)]
+    pub words: Vec<Change<String>>,
+    /// What the user made of a rewritten bullet, `None` until they review it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub decision: Option<BulletDecision>,
+}
+
+/// The user's call on one rewritten bullet
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(tag = "choice", rename_all = "lowercase")]
+pub enum BulletDecision {
+    /// Take the rewrite, or leave out a bullet the rewrite dropped
+    Accepted,
+    /// Keep the original, or leave out a bullet the rewrite added
+    Rejected,
+    Edited {
+        text: String,
+    },
+}
+
+/// Who wrote a bullet of a reviewed résumé
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum BulletSource {
+    Original,
+    Ai,
+    User,
+}
+
+/// One bullet of a reviewed résumé, with the original and the rewrite it was chosen from
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BulletAudit {
+    pub company: String,
+    pub title: String,
+    pub text: String,
+    pub source: BulletSource,
+    pub original: Option<String>,
+    pub suggested: Option<String>,
+}
+
+impl TextDiff {
+    /// `None` when there is no text on either side
+    pub fn between(
+        old: Option<&str>,
+        new: Option<&str>,
+    ) -> Option<Self> {
+        let op = match (old, new) {
+            (None, None) => return None,
+            (None, Some(_)) => DiffOp::Inserted,
+            (Some(_), None) => DiffOp::Deleted,
+            (Some(o), Some(n)) if o.trim() == n.trim() => DiffOp::Unchanged,
+            (Some(_), Some(_)) => DiffOp::Modified,
+        };
+        Some(TextDiff {
+            op,
+            old: old.map(str::to_string),
+            new: new.map(str::to_string),
+            words: match (op, old, new) {
+                (DiffOp::Modified, Some(o), Some(n)) => diff_words(o, n),
+                _ => vec![],
+            },
+            decision: None,
+        })
+    }
+
+    /// The bullet the review keeps and who wrote it, `None` when it is left out.
+    /// Bullets nobody decided on stay as they were in the original.
+    fn reviewed(&self) -> Option<(String, BulletSource)> {
+        match &self.decision {
+            Some(BulletDecision::Accepted) if self.op == DiffOp::Unchanged => self.new.clone().map(|n| (n, BulletSource::Original)),
+            Some(BulletDecision::Accepted) => self.new.clone().map(|n| (n, BulletSource::Ai)),
+            Some(BulletDecision::Edited { text }) => Some(text.trim().to_string()).filter(|t| !t.is_empty()).map(|t| (t, BulletSource::User)),
+            Some(BulletDecision::Rejected) | None => self.old.clone().map(|o| (o, BulletSource::Original)),
+        }
+    }
+}
+
+/// A changed header field (company, title, dates or location) of a modified entry
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct FieldDiff {
+    pub field: String,
+    pub diff: TextDiff,
+}
+
+/// One work experience entry, labelled with its newest company, title and dates
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct WorkExperienceChange {
+    pub op: DiffOp,
+    pub company: String,
+    pub title: String,
+    pub dates: String,
+    pub location: Option<String>,
+    #[serde(default)]
+    pub fields: Vec<FieldDiff>,
+    pub bullets: Vec<TextDiff>,
+}
+
+/// Work experience entries in the new order, deleted entries placed where they used to be
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct WorkExperienceDiff {
+    pub entries: Vec<WorkExperienceChange>,
+    pub inserted: u32,
+    pub deleted: u32,
+    pub modified: u32,
+}
+
+impl WorkExperienceDiff {
+    pub fn between(
+        old: &[WorkExperience],
+        new: &[WorkExperience],
+    ) -> Self {
+        let old_for_new = align(old, new);
+        let mut emitted = vec![false; old.len()];
+        let mut work_diff = WorkExperienceDiff::default();
+        for (j, new_entry) in new.iter().enumerate() {
+            match old_for_new[j] {
+                Some(i) => {
+                    for k in 0..i {
+                        if !emitted[k] && !old_for_new.contains(&Some(k)) {
+                            emitted[k] = true;
+                            work_diff.push(whole_entry(&old[k], DiffOp::Deleted));
+                        }
+                    }
+                    emitted[i] = true;
+                    work_diff.push(compare_entries(&old[i], new_entry));
+                }
+                None => work_diff.push(whole_entry(new_entry, DiffOp::Inserted)),
+            }
+        }
+        for (k, old_entry) in old.iter().enumerate() {
+            if !emitted[k] {
+                work_diff.push(whole_entry(old_entry, DiffOp::Deleted));
+            }
+        }
+        work_diff
+    }
+
+    pub fn has_changes(&self) -> bool {
+        self.inserted + self.deleted + self.modified > 0
+    }
+
+    /// The new side of the diff, i.e. the work experience with every change taken
+    pub fn rewritten(&self) -> Vec<WorkExperience> {
+        self.entries
+            .iter()
+            .filter(|e| e.op != DiffOp::Deleted)
+            .map(|e| WorkExperience {
+                company: e.company.clone(),
+                location: e.location.clone(),
+                title: e.title.clone(),
+                dates: e.dates.clone(),
+                responsibilities: e.bullets.iter().filter_map(|b| b.new.clone()).collect(),
+            })
+            .collect()
+    }
+
+    /// The work experience with the user's decision taken for every bullet, and where each bullet came from.
+    /// An entry the rewrite added or dropped is left out once none of its bullets are kept, so accepting every
+    /// bullet of a dropped entry drops it. Entries on both sides stay, even with no bullets left.
+    pub fn reviewed(&self) -> (Vec<WorkExperience>, Vec<BulletAudit>) {
+        let mut work_experience = vec![];
+        let mut audit = vec![];
+        for entry in &self.entries {
+            let mut responsibilities = vec![];
+            for bullet in &entry.bullets {
+                if let Some((text, source)) = bullet.reviewed() {
+                    audit.push(BulletAudit {
+                        company: entry.company.clone(),
+                        title: entry.title.clone(),
+                        text: text.clone(),
+                        source,
+                        original: bullet.old.clone(),
+                        suggested: bullet.new.clone().filter(|_| bullet.op != DiffOp::Unchanged),
+                    });
+                    responsibilities.push(text);
+                }
+            }
+            if responsibilities.is_empty() && entry.op != DiffOp::Unchanged && entry.op != DiffOp::Modified {
+                continue;
+            }
+            work_experience.push(WorkExperience {
+                company: entry.company.clone(),
+                location: entry.location.clone(),
+                title: entry.title.clone(),
+                dates: entry.dates.clone(),
+                responsibilities,
+            });
+        }
+        (work_experience, audit)
+    }
+
+    /// Records the user's decision on one bullet, `false` when there is no such bullet
+    pub fn decide(
+        &mut self,
+        entry: usize,
+        bullet: usize,
+        decision: BulletDecision,
+    ) -> bool {
+        match self.entries.get_mut(entry).and_then(|e| e.bullets.get_mut(bullet)) {
+            Some(b) => {
+                b.decision = Some(decision);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn push(
+        &mut self,
+        change: WorkExperienceChange,
+    ) {
+        match change.op {
+            DiffOp::Inserted => self.inserted += 1,
+            DiffOp::Deleted => self.deleted += 1,
+            DiffOp::Modified => self.modified += 1,
+            DiffOp::Unchanged => {}
+        }
+        self.entries.push(change);
+    }
+}
+
+/// Structural diff of two parsed résumés
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ResumeDiff {
+    pub summary: Option<TextDiff>,
+    pub work_experience: WorkExperienceDiff,
+    /// Only the added and removed skills
+    pub skills: Vec<Change<String>>,
+    pub has_changes: bool,
+}
+
+impl ResumeDiff {
+    pub fn between(
+        old: &ParsedResume,
+        new: &ParsedResume,
+    ) -> Self {
+        let summary = TextDiff::between(old.summary.as_deref(), new.summary.as_deref());
+        let work_experience = WorkExperienceDiff::between(&old.work_experience, &new.work_experience);
+        let skills = diff_skills(old, new);
+        let has_changes = summary.as_ref().is_some_and(|s| s.op != DiffOp::Unchanged) || work_experience.has_changes() || !skills.is_empty();
+        ResumeDiff {
+            summary,
+            work_experience,
+            skills,
+            has_changes,
+        }
+    }
+}
+
+fn normalize(text: &str) -> String {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(str::to_lowercase)
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+/// `None` when a part of the key is blank, blank fields never pair entries
+fn alignment_key(
+    entry: &WorkExperience,
+    level: usize,
+) -> Option<String> {
+    let parts = match level {
+        0 => vec![&entry.company, &entry.title, &entry.dates],
+        1 => vec![&entry.company, &entry.title],
+        2 => vec![&entry.company, &entry.dates],
+        3 => vec![&entry.title, &entry.dates],
+        _ => vec![&entry.company],
+    };
+    let parts: Vec<String> = parts.into_iter().map(|p| normalize(p)).collect();
+    if parts.iter().any(|p| p.is_empty()) {
+        return None;
+    }
+    Some(parts.join("|"))
+}
+
+/// For each new entry, the index of the old entry it continues
+fn align(
+    old: &[WorkExperience],
+    new: &[WorkExperience],
+) -> Vec<Option<usize>> {
+    let mut old_for_new = vec![None; new.len()];
+    let mut matched = vec![false; old.len()];
+    for level in 0..ALIGNMENT_LEVELS {
+        for (j, new_entry) in new.iter().enumerate() {
+            if old_for_new[j].is_some() {
+                continue;
+            }
+            let key = match alignment_key(new_entry, level) {
+                Some(k) => k,
+                None => continue,
+            };
+            if let Some(i) = (0..old.len()).find(|&i| !matched[i] && alignment_key(&old[i], level).as_ref() == Some(&key)) {
+                matched[i] = true;
+                old_for_new[j] = Some(i);
+            }
+        }
+    }
+    old_for_new
+}
+
+fn whole_entry(
+    entry: &WorkExperience,
+    op: DiffOp,
+) -> WorkExperienceChange {
+    let text = |r: &String| match op {
+        DiffOp::Deleted => TextDiff::between(Some(r), None),
+        _ => TextDiff::between(None, Some(r)),
+    };
+    WorkExperienceChange {
+        op,
+        company: entry.company.clone(),
+        title: entry.title.clone(),
+        dates: entry.dates.clone(),
+        location: entry.location.clone(),
+        fields: vec![],
+        bullets: entry.responsibilities.iter().filter_map(text).collect(),
+    }
+}
+
+fn compare_entries(
+    old: &WorkExperience,
+    new: &WorkExperience,
+) -> WorkExperienceChange {
+    let fields: Vec<FieldDiff> = [
+        ("company", Some(old.company.as_str()), Some(new.company.as_str())),
+        ("title", Some(old.title.as_str()), Some(new.title.as_str())),
+        ("dates", Some(old.dates.as_str()), Some(new.dates.as_str())),
+        ("location", old.location.as_deref(), new.location.as_deref()),
+    ]
+    .into_iter()
+    .filter_map(|(field, o, n)| TextDiff::between(o, n).map(|diff| (field, diff)))
+    .filter(|(_, diff)| diff.op != DiffOp::Unchanged)
+    .map(|(field, diff)| FieldDiff { field: field.to_string(), diff })
+    .collect();
+    let bullets = diff_bullets(&old.responsibilities, &new.responsibilities);
+    let changed = !fields.is_empty() || bullets.iter().any(|b| b.op != DiffOp::Unchanged);
+    WorkExperienceChange {
+        op: if changed { DiffOp::Modified } else { DiffOp::Unchanged },
+        company: new.company.clone(),
+        title: new.title.clone(),
+        dates: new.dates.clone(),
+        location: new.location.clone(),
+        fields,
+        bullets,
+    }
+}
+
+/// Bullet diff in the new order, a removed bullet similar enough to an added one becomes a modification
+fn diff_bullets(
+    old: &[String],
+    new: &[String],
+) -> Vec<TextDiff> {
+    let mut bullets = vec![];
+    let (mut removed, mut added) = (vec![], vec![]);
+    for change in diff(old, new) {
+        match change.kind {
+            ChangeKind::Equal => {
+                pair_bullets(&mut bullets, &mut removed, &mut added);
+                bullets.extend(TextDiff::between(Some(&change.value), Some(&change.value)));
+            }
+            ChangeKind::Removed => removed.push(change.value),
+            ChangeKind::Added => added.push(change.value),
+        }
+    }
+    pair_bullets(&mut bullets, &mut removed, &mut added);
+    bullets
+}
+
+/// Pairs each added bullet of a hunk with the most similar unpaired removed one
+fn pair_bullets(
+    bullets: &mut Vec<TextDiff>,
+    removed: &mut Vec<String>,
+    added: &mut Vec<String>,
+) {
+    let mut paired = vec![false; removed.len()];
+    let mut old_for_new = vec![None; added.len()];
+    for (j, new_bullet) in added.iter().enumerate() {
+        let best = removed
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| !paired[*i])
+            .map(|(i, old_bullet)| (i, word_similarity(old_bullet, new_bullet)))
+            .filter(|(_, similarity)| *similarity >= MODIFIED_SIMILARITY)
+            .max_by(|a, b| a.1.total_cmp(&b.1));
+        if let Some((i, _)) = best {
+            paired[i] = true;
+            old_for_new[j] = Some(i);
+        }
+    }
+    for (i, old_bullet) in removed.iter().enumerate() {
+        if !paired[i] {
+            bullets.extend(TextDiff::between(Some(old_bullet), None));
+        }
+    }
+    for (j, new_bullet) in added.iter().enumerate() {
+        bullets.extend(TextDiff::between(old_for_new[j].map(|i| removed[i].as_str()), Some(new_bullet)));
+    }
+    removed.clear();
+    added.clear();
+}
+
+fn all_skills(resume: &ParsedResume) -> Vec<String> {
+    let mut skills: Vec<String> = vec![];
+    if let Some(s) = &resume.skills {
+        for skill in s.technical.iter().chain(&s.soft_skills).chain(&s.other_skills) {
+            if !skills.iter().any(|k| normalize(k) == normalize(skill)) {
+                skills.push(skill.clone());
+            }
+        }
+    }
+    skills
+}
+
+fn diff_skills(
+    old: &ParsedResume,
+    new: &ParsedResume,
+) -> Vec<Change<String>> {
+    let (old, new) = (all_skills(old), all_skills(new));
+    let missing_from = |skills: &[String], skill: &String| !skills.iter().any(|k| normalize(k) == normalize(skill));
+    let removed = old.iter().filter(|s| missing_from(&new, s)).map(|s| Change {
+        kind: ChangeKind::Removed,
+        value: s.clone(),
+    });
+    let added = new.iter().filter(|s| missing_from(&old, s)).map(|s| Change {
+        kind: ChangeKind::Added,
+        value: s.clone(),
+    });
+    removed.chain(added).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(
+        company: &str,
+        responsibilities: &[&str],
+    ) -> WorkExperience {
+        WorkExperience {
+            company: company.to_string(),
+            location: None,
+            title: "Engineer".to_string(),
+            dates: "2020 – 2021".to_string(),
+            responsibilities: responsibilities.iter().map(|r| r.to_string()).collect(),
+        }
+    }
+
+    /// Acme is kept, Initech is dropped by the rewrite
+    fn dropped_entry_diff() -> WorkExperienceDiff {
+        let old = [entry("Acme", &["Led the payments rewrite"]), entry("Initech", &["Built the billing API", "Ran the on-call rota"])];
+        let new = [entry("Acme", &["Led the payments rewrite"])];
+        let work_diff = WorkExperienceDiff::between(&old, &new);
+        assert_eq!(
+            work_diff.entries.iter().map(|e| (e.company.as_str(), e.op)).collect::<Vec<_>>(),
+            vec![("Acme", DiffOp::Unchanged), ("Initech", DiffOp::Deleted)]
+        );
+        work_diff
+    }
+
+    fn companies(work_experience: &[WorkExperience]) -> Vec<&str> {
+        work_experience.iter().map(|w| w.company.as_str()).collect()
+    }
+
+    #[test]
+    fn an_undecided_dropped_entry_stays_as_it_was() {
+        let (work_experience, audit) = dropped_entry_diff().reviewed();
+        assert_eq!(companies(&work_experience), vec!["Acme", "Initech"]);
+        assert_eq!(work_experience[1].responsibilities, vec!["Built the billing API", "Ran the on-call rota"]);
+        assert!(audit.iter().all(|a| a.source == BulletSource::Original));
+    }
+
+    #[test]
+    fn accepting_every_bullet_of_a_dropped_entry_drops_it() {
+        let mut work_diff = dropped_entry_diff();
+        assert!(work_diff.decide(1, 0, BulletDecision::Accepted));
+        assert!(work_diff.decide(1, 1, BulletDecision::Accepted));
+        let (work_experience, audit) = work_diff.reviewed();
+        assert_eq!(companies(&work_experience), vec!["Acme"]);
+        assert!(audit.iter().all(|a| a.company == "Acme"));
+    }
+
+    #[test]
+    fn a_dropped_entry_keeps_the_bullets_rejected_or_edited() {
+        let mut work_diff = dropped_entry_diff();
+        assert!(work_diff.decide(1, 0, BulletDecision::Accepted));
+        assert!(work_diff.decide(1, 1, BulletDecision::Edited { text: " Ran on-call ".to_string() }));
+        let (work_experience, _) = work_diff.reviewed();
+        assert_eq!(companies(&work_experience), vec!["Acme", "Initech"]);
+        assert_eq!(work_experience[1].responsibilities, vec!["Ran on-call"]);
+
+        assert!(work_diff.decide(1, 1, BulletDecision::Rejected));
+        let (work_experience, _) = work_diff.reviewed();
+        assert_eq!(work_experience[1].responsibilities, vec!["Ran the on-call rota"]);
+    }
+
+    #[test]
+    fn an_entry_on_both_sides_stays_with_no_bullets() {
+        let old = [entry("Acme", &["Led the payments rewrite"])];
+        let new = [entry("Acme", &[])];
+        let mut work_diff = WorkExperienceDiff::between(&old, &new);
+        assert!(work_diff.decide(0, 0, BulletDecision::Accepted));
+        let (work_experience, _) = work_diff.reviewed();
+        assert_eq!(companies(&work_experience), vec!["Acme"]);
+        assert!(work_experience[0].responsibilities.is_empty());
+    }
+
+    fn job(
+        company: &str,
+        title: &str,
+        dates: &str,
+    ) -> WorkExperience {
+        WorkExperience {
+            company: company.to_string(),
+            location: None,
+            title: title.to_string(),
+            dates: dates.to_string(),
+            responsibilities: vec![],
+        }
+    }
+
+    #[test]
+    fn entries_pair_on_each_alignment_level() {
+        let old = job("Acme", "Engineer", "2020 – 2021");
+        for (level, new) in [
+            (0, job("ACME", "engineer", "2020-2021")),
+            (1, job("Acme", "Engineer", "2019 – 2021")),
+            (2, job("Acme", "Senior Engineer", "2020 – 2021")),
+            (3, job("Acme Corp", "Engineer", "2020 – 2021")),
+            (4, job("Acme", "Staff Engineer", "2022 – now")),
+        ] {
+            assert_eq!(align(std::slice::from_ref(&old), std::slice::from_ref(&new)), vec![Some(0)], "level {}", level);
+        }
+        let stranger = job("Initech", "Manager", "2022 – now");
+        assert_eq!(align(&[old], &[stranger]), vec![None]);
+    }
+
+    #[test]
+    fn a_closer_level_wins_over_an_earlier_entry() {
+        // the first old entry only shares the company, the second shares everything
+        let old = [job("Acme", "Intern", "2015"), job("Acme", "Engineer", "2020 – 2021")];
+        let new = [job("Acme", "Engineer", "2020 – 2021"), job("Acme", "Intern", "2016")];
+        assert_eq!(align(&old, &new), vec![Some(1), Some(0)]);
+    }
+
+    #[test]
+    fn blank_fields_never_pair_entries() {
+        let old = [job("", "", "2020")];
+        let new = [job("", "", "2020")];
+        assert_eq!(align(&old, &new), vec![None]);
+    }
+
+    #[test]
+    fn reordered_entries_are_unchanged() {
+        let old = [entry("Acme", &["Led the payments rewrite"]), entry("Initech", &["Built the billing API"])];
+        let new = [old[1].clone(), old[0].clone()];
+        let work_diff = WorkExperienceDiff::between(&old, &new);
+        assert_eq!(
+            work_diff.entries.iter().map(|e| (e.company.as_str(), e.op)).collect::<Vec<_>>(),
+            vec![("Initech", DiffOp::Unchanged), ("Acme", DiffOp::Unchanged)]
+        );
+        assert_eq!((work_diff.inserted, work_diff.deleted, work_diff.modified), (0, 0, 0));
+        assert!(!work_diff.has_changes());
+    }
+
+    #[test]
+    fn a_similar_bullet_is_a_rewrite_of_the_old_one() {
+        let old = strings(&["Led the payments rewrite", "Organised the summer party"]);
+        let new = strings(&["Led the payments platform rewrite", "Mentored four engineers"]);
+        let bullets = diff_bullets(&old, &new);
+        let ops: Vec<_> = bullets.iter().map(|b| (b.op, b.old.as_deref(), b.new.as_deref())).collect();
+        assert_eq!(
+            ops,
+            vec![
+                (DiffOp::Deleted, Some("Organised the summer party"), None),
+                (DiffOp::Modified, Some("Led the payments rewrite"), Some("Led the payments platform rewrite")),
+                (DiffOp::Inserted, None, Some("Mentored four engineers")),
+            ]
+        );
+    }
+
+    #[test]
+    fn a_bullet_below_the_similarity_threshold_is_replaced() {
+        // one word of five in common
+        let (old, new) = ("Owned the payments service", "Wrote payments documentation for partners");
+        assert!(word_similarity(old, new) < MODIFIED_SIMILARITY);
+        let ops: Vec<_> = diff_bullets(&strings(&[old]), &strings(&[new])).iter().map(|b| b.op).collect();
+        assert_eq!(ops, vec![DiffOp::Deleted, DiffOp::Inserted]);
+    }
+
+    #[test]
+    fn a_rewritten_bullet_shows_its_word_changes() {
+        let bullet = TextDiff::between(Some("Led the payments rewrite"), Some("Led the payments platform rewrite in Rust")).unwrap();
+        let words: Vec<_> = bullet.words.iter().map(|w| (w.kind, w.value.as_str())).collect();
+        assert_eq!(
+            words,
+            vec![
+                (ChangeKind::Equal, "Led the payments"),
+                (ChangeKind::Added, "platform"),
+                (ChangeKind::Equal, "rewrite"),
+                (ChangeKind::Added, "in Rust")
+            ]
+        );
+        let unchanged = TextDiff::between(Some("Led the rewrite"), Some(" Led the rewrite ")).unwrap();
+        assert_eq!(unchanged.op, DiffOp::Unchanged);
+        assert!(unchanged.words.is_empty());
+    }
+
+    #[test]
+    fn skills_differ_regardless_of_case() {
+        let mut old = crate::features::resumes::interchange::full_resume();
+        let mut new = old.clone();
+        old.skills.as_mut().unwrap().technical = strings(&["Rust", "SQL", "Kafka"]);
+        new.skills.as_mut().unwrap().technical = strings(&["rust", "kafka", "Kubernetes"]);
+        let skills: Vec<_> = diff_skills(&old, &new).into_iter().map(|c| (c.kind, c.value)).collect();
+        assert_eq!(skills, vec![(ChangeKind::Removed, "SQL".to_string()), (ChangeKind::Added, "Kubernetes".to_string())]);
+    }
+
+    fn strings(values: &[&str]) -> Vec<String> {
+        values.iter().map(|v| v.to_string()).collect()
+    }
+}
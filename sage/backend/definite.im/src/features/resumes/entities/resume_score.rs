@@ -1,45 +1,70 @@
-use log::debug;
-use mongodb::{
-    bson::{self, doc},
-    Client,
-};
+use std::collections::BTreeMap;
+
+use async_trait::async_trait;
+use mongodb::bson::{oid::ObjectId, Document};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use serde_json::json;
+use serde_json::{json, Value};
 
+use crate::shared::entities::llm_usage::LlmOperation;
 use crate::shared::ops::{
     date_ops,
-    db_ops::Database,
+    migration_ops::{self, Migration, Versioned},
     openai::{
+        completion_error::CompletionError,
         completion_request::{Content, ContentType, Message, ResponseFormat, ResponseFormatType},
-        post_chat_completion,
+        parse_completion, post_chat_completion,
+        provider::LlmProvider,
+        stream::DeltaStream,
+        stream_chat_completion,
     },
+    repository_ops::Repository,
     schema_ops,
 };
 
-use super::resume::Persona;
+use super::resume::{ParsedResume, Persona};
+use crate::features::resumes::{
+    migrations::SCORE_MIGRATIONS,
+    rubric::{PersonaRubric, Weight},
+};
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct ScoreEntity {
-    pub _id: bson::oid::ObjectId,
+    pub _id: ObjectId,
     pub user_id: String,
     pub resume_id: String,
     pub score: i32,
     pub max_score: i32,
     pub detail: ResumeScore,
+    /// The `RubricVersionEntity` version it was scored against, `None` for the rubric file
+    #[serde(default)]
+    pub rubric_version: Option<u32>,
+    /// `Rubric::content_hash` of the rubric it was scored against, which tells apart edits of the rubric file.
+    /// `None` for scores saved before it was recorded.
+    #[serde(default)]
+    pub rubric_hash: Option<String>,
     pub timestamp: i64,
+    /// Bumped by every `Repository::save`
+    #[serde(default)]
+    pub revision: u32,
+    #[serde(default)]
+    pub schema_version: u32,
 }
 
 impl Default for ScoreEntity {
     fn default() -> Self {
         ScoreEntity {
-            _id: bson::oid::ObjectId::new(),
+            _id: ObjectId::new(),
             resume_id: "not-set".to_string(),
             user_id: "not-set".to_string(),
             score: 0,
             max_score: 0,
             timestamp: date_ops::to_timestamp(),
             detail: ResumeScore::default(),
+            rubric_version: None,
+            rubric_hash: None,
+            revision: 0,
+            schema_version: Self::SCHEMA_VERSION,
         }
     }
 }
@@ -86,6 +111,10 @@ pub struct FieldScore {
 
     /// Optional explanation for the score (e.g., reason for partial or missing points).
     pub description: Option<String>,
+
+    /// How to earn the missing points, written by the model
+    #[serde(default)]
+    pub guidance: Option<String>,
 }
 
 /// Represents a gap in a subfield category (e.g., "education", "work_experience"),
@@ -117,23 +146,168 @@ impl Default for ResumeScore {
     }
 }
 
+/// Prescriptive advice for the gaps `ResumeScore::compute` found, the only part of a score the model writes
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
+pub struct ScoreGuidance {
+    /// One entry per field that is missing points
+    pub fields: Vec<FieldGuidance>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
+pub struct FieldGuidance {
+    /// The field name exactly as given
+    pub field: String,
+    /// One or two sentences on what to add or change to earn the missing points
+    pub guidance: String,
+}
+
+/// Whether a value counts as present: blank strings, empty lists and objects with nothing filled in do not
+fn is_filled(value: Option<&Value>) -> bool {
+    match value {
+        None | Some(Value::Null) => false,
+        Some(Value::String(s)) => !s.trim().is_empty(),
+        Some(Value::Array(items)) => items.iter().any(|i| is_filled(Some(i))),
+        Some(Value::Object(fields)) => fields.values().any(|v| is_filled(Some(v))),
+        Some(_) => true,
+    }
+}
+
 impl ResumeScore {
-    pub async fn evaluate(
-        parsed_resume: String,
-        rubric_text: String,
-        user_id: String,
-    ) -> Option<Self> {
+    /// Scores the résumé against a rubric, the same résumé always gets the same score.
+    /// A field earns its weight when filled in. Each of its subfields earns its weight in proportion to
+    /// the entries that fill it in, rounded down, so no field scores above its maximum.
+    pub fn compute(
+        parsed_resume: &ParsedResume,
+        rubric: &PersonaRubric,
+    ) -> Self {
+        let resume = serde_json::to_value(parsed_resume).unwrap_or_default();
+        let mut missing_fields = vec![];
+        let mut subfield_gaps = vec![];
+        let mut field_scores = |fields: &BTreeMap<String, Weight>| -> Vec<FieldScore> {
+            let mut scores = vec![];
+            for (field, weight) in fields {
+                let value = resume.get(field);
+                let mut reasons = vec![];
+                let (mut score, mut max) = match weight {
+                    Weight::Points(points) => (if is_filled(value) { *points } else { 0 }, *points),
+                    Weight::Keys(keys) => {
+                        let (mut score, mut max) = (0, 0);
+                        for (key, points) in keys {
+                            max += points;
+                            if is_filled(value.and_then(|v| v.get(key))) {
+                                score += points;
+                            } else {
+                                reasons.push(format!("no {}", key.replace('_', " ")));
+                            }
+                        }
+                        (score, max)
+                    }
+                };
+                if !is_filled(value) {
+                    missing_fields.push(field.clone());
+                }
+                if let Some(subfields) = rubric.subfield_details.get(field) {
+                    let entries: Vec<&Value> = match value {
+                        Some(Value::Array(items)) => items.iter().filter(|i| is_filled(Some(i))).collect(),
+                        Some(object @ Value::Object(_)) if is_filled(Some(object)) => vec![object],
+                        _ => vec![],
+                    };
+                    let mut missing = vec![];
+                    for (subfield, points) in subfields {
+                        max += points;
+                        let filled = entries.iter().filter(|e| is_filled(e.get(subfield))).count();
+                        if !entries.is_empty() {
+                            score += points * filled as i32 / entries.len() as i32;
+                        }
+                        if filled < entries.len() {
+                            missing.push(subfield.clone());
+                            reasons.push(match (entries.len(), filled) {
+                                (1, _) => format!("no {}", subfield.replace('_', " ")),
+                                (total, 0) => format!("no {} in any of {} entries", subfield.replace('_', " "), total),
+                                (total, filled) => format!("no {} in {} of {} entries", subfield.replace('_', " "), total - filled, total),
+                            });
+                        }
+                    }
+                    if !missing.is_empty() {
+                        subfield_gaps.push(SubfieldGap {
+                            subfield: field.clone(),
+                            missing,
+                            description: Some(reasons.join(", ")),
+                        });
+                    }
+                }
+                let description = if !is_filled(value) {
+                    Some("Not found on the résumé".to_string())
+                } else if reasons.is_empty() {
+                    None
+                } else {
+                    let mut description = reasons.join(", ");
+                    description[..1].make_ascii_uppercase();
+                    Some(description)
+                };
+                scores.push(FieldScore {
+                    field: field.clone(),
+                    score,
+                    max,
+                    description,
+                    guidance: None,
+                });
+            }
+            // biggest weights first, the map orders them by name
+            scores.sort_by_key(|s| std::cmp::Reverse(s.max));
+            scores
+        };
+        let required_field_scores = field_scores(&rubric.required_fields);
+        let optional_field_scores = field_scores(&rubric.optional_fields);
+
+        let overall_score = required_field_scores.iter().chain(&optional_field_scores).map(|s| s.score).sum();
+        let maximum_possible_score = required_field_scores.iter().chain(&optional_field_scores).map(|s| s.max).sum();
+        ResumeScore {
+            persona: parsed_resume.persona.clone(),
+            overall_score,
+            maximum_possible_score,
+            missing_points: maximum_possible_score - overall_score,
+            required_field_scores: Some(required_field_scores),
+            optional_field_scores: Some(optional_field_scores),
+            missing_fields: Some(missing_fields).filter(|m| !m.is_empty()),
+            subfield_gaps: Some(subfield_gaps).filter(|g| !g.is_empty()),
+        }
+    }
+
+    /// Fields that are missing points, the ones the model is asked about
+    pub fn gaps(&self) -> Vec<&FieldScore> {
+        self.required_field_scores
+            .iter()
+            .flatten()
+            .chain(self.optional_field_scores.iter().flatten())
+            .filter(|s| s.score < s.max)
+            .collect()
+    }
+
+    fn guidance_request(
+        &self,
+        parsed_resume: &ParsedResume,
+    ) -> (Vec<Message>, ResponseFormat) {
+        let gaps = self
+            .gaps()
+            .iter()
+            .map(|s| format!("- {}: {} of {} points. {}", s.field, s.score, s.max, s.description.clone().unwrap_or_default()))
+            .collect::<Vec<String>>()
+            .join("\n");
         let messages = vec![Message {
             role: "user".to_string(),
             content: vec![Content {
                 content_type: ContentType::Text,
                 text: Some(format!(
-                    "Given the following parsed resume and scoring rubric, evaluate the ATS score of the resume and find the gaps. \
-                    Explain the gap and provide prescriptive guidance on how to bridge the gaps. \
-                    Use the provided schema to format your response. The schema description field contains the purpose of each field. \
-                    \n===Parsed Resume=== \n {} \
-                    \n===Scoring Rubric=== \n{}",
-                    parsed_resume, rubric_text
+                    "A {} résumé was scored for ATS completeness and is missing points in the fields below. \
+                    For every field, give prescriptive guidance on how to bridge the gap, specific to this résumé. \
+                    Do not score the résumé or change the numbers. \
+                    Use the provided schema to format your response. \
+                    \n===Gaps=== \n{} \
+                    \n===Parsed Resume=== \n{}",
+                    self.persona.label(),
+                    gaps,
+                    serde_json::to_string(parsed_resume).unwrap()
                 )),
                 image_url: None,
             }],
@@ -141,85 +315,225 @@ impl ResumeScore {
 
         let response_format = ResponseFormat {
             format_type: ResponseFormatType::JsonSchema,
-            json_schema: Some(json!(schema_ops::to_openai_schema::<ResumeScore>().unwrap())),
+            json_schema: Some(json!(schema_ops::to_openai_schema::<ScoreGuidance>().unwrap())),
         };
-        let openai_response = match post_chat_completion(messages, Some(response_format), Some(user_id)).await {
-            Some(r) => r,
-            None => {
-                log::error!("Completion Error scoring resume");
-                return None;
-            }
-        };
-        let parsed_resume = match serde_json::from_str(&openai_response) {
+        (messages, response_format)
+    }
+
+    /// Asks the model for guidance on the gaps, the score itself comes from `compute`
+    pub async fn guide(
+        &self,
+        parsed_resume: &ParsedResume,
+        user_id: String,
+        provider: &dyn LlmProvider,
+    ) -> Result<ScoreGuidance, CompletionError> {
+        let (messages, response_format) = self.guidance_request(parsed_resume);
+        let openai_response = match post_chat_completion(provider, messages, Some(response_format), Some(user_id), LlmOperation::ScoreResume).await {
             Ok(r) => r,
             Err(e) => {
-                log::error!("Error serializing score: {:?}", e);
-                return None;
+                log::error!("Completion Error writing score guidance: {}", e);
+                return Err(e);
             }
         };
-        Some(parsed_resume)
+        match parse_completion::<ScoreGuidance>(&openai_response) {
+            Ok(r) => Ok(r),
+            Err(e) => {
+                log::error!("Error serializing score guidance: {}", e);
+                Err(e)
+            }
+        }
+    }
+
+    /// Same as `guide` but yields the raw json as it is generated, finish with `parse_completion`
+    pub async fn guide_stream(
+        &self,
+        parsed_resume: &ParsedResume,
+        user_id: String,
+        provider: &dyn LlmProvider,
+    ) -> Result<DeltaStream, CompletionError> {
+        let (messages, response_format) = self.guidance_request(parsed_resume);
+        stream_chat_completion(provider, messages, Some(response_format), Some(user_id), LlmOperation::ScoreResume).await
+    }
+
+    /// Attaches the guidance to the field scores it names, anything else the model returned is dropped
+    pub fn apply_guidance(
+        &mut self,
+        guidance: ScoreGuidance,
+    ) {
+        for item in guidance.fields {
+            let field_score = self
+                .required_field_scores
+                .iter_mut()
+                .flatten()
+                .chain(self.optional_field_scores.iter_mut().flatten())
+                .find(|s| s.field == item.field && s.score < s.max);
+            if let Some(field_score) = field_score {
+                field_score.guidance = Some(item.guidance);
+            }
+        }
     }
 }
 
 const SCORE_COLLECTION: &str = "resume_scores";
 
-impl ScoreEntity {
-    pub async fn update(
-        &self,
-        mongoc: &Client,
-    ) -> Option<ScoreEntity> {
-        let collection = Database::get_collection::<ScoreEntity>(mongoc, SCORE_COLLECTION);
-        Database::find(collection, &self._id).await
+impl Versioned for ScoreEntity {
+    const COLLECTION: &'static str = SCORE_COLLECTION;
+    const SCHEMA_VERSION: u32 = 1;
+
+    fn migrations() -> &'static [Migration] {
+        SCORE_MIGRATIONS
     }
+}
 
-    pub async fn upsert(
-        &self,
-        mongoc: &Client,
-    ) -> Option<String> {
-        match Self::find(self, mongoc).await {
-            Some(r) => match r.update(mongoc).await {
-                Some(r) => Some(r._id.to_hex()),
-                None => {
-                    debug!("Error updating resume");
-                    None
-                }
-            },
-            None => {
-                return self.create(mongoc).await;
-            }
+#[async_trait]
+impl Repository for ScoreEntity {
+    const COLLECTION: &'static str = SCORE_COLLECTION;
+
+    fn id(&self) -> ObjectId {
+        self._id
+    }
+
+    fn set_id(
+        &mut self,
+        id: ObjectId,
+    ) {
+        self._id = id;
+    }
+
+    fn owner(&self) -> &str {
+        &self.user_id
+    }
+
+    fn revision(&self) -> u32 {
+        self.revision
+    }
+
+    fn set_revision(
+        &mut self,
+        revision: u32,
+    ) {
+        self.revision = revision;
+    }
+
+    fn decode(document: Document) -> Result<Self, String> {
+        migration_ops::try_decode::<Self>(document)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::features::resumes::{interchange::full_resume, rubric::Rubric};
+
+    fn rubric() -> Rubric {
+        serde_json::from_str(include_str!("../../../../assets/data/rubric.json")).unwrap()
+    }
+
+    fn empty_resume() -> ParsedResume {
+        serde_json::from_value(json!({
+            "name_slug": "",
+            "name": " ",
+            "persona": "SoftwareEngineer",
+            "contact": {},
+            "education": [],
+            "work_experience": [],
+        }))
+        .unwrap()
+    }
+
+    /// `full_resume` has an entry without a location, this one has nothing left out
+    fn complete_resume() -> ParsedResume {
+        let mut resume = full_resume();
+        resume.work_experience[1].location = Some("Austin".to_string());
+        resume
+    }
+
+    fn field<'a>(
+        score: &'a ResumeScore,
+        name: &str,
+    ) -> &'a FieldScore {
+        score
+            .required_field_scores
+            .iter()
+            .flatten()
+            .chain(score.optional_field_scores.iter().flatten())
+            .find(|s| s.field == name)
+            .unwrap_or_else(|| panic!("no score for {}", name))
+    }
+
+    #[test]
+    fn an_empty_resume_scores_nothing() {
+        let rubric = rubric();
+        for persona in Persona::ALL {
+            let score = ResumeScore::compute(&empty_resume(), rubric.for_persona(&persona).unwrap());
+            assert_eq!(score.overall_score, 0, "{:?}", persona);
+            assert!(score.maximum_possible_score > 0);
+            assert_eq!(score.missing_points, score.maximum_possible_score);
+            let missing = score.missing_fields.clone().unwrap();
+            assert!(missing.contains(&"name".to_string()) && missing.contains(&"work_experience".to_string()), "{:?}", missing);
+            assert!(score.gaps().iter().all(|s| s.description.as_deref() == Some("Not found on the résumé")));
+            // nothing to list a subfield of
+            assert!(score.subfield_gaps.is_none());
         }
     }
 
-    pub async fn create(
-        &self,
-        mongoc: &Client,
-    ) -> Option<String> {
-        let resume = Database::get_collection::<ScoreEntity>(mongoc, SCORE_COLLECTION);
-        Database::create(&resume, self).await
+    #[test]
+    fn a_complete_resume_scores_the_maximum() {
+        let score = ResumeScore::compute(&complete_resume(), rubric().for_persona(&Persona::SoftwareEngineer).unwrap());
+        assert_eq!(score.overall_score, score.maximum_possible_score);
+        assert_eq!(score.missing_points, 0);
+        assert!(score.gaps().is_empty());
+        assert!(score.missing_fields.is_none());
+        assert!(score.subfield_gaps.is_none());
     }
 
-    pub async fn find(
-        &self,
-        mongoc: &Client,
-    ) -> Option<ScoreEntity> {
-        let collection = Database::get_collection::<ScoreEntity>(mongoc, SCORE_COLLECTION);
-        Database::find(collection, &self._id).await
+    #[test]
+    fn partial_subfields_earn_their_share_rounded_down() {
+        let mut resume = full_resume();
+        resume.contact.phone = None;
+        let score = ResumeScore::compute(&resume, rubric().for_persona(&Persona::SoftwareEngineer).unwrap());
+
+        // 15 for the field and 2 for responsibilities, half of the 1 point for location rounds down to 0
+        let work = field(&score, "work_experience");
+        assert_eq!((work.score, work.max), (17, 18));
+        assert_eq!(work.description.as_deref(), Some("No location in 1 of 2 entries"));
+
+        let contact = field(&score, "contact");
+        assert_eq!((contact.score, contact.max), (5, 10));
+        assert_eq!(contact.description.as_deref(), Some("No phone"));
+
+        let gaps = score.subfield_gaps.unwrap();
+        assert_eq!(gaps.len(), 1);
+        assert_eq!((gaps[0].subfield.as_str(), gaps[0].missing.clone()), ("work_experience", vec!["location".to_string()]));
+        assert_eq!(score.missing_points, 1 + 5);
     }
 
-    pub async fn filter(
-        &self,
-        mongoc: &Client,
-        filter: bson::Document,
-    ) -> Option<Vec<ScoreEntity>> {
-        let collection = Database::get_collection::<ScoreEntity>(mongoc, SCORE_COLLECTION);
-        let result = match Database::scan::<ScoreEntity>(collection, filter).await {
-            Ok(r) => r,
-            Err(e) => {
-                log::error!("Error filtering resumes: {:?}", e);
-                return None;
+    #[test]
+    fn no_field_scores_above_its_maximum() {
+        let rubric = rubric();
+        let mut sparse = full_resume();
+        sparse.education[0].degree = None;
+        sparse.projects.as_mut().unwrap()[0].technologies.clear();
+        sparse.skills = None;
+        for resume in [empty_resume(), full_resume(), complete_resume(), sparse] {
+            for persona in Persona::ALL {
+                let score = ResumeScore::compute(&resume, rubric.for_persona(&persona).unwrap());
+                for field in score.required_field_scores.iter().flatten().chain(score.optional_field_scores.iter().flatten()) {
+                    assert!((0..=field.max).contains(&field.score), "{:?} {} scored {} of {}", persona, field.field, field.score, field.max);
+                }
+                assert!(score.overall_score <= score.maximum_possible_score);
+                assert_eq!(score.missing_points, score.maximum_possible_score - score.overall_score);
             }
-        };
+        }
+    }
 
-        Some(result)
+    #[test]
+    fn the_same_resume_always_gets_the_same_score() {
+        let rubric = rubric();
+        let rubric = rubric.for_persona(&Persona::SoftwareEngineer).unwrap();
+        let first = serde_json::to_value(ResumeScore::compute(&full_resume(), rubric)).unwrap();
+        for _ in 0..5 {
+            assert_eq!(serde_json::to_value(ResumeScore::compute(&full_resume(), rubric)).unwrap(), first);
+        }
     }
 }
@@ -0,0 +1,171 @@
+use mongodb::{
+    bson::{doc, oid::ObjectId, Document},
+    Client,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::features::resumes::migrations::RESUME_VERSION_MIGRATIONS;
+use crate::shared::ops::{
+    date_ops,
+    migration_ops::{self, Migration, Versioned},
+    repository_ops::{Query, Repository, RepositoryError, SortOrder},
+};
+
+use super::resume::{ParsedResume, ResumeEntity, ResumeKind, SourceFile};
+
+const RESUME_VERSION_COLLECTION: &str = "resume_versions";
+
+/// An immutable snapshot of a résumé. `ResumeEntity` keeps its id across uploads and always holds
+/// the latest version, every change appends a snapshot here.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ResumeVersionEntity {
+    pub _id: ObjectId,
+    /// Id of the `ResumeEntity` this is a version of
+    pub resume_id: String,
+    pub user_id: String,
+    /// Starts at 1 and increases by one per snapshot
+    pub version: u32,
+    /// The version this one replaced, `None` for the first
+    pub parent_id: Option<ObjectId>,
+    pub source: ResumeKind,
+    /// Set when the snapshot restores an earlier version
+    pub restored_from: Option<u32>,
+    pub resume_text: String,
+    pub parsed_resume: Option<ParsedResume>,
+    #[serde(default)]
+    pub source_file: Option<SourceFile>,
+    pub name: String,
+    pub timestamp: i64,
+    /// Bumped by every `Repository::save`, snapshots are never saved so it stays 0
+    #[serde(default)]
+    pub revision: u32,
+    #[serde(default)]
+    pub schema_version: u32,
+}
+
+impl Versioned for ResumeVersionEntity {
+    const COLLECTION: &'static str = RESUME_VERSION_COLLECTION;
+    const SCHEMA_VERSION: u32 = 2;
+
+    // the collection was created at version 1
+    fn migrations() -> &'static [Migration] {
+        RESUME_VERSION_MIGRATIONS
+    }
+}
+
+impl ResumeVersionEntity {
+    /// Snapshot of `resume` as the version after `parent`
+    pub fn next(
+        resume: &ResumeEntity,
+        parent: Option<&ResumeVersionEntity>,
+        source: ResumeKind,
+        restored_from: Option<u32>,
+    ) -> Self {
+        ResumeVersionEntity {
+            _id: ObjectId::new(),
+            resume_id: resume._id.to_hex(),
+            user_id: resume.user_id.clone(),
+            version: parent.map(|p| p.version + 1).unwrap_or(1),
+            parent_id: parent.map(|p| p._id),
+            source,
+            restored_from,
+            resume_text: resume.resume_text.clone(),
+            parsed_resume: resume.parsed_resume.clone(),
+            source_file: resume.source_file.clone(),
+            name: resume.name.clone(),
+            timestamp: date_ops::to_timestamp(),
+            revision: 0,
+            schema_version: Self::SCHEMA_VERSION,
+        }
+    }
+
+    /// All versions of a résumé, newest first
+    pub async fn history(
+        mongoc: &Client,
+        resume_id: &str,
+        user_id: &str,
+    ) -> Result<Vec<ResumeVersionEntity>, RepositoryError> {
+        let query = Query::new().filter(doc! {"resume_id": resume_id}).sort("version", SortOrder::Descending);
+        Self::list(mongoc, user_id, &query).await
+    }
+
+    pub async fn find_version(
+        mongoc: &Client,
+        resume_id: &str,
+        user_id: &str,
+        version: u32,
+    ) -> Result<Option<ResumeVersionEntity>, RepositoryError> {
+        Self::find_one(mongoc, user_id, doc! {"resume_id": resume_id, "version": version as i64}).await
+    }
+
+    /// The highest version, the one the next snapshot follows
+    pub async fn latest(
+        mongoc: &Client,
+        resume_id: &str,
+        user_id: &str,
+    ) -> Result<Option<ResumeVersionEntity>, RepositoryError> {
+        let query = Query::new().filter(doc! {"resume_id": resume_id}).sort("version", SortOrder::Descending).page(0, 1);
+        Ok(Self::list(mongoc, user_id, &query).await?.into_iter().next())
+    }
+}
+
+/// `create` reports a version number a concurrent save took first as `RepositoryError::Conflict`, `(resume_id, version)` is unique
+impl Repository for ResumeVersionEntity {
+    const COLLECTION: &'static str = RESUME_VERSION_COLLECTION;
+
+    fn id(&self) -> ObjectId {
+        self._id
+    }
+
+    fn set_id(
+        &mut self,
+        id: ObjectId,
+    ) {
+        self._id = id;
+    }
+
+    fn owner(&self) -> &str {
+        &self.user_id
+    }
+
+    fn revision(&self) -> u32 {
+        self.revision
+    }
+
+    fn set_revision(
+        &mut self,
+        revision: u32,
+    ) {
+        self.revision = revision;
+    }
+
+    fn decode(document: Document) -> Result<Self, String> {
+        migration_ops::try_decode::<Self>(document)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{features::resumes::migrations, shared::ops::db_ops::Database};
+
+    #[actix_web::test]
+    #[ignore = "needs MongoDB: set DB_CONNECTION_STRING and DB_NAME"]
+    async fn a_version_number_taken_first_is_a_conflict() {
+        let mongoc = Database::get_client().await;
+        migrations::ensure_indexes(&mongoc, RESUME_VERSION_COLLECTION).await.unwrap();
+        let resume = ResumeEntity {
+            user_id: "repository-test-alice".to_string(),
+            ..Default::default()
+        };
+        let first = ResumeVersionEntity::next(&resume, None, ResumeKind::Uploaded, None);
+        let created = first.create(&mongoc).await;
+        let racing = ResumeVersionEntity::next(&resume, None, ResumeKind::Edited, None).create(&mongoc).await;
+        let latest = ResumeVersionEntity::latest(&mongoc, &first.resume_id, &resume.user_id).await;
+        first.delete(&mongoc).await.unwrap();
+
+        assert!(created.is_ok(), "{:?}", created);
+        assert!(matches!(racing, Err(RepositoryError::Conflict { .. })), "{:?}", racing);
+        assert_eq!(latest.unwrap().map(|v| (v.version, v._id)), Some((1, first._id)));
+    }
+}
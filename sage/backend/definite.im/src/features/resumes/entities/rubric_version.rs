@@ -0,0 +1,151 @@
+use mongodb::{
+    bson::{doc, oid::ObjectId, Document},
+    Client,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::features::resumes::{migrations::RUBRIC_VERSION_MIGRATIONS, rubric::Rubric};
+use crate::shared::ops::{
+    date_ops,
+    migration_ops::{self, Migration, Versioned},
+    repository_ops::{Query, Repository, RepositoryError, SortOrder},
+};
+
+const RUBRIC_VERSION_COLLECTION: &str = "rubric_versions";
+
+/// Rubric versions are the whole app's rather than one admin's, `Repository` calls pass this as their owner
+pub const RUBRIC_OWNER: &str = "rubrics";
+
+/// A saved rubric. Versions are never edited, saving an edit adds the next version, and the
+/// most recently published one scores new résumés.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RubricVersionEntity {
+    pub _id: ObjectId,
+    /// Starts at 1 and increases by one per save
+    pub version: u32,
+    pub rubric: Rubric,
+    /// The version the edit started from, `None` when it started from the rubric file
+    pub parent_version: Option<u32>,
+    pub note: Option<String>,
+    /// Email of the admin who saved it
+    pub author: String,
+    pub timestamp: i64,
+    /// When it was last published in milliseconds, `None` for a draft
+    pub published_at: Option<i64>,
+    /// Bumped by every `Repository::save`
+    #[serde(default)]
+    pub revision: u32,
+    #[serde(default)]
+    pub schema_version: u32,
+}
+
+impl Versioned for RubricVersionEntity {
+    const COLLECTION: &'static str = RUBRIC_VERSION_COLLECTION;
+    const SCHEMA_VERSION: u32 = 2;
+
+    fn migrations() -> &'static [Migration] {
+        RUBRIC_VERSION_MIGRATIONS
+    }
+}
+
+impl Repository for RubricVersionEntity {
+    const COLLECTION: &'static str = RUBRIC_VERSION_COLLECTION;
+
+    fn id(&self) -> ObjectId {
+        self._id
+    }
+
+    fn set_id(
+        &mut self,
+        id: ObjectId,
+    ) {
+        self._id = id;
+    }
+
+    fn owner(&self) -> &str {
+        RUBRIC_OWNER
+    }
+
+    /// Every admin sees every version, so nothing is narrowed
+    fn owned(
+        _owner: &str,
+        filter: Document,
+    ) -> Document {
+        filter
+    }
+
+    fn revision(&self) -> u32 {
+        self.revision
+    }
+
+    fn set_revision(
+        &mut self,
+        revision: u32,
+    ) {
+        self.revision = revision;
+    }
+
+    fn decode(document: Document) -> Result<Self, String> {
+        migration_ops::try_decode::<Self>(document)
+    }
+}
+
+impl RubricVersionEntity {
+    /// A draft of `rubric` as the version after `latest`
+    pub fn next(
+        rubric: Rubric,
+        latest: Option<&RubricVersionEntity>,
+        parent_version: Option<u32>,
+        note: Option<String>,
+        author: String,
+    ) -> Self {
+        RubricVersionEntity {
+            _id: ObjectId::new(),
+            version: latest.map(|l| l.version + 1).unwrap_or(1),
+            rubric,
+            parent_version,
+            note,
+            author,
+            timestamp: date_ops::to_timestamp(),
+            published_at: None,
+            revision: 0,
+            schema_version: Self::SCHEMA_VERSION,
+        }
+    }
+
+    /// The first version in `query`'s order
+    async fn first(
+        mongoc: &Client,
+        query: Query,
+    ) -> Result<Option<RubricVersionEntity>, RepositoryError> {
+        Ok(Self::list(mongoc, RUBRIC_OWNER, &query.page(0, 1)).await?.into_iter().next())
+    }
+
+    /// The highest version, the one the next save follows
+    pub async fn latest(mongoc: &Client) -> Result<Option<RubricVersionEntity>, RepositoryError> {
+        Self::first(mongoc, Query::new().sort("version", SortOrder::Descending)).await
+    }
+
+    /// The version that scores new résumés, `None` until one is published
+    pub async fn published(mongoc: &Client) -> Result<Option<RubricVersionEntity>, RepositoryError> {
+        let query = Query::new()
+            .filter(doc! {"published_at": {"$ne": null}})
+            .sort("published_at", SortOrder::Descending)
+            .sort("version", SortOrder::Descending);
+        Self::first(mongoc, query).await
+    }
+
+    /// Publishes this version, publishing an older one again rolls back to it
+    pub async fn publish(
+        &mut self,
+        mongoc: &Client,
+    ) -> Result<(), RepositoryError> {
+        let previous = self.published_at;
+        self.published_at = Some(date_ops::to_timestamp_millis());
+        let saved = self.save(mongoc).await;
+        if saved.is_err() {
+            self.published_at = previous;
+        }
+        saved
+    }
+}
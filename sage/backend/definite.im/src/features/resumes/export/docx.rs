@@ -0,0 +1,92 @@
+use std::io::{Cursor, Write};
+
+use zip::{result::ZipResult, write::SimpleFileOptions, CompressionMethod, ZipWriter};
+
+use super::{Block, TemplateStyle};
+
+const CONTENT_TYPES: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<Types xmlns="http://schemas.openxmlformats.org/package/2006/content-types"><Default Extension="rels" ContentType="application/vnd.openxmlformats-package.relationships+xml"/><Default Extension="xml" ContentType="application/xml"/><Override PartName="/word/document.xml" ContentType="application/vnd.openxmlformats-officedocument.wordprocessingml.document.main+xml"/></Types>"#;
+
+const RELATIONSHIPS: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships"><Relationship Id="rId1" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/officeDocument" Target="word/document.xml"/></Relationships>"#;
+
+/// US Letter with 0.75in margins, in twentieths of a point
+const SECTION: &str = r#"<w:sectPr><w:pgSz w:w="12240" w:h="15840"/><w:pgMar w:top="1080" w:right="1080" w:bottom="1080" w:left="1080" w:header="0" w:footer="0" w:gutter="0"/></w:sectPr>"#;
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+/// Sizes are in points, Word wants half points
+fn run(
+    text: &str,
+    size: f32,
+    bold: bool,
+) -> String {
+    format!(
+        r#"<w:r><w:rPr><w:rFonts w:ascii="Arial" w:hAnsi="Arial" w:cs="Arial"/>{}<w:sz w:val="{}"/></w:rPr><w:t xml:space="preserve">{}</w:t></w:r>"#,
+        if bold { "<w:b/>" } else { "" },
+        (size * 2.0).round() as u32,
+        escape(text)
+    )
+}
+
+/// `before` is the space above the paragraph in points. Word insists on schema order inside `w:pPr`,
+/// `properties` go before the spacing and `indent` after it.
+fn paragraph(
+    properties: &str,
+    before: f32,
+    indent: &str,
+    runs: &str,
+) -> String {
+    format!(
+        r#"<w:p><w:pPr>{}<w:spacing w:before="{}" w:after="0"/>{}</w:pPr>{}</w:p>"#,
+        properties,
+        (before * 20.0).round() as u32,
+        indent,
+        runs
+    )
+}
+
+/// Renders the blocks as a single-column Word document with direct formatting only.
+/// Bullets are a literal "•" with a hanging indent rather than a numbering definition, which some parsers drop.
+pub fn render(
+    blocks: &[Block],
+    style: &TemplateStyle,
+) -> ZipResult<Vec<u8>> {
+    let mut body = String::new();
+    for block in blocks {
+        let xml = match block {
+            Block::Name(name) => paragraph("", 0.0, "", &run(name, style.name_size, true)),
+            Block::Contact(contact) => paragraph("", 2.0, "", &run(contact, style.body_size, false)),
+            Block::Heading(heading) => paragraph(
+                r#"<w:keepNext/><w:pBdr><w:bottom w:val="single" w:sz="4" w:space="1" w:color="auto"/></w:pBdr>"#,
+                style.section_spacing,
+                "",
+                &run(heading, style.heading_size, true),
+            ),
+            Block::Entry { title, detail } if detail.is_empty() => paragraph("<w:keepNext/>", style.body_size * 0.3, "", &run(title, style.body_size, true)),
+            Block::Entry { title, detail } => format!(
+                "{}{}",
+                paragraph("<w:keepNext/>", style.body_size * 0.3, "", &run(title, style.body_size, true)),
+                paragraph("<w:keepNext/>", 0.0, "", &run(detail, style.body_size, false))
+            ),
+            Block::Paragraph(text) => paragraph("", 1.0, "", &run(text, style.body_size, false)),
+            Block::Bullet(bullet) => paragraph("", 1.0, r#"<w:ind w:left="360" w:hanging="240"/>"#, &run(&format!("• {}", bullet), style.body_size, false)),
+        };
+        body.push_str(&xml);
+    }
+    let document = format!(
+        r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<w:document xmlns:w="http://schemas.openxmlformats.org/wordprocessingml/2006/main"><w:body>{}{}</w:body></w:document>"#,
+        body, SECTION
+    );
+
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+    for (name, content) in [("[Content_Types].xml", CONTENT_TYPES), ("_rels/.rels", RELATIONSHIPS), ("word/document.xml", document.as_str())] {
+        zip.start_file(name, options)?;
+        zip.write_all(content.as_bytes())?;
+    }
+    Ok(zip.finish()?.into_inner())
+}
@@ -0,0 +1,400 @@
+pub mod docx;
+pub mod pdf;
+pub mod text;
+
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use super::{
+    entities::resume::ParsedResume,
+    interchange::{europass, json_resume},
+};
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    Pdf,
+    Docx,
+    Txt,
+    /// JSON Resume v1, for other résumé tools rather than applications
+    JsonResume,
+    /// Europass CV XML
+    Europass,
+}
+
+impl ExportFormat {
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            ExportFormat::Pdf => "application/pdf",
+            ExportFormat::Docx => "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+            ExportFormat::Txt => "text/plain; charset=utf-8",
+            ExportFormat::JsonResume => "application/json",
+            ExportFormat::Europass => "application/xml",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Pdf => "pdf",
+            ExportFormat::Docx => "docx",
+            ExportFormat::Txt => "txt",
+            ExportFormat::JsonResume => "json",
+            ExportFormat::Europass => "xml",
+        }
+    }
+
+    /// Structured formats carry the data only, the template does not apply to them
+    pub fn uses_template(&self) -> bool {
+        matches!(self, ExportFormat::Pdf | ExportFormat::Docx | ExportFormat::Txt)
+    }
+}
+
+/// Why a résumé could not be exported
+#[derive(Debug)]
+pub enum ExportError {
+    /// The format's fonts cannot show these characters, the user can pick another format
+    UnsupportedCharacters {
+        format: ExportFormat,
+        characters: Vec<char>,
+    },
+    Failed {
+        format: ExportFormat,
+        message: String,
+    },
+}
+
+impl fmt::Display for ExportError {
+    fn fmt(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+    ) -> fmt::Result {
+        match self {
+            ExportError::UnsupportedCharacters { format, characters } => write!(
+                f,
+                "The {} export cannot show {}, download the résumé as Word (DOCX) or plain text instead",
+                format.extension().to_uppercase(),
+                characters.iter().map(char::to_string).collect::<Vec<String>>().join(" ")
+            ),
+            ExportError::Failed { format, message } => write!(f, "Error writing {}: {}", format.extension(), message),
+        }
+    }
+}
+
+impl std::error::Error for ExportError {}
+
+/// The sections a template can place, in the order it lists them
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Section {
+    Summary,
+    Skills,
+    Experience,
+    Education,
+    Projects,
+    Certifications,
+    Achievements,
+    Publications,
+    Authoring,
+    Patents,
+    OpenSource,
+    Blogs,
+    Volunteer,
+    Interests,
+}
+
+/// Every template is a single column of plain text runs, no tables, images or text boxes,
+/// so applicant tracking systems read them in order. They differ in density and section order.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportTemplate {
+    /// Experience first, every section
+    #[default]
+    Classic,
+    /// Summary and skills up front for keyword matching
+    Modern,
+    /// Smaller type and only the sections recruiters read, aims for one page
+    Compact,
+}
+
+impl ExportTemplate {
+    pub const ALL: [ExportTemplate; 3] = [ExportTemplate::Classic, ExportTemplate::Modern, ExportTemplate::Compact];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            ExportTemplate::Classic => "Classic",
+            ExportTemplate::Modern => "Modern",
+            ExportTemplate::Compact => "Compact",
+        }
+    }
+
+    pub fn style(&self) -> TemplateStyle {
+        match self {
+            ExportTemplate::Classic => TemplateStyle {
+                body_size: 10.5,
+                name_size: 18.0,
+                heading_size: 12.0,
+                line_spacing: 1.3,
+                section_spacing: 10.0,
+                uppercase_headings: true,
+                sections: &[
+                    Section::Summary,
+                    Section::Experience,
+                    Section::Education,
+                    Section::Skills,
+                    Section::Projects,
+                    Section::Certifications,
+                    Section::Achievements,
+                    Section::Publications,
+                    Section::Authoring,
+                    Section::Patents,
+                    Section::OpenSource,
+                    Section::Blogs,
+                    Section::Volunteer,
+                    Section::Interests,
+                ],
+            },
+            ExportTemplate::Modern => TemplateStyle {
+                body_size: 10.5,
+                name_size: 22.0,
+                heading_size: 13.0,
+                line_spacing: 1.35,
+                section_spacing: 12.0,
+                uppercase_headings: false,
+                sections: &[
+                    Section::Summary,
+                    Section::Skills,
+                    Section::Experience,
+                    Section::Projects,
+                    Section::OpenSource,
+                    Section::Education,
+                    Section::Certifications,
+                    Section::Achievements,
+                    Section::Publications,
+                    Section::Authoring,
+                    Section::Patents,
+                    Section::Blogs,
+                    Section::Volunteer,
+                ],
+            },
+            ExportTemplate::Compact => TemplateStyle {
+                body_size: 9.5,
+                name_size: 15.0,
+                heading_size: 10.5,
+                line_spacing: 1.2,
+                section_spacing: 6.0,
+                uppercase_headings: true,
+                sections: &[Section::Summary, Section::Experience, Section::Skills, Section::Education, Section::Certifications],
+            },
+        }
+    }
+}
+
+pub struct TemplateStyle {
+    /// Font sizes in points
+    pub body_size: f32,
+    pub name_size: f32,
+    pub heading_size: f32,
+    /// Line height as a multiple of the font size
+    pub line_spacing: f32,
+    /// Extra space above a section heading, in points
+    pub section_spacing: f32,
+    pub uppercase_headings: bool,
+    pub sections: &'static [Section],
+}
+
+/// A résumé laid out as a flat list of blocks, shared by every export format
+#[derive(Debug, Clone, PartialEq)]
+pub enum Block {
+    Name(String),
+    Contact(String),
+    Heading(String),
+    /// An entry's bold first line (e.g. "Engineer, Acme") and its plain second line (e.g. "Berlin | 2020 - 2023")
+    Entry {
+        title: String,
+        detail: String,
+    },
+    Paragraph(String),
+    Bullet(String),
+}
+
+/// Joins the non-empty parts with `separator`
+fn join(
+    parts: &[Option<&str>],
+    separator: &str,
+) -> String {
+    parts.iter().flatten().map(|p| p.trim()).filter(|p| !p.is_empty()).collect::<Vec<&str>>().join(separator)
+}
+
+fn entry(
+    title: &[Option<&str>],
+    detail: &[Option<&str>],
+) -> Block {
+    Block::Entry {
+        title: join(title, ", "),
+        detail: join(detail, " | "),
+    }
+}
+
+fn heading(
+    style: &TemplateStyle,
+    title: &str,
+) -> Block {
+    Block::Heading(if style.uppercase_headings { title.to_uppercase() } else { title.to_string() })
+}
+
+fn section_blocks(
+    resume: &ParsedResume,
+    section: Section,
+) -> (&'static str, Vec<Block>) {
+    let mut blocks = vec![];
+    let title = match section {
+        Section::Summary => {
+            blocks.extend(resume.summary.iter().filter(|s| !s.trim().is_empty()).map(|s| Block::Paragraph(s.trim().to_string())));
+            "Summary"
+        }
+        Section::Skills => {
+            if let Some(skills) = &resume.skills {
+                for (label, list) in [("Technical", &skills.technical), ("Soft skills", &skills.soft_skills), ("Other", &skills.other_skills)] {
+                    if !list.is_empty() {
+                        blocks.push(Block::Paragraph(format!("{}: {}", label, list.join(", "))));
+                    }
+                }
+            }
+            "Skills"
+        }
+        Section::Experience => {
+            for work in &resume.work_experience {
+                blocks.push(entry(&[Some(&work.title), Some(&work.company)], &[work.location.as_deref(), Some(&work.dates)]));
+                blocks.extend(work.responsibilities.iter().map(|r| Block::Bullet(r.clone())));
+            }
+            "Experience"
+        }
+        Section::Education => {
+            for education in &resume.education {
+                blocks.push(entry(
+                    &[education.degree.as_deref(), education.field_of_study.as_deref(), Some(&education.institution)],
+                    &[education.location.as_deref(), Some(&education.dates), education.gpa.as_ref().map(|g| format!("GPA {}", g)).as_deref()],
+                ));
+            }
+            "Education"
+        }
+        Section::Projects => {
+            for project in resume.projects.iter().flatten() {
+                blocks.push(entry(&[Some(&project.name)], &[project.dates.as_deref(), project.link.as_deref()]));
+                blocks.extend(project.description.iter().map(|d| Block::Paragraph(d.clone())));
+                if !project.technologies.is_empty() {
+                    blocks.push(Block::Paragraph(format!("Technologies: {}", project.technologies.join(", "))));
+                }
+            }
+            "Projects"
+        }
+        Section::Certifications => {
+            for certification in resume.certifications.iter().flatten() {
+                blocks.push(Block::Bullet(join(&[Some(&certification.name), certification.issuer.as_deref(), certification.date.as_deref()], ", ")));
+            }
+            "Certifications"
+        }
+        Section::Achievements => {
+            for achievement in resume.achievements.iter().flatten() {
+                blocks.push(Block::Bullet(join(&[Some(&achievement.title), achievement.details.as_deref(), achievement.date.as_deref()], " - ")));
+            }
+            "Achievements"
+        }
+        Section::Publications => {
+            for publication in resume.publications.iter().flatten() {
+                blocks.push(Block::Bullet(join(
+                    &[publication.title.as_deref(), publication.journal_or_conference.as_deref(), publication.date.as_deref()],
+                    ", ",
+                )));
+            }
+            "Publications"
+        }
+        Section::Authoring => {
+            for authored in resume.authoring.iter().flatten() {
+                blocks.push(Block::Bullet(join(&[Some(&authored.title), authored.publisher.as_deref(), authored.date.as_deref()], ", ")));
+            }
+            "Books and Articles"
+        }
+        Section::Patents => {
+            for patent in resume.patents.iter().flatten() {
+                blocks.push(Block::Bullet(join(&[Some(&patent.title), patent.patent_number.as_deref(), patent.date.as_deref()], ", ")));
+            }
+            "Patents"
+        }
+        Section::OpenSource => {
+            for contribution in resume.open_source_contributions.iter().flatten() {
+                blocks.push(entry(&[Some(&contribution.project_name)], &[contribution.dates.as_deref(), contribution.link.as_deref()]));
+                blocks.extend(contribution.description.iter().map(|d| Block::Paragraph(d.clone())));
+            }
+            "Open Source"
+        }
+        Section::Blogs => {
+            for blog in resume.blogs.iter().flatten() {
+                blocks.push(Block::Bullet(join(&[Some(&blog.title), blog.date.as_deref(), blog.link.as_deref()], ", ")));
+            }
+            "Writing"
+        }
+        Section::Volunteer => {
+            for volunteer in resume.volunteer_experience.iter().flatten() {
+                blocks.push(entry(&[volunteer.role.as_deref(), volunteer.organization.as_deref()], &[volunteer.dates.as_deref()]));
+                blocks.extend(volunteer.description.iter().map(|d| Block::Paragraph(d.clone())));
+            }
+            "Volunteering"
+        }
+        Section::Interests => {
+            if let Some(interests) = resume.interests_hobbies.as_ref().filter(|i| !i.is_empty()) {
+                blocks.push(Block::Paragraph(interests.join(", ")));
+            }
+            "Interests"
+        }
+    };
+    (title, blocks)
+}
+
+/// Lays the résumé out in the template's section order, skipping empty sections
+pub fn layout(
+    resume: &ParsedResume,
+    template: ExportTemplate,
+) -> Vec<Block> {
+    let style = template.style();
+    let contact = &resume.contact;
+    let mut blocks = vec![Block::Name(resume.name.clone())];
+    let contact_line = join(
+        &[
+            contact.email.as_deref(),
+            contact.phone.as_deref(),
+            contact.linkedin.as_deref(),
+            contact.github.as_deref(),
+            contact.website.as_deref(),
+            contact.twitter.as_deref(),
+        ],
+        " | ",
+    );
+    if !contact_line.is_empty() {
+        blocks.push(Block::Contact(contact_line));
+    }
+    for section in style.sections {
+        let (title, section) = section_blocks(resume, *section);
+        if !section.is_empty() {
+            blocks.push(heading(&style, title));
+            blocks.extend(section);
+        }
+    }
+    blocks
+}
+
+/// Renders the résumé to a downloadable file
+pub fn export(
+    resume: &ParsedResume,
+    format: ExportFormat,
+    template: ExportTemplate,
+) -> Result<Vec<u8>, ExportError> {
+    let failed = |message: String| ExportError::Failed { format, message };
+    match format {
+        ExportFormat::Pdf => pdf::render(&layout(resume, template), &template.style(), &resume.name),
+        ExportFormat::Docx => docx::render(&layout(resume, template), &template.style()).map_err(|e| failed(e.to_string())),
+        ExportFormat::Txt => Ok(text::render(&layout(resume, template)).into_bytes()),
+        ExportFormat::JsonResume => json_resume::to_json(resume).map_err(|e| failed(e.to_string())),
+        ExportFormat::Europass => europass::to_xml(resume).map_err(|e| failed(e.to_string())),
+    }
+}
@@ -0,0 +1,300 @@
+use pdf_writer::{Content, Name, Pdf, Rect, Ref, Str, TextStr};
+
+use super::{Block, ExportError, ExportFormat, TemplateStyle};
+
+/// US Letter in points
+const PAGE_WIDTH: f32 = 612.0;
+const PAGE_HEIGHT: f32 = 792.0;
+const MARGIN: f32 = 54.0;
+const BULLET_INDENT: f32 = 12.0;
+
+const REGULAR: Name = Name(b"F1");
+const BOLD: Name = Name(b"F2");
+
+/// Helvetica advance widths for ' '..='~' in thousandths of an em, from the standard 14 font metrics
+const HELVETICA_WIDTHS: [u16; 95] = [
+    278, 278, 355, 556, 556, 889, 667, 191, 333, 333, 389, 584, 278, 333, 278, 278, 556, 556, 556, 556, 556, 556, 556, 556, 556, 556, 278, 278, 584, 584, 584, 556, 1015, 667, 667, 722, 722, 667, 611,
+    778, 722, 278, 500, 667, 556, 833, 722, 778, 667, 778, 722, 667, 611, 722, 667, 944, 667, 667, 611, 278, 278, 278, 469, 556, 333, 556, 556, 500, 556, 556, 278, 556, 556, 222, 222, 500, 222, 833,
+    556, 556, 556, 556, 333, 500, 278, 556, 500, 722, 500, 500, 500, 334, 260, 334, 584,
+];
+
+/// Helvetica-Bold runs a little wider, close enough for line breaking
+const BOLD_WIDTH_FACTOR: f32 = 1.08;
+
+struct Line {
+    text: String,
+    x: f32,
+    y: f32,
+    size: f32,
+    bold: bool,
+}
+
+/// A horizontal rule under a section heading
+struct Rule {
+    y: f32,
+}
+
+#[derive(Default)]
+struct Page {
+    lines: Vec<Line>,
+    rules: Vec<Rule>,
+}
+
+fn char_width(c: char) -> f32 {
+    match c {
+        ' '..='~' => HELVETICA_WIDTHS[c as usize - 32] as f32,
+        _ => 556.0,
+    }
+}
+
+fn text_width(
+    text: &str,
+    size: f32,
+    bold: bool,
+) -> f32 {
+    let width: f32 = text.chars().map(char_width).sum::<f32>() * size / 1000.0;
+    if bold {
+        width * BOLD_WIDTH_FACTOR
+    } else {
+        width
+    }
+}
+
+/// Greedy word wrap, words wider than a line are broken between characters
+fn wrap(
+    text: &str,
+    size: f32,
+    bold: bool,
+    max_width: f32,
+) -> Vec<String> {
+    let mut lines = vec![];
+    let mut line = String::new();
+    for word in text.split_whitespace() {
+        let candidate = if line.is_empty() { word.to_string() } else { format!("{} {}", line, word) };
+        if text_width(&candidate, size, bold) <= max_width {
+            line = candidate;
+            continue;
+        }
+        if !line.is_empty() {
+            lines.push(std::mem::take(&mut line));
+        }
+        for c in word.chars() {
+            if !line.is_empty() && text_width(&format!("{}{}", line, c), size, bold) > max_width {
+                lines.push(std::mem::take(&mut line));
+            }
+            line.push(c);
+        }
+    }
+    if !line.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
+
+/// The WinAnsiEncoding byte for `c`, the encoding the standard fonts use. `None` for characters they cannot show.
+fn win_ansi(c: char) -> Option<u8> {
+    let byte = match c {
+        ' '..='~' | '\u{a0}'..='\u{ff}' => c as u8,
+        '€' => 0x80,
+        '‚' => 0x82,
+        'ƒ' => 0x83,
+        '„' => 0x84,
+        '…' => 0x85,
+        '†' => 0x86,
+        '‡' => 0x87,
+        'ˆ' => 0x88,
+        '‰' => 0x89,
+        'Š' => 0x8a,
+        '‹' => 0x8b,
+        'Œ' => 0x8c,
+        'Ž' => 0x8e,
+        '‘' => 0x91,
+        '’' => 0x92,
+        '“' => 0x93,
+        '”' => 0x94,
+        '•' => 0x95,
+        '–' => 0x96,
+        '—' => 0x97,
+        '˜' => 0x98,
+        '™' => 0x99,
+        'š' => 0x9a,
+        '›' => 0x9b,
+        'œ' => 0x9c,
+        'ž' => 0x9e,
+        'Ÿ' => 0x9f,
+        _ => return None,
+    };
+    Some(byte)
+}
+
+/// `render` refuses text with characters `win_ansi` has no byte for, none are left out here
+fn encode(text: &str) -> Vec<u8> {
+    text.chars().filter_map(win_ansi).collect()
+}
+
+/// Characters in `lines` the standard fonts cannot show, each once
+fn unsupported<'a>(lines: impl Iterator<Item = &'a Line>) -> Vec<char> {
+    let mut characters: Vec<char> = lines.flat_map(|l| l.text.chars()).filter(|c| win_ansi(*c).is_none()).collect();
+    characters.sort_unstable();
+    characters.dedup();
+    characters
+}
+
+/// Places every line, starting a new page when the next one would run into the bottom margin
+struct Layout<'a> {
+    style: &'a TemplateStyle,
+    pages: Vec<Page>,
+    y: f32,
+}
+
+impl Layout<'_> {
+    fn page(&mut self) -> &mut Page {
+        self.pages.last_mut().expect("layout always has a page")
+    }
+
+    fn advance(
+        &mut self,
+        height: f32,
+    ) {
+        if self.y - height < MARGIN {
+            self.pages.push(Page::default());
+            self.y = PAGE_HEIGHT - MARGIN;
+        }
+        self.y -= height;
+    }
+
+    /// Wraps `text` from `x` to the right margin. A `marker` (the bullet) is written just before the
+    /// first line, so text extraction reads it in order.
+    fn text(
+        &mut self,
+        text: &str,
+        x: f32,
+        size: f32,
+        bold: bool,
+        marker: Option<&str>,
+    ) {
+        for (i, line) in wrap(text, size, bold, PAGE_WIDTH - MARGIN - x).into_iter().enumerate() {
+            self.advance(size * self.style.line_spacing);
+            let y = self.y;
+            if let (0, Some(marker)) = (i, marker) {
+                self.page().lines.push(Line {
+                    text: marker.to_string(),
+                    x: MARGIN + 2.0,
+                    y,
+                    size,
+                    bold: false,
+                });
+            }
+            self.page().lines.push(Line { text: line, x, y, size, bold });
+        }
+    }
+}
+
+/// Renders the blocks as a text-only PDF with the built-in Helvetica, so every character stays selectable.
+/// Text Helvetica cannot show (e.g. 'ł' or CJK) is refused rather than printed as '?'.
+pub fn render(
+    blocks: &[Block],
+    style: &TemplateStyle,
+    title: &str,
+) -> Result<Vec<u8>, ExportError> {
+    let mut layout = Layout {
+        style,
+        pages: vec![Page::default()],
+        y: PAGE_HEIGHT - MARGIN,
+    };
+    for block in blocks {
+        match block {
+            Block::Name(name) => layout.text(name, MARGIN, style.name_size, true, None),
+            Block::Contact(contact) => layout.text(contact, MARGIN, style.body_size, false, None),
+            Block::Heading(heading) => {
+                layout.advance(style.section_spacing);
+                layout.text(heading, MARGIN, style.heading_size, true, None);
+                layout.advance(3.0);
+                let y = layout.y;
+                layout.page().rules.push(Rule { y });
+                layout.advance(3.0);
+            }
+            Block::Entry { title, detail } => {
+                layout.advance(style.body_size * 0.3);
+                layout.text(title, MARGIN, style.body_size, true, None);
+                if !detail.is_empty() {
+                    layout.text(detail, MARGIN, style.body_size, false, None);
+                }
+            }
+            Block::Paragraph(paragraph) => layout.text(paragraph, MARGIN, style.body_size, false, None),
+            Block::Bullet(bullet) => layout.text(bullet, MARGIN + BULLET_INDENT, style.body_size, false, Some("•")),
+        }
+    }
+
+    let characters = unsupported(layout.pages.iter().flat_map(|p| &p.lines));
+    if !characters.is_empty() {
+        return Err(ExportError::UnsupportedCharacters {
+            format: ExportFormat::Pdf,
+            characters,
+        });
+    }
+
+    let catalog_id = Ref::new(1);
+    let page_tree_id = Ref::new(2);
+    let regular_id = Ref::new(3);
+    let bold_id = Ref::new(4);
+    let info_id = Ref::new(5);
+    let page_ids: Vec<Ref> = (0..layout.pages.len()).map(|i| Ref::new(6 + 2 * i as i32)).collect();
+
+    let mut pdf = Pdf::new();
+    pdf.catalog(catalog_id).pages(page_tree_id);
+    pdf.pages(page_tree_id).kids(page_ids.iter().copied()).count(page_ids.len() as i32);
+    pdf.type1_font(regular_id).base_font(Name(b"Helvetica")).encoding_predefined(Name(b"WinAnsiEncoding"));
+    pdf.type1_font(bold_id).base_font(Name(b"Helvetica-Bold")).encoding_predefined(Name(b"WinAnsiEncoding"));
+    pdf.document_info(info_id).title(TextStr(title));
+
+    for (page, page_id) in layout.pages.iter().zip(&page_ids) {
+        let content_id = Ref::new(page_id.get() + 1);
+        let mut content = Content::new();
+        for line in &page.lines {
+            content.begin_text();
+            content.set_font(if line.bold { BOLD } else { REGULAR }, line.size);
+            content.next_line(line.x, line.y);
+            content.show(Str(&encode(&line.text)));
+            content.end_text();
+        }
+        for rule in &page.rules {
+            content.set_line_width(0.5);
+            content.move_to(MARGIN, rule.y);
+            content.line_to(PAGE_WIDTH - MARGIN, rule.y);
+            content.stroke();
+        }
+        let mut pdf_page = pdf.page(*page_id);
+        pdf_page.parent(page_tree_id).media_box(Rect::new(0.0, 0.0, PAGE_WIDTH, PAGE_HEIGHT)).contents(content_id);
+        pdf_page.resources().fonts().pair(REGULAR, regular_id).pair(BOLD, bold_id);
+        drop(pdf_page);
+        pdf.stream(content_id, &content.finish());
+    }
+    Ok(pdf.finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::features::resumes::export::ExportTemplate;
+
+    fn render_text(text: &str) -> Result<Vec<u8>, ExportError> {
+        render(&[Block::Name(text.to_string()), Block::Bullet(text.to_string())], &ExportTemplate::default().style(), "test")
+    }
+
+    #[test]
+    fn win_ansi_text_renders() {
+        assert!(render_text("Zoë Müller – “Café” € 100").is_ok());
+    }
+
+    #[test]
+    fn text_outside_win_ansi_is_refused_instead_of_replaced() {
+        match render_text("Łukasz Wałęsa 王") {
+            Err(ExportError::UnsupportedCharacters { format, characters }) => {
+                assert_eq!(format, ExportFormat::Pdf);
+                assert_eq!(characters, vec!['ę', 'Ł', 'ł', '王']);
+            }
+            other => panic!("expected unsupported characters, got {:?}", other.map(|b| b.len())),
+        }
+    }
+}
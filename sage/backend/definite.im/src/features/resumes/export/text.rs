@@ -0,0 +1,18 @@
+use super::Block;
+
+/// Plain text with a blank line before each section, the safest format for pasting into application forms
+pub fn render(blocks: &[Block]) -> String {
+    let mut text = String::new();
+    for block in blocks {
+        match block {
+            Block::Name(name) => text.push_str(&format!("{}\n", name)),
+            Block::Contact(contact) => text.push_str(&format!("{}\n", contact)),
+            Block::Heading(heading) => text.push_str(&format!("\n{}\n", heading)),
+            Block::Entry { title, detail } if detail.is_empty() => text.push_str(&format!("{}\n", title)),
+            Block::Entry { title, detail } => text.push_str(&format!("{}\n{}\n", title, detail)),
+            Block::Paragraph(paragraph) => text.push_str(&format!("{}\n", paragraph)),
+            Block::Bullet(bullet) => text.push_str(&format!("- {}\n", bullet)),
+        }
+    }
+    text
+}
@@ -0,0 +1,228 @@
+/// Month names as résumés write them, matched on their first three letters
+const MONTHS: [&str; 12] = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+
+/// Words that stand for an open end, e.g. "2021 - Present"
+const OPEN_ENDED: [&str; 6] = ["present", "current", "now", "today", "ongoing", "date"];
+
+/// A date as résumés give it, often without a day or month
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PartialDate {
+    pub year: i32,
+    pub month: Option<u32>,
+    pub day: Option<u32>,
+}
+
+impl PartialDate {
+    /// Reads "2021", "2021-03", "2021-03-14", "03/2021", "Mar 2021", "March 2021" and "Mar. 2021"
+    pub fn parse(text: &str) -> Option<Self> {
+        let text = text.trim().trim_end_matches('.');
+        let year = |y: &str| y.parse::<i32>().ok().filter(|y| (1900..=2200).contains(y) && y.to_string().len() == 4);
+        let month = |m: &str| m.parse::<u32>().ok().filter(|m| (1..=12).contains(m));
+        if let Some(year) = year(text) {
+            return Some(PartialDate { year, month: None, day: None });
+        }
+        let iso: Vec<&str> = text.split('-').collect();
+        match iso.as_slice() {
+            [y, m] => {
+                if let (Some(year), Some(month)) = (year(y), month(m)) {
+                    return Some(PartialDate { year, month: Some(month), day: None });
+                }
+            }
+            [y, m, d] => {
+                if let (Some(year), Some(month), Some(day)) = (year(y), month(m), d.parse::<u32>().ok().filter(|d| (1..=31).contains(d))) {
+                    return Some(PartialDate {
+                        year,
+                        month: Some(month),
+                        day: Some(day),
+                    });
+                }
+            }
+            _ => {}
+        }
+        if let Some((m, y)) = text.split_once('/') {
+            if let (Some(year), Some(month)) = (year(y), month(m)) {
+                return Some(PartialDate { year, month: Some(month), day: None });
+            }
+        }
+        let (m, y) = text.rsplit_once(' ')?;
+        let m = m.trim().trim_end_matches(['.', ',']);
+        let month = MONTHS.iter().position(|name| m.len() >= 3 && m.is_char_boundary(3) && m[..3].eq_ignore_ascii_case(name))? as u32 + 1;
+        Some(PartialDate {
+            year: year(y.trim())?,
+            month: Some(month),
+            day: None,
+        })
+    }
+
+    /// ISO 8601 truncated to what is known, as JSON Resume stores dates
+    pub fn iso(&self) -> String {
+        match (self.month, self.day) {
+            (Some(month), Some(day)) => format!("{:04}-{:02}-{:02}", self.year, month, day),
+            (Some(month), None) => format!("{:04}-{:02}", self.year, month),
+            _ => format!("{:04}", self.year),
+        }
+    }
+
+    /// The way the parsed résumés write dates, "Mar 2021" or "2021"
+    pub fn display(&self) -> String {
+        match self.month {
+            Some(month) => format!("{} {}", MONTHS[month as usize - 1], self.year),
+            None => self.year.to_string(),
+        }
+    }
+}
+
+/// The `dates` of an entry. A single date (e.g. a graduation year) is kept as the end.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DateRange {
+    pub start: Option<PartialDate>,
+    pub end: Option<PartialDate>,
+}
+
+impl DateRange {
+    /// `None` unless every part of the text is understood, so nothing is dropped silently
+    pub fn parse(text: &str) -> Option<Self> {
+        let text = text.trim();
+        if text.is_empty() {
+            return None;
+        }
+        let (start, end) = match split_range(text) {
+            Some(parts) => parts,
+            None => return PartialDate::parse(text).map(|end| DateRange { start: None, end: Some(end) }),
+        };
+        let start = PartialDate::parse(start)?;
+        let end = if OPEN_ENDED.iter().any(|word| end.trim().to_lowercase().ends_with(word)) {
+            None
+        } else {
+            Some(PartialDate::parse(end)?)
+        };
+        Some(DateRange { start: Some(start), end })
+    }
+
+    /// "Mar 2021 – Present", "2018 – 2020" or "2020"
+    pub fn display(&self) -> String {
+        match (self.start, self.end) {
+            (Some(start), Some(end)) => format!("{} – {}", start.display(), end.display()),
+            (Some(start), None) => format!("{} – Present", start.display()),
+            (None, Some(end)) => end.display(),
+            (None, None) => String::new(),
+        }
+    }
+}
+
+/// Splits on the first range separator: a dash with spaces around it, an en or em dash, or "to"
+fn split_range(text: &str) -> Option<(&str, &str)> {
+    for separator in [" - ", "–", "—", " to ", " To "] {
+        if let Some(parts) = text.split_once(separator) {
+            return Some(parts);
+        }
+    }
+    // "2018-2020", which is not a year and month
+    text.split_once('-').filter(|(start, end)| start.trim().len() == 4 && end.trim().len() == 4)
+}
+
+/// ISO date and, if it could not be read, the original text to keep alongside it
+pub fn to_iso(date: Option<&str>) -> (Option<String>, Option<String>) {
+    match date.map(str::trim).filter(|d| !d.is_empty()) {
+        Some(date) => match PartialDate::parse(date) {
+            Some(parsed) => (Some(parsed.iso()), None),
+            None => (None, Some(date.to_string())),
+        },
+        None => (None, None),
+    }
+}
+
+/// Reverses `to_iso`. Dates another tool wrote in a form we cannot read are kept as they are.
+pub fn from_iso(
+    iso: Option<&str>,
+    original: Option<&str>,
+) -> Option<String> {
+    if let Some(original) = original {
+        return Some(original.to_string());
+    }
+    let iso = iso.map(str::trim).filter(|d| !d.is_empty())?;
+    Some(PartialDate::parse(iso).map(|d| d.display()).unwrap_or(iso.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(
+        year: i32,
+        month: Option<u32>,
+        day: Option<u32>,
+    ) -> Option<PartialDate> {
+        Some(PartialDate { year, month, day })
+    }
+
+    #[test]
+    fn reads_the_usual_date_forms() {
+        assert_eq!(PartialDate::parse("2021"), date(2021, None, None));
+        assert_eq!(PartialDate::parse("2021-03"), date(2021, Some(3), None));
+        assert_eq!(PartialDate::parse("2021-03-14"), date(2021, Some(3), Some(14)));
+        assert_eq!(PartialDate::parse("03/2021"), date(2021, Some(3), None));
+        assert_eq!(PartialDate::parse("Mar 2021"), date(2021, Some(3), None));
+        assert_eq!(PartialDate::parse("march 2021"), date(2021, Some(3), None));
+        assert_eq!(PartialDate::parse(" Sept. 2021. "), date(2021, Some(9), None));
+    }
+
+    #[test]
+    fn invalid_dates_are_not_read() {
+        for text in ["", "21", "20210", "1850", "2021-13", "2021-00", "2021-02-32", "13/2021", "Spring 2021", "Ma 2021", "Mar 21", "soon"] {
+            assert_eq!(PartialDate::parse(text), None, "{:?}", text);
+        }
+    }
+
+    #[test]
+    fn year_only_ranges_and_single_years() {
+        let range = DateRange::parse("2018 - 2020").unwrap();
+        assert_eq!((range.start, range.end), (date(2018, None, None), date(2020, None, None)));
+        assert_eq!(DateRange::parse("2018-2020"), Some(range));
+        assert_eq!(range.display(), "2018 – 2020");
+        // a graduation year is the end of its range
+        assert_eq!(
+            DateRange::parse("2016"),
+            Some(DateRange {
+                start: None,
+                end: date(2016, None, None)
+            })
+        );
+        assert_eq!(DateRange::parse("2016").unwrap().display(), "2016");
+    }
+
+    #[test]
+    fn open_ended_ranges_have_no_end() {
+        for text in ["Mar 2021 - Present", "Mar 2021 – present", "2021-03 to Current", "Mar 2021 — Today", "03/2021 - to date"] {
+            let range = DateRange::parse(text).unwrap_or_else(|| panic!("{:?} not read", text));
+            assert_eq!((range.start, range.end), (date(2021, Some(3), None), None), "{:?}", text);
+            assert_eq!(range.display(), "Mar 2021 – Present");
+        }
+    }
+
+    #[test]
+    fn ranges_with_an_unreadable_part_are_not_read() {
+        for text in ["", "  ", "Summer 2019", "2019 - sometime", "soon - 2020", "2021-03 - 2021-13"] {
+            assert_eq!(DateRange::parse(text), None, "{:?}", text);
+        }
+    }
+
+    #[test]
+    fn iso_is_truncated_to_what_is_known() {
+        assert_eq!(date(2021, None, None).unwrap().iso(), "2021");
+        assert_eq!(date(2021, Some(3), None).unwrap().iso(), "2021-03");
+        assert_eq!(date(2021, Some(3), Some(4)).unwrap().iso(), "2021-03-04");
+    }
+
+    #[test]
+    fn unreadable_dates_survive_iso_and_back() {
+        assert_eq!(to_iso(Some("Mar 2021")), (Some("2021-03".to_string()), None));
+        assert_eq!(to_iso(Some("Summer 2019")), (None, Some("Summer 2019".to_string())));
+        assert_eq!(to_iso(Some("  ")), (None, None));
+        assert_eq!(from_iso(Some("2021-03"), None).as_deref(), Some("Mar 2021"));
+        assert_eq!(from_iso(None, Some("Summer 2019")).as_deref(), Some("Summer 2019"));
+        // another tool's date we cannot read is kept as it is
+        assert_eq!(from_iso(Some("2021-W10"), None).as_deref(), Some("2021-W10"));
+        assert_eq!(from_iso(Some(""), None), None);
+    }
+}
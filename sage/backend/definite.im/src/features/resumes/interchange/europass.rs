@@ -0,0 +1,926 @@
+use quick_xml::se::Serializer;
+use serde::{Deserialize, Serialize};
+
+use crate::features::resumes::entities::resume::{
+    Achievement, Authoring, Blog, Certification, Contact, Education, OpenSourceContribution, ParsedResume, Patent, Project, Publication, Skills, VolunteerExperience, WorkExperience,
+};
+
+use super::{
+    contact_slot,
+    dates::{DateRange, PartialDate},
+    inventors_from, persona_for, slug, INVENTORS_PREFIX,
+};
+
+const NAMESPACE: &str = "http://europass.cedefop.europa.eu/Europass";
+const XSD_VERSION: &str = "V3.4";
+
+/// Europass CV XML, schema v3. Free text fields (activities, skills, achievements) hold HTML.
+#[derive(Debug, Serialize, Deserialize, Default)]
+#[serde(rename = "SkillsPassport")]
+pub struct SkillsPassport {
+    #[serde(rename = "@xmlns", skip_serializing_if = "Option::is_none", default)]
+    pub xmlns: Option<String>,
+    #[serde(rename = "@locale", skip_serializing_if = "Option::is_none", default)]
+    pub locale: Option<String>,
+    #[serde(rename = "DocumentInfo", skip_serializing_if = "Option::is_none", default)]
+    pub document_info: Option<DocumentInfo>,
+    #[serde(rename = "LearnerInfo", default)]
+    pub learner_info: LearnerInfo,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct DocumentInfo {
+    #[serde(rename = "DocumentType", default)]
+    pub document_type: String,
+    #[serde(rename = "CreationDate", skip_serializing_if = "Option::is_none", default)]
+    pub creation_date: Option<String>,
+    #[serde(rename = "XSDVersion", skip_serializing_if = "Option::is_none", default)]
+    pub xsd_version: Option<String>,
+    #[serde(rename = "Generator", skip_serializing_if = "Option::is_none", default)]
+    pub generator: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct LearnerInfo {
+    #[serde(rename = "Identification", default)]
+    pub identification: Identification,
+    #[serde(rename = "Headline", skip_serializing_if = "Option::is_none", default)]
+    pub headline: Option<Headline>,
+    #[serde(rename = "WorkExperienceList", skip_serializing_if = "Option::is_none", default)]
+    pub work_experience_list: Option<WorkExperienceList>,
+    #[serde(rename = "EducationList", skip_serializing_if = "Option::is_none", default)]
+    pub education_list: Option<EducationList>,
+    #[serde(rename = "Skills", skip_serializing_if = "Option::is_none", default)]
+    pub skills: Option<EuropassSkills>,
+    #[serde(rename = "AchievementList", skip_serializing_if = "Option::is_none", default)]
+    pub achievement_list: Option<AchievementList>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct Identification {
+    #[serde(rename = "PersonName", default)]
+    pub person_name: PersonName,
+    #[serde(rename = "ContactInfo", skip_serializing_if = "Option::is_none", default)]
+    pub contact_info: Option<ContactInfo>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct PersonName {
+    #[serde(rename = "FirstName", default)]
+    pub first_name: String,
+    #[serde(rename = "Surname", default)]
+    pub surname: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct ContactInfo {
+    #[serde(rename = "Email", skip_serializing_if = "Option::is_none", default)]
+    pub email: Option<ContactEntry>,
+    #[serde(rename = "TelephoneList", skip_serializing_if = "Option::is_none", default)]
+    pub telephone_list: Option<TelephoneList>,
+    #[serde(rename = "WebsiteList", skip_serializing_if = "Option::is_none", default)]
+    pub website_list: Option<WebsiteList>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct ContactEntry {
+    #[serde(rename = "Contact", default)]
+    pub contact: String,
+    #[serde(rename = "Use", skip_serializing_if = "Option::is_none", default)]
+    pub usage: Option<Coded>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct TelephoneList {
+    #[serde(rename = "Telephone", default)]
+    pub telephones: Vec<ContactEntry>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct WebsiteList {
+    #[serde(rename = "Website", default)]
+    pub websites: Vec<ContactEntry>,
+}
+
+/// A Europass vocabulary term, `Code` is left out for free text
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct Coded {
+    #[serde(rename = "Code", skip_serializing_if = "Option::is_none", default)]
+    pub code: Option<String>,
+    #[serde(rename = "Label", skip_serializing_if = "Option::is_none", default)]
+    pub label: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct Headline {
+    #[serde(rename = "Type", default)]
+    pub headline_type: Coded,
+    #[serde(rename = "Description", default)]
+    pub description: Coded,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct WorkExperienceList {
+    #[serde(rename = "WorkExperience", default)]
+    pub entries: Vec<EuropassWork>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct EuropassWork {
+    #[serde(rename = "Period", skip_serializing_if = "Option::is_none", default)]
+    pub period: Option<Period>,
+    #[serde(rename = "Position", default)]
+    pub position: Coded,
+    #[serde(rename = "Activities", skip_serializing_if = "Option::is_none", default)]
+    pub activities: Option<String>,
+    #[serde(rename = "Employer", default)]
+    pub employer: Organisation,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct EducationList {
+    #[serde(rename = "Education", default)]
+    pub entries: Vec<EuropassEducation>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct EuropassEducation {
+    #[serde(rename = "Period", skip_serializing_if = "Option::is_none", default)]
+    pub period: Option<Period>,
+    #[serde(rename = "Title", skip_serializing_if = "Option::is_none", default)]
+    pub title: Option<String>,
+    #[serde(rename = "Activities", skip_serializing_if = "Option::is_none", default)]
+    pub activities: Option<String>,
+    #[serde(rename = "Organisation", default)]
+    pub organisation: Organisation,
+    #[serde(rename = "Field", skip_serializing_if = "Option::is_none", default)]
+    pub field: Option<Coded>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct Organisation {
+    #[serde(rename = "Name", default)]
+    pub name: String,
+    #[serde(rename = "ContactInfo", skip_serializing_if = "Option::is_none", default)]
+    pub contact_info: Option<OrganisationContact>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct OrganisationContact {
+    #[serde(rename = "Address", default)]
+    pub address: Address,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct Address {
+    #[serde(rename = "Contact", default)]
+    pub contact: AddressContact,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct AddressContact {
+    #[serde(rename = "Municipality", skip_serializing_if = "Option::is_none", default)]
+    pub municipality: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct Period {
+    #[serde(rename = "From", skip_serializing_if = "Option::is_none", default)]
+    pub from: Option<EuropassDate>,
+    #[serde(rename = "To", skip_serializing_if = "Option::is_none", default)]
+    pub to: Option<EuropassDate>,
+    #[serde(rename = "Current", skip_serializing_if = "Option::is_none", default)]
+    pub current: Option<bool>,
+}
+
+/// Europass writes months and days as XML Schema `gMonth` and `gDay`, "--03" and "---14"
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct EuropassDate {
+    #[serde(rename = "@year")]
+    pub year: i32,
+    #[serde(rename = "@month", skip_serializing_if = "Option::is_none", default)]
+    pub month: Option<String>,
+    #[serde(rename = "@day", skip_serializing_if = "Option::is_none", default)]
+    pub day: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct EuropassSkills {
+    #[serde(rename = "Communication", skip_serializing_if = "Option::is_none", default)]
+    pub communication: Option<SkillDescription>,
+    #[serde(rename = "Organisational", skip_serializing_if = "Option::is_none", default)]
+    pub organisational: Option<SkillDescription>,
+    #[serde(rename = "JobRelated", skip_serializing_if = "Option::is_none", default)]
+    pub job_related: Option<SkillDescription>,
+    #[serde(rename = "Computer", skip_serializing_if = "Option::is_none", default)]
+    pub computer: Option<SkillDescription>,
+    #[serde(rename = "Other", skip_serializing_if = "Option::is_none", default)]
+    pub other: Option<SkillDescription>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct SkillDescription {
+    #[serde(rename = "Description", default)]
+    pub description: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct AchievementList {
+    #[serde(rename = "Achievement", default)]
+    pub entries: Vec<EuropassAchievement>,
+}
+
+/// One entry of a section Europass has no list for. Its description is the entry's title followed
+/// by "Key: value" paragraphs, readable in the Europass editor and parsed back on import.
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct EuropassAchievement {
+    #[serde(rename = "Title", default)]
+    pub title: Coded,
+    #[serde(rename = "Description", default)]
+    pub description: String,
+}
+
+/// Achievement titles, the ones with a code are Europass vocabulary
+const PROJECTS: (Option<&str>, &str) = (Some("projects"), "Projects");
+const PUBLICATIONS: (Option<&str>, &str) = (Some("publications"), "Publications");
+const CERTIFICATIONS: (Option<&str>, &str) = (Some("certifications"), "Certifications");
+const HONORS_AWARDS: (Option<&str>, &str) = (Some("honors_awards"), "Honours and awards");
+const AUTHORING: (Option<&str>, &str) = (None, "Books and articles");
+const BLOGS: (Option<&str>, &str) = (None, "Blogs");
+const PATENTS: (Option<&str>, &str) = (None, "Patents");
+const OPEN_SOURCE: (Option<&str>, &str) = (None, "Open source contributions");
+const VOLUNTEERING: (Option<&str>, &str) = (None, "Volunteering");
+const INTERESTS: (Option<&str>, &str) = (None, "Interests");
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+fn unescape(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&nbsp;", " ")
+        .replace("&amp;", "&")
+}
+
+fn html_list(items: &[String]) -> String {
+    format!("<ul>{}</ul>", items.iter().map(|i| format!("<li>{}</li>", escape(i))).collect::<String>())
+}
+
+/// The text of every paragraph and list item, or the lines of plain text
+fn html_items(html: &str) -> Vec<String> {
+    let mut items = vec![];
+    let mut rest = html;
+    while let Some(start) = rest.find(['<']) {
+        let text = unescape(rest[..start].trim());
+        if !text.is_empty() {
+            items.push(text);
+        }
+        let end = match rest[start..].find('>') {
+            Some(end) => start + end + 1,
+            None => break,
+        };
+        rest = &rest[end..];
+    }
+    items.extend(rest.lines().map(|l| unescape(l.trim())).filter(|l| !l.is_empty()));
+    items
+}
+
+/// `<p>title</p><p>Key: value</p>...` for the fields that are set
+fn achievement(
+    (code, label): (Option<&str>, &str),
+    title: &str,
+    fields: &[(&str, Option<&str>)],
+) -> EuropassAchievement {
+    let mut description = format!("<p>{}</p>", escape(title));
+    for (key, value) in fields {
+        if let Some(value) = value.map(str::trim).filter(|v| !v.is_empty()) {
+            description.push_str(&format!("<p>{}: {}</p>", key, escape(value)));
+        }
+    }
+    EuropassAchievement {
+        title: Coded {
+            code: code.map(str::to_string),
+            label: Some(label.to_string()),
+        },
+        description,
+    }
+}
+
+fn key_value(item: String) -> (String, String) {
+    match item.split_once(": ") {
+        Some((key, value)) if key.len() < 20 => (key.to_string(), value.to_string()),
+        // text another tool wrote, kept as details
+        _ => ("Details".to_string(), item),
+    }
+}
+
+/// Reverses `achievement`: the entry title and a lookup for its fields
+struct AchievementFields {
+    title: String,
+    fields: Vec<(String, String)>,
+}
+
+impl AchievementFields {
+    fn parse(html: &str) -> Self {
+        let mut items = html_items(html).into_iter();
+        AchievementFields {
+            title: items.next().unwrap_or_default(),
+            fields: items.map(key_value).collect(),
+        }
+    }
+
+    fn get(
+        &self,
+        key: &str,
+    ) -> Option<String> {
+        let values: Vec<&str> = self.fields.iter().filter(|(k, _)| k.eq_ignore_ascii_case(key)).map(|(_, v)| v.as_str()).collect();
+        if values.is_empty() {
+            None
+        } else {
+            Some(values.join("\n"))
+        }
+    }
+}
+
+fn europass_date(date: &PartialDate) -> EuropassDate {
+    EuropassDate {
+        year: date.year,
+        month: date.month.map(|m| format!("--{:02}", m)),
+        day: date.day.map(|d| format!("---{:02}", d)),
+    }
+}
+
+fn partial_date(date: &EuropassDate) -> PartialDate {
+    PartialDate {
+        year: date.year,
+        month: date.month.as_deref().and_then(|m| m.trim_start_matches('-').parse().ok()),
+        day: date.day.as_deref().and_then(|d| d.trim_start_matches('-').parse().ok()),
+    }
+}
+
+/// The period and, when the dates could not be read, the original text to keep in the activities
+fn period(dates: &str) -> (Option<Period>, Option<String>) {
+    if dates.trim().is_empty() {
+        return (None, None);
+    }
+    match DateRange::parse(dates) {
+        // Europass needs a start, a single date is both
+        Some(range) => (
+            Some(Period {
+                from: range.start.or(range.end).as_ref().map(europass_date),
+                to: range.end.as_ref().map(europass_date),
+                current: if range.end.is_none() { Some(true) } else { None },
+            }),
+            None,
+        ),
+        None => (None, Some(dates.trim().to_string())),
+    }
+}
+
+fn period_text(period: &Option<Period>) -> Option<String> {
+    let period = period.as_ref()?;
+    let from = period.from.as_ref().map(partial_date);
+    let to = period.to.as_ref().map(partial_date);
+    let range = match (from, to) {
+        (Some(from), Some(to)) if from == to => DateRange { start: None, end: Some(to) },
+        (from, _) if period.current == Some(true) => DateRange { start: from, end: None },
+        (from, to) => DateRange { start: from, end: to },
+    };
+    Some(range.display()).filter(|d| !d.is_empty())
+}
+
+fn organisation(
+    name: &str,
+    location: Option<&str>,
+) -> Organisation {
+    Organisation {
+        name: name.to_string(),
+        contact_info: location.map(|l| OrganisationContact {
+            address: Address {
+                contact: AddressContact { municipality: Some(l.to_string()) },
+            },
+        }),
+    }
+}
+
+fn municipality(organisation: &Organisation) -> Option<String> {
+    organisation.contact_info.as_ref()?.address.contact.municipality.clone()
+}
+
+fn website(
+    url: &Option<String>,
+    code: &str,
+) -> Option<ContactEntry> {
+    Some(ContactEntry {
+        contact: url.clone()?,
+        usage: Some(Coded {
+            code: Some(code.to_string()),
+            label: None,
+        }),
+    })
+}
+
+fn skill_description(list: &[String]) -> Option<SkillDescription> {
+    if list.is_empty() {
+        None
+    } else {
+        Some(SkillDescription { description: html_list(list) })
+    }
+}
+
+fn non_empty_list<T>(list: Vec<T>) -> Option<Vec<T>> {
+    if list.is_empty() {
+        None
+    } else {
+        Some(list)
+    }
+}
+
+/// Converts a parsed résumé to a Europass CV. Sections Europass has no list for become achievements.
+pub fn to_skills_passport(resume: &ParsedResume) -> SkillsPassport {
+    let contact = &resume.contact;
+    let (first_name, surname) = match resume.name.trim().rsplit_once(' ') {
+        Some((first, last)) => (first.to_string(), last.to_string()),
+        None => (String::new(), resume.name.trim().to_string()),
+    };
+    let websites: Vec<ContactEntry> = [
+        website(&contact.website, "personal"),
+        website(&contact.linkedin, "business"),
+        website(&contact.github, "portfolio"),
+        website(&contact.twitter, "other"),
+    ]
+    .into_iter()
+    .flatten()
+    .collect();
+
+    let mut achievements = vec![];
+    for project in resume.projects.iter().flatten() {
+        let technologies = project.technologies.join(", ");
+        achievements.push(achievement(
+            PROJECTS,
+            &project.name,
+            &[
+                ("Description", project.description.as_deref()),
+                ("Dates", project.dates.as_deref()),
+                ("Technologies", Some(&technologies)),
+                ("Link", project.link.as_deref()),
+            ],
+        ));
+    }
+    for contribution in resume.open_source_contributions.iter().flatten() {
+        let technologies = contribution.technologies.join(", ");
+        achievements.push(achievement(
+            OPEN_SOURCE,
+            &contribution.project_name,
+            &[
+                ("Description", contribution.description.as_deref()),
+                ("Dates", contribution.dates.as_deref()),
+                ("Technologies", Some(&technologies)),
+                ("Link", contribution.link.as_deref()),
+            ],
+        ));
+    }
+    for certification in resume.certifications.iter().flatten() {
+        achievements.push(achievement(
+            CERTIFICATIONS,
+            &certification.name,
+            &[
+                ("Issuer", certification.issuer.as_deref()),
+                ("Date", certification.date.as_deref()),
+                ("Link", certification.link.as_deref()),
+            ],
+        ));
+    }
+    for award in resume.achievements.iter().flatten() {
+        achievements.push(achievement(
+            HONORS_AWARDS,
+            &award.title,
+            &[("Details", award.details.as_deref()), ("Date", award.date.as_deref()), ("Link", award.link.as_deref())],
+        ));
+    }
+    for publication in resume.publications.iter().flatten() {
+        achievements.push(achievement(
+            PUBLICATIONS,
+            publication.title.as_deref().unwrap_or_default(),
+            &[
+                ("Published in", publication.journal_or_conference.as_deref()),
+                ("Date", publication.date.as_deref()),
+                ("Link", publication.link.as_deref()),
+            ],
+        ));
+    }
+    for authored in resume.authoring.iter().flatten() {
+        achievements.push(achievement(
+            AUTHORING,
+            &authored.title,
+            &[
+                ("Publisher", authored.publisher.as_deref()),
+                ("Date", authored.date.as_deref()),
+                ("Link", authored.link.as_deref()),
+                ("Description", authored.description.as_deref()),
+            ],
+        ));
+    }
+    for blog in resume.blogs.iter().flatten() {
+        achievements.push(achievement(
+            BLOGS,
+            &blog.title,
+            &[("Date", blog.date.as_deref()), ("Link", blog.link.as_deref()), ("Summary", blog.summary.as_deref())],
+        ));
+    }
+    for patent in resume.patents.iter().flatten() {
+        let inventors = patent.inventors.as_ref().map(|i| i.join(", "));
+        achievements.push(achievement(
+            PATENTS,
+            &patent.title,
+            &[
+                ("Number", patent.patent_number.as_deref()),
+                ("Date", patent.date.as_deref()),
+                ("Link", patent.link.as_deref()),
+                (INVENTORS_PREFIX.trim_end_matches([':', ' ']), inventors.as_deref()),
+            ],
+        ));
+    }
+    for volunteer in resume.volunteer_experience.iter().flatten() {
+        achievements.push(achievement(
+            VOLUNTEERING,
+            volunteer.role.as_deref().unwrap_or_default(),
+            &[
+                ("Organisation", volunteer.organization.as_deref()),
+                ("Dates", volunteer.dates.as_deref()),
+                ("Description", volunteer.description.as_deref()),
+            ],
+        ));
+    }
+    for interest in resume.interests_hobbies.iter().flatten() {
+        achievements.push(achievement(INTERESTS, interest, &[]));
+    }
+
+    SkillsPassport {
+        xmlns: Some(NAMESPACE.to_string()),
+        locale: Some("en".to_string()),
+        document_info: Some(DocumentInfo {
+            document_type: "ECV".to_string(),
+            creation_date: Some(chrono::Utc::now().format("%Y-%m-%dT%H:%M:%S%.3fZ").to_string()),
+            xsd_version: Some(XSD_VERSION.to_string()),
+            generator: Some("definite.im".to_string()),
+        }),
+        learner_info: LearnerInfo {
+            identification: Identification {
+                person_name: PersonName { first_name, surname },
+                contact_info: Some(ContactInfo {
+                    email: contact.email.clone().map(|email| ContactEntry { contact: email, usage: None }),
+                    telephone_list: contact.phone.clone().map(|phone| TelephoneList {
+                        telephones: vec![ContactEntry {
+                            contact: phone,
+                            usage: Some(Coded {
+                                code: Some("mobile".to_string()),
+                                label: None,
+                            }),
+                        }],
+                    }),
+                    website_list: if websites.is_empty() { None } else { Some(WebsiteList { websites }) },
+                }),
+            },
+            // a personal statement is the closest Europass has to a summary, a position otherwise
+            headline: Some(match &resume.summary {
+                Some(summary) => Headline {
+                    headline_type: Coded {
+                        code: Some("personal_statement".to_string()),
+                        label: Some("Personal statement".to_string()),
+                    },
+                    description: Coded {
+                        code: None,
+                        label: Some(summary.clone()),
+                    },
+                },
+                None => Headline {
+                    headline_type: Coded {
+                        code: Some("position".to_string()),
+                        label: Some("Position".to_string()),
+                    },
+                    description: Coded {
+                        code: None,
+                        label: Some(resume.persona.label().to_string()),
+                    },
+                },
+            }),
+            work_experience_list: Some(WorkExperienceList {
+                entries: resume
+                    .work_experience
+                    .iter()
+                    .map(|work| {
+                        let (period, original) = period(&work.dates);
+                        let dates = original.map(|d| format!("<p>Dates: {}</p>", escape(&d))).unwrap_or_default();
+                        EuropassWork {
+                            period,
+                            position: Coded {
+                                code: None,
+                                label: Some(work.title.clone()),
+                            },
+                            activities: Some(format!("{}{}", dates, html_list(&work.responsibilities))),
+                            employer: organisation(&work.company, work.location.as_deref()),
+                        }
+                    })
+                    .collect(),
+            })
+            .filter(|l| !l.entries.is_empty()),
+            education_list: Some(EducationList {
+                entries: resume
+                    .education
+                    .iter()
+                    .map(|education| {
+                        let (period, original) = period(&education.dates);
+                        let activities = [original.map(|d| format!("Dates: {}", d)), education.gpa.as_ref().map(|g| format!("GPA: {}", g))]
+                            .into_iter()
+                            .flatten()
+                            .map(|p| format!("<p>{}</p>", escape(&p)))
+                            .collect::<String>();
+                        EuropassEducation {
+                            period,
+                            title: education.degree.clone(),
+                            activities: Some(activities).filter(|a| !a.is_empty()),
+                            organisation: organisation(&education.institution, education.location.as_deref()),
+                            field: education.field_of_study.clone().map(|f| Coded { code: None, label: Some(f) }),
+                        }
+                    })
+                    .collect(),
+            })
+            .filter(|l| !l.entries.is_empty()),
+            skills: resume.skills.as_ref().map(|skills| EuropassSkills {
+                communication: skill_description(&skills.soft_skills),
+                organisational: None,
+                job_related: None,
+                computer: skill_description(&skills.technical),
+                other: skill_description(&skills.other_skills),
+            }),
+            achievement_list: if achievements.is_empty() { None } else { Some(AchievementList { entries: achievements }) },
+        },
+    }
+}
+
+/// Converts a Europass CV into a parsed résumé. Achievements written by `to_skills_passport` go back
+/// to their own lists by title, others become achievements.
+pub fn from_skills_passport(passport: SkillsPassport) -> ParsedResume {
+    let learner = passport.learner_info;
+    let identification = learner.identification;
+    let name = format!("{} {}", identification.person_name.first_name.trim(), identification.person_name.surname.trim())
+        .trim()
+        .to_string();
+
+    let mut contact = Contact {
+        phone: None,
+        email: None,
+        linkedin: None,
+        github: None,
+        website: None,
+        twitter: None,
+    };
+    if let Some(info) = identification.contact_info {
+        contact.email = info.email.map(|e| e.contact).filter(|e| !e.trim().is_empty());
+        contact.phone = info.telephone_list.and_then(|l| l.telephones.into_iter().next()).map(|t| t.contact);
+        for website in info.website_list.map(|l| l.websites).unwrap_or_default() {
+            let slot = contact_slot(&mut contact, "", &website.contact);
+            if slot.is_none() {
+                *slot = Some(website.contact);
+            }
+        }
+    }
+
+    let work_experience: Vec<WorkExperience> = learner
+        .work_experience_list
+        .map(|l| l.entries)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|work| {
+            let mut responsibilities = html_items(work.activities.as_deref().unwrap_or_default());
+            let original = match responsibilities.first().and_then(|r| r.strip_prefix("Dates: ")) {
+                Some(dates) if work.period.is_none() => Some(dates.to_string()),
+                _ => None,
+            };
+            if original.is_some() {
+                responsibilities.remove(0);
+            }
+            WorkExperience {
+                location: municipality(&work.employer),
+                company: work.employer.name,
+                title: work.position.label.unwrap_or_default(),
+                dates: original.or(period_text(&work.period)).unwrap_or_default(),
+                responsibilities,
+            }
+        })
+        .collect();
+
+    let education = learner
+        .education_list
+        .map(|l| l.entries)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|education| {
+            let fields = AchievementFields {
+                title: String::new(),
+                fields: html_items(education.activities.as_deref().unwrap_or_default()).into_iter().map(key_value).collect(),
+            };
+            Education {
+                location: municipality(&education.organisation),
+                institution: education.organisation.name,
+                degree: education.title.filter(|t| !t.trim().is_empty()),
+                field_of_study: education.field.and_then(|f| f.label),
+                gpa: fields.get("GPA"),
+                dates: fields.get("Dates").or(period_text(&education.period)).unwrap_or_default(),
+            }
+        })
+        .collect();
+
+    let skills = learner.skills.map(|skills| {
+        let list = |s: Option<SkillDescription>| s.map(|s| html_items(&s.description)).unwrap_or_default();
+        let mut technical = list(skills.computer);
+        technical.extend(list(skills.job_related));
+        let mut soft_skills = list(skills.communication);
+        soft_skills.extend(list(skills.organisational));
+        Skills {
+            technical,
+            soft_skills,
+            other_skills: list(skills.other),
+        }
+    });
+
+    let (mut projects, mut open_source, mut certifications, mut achievements, mut publications) = (vec![], vec![], vec![], vec![], vec![]);
+    let (mut authoring, mut blogs, mut patents, mut volunteering, mut interests) = (vec![], vec![], vec![], vec![], vec![]);
+    for entry in learner.achievement_list.map(|l| l.entries).unwrap_or_default() {
+        let fields = AchievementFields::parse(&entry.description);
+        let technologies = || {
+            fields
+                .get("Technologies")
+                .map(|t| t.split(',').map(|t| t.trim().to_string()).filter(|t| !t.is_empty()).collect())
+                .unwrap_or_default()
+        };
+        let is = |(code, label): (Option<&str>, &str)| match (code, &entry.title.code) {
+            (Some(code), Some(entry_code)) => code == entry_code,
+            _ => entry.title.label.as_deref().is_some_and(|l| l.eq_ignore_ascii_case(label)),
+        };
+        if is(PROJECTS) {
+            projects.push(Project {
+                name: fields.title.clone(),
+                description: fields.get("Description"),
+                dates: fields.get("Dates"),
+                technologies: technologies(),
+                link: fields.get("Link"),
+            });
+        } else if is(OPEN_SOURCE) {
+            open_source.push(OpenSourceContribution {
+                project_name: fields.title.clone(),
+                description: fields.get("Description"),
+                link: fields.get("Link"),
+                dates: fields.get("Dates"),
+                technologies: technologies(),
+            });
+        } else if is(CERTIFICATIONS) {
+            certifications.push(Certification {
+                name: fields.title.clone(),
+                issuer: fields.get("Issuer"),
+                date: fields.get("Date"),
+                link: fields.get("Link"),
+            });
+        } else if is(PUBLICATIONS) {
+            publications.push(Publication {
+                title: Some(fields.title.clone()).filter(|t| !t.is_empty()),
+                journal_or_conference: fields.get("Published in"),
+                date: fields.get("Date"),
+                link: fields.get("Link"),
+            });
+        } else if is(AUTHORING) {
+            authoring.push(Authoring {
+                title: fields.title.clone(),
+                publisher: fields.get("Publisher"),
+                date: fields.get("Date"),
+                link: fields.get("Link"),
+                description: fields.get("Description"),
+            });
+        } else if is(BLOGS) {
+            blogs.push(Blog {
+                title: fields.title.clone(),
+                date: fields.get("Date"),
+                link: fields.get("Link"),
+                summary: fields.get("Summary"),
+            });
+        } else if is(PATENTS) {
+            patents.push(Patent {
+                title: fields.title.clone(),
+                patent_number: fields.get("Number"),
+                date: fields.get("Date"),
+                link: fields.get("Link"),
+                inventors: inventors_from(fields.get("Inventors").map(|i| format!("{}{}", INVENTORS_PREFIX, i)).as_deref()),
+            });
+        } else if is(VOLUNTEERING) {
+            volunteering.push(VolunteerExperience {
+                organization: fields.get("Organisation"),
+                role: Some(fields.title.clone()).filter(|t| !t.is_empty()),
+                dates: fields.get("Dates"),
+                description: fields.get("Description"),
+            });
+        } else if is(INTERESTS) {
+            interests.push(fields.title.clone());
+        } else {
+            // honours and awards, and sections such as memberships or conferences we have no list for
+            let title = match entry.title.label.as_deref() {
+                Some(label) if !is(HONORS_AWARDS) => format!("{}: {}", label, fields.title),
+                _ => fields.title.clone(),
+            };
+            achievements.push(Achievement {
+                title,
+                details: fields.get("Details"),
+                date: fields.get("Date"),
+                link: fields.get("Link"),
+            });
+        }
+    }
+
+    let headline = learner.headline;
+    let statement = headline
+        .as_ref()
+        .filter(|h| h.headline_type.code.as_deref() == Some("personal_statement"))
+        .and_then(|h| h.description.label.clone());
+    let position = headline
+        .as_ref()
+        .filter(|h| h.headline_type.code.as_deref() != Some("personal_statement"))
+        .and_then(|h| h.description.label.clone());
+    let persona = persona_for(work_experience.first().map(|w| w.title.as_str()).or(position.as_deref()).unwrap_or_default());
+    ParsedResume {
+        name_slug: slug(&name),
+        name,
+        persona,
+        contact,
+        summary: statement,
+        education,
+        work_experience,
+        work_experience_diff: None,
+        projects: non_empty_list(projects),
+        skills: skills.filter(|s| !(s.technical.is_empty() && s.soft_skills.is_empty() && s.other_skills.is_empty())),
+        certifications: non_empty_list(certifications),
+        achievements: non_empty_list(achievements),
+        publications: non_empty_list(publications),
+        volunteer_experience: non_empty_list(volunteering),
+        interests_hobbies: non_empty_list(interests),
+        keywords: None,
+        blogs: non_empty_list(blogs),
+        open_source_contributions: non_empty_list(open_source),
+        authoring: non_empty_list(authoring),
+        patents: non_empty_list(patents),
+    }
+}
+
+pub fn to_xml(resume: &ParsedResume) -> Result<Vec<u8>, String> {
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    let mut serializer = Serializer::new(&mut xml);
+    serializer.indent(' ', 2);
+    to_skills_passport(resume).serialize(serializer).map_err(|e| e.to_string())?;
+    Ok(xml.into_bytes())
+}
+
+pub fn from_xml(text: &str) -> Result<ParsedResume, String> {
+    let passport: SkillsPassport = quick_xml::de::from_str(text).map_err(|e| e.to_string())?;
+    Ok(from_skills_passport(passport))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::features::resumes::interchange::full_resume;
+
+    fn round_trip(resume: &ParsedResume) -> ParsedResume {
+        from_xml(std::str::from_utf8(&to_xml(resume).unwrap()).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn a_full_resume_survives_the_round_trip() {
+        let resume = full_resume();
+        // Europass has no place for the generated keywords
+        let expected = ParsedResume { keywords: None, ..resume.clone() };
+        assert_eq!(serde_json::to_value(round_trip(&resume)).unwrap(), serde_json::to_value(&expected).unwrap());
+    }
+
+    #[test]
+    fn a_resume_without_summary_keeps_its_persona_as_the_headline() {
+        let resume = ParsedResume { summary: None, ..full_resume() };
+        let passport = to_skills_passport(&resume);
+        let headline = passport.learner_info.headline.as_ref().unwrap();
+        assert_eq!(headline.headline_type.code.as_deref(), Some("position"));
+        assert!(round_trip(&resume).summary.is_none());
+    }
+
+    #[test]
+    fn single_word_names_are_a_surname() {
+        let resume = ParsedResume {
+            name: "Cher".to_string(),
+            name_slug: "cher".to_string(),
+            ..full_resume()
+        };
+        let passport = to_skills_passport(&resume);
+        assert_eq!(passport.learner_info.identification.person_name.surname, "Cher");
+        assert_eq!(round_trip(&resume).name, "Cher");
+    }
+}
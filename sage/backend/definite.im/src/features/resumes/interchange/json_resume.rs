@@ -0,0 +1,851 @@
+use serde::{Deserialize, Serialize};
+
+use crate::features::resumes::entities::resume::{
+    Achievement, Authoring, Blog, Certification, Contact, Education, Keyword, OpenSourceContribution, ParsedResume, Patent, Persona, Project, Publication, Skills, VolunteerExperience, WorkExperience,
+};
+
+use super::{
+    contact_slot,
+    dates::{self, DateRange},
+    inventors_from, persona_for, slug, INVENTORS_PREFIX,
+};
+
+const SCHEMA_URL: &str = "https://raw.githubusercontent.com/jsonresume/resume-schema/v1.0.0/schema.json";
+
+/// Which `ParsedResume` list a JSON Resume entry came from, where that is not its own section
+const BLOGS: &str = "blogs";
+const AUTHORING: &str = "authoring";
+const PATENTS: &str = "patents";
+const OPEN_SOURCE: &str = "open_source_contributions";
+const TECHNICAL: &str = "technical";
+const SOFT_SKILLS: &str = "soft_skills";
+const OTHER_SKILLS: &str = "other_skills";
+
+/// JSON Resume v1. Only the root rejects unknown properties, so what the schema has no place for
+/// is kept on the entries under `x-definite*` keys and in `meta`, and other tools ignore it.
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct JsonResume {
+    #[serde(rename = "$schema", skip_serializing_if = "Option::is_none", default)]
+    pub schema: Option<String>,
+    #[serde(default)]
+    pub basics: Basics,
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub work: Vec<Work>,
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub volunteer: Vec<Volunteer>,
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub education: Vec<JsonEducation>,
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub awards: Vec<Award>,
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub certificates: Vec<Certificate>,
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub publications: Vec<JsonPublication>,
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub skills: Vec<Skill>,
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub languages: Vec<Language>,
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub interests: Vec<Interest>,
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub projects: Vec<JsonProject>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub meta: Option<Meta>,
+}
+
+/// What JSON Resume has no field for. Each is set only when needed.
+#[derive(Debug, Serialize, Deserialize, Default, Clone)]
+pub struct Extension {
+    /// The `ParsedResume` list the entry belongs to, e.g. "blogs" for a blog post kept in `publications`
+    #[serde(rename = "x-definite", skip_serializing_if = "Option::is_none", default)]
+    pub section: Option<String>,
+    /// Dates as written on the résumé when they are not ISO 8601 dates
+    #[serde(rename = "x-definite-dates", skip_serializing_if = "Option::is_none", default)]
+    pub dates: Option<String>,
+    #[serde(rename = "x-definite-location", skip_serializing_if = "Option::is_none", default)]
+    pub location: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct Basics {
+    #[serde(default)]
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub label: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub email: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub phone: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub summary: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub profiles: Vec<Profile>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct Profile {
+    #[serde(default)]
+    pub network: String,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub username: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub url: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct Work {
+    /// `company` before v1
+    #[serde(alias = "company", default)]
+    pub name: String,
+    #[serde(default)]
+    pub position: String,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub location: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub start_date: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub end_date: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub summary: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub highlights: Vec<String>,
+    #[serde(flatten)]
+    pub extension: Extension,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct Volunteer {
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub organization: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub position: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub start_date: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub end_date: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub summary: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub highlights: Vec<String>,
+    #[serde(flatten)]
+    pub extension: Extension,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct JsonEducation {
+    #[serde(default)]
+    pub institution: String,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub area: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub study_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub start_date: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub end_date: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub score: Option<String>,
+    #[serde(flatten)]
+    pub extension: Extension,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct Award {
+    #[serde(default)]
+    pub title: String,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub date: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub awarder: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub summary: Option<String>,
+    /// Not in the schema, which allows extra properties on entries
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub url: Option<String>,
+    #[serde(flatten)]
+    pub extension: Extension,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct Certificate {
+    #[serde(default)]
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub date: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub issuer: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub url: Option<String>,
+    #[serde(flatten)]
+    pub extension: Extension,
+}
+
+/// Also holds blog posts, authored works and patents, tagged with their `section`
+#[derive(Debug, Serialize, Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct JsonPublication {
+    #[serde(default)]
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub publisher: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub release_date: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub summary: Option<String>,
+    #[serde(flatten)]
+    pub extension: Extension,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct Skill {
+    #[serde(default)]
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub level: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub keywords: Vec<String>,
+    #[serde(flatten)]
+    pub extension: Extension,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct Language {
+    #[serde(default)]
+    pub language: String,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub fluency: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct Interest {
+    #[serde(default)]
+    pub name: String,
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub keywords: Vec<String>,
+}
+
+/// Also holds open source contributions, tagged with their `section`
+#[derive(Debug, Serialize, Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct JsonProject {
+    #[serde(default)]
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub description: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub highlights: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub keywords: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub start_date: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub end_date: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub url: Option<String>,
+    #[serde(flatten)]
+    pub extension: Extension,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct Meta {
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub version: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub last_modified: Option<String>,
+    #[serde(rename = "x-definite", skip_serializing_if = "Option::is_none", default)]
+    pub definite: Option<MetaExtension>,
+}
+
+/// The generated parts of a `ParsedResume`, so a round trip does not lose them
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct MetaExtension {
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub name_slug: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub persona: Option<Persona>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub keywords: Option<Vec<Keyword>>,
+}
+
+/// `startDate`, `endDate` and, when the dates could not be read, the original text
+fn range_to_iso(text: &str) -> (Option<String>, Option<String>, Option<String>) {
+    if text.trim().is_empty() {
+        return (None, None, None);
+    }
+    match DateRange::parse(text) {
+        Some(range) => (range.start.map(|d| d.iso()), range.end.map(|d| d.iso()), None),
+        None => (None, None, Some(text.trim().to_string())),
+    }
+}
+
+/// Reverses `range_to_iso`, a missing `endDate` means the entry is ongoing
+fn range_from_iso(
+    start: Option<&str>,
+    end: Option<&str>,
+    original: Option<&str>,
+) -> String {
+    if let Some(original) = original {
+        return original.to_string();
+    }
+    match (dates::from_iso(start, None), dates::from_iso(end, None)) {
+        (Some(start), Some(end)) => format!("{} – {}", start, end),
+        (Some(start), None) => format!("{} – Present", start),
+        (None, Some(end)) => end,
+        (None, None) => String::new(),
+    }
+}
+
+fn non_empty(text: Option<String>) -> Option<String> {
+    text.map(|t| t.trim().to_string()).filter(|t| !t.is_empty())
+}
+
+fn non_empty_list<T>(list: Vec<T>) -> Option<Vec<T>> {
+    if list.is_empty() {
+        None
+    } else {
+        Some(list)
+    }
+}
+
+/// The part of a profile url after the host, e.g. "jdoe" for "https://github.com/jdoe"
+fn username(url: &str) -> Option<String> {
+    let path = url.trim_end_matches('/').split("//").last()?;
+    let (_, rest) = path.split_once('/')?;
+    rest.rsplit('/').next().filter(|u| !u.is_empty()).map(|u| u.trim_start_matches('@').to_string())
+}
+
+fn profile(
+    network: &str,
+    url: &Option<String>,
+) -> Option<Profile> {
+    let url = url.as_ref()?;
+    Some(Profile {
+        network: network.to_string(),
+        username: username(url),
+        url: Some(url.clone()),
+    })
+}
+
+/// Converts a parsed résumé to JSON Resume. The generated `name_slug`, persona and keywords go to `meta`.
+pub fn to_json_resume(resume: &ParsedResume) -> JsonResume {
+    let contact = &resume.contact;
+    let mut publications = vec![];
+    for publication in resume.publications.iter().flatten() {
+        let (release_date, original) = dates::to_iso(publication.date.as_deref());
+        publications.push(JsonPublication {
+            name: publication.title.clone().unwrap_or_default(),
+            publisher: publication.journal_or_conference.clone(),
+            release_date,
+            url: publication.link.clone(),
+            summary: None,
+            extension: Extension {
+                dates: original,
+                ..Default::default()
+            },
+        });
+    }
+    for authored in resume.authoring.iter().flatten() {
+        let (release_date, original) = dates::to_iso(authored.date.as_deref());
+        publications.push(JsonPublication {
+            name: authored.title.clone(),
+            publisher: authored.publisher.clone(),
+            release_date,
+            url: authored.link.clone(),
+            summary: authored.description.clone(),
+            extension: Extension {
+                section: Some(AUTHORING.to_string()),
+                dates: original,
+                ..Default::default()
+            },
+        });
+    }
+    for blog in resume.blogs.iter().flatten() {
+        let (release_date, original) = dates::to_iso(blog.date.as_deref());
+        publications.push(JsonPublication {
+            name: blog.title.clone(),
+            publisher: None,
+            release_date,
+            url: blog.link.clone(),
+            summary: blog.summary.clone(),
+            extension: Extension {
+                section: Some(BLOGS.to_string()),
+                dates: original,
+                ..Default::default()
+            },
+        });
+    }
+    for patent in resume.patents.iter().flatten() {
+        let (release_date, original) = dates::to_iso(patent.date.as_deref());
+        publications.push(JsonPublication {
+            name: patent.title.clone(),
+            publisher: patent.patent_number.clone(),
+            release_date,
+            url: patent.link.clone(),
+            summary: patent.inventors.as_ref().filter(|i| !i.is_empty()).map(|i| format!("{}{}", INVENTORS_PREFIX, i.join(", "))),
+            extension: Extension {
+                section: Some(PATENTS.to_string()),
+                dates: original,
+                ..Default::default()
+            },
+        });
+    }
+
+    let mut projects = vec![];
+    for project in resume.projects.iter().flatten() {
+        let (start_date, end_date, original) = range_to_iso(project.dates.as_deref().unwrap_or_default());
+        projects.push(JsonProject {
+            name: project.name.clone(),
+            description: project.description.clone(),
+            keywords: project.technologies.clone(),
+            start_date,
+            end_date,
+            url: project.link.clone(),
+            extension: Extension {
+                dates: original,
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+    }
+    for contribution in resume.open_source_contributions.iter().flatten() {
+        let (start_date, end_date, original) = range_to_iso(contribution.dates.as_deref().unwrap_or_default());
+        projects.push(JsonProject {
+            name: contribution.project_name.clone(),
+            description: contribution.description.clone(),
+            keywords: contribution.technologies.clone(),
+            start_date,
+            end_date,
+            url: contribution.link.clone(),
+            extension: Extension {
+                section: Some(OPEN_SOURCE.to_string()),
+                dates: original,
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+    }
+
+    let mut skills = vec![];
+    if let Some(s) = &resume.skills {
+        for (name, section, keywords) in [
+            ("Technical", TECHNICAL, &s.technical),
+            ("Soft skills", SOFT_SKILLS, &s.soft_skills),
+            ("Other", OTHER_SKILLS, &s.other_skills),
+        ] {
+            if !keywords.is_empty() {
+                skills.push(Skill {
+                    name: name.to_string(),
+                    level: None,
+                    keywords: keywords.clone(),
+                    extension: Extension {
+                        section: Some(section.to_string()),
+                        ..Default::default()
+                    },
+                });
+            }
+        }
+    }
+
+    JsonResume {
+        schema: Some(SCHEMA_URL.to_string()),
+        basics: Basics {
+            name: resume.name.clone(),
+            label: Some(resume.work_experience.first().map(|w| w.title.clone()).unwrap_or(resume.persona.label().to_string())),
+            email: contact.email.clone(),
+            phone: contact.phone.clone(),
+            url: contact.website.clone(),
+            summary: resume.summary.clone(),
+            profiles: [profile("LinkedIn", &contact.linkedin), profile("GitHub", &contact.github), profile("Twitter", &contact.twitter)]
+                .into_iter()
+                .flatten()
+                .collect(),
+        },
+        work: resume
+            .work_experience
+            .iter()
+            .map(|work| {
+                let (start_date, end_date, original) = range_to_iso(&work.dates);
+                Work {
+                    name: work.company.clone(),
+                    position: work.title.clone(),
+                    location: work.location.clone(),
+                    start_date,
+                    end_date,
+                    summary: None,
+                    highlights: work.responsibilities.clone(),
+                    extension: Extension {
+                        dates: original,
+                        ..Default::default()
+                    },
+                }
+            })
+            .collect(),
+        volunteer: resume
+            .volunteer_experience
+            .iter()
+            .flatten()
+            .map(|volunteer| {
+                let (start_date, end_date, original) = range_to_iso(volunteer.dates.as_deref().unwrap_or_default());
+                Volunteer {
+                    organization: volunteer.organization.clone(),
+                    position: volunteer.role.clone(),
+                    start_date,
+                    end_date,
+                    summary: volunteer.description.clone(),
+                    highlights: vec![],
+                    extension: Extension {
+                        dates: original,
+                        ..Default::default()
+                    },
+                }
+            })
+            .collect(),
+        education: resume
+            .education
+            .iter()
+            .map(|education| {
+                let (start_date, end_date, original) = range_to_iso(&education.dates);
+                JsonEducation {
+                    institution: education.institution.clone(),
+                    area: education.field_of_study.clone(),
+                    study_type: education.degree.clone(),
+                    start_date,
+                    end_date,
+                    score: education.gpa.clone(),
+                    extension: Extension {
+                        section: None,
+                        dates: original,
+                        location: education.location.clone(),
+                    },
+                }
+            })
+            .collect(),
+        awards: resume
+            .achievements
+            .iter()
+            .flatten()
+            .map(|achievement| {
+                let (date, original) = dates::to_iso(achievement.date.as_deref());
+                Award {
+                    title: achievement.title.clone(),
+                    date,
+                    awarder: None,
+                    summary: achievement.details.clone(),
+                    url: achievement.link.clone(),
+                    extension: Extension {
+                        dates: original,
+                        ..Default::default()
+                    },
+                }
+            })
+            .collect(),
+        certificates: resume
+            .certifications
+            .iter()
+            .flatten()
+            .map(|certification| {
+                let (date, original) = dates::to_iso(certification.date.as_deref());
+                Certificate {
+                    name: certification.name.clone(),
+                    date,
+                    issuer: certification.issuer.clone(),
+                    url: certification.link.clone(),
+                    extension: Extension {
+                        dates: original,
+                        ..Default::default()
+                    },
+                }
+            })
+            .collect(),
+        publications,
+        skills,
+        languages: vec![],
+        interests: resume.interests_hobbies.iter().flatten().map(|name| Interest { name: name.clone(), keywords: vec![] }).collect(),
+        projects,
+        meta: Some(Meta {
+            version: Some("v1.0.0".to_string()),
+            last_modified: Some(chrono::Utc::now().format("%Y-%m-%dT%H:%M:%S").to_string()),
+            definite: Some(MetaExtension {
+                name_slug: Some(resume.name_slug.clone()),
+                persona: Some(resume.persona.clone()),
+                keywords: resume.keywords.clone(),
+            }),
+        }),
+    }
+}
+
+/// Converts a JSON Resume into a parsed résumé. Entries tagged by `to_json_resume` go back to
+/// their own lists, everything else lands in the closest `ParsedResume` field.
+pub fn from_json_resume(json: JsonResume) -> ParsedResume {
+    let basics = json.basics;
+    let meta = json.meta.and_then(|m| m.definite).unwrap_or_default();
+
+    let mut contact = Contact {
+        phone: non_empty(basics.phone),
+        email: non_empty(basics.email),
+        linkedin: None,
+        github: None,
+        website: None,
+        twitter: None,
+    };
+    // the own website first, so a profile on another network does not take its place
+    if let Some(url) = non_empty(basics.url) {
+        let slot = contact_slot(&mut contact, "", &url);
+        if slot.is_none() {
+            *slot = Some(url);
+        }
+    }
+    for profile in basics.profiles {
+        let url = match (profile.url, profile.username) {
+            (Some(url), _) if !url.trim().is_empty() => url,
+            (_, Some(username)) if !username.trim().is_empty() => match profile.network.to_lowercase().as_str() {
+                "linkedin" => format!("https://www.linkedin.com/in/{}", username),
+                "github" => format!("https://github.com/{}", username),
+                "twitter" | "x" => format!("https://x.com/{}", username),
+                _ => continue,
+            },
+            _ => continue,
+        };
+        let slot = contact_slot(&mut contact, &profile.network, &url);
+        if slot.is_none() {
+            *slot = Some(url);
+        }
+    }
+
+    let work_experience: Vec<WorkExperience> = json
+        .work
+        .into_iter()
+        .map(|work| WorkExperience {
+            dates: range_from_iso(work.start_date.as_deref(), work.end_date.as_deref(), work.extension.dates.as_deref()),
+            company: work.name,
+            location: non_empty(work.location),
+            title: work.position,
+            // the schema has a summary per role, the parsed résumé keeps it as the first bullet
+            responsibilities: non_empty(work.summary).into_iter().chain(work.highlights).collect(),
+        })
+        .collect();
+
+    let (mut publications, mut authoring, mut blogs, mut patents) = (vec![], vec![], vec![], vec![]);
+    for publication in json.publications {
+        let date = dates::from_iso(publication.release_date.as_deref(), publication.extension.dates.as_deref());
+        match publication.extension.section.as_deref() {
+            Some(AUTHORING) => authoring.push(Authoring {
+                title: publication.name,
+                publisher: publication.publisher,
+                date,
+                link: publication.url,
+                description: publication.summary,
+            }),
+            Some(BLOGS) => blogs.push(Blog {
+                title: publication.name,
+                date,
+                link: publication.url,
+                summary: publication.summary,
+            }),
+            Some(PATENTS) => patents.push(Patent {
+                inventors: inventors_from(publication.summary.as_deref()),
+                title: publication.name,
+                patent_number: publication.publisher,
+                date,
+                link: publication.url,
+            }),
+            _ => publications.push(Publication {
+                title: non_empty(Some(publication.name)),
+                journal_or_conference: publication.publisher,
+                date,
+                link: publication.url,
+            }),
+        }
+    }
+
+    let (mut projects, mut open_source_contributions) = (vec![], vec![]);
+    for project in json.projects {
+        let dates = non_empty(Some(range_from_iso(project.start_date.as_deref(), project.end_date.as_deref(), project.extension.dates.as_deref())));
+        let description = non_empty(Some(project.description.into_iter().chain(project.highlights).collect::<Vec<String>>().join("\n")));
+        if project.extension.section.as_deref() == Some(OPEN_SOURCE) {
+            open_source_contributions.push(OpenSourceContribution {
+                project_name: project.name,
+                description,
+                link: project.url,
+                dates,
+                technologies: project.keywords,
+            });
+        } else {
+            projects.push(Project {
+                name: project.name,
+                description,
+                dates,
+                technologies: project.keywords,
+                link: project.url,
+            });
+        }
+    }
+
+    let mut skills = Skills {
+        technical: vec![],
+        soft_skills: vec![],
+        other_skills: vec![],
+    };
+    for skill in json.skills {
+        let section = skill.extension.section.clone().unwrap_or_else(|| {
+            let name = skill.name.to_lowercase();
+            if name.contains("soft") || name.contains("interpersonal") {
+                SOFT_SKILLS.to_string()
+            } else if name == "other" || name.contains("language") {
+                OTHER_SKILLS.to_string()
+            } else {
+                TECHNICAL.to_string()
+            }
+        });
+        // a skill group lists its skills as keywords, a single skill may have none
+        let list = if skill.keywords.is_empty() { vec![skill.name] } else { skill.keywords };
+        match section.as_str() {
+            SOFT_SKILLS => skills.soft_skills.extend(list),
+            OTHER_SKILLS => skills.other_skills.extend(list),
+            _ => skills.technical.extend(list),
+        }
+    }
+    // no field for spoken languages either, they are listed as other skills
+    for language in json.languages.into_iter().filter(|l| !l.language.trim().is_empty()) {
+        skills.other_skills.push(match non_empty(language.fluency) {
+            Some(fluency) => format!("{} ({})", language.language, fluency),
+            None => language.language,
+        });
+    }
+
+    let persona = meta
+        .persona
+        .unwrap_or_else(|| persona_for(basics.label.as_deref().or(work_experience.first().map(|w| w.title.as_str())).unwrap_or_default()));
+    ParsedResume {
+        name_slug: meta.name_slug.unwrap_or_else(|| slug(&basics.name)),
+        name: basics.name.trim().to_string(),
+        persona,
+        contact,
+        summary: non_empty(basics.summary),
+        education: json
+            .education
+            .into_iter()
+            .map(|education| Education {
+                dates: range_from_iso(education.start_date.as_deref(), education.end_date.as_deref(), education.extension.dates.as_deref()),
+                institution: education.institution,
+                location: education.extension.location,
+                degree: non_empty(education.study_type),
+                field_of_study: non_empty(education.area),
+                gpa: non_empty(education.score),
+            })
+            .collect(),
+        work_experience,
+        work_experience_diff: None,
+        projects: non_empty_list(projects),
+        skills: if skills.technical.is_empty() && skills.soft_skills.is_empty() && skills.other_skills.is_empty() {
+            None
+        } else {
+            Some(skills)
+        },
+        certifications: non_empty_list(
+            json.certificates
+                .into_iter()
+                .map(|certificate| Certification {
+                    date: dates::from_iso(certificate.date.as_deref(), certificate.extension.dates.as_deref()),
+                    name: certificate.name,
+                    issuer: non_empty(certificate.issuer),
+                    link: certificate.url,
+                })
+                .collect(),
+        ),
+        achievements: non_empty_list(
+            json.awards
+                .into_iter()
+                .map(|award| Achievement {
+                    date: dates::from_iso(award.date.as_deref(), award.extension.dates.as_deref()),
+                    details: non_empty(Some([award.awarder, award.summary].into_iter().flatten().collect::<Vec<String>>().join(" - "))),
+                    title: award.title,
+                    link: award.url,
+                })
+                .collect(),
+        ),
+        publications: non_empty_list(publications),
+        volunteer_experience: non_empty_list(
+            json.volunteer
+                .into_iter()
+                .map(|volunteer| VolunteerExperience {
+                    dates: non_empty(Some(range_from_iso(
+                        volunteer.start_date.as_deref(),
+                        volunteer.end_date.as_deref(),
+                        volunteer.extension.dates.as_deref(),
+                    ))),
+                    organization: non_empty(volunteer.organization),
+                    role: non_empty(volunteer.position),
+                    description: non_empty(Some(volunteer.summary.into_iter().chain(volunteer.highlights).collect::<Vec<String>>().join("\n"))),
+                })
+                .collect(),
+        ),
+        interests_hobbies: non_empty_list(json.interests.into_iter().map(|i| i.name).filter(|i| !i.trim().is_empty()).collect()),
+        keywords: meta.keywords,
+        blogs: non_empty_list(blogs),
+        open_source_contributions: non_empty_list(open_source_contributions),
+        authoring: non_empty_list(authoring),
+        patents: non_empty_list(patents),
+    }
+}
+
+pub fn to_json(resume: &ParsedResume) -> Result<Vec<u8>, String> {
+    serde_json::to_vec_pretty(&to_json_resume(resume)).map_err(|e| e.to_string())
+}
+
+pub fn from_json(text: &str) -> Result<ParsedResume, String> {
+    let json: JsonResume = serde_json::from_str(text).map_err(|e| e.to_string())?;
+    Ok(from_json_resume(json))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::features::resumes::interchange::full_resume;
+
+    fn round_trip(resume: &ParsedResume) -> ParsedResume {
+        from_json(std::str::from_utf8(&to_json(resume).unwrap()).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn a_full_resume_survives_the_round_trip() {
+        let resume = full_resume();
+        assert_eq!(serde_json::to_value(round_trip(&resume)).unwrap(), serde_json::to_value(&resume).unwrap());
+    }
+
+    #[test]
+    fn dates_are_written_as_iso_and_unreadable_ones_are_kept() {
+        let json = to_json_resume(&full_resume());
+        assert_eq!((json.work[0].start_date.as_deref(), json.work[0].end_date.as_deref()), (Some("2021-03"), None));
+        assert_eq!(json.work[1].extension.dates.as_deref(), Some("Summer 2019"));
+        assert_eq!((json.education[0].start_date.as_deref(), json.education[0].end_date.as_deref()), (Some("2012"), Some("2016")));
+    }
+
+    #[test]
+    fn other_tools_output_lands_in_the_closest_field() {
+        let resume = from_json(
+            r#"{"basics": {"name": " Jane Doe ", "label": "Product Manager", "profiles": [{"network": "GitHub", "username": "janedoe"}]},
+                "work": [{"name": "Acme", "position": "PM", "startDate": "2020-01-15", "summary": "Owned checkout", "highlights": ["Grew conversion"]}],
+                "skills": [{"name": "Soft skills", "keywords": ["Negotiation"]}],
+                "languages": [{"language": "French", "fluency": "Fluent"}]}"#,
+        )
+        .unwrap();
+        assert_eq!(resume.name, "Jane Doe");
+        assert_eq!(resume.name_slug, "jane-doe");
+        assert_eq!(resume.persona, Persona::ProductManager);
+        assert_eq!(resume.contact.github.as_deref(), Some("https://github.com/janedoe"));
+        assert_eq!(resume.work_experience[0].dates, "Jan 2020 – Present");
+        assert_eq!(resume.work_experience[0].responsibilities, vec!["Owned checkout", "Grew conversion"]);
+        let skills = resume.skills.unwrap();
+        assert_eq!(skills.soft_skills, vec!["Negotiation"]);
+        assert_eq!(skills.other_skills, vec!["French (Fluent)"]);
+    }
+}
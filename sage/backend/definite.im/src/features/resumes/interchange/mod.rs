@@ -0,0 +1,197 @@
+pub mod dates;
+pub mod europass;
+pub mod json_resume;
+
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use super::entities::resume::{Contact, ParsedResume, Persona};
+
+/// Structured résumé formats that are converted directly, without asking the model to parse them
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub enum ImportFormat {
+    /// JSON Resume v1, https://jsonresume.org/schema
+    JsonResume,
+    /// Europass CV XML (`SkillsPassport`, schema v3)
+    Europass,
+}
+
+impl ImportFormat {
+    pub const ACCEPTED_EXTENSIONS: &'static str = ".json,.xml";
+
+    pub fn from_filename(filename: &str) -> Option<Self> {
+        let extension = filename.rsplit_once('.')?.1.to_lowercase();
+        match extension.as_str() {
+            "json" => Some(ImportFormat::JsonResume),
+            "xml" => Some(ImportFormat::Europass),
+            _ => None,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            ImportFormat::JsonResume => "JSON Resume",
+            ImportFormat::Europass => "Europass CV",
+        }
+    }
+}
+
+/// Why a structured résumé could not be imported. The messages are shown to the user.
+#[derive(Debug)]
+pub enum ImportError {
+    UnsupportedType {
+        filename: String,
+    },
+    Invalid {
+        format: ImportFormat,
+        message: String,
+    },
+    /// Valid, but without the name every résumé needs
+    MissingName {
+        format: ImportFormat,
+    },
+}
+
+impl fmt::Display for ImportError {
+    fn fmt(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+    ) -> fmt::Result {
+        match self {
+            ImportError::UnsupportedType { filename } => write!(f, "{} is not a JSON Resume (.json) or Europass (.xml) file", filename),
+            ImportError::Invalid { format, message } => write!(f, "The file is not a valid {}: {}", format.label(), message),
+            ImportError::MissingName { format } => write!(f, "The {} has no name", format.label()),
+        }
+    }
+}
+
+/// Converts a JSON Resume or Europass document straight into a `ParsedResume`
+pub fn import(
+    format: ImportFormat,
+    bytes: &[u8],
+) -> Result<ParsedResume, ImportError> {
+    let invalid = |message: String| ImportError::Invalid { format, message };
+    let text = std::str::from_utf8(bytes).map_err(|_| invalid("it is not UTF-8 text".to_string()))?;
+    let resume = match format {
+        ImportFormat::JsonResume => json_resume::from_json(text).map_err(invalid)?,
+        ImportFormat::Europass => europass::from_xml(text).map_err(invalid)?,
+    };
+    if resume.name.trim().is_empty() {
+        return Err(ImportError::MissingName { format });
+    }
+    Ok(resume)
+}
+
+/// "Jane Q. Doe" becomes "jane-q-doe", the slug the model would generate
+pub fn slug(name: &str) -> String {
+    name.to_lowercase().split(|c: char| !c.is_alphanumeric()).filter(|w| !w.is_empty()).collect::<Vec<&str>>().join("-")
+}
+
+/// Best guess of the persona from a job title, neither format records one
+pub fn persona_for(title: &str) -> Persona {
+    let title = title.to_lowercase();
+    let has = |words: &[&str]| words.iter().any(|w| title.contains(w));
+    if has(&["architect", "consultant"]) {
+        if has(&["manager", "head", "director", "lead"]) {
+            Persona::SolutionArchitectManager
+        } else {
+            Persona::SolutionArchitect
+        }
+    } else if has(&["product manager", "product owner"]) {
+        Persona::ProductManager
+    } else if has(&["program manager", "project manager", "programme manager", "scrum master"]) {
+        Persona::ProgramManager
+    } else if has(&["engineering manager", "development manager", "head of engineering", "director of engineering", "vp engineering", "cto"]) {
+        Persona::EngineeringManager
+    } else if has(&["software engineer", "sde", "swe", "backend", "frontend", "full stack", "fullstack", "platform engineer"]) {
+        Persona::SoftwareEngineer
+    } else if has(&["developer", "programmer"]) {
+        Persona::ApplicationDeveloper
+    } else {
+        Persona::TechProfessional
+    }
+}
+
+/// Which contact field a profile or web address belongs in, by its host
+pub fn contact_slot<'a>(
+    contact: &'a mut Contact,
+    network: &str,
+    url: &str,
+) -> &'a mut Option<String> {
+    let key = format!("{} {}", network, url).to_lowercase();
+    if key.contains("linkedin") {
+        &mut contact.linkedin
+    } else if key.contains("github") {
+        &mut contact.github
+    } else if key.contains("twitter") || key.contains("//x.com") || key.starts_with("x ") {
+        &mut contact.twitter
+    } else {
+        &mut contact.website
+    }
+}
+
+/// The patent inventors are written as "Inventors: A, B" where a format has no field for them
+pub const INVENTORS_PREFIX: &str = "Inventors: ";
+
+pub fn inventors_from(text: Option<&str>) -> Option<Vec<String>> {
+    let list = text?.strip_prefix(INVENTORS_PREFIX)?;
+    Some(list.split(',').map(|i| i.trim().to_string()).filter(|i| !i.is_empty()).collect())
+}
+
+/// A résumé with every section filled, dates written the way `DateRange::display` writes them
+#[cfg(test)]
+pub fn full_resume() -> ParsedResume {
+    serde_json::from_value(serde_json::json!({
+        "name_slug": "jane-doe",
+        "name": "Jane Doe",
+        "persona": "SoftwareEngineer",
+        "contact": {
+            "phone": "+1 555 0100",
+            "email": "jane@example.com",
+            "linkedin": "https://www.linkedin.com/in/janedoe",
+            "github": "https://github.com/janedoe",
+            "website": "https://janedoe.dev",
+            "twitter": "https://twitter.com/janedoe"
+        },
+        "summary": "Backend engineer who ships payment systems.",
+        "education": [
+            {"institution": "State University", "location": "Springfield", "degree": "BSc", "field_of_study": "Computer Science", "gpa": "3.8", "dates": "2012 – 2016"}
+        ],
+        "work_experience": [
+            {"company": "Acme", "location": "Berlin", "title": "Senior Software Engineer", "dates": "Mar 2021 – Present", "responsibilities": ["Led the payments rewrite", "Cut p99 latency by 40%"]},
+            {"company": "Initech", "title": "Software Engineer", "dates": "Summer 2019", "responsibilities": ["Built the billing API"]}
+        ],
+        "projects": [
+            {"name": "ledger", "description": "Double-entry bookkeeping library", "dates": "Jan 2020 – Jun 2020", "technologies": ["Rust", "Postgres"], "link": "https://github.com/janedoe/ledger"}
+        ],
+        "skills": {"technical": ["Rust", "Go"], "soft_skills": ["Mentoring"], "other_skills": ["German"]},
+        "certifications": [
+            {"name": "AWS Solutions Architect", "issuer": "Amazon", "date": "May 2022", "link": "https://aws.example/cert"}
+        ],
+        "achievements": [
+            {"title": "Hackathon winner", "details": "Best fintech hack", "date": "2019"}
+        ],
+        "publications": [
+            {"title": "Idempotent payments", "journal_or_conference": "RustConf", "date": "Sep 2021", "link": "https://rustconf.example/talk"}
+        ],
+        "volunteer_experience": [
+            {"organization": "Code Club", "role": "Mentor", "dates": "2018 – 2020", "description": "Taught kids Python"}
+        ],
+        "interests_hobbies": ["Climbing", "Chess"],
+        "keywords": [{"keyword": "payments", "context": "technical"}],
+        "blogs": [
+            {"title": "Why ledgers", "date": "Feb 2023", "link": "https://janedoe.dev/ledgers", "summary": "On double entry"}
+        ],
+        "open_source_contributions": [
+            {"project_name": "tokio", "description": "Fixed a timer bug", "link": "https://github.com/tokio-rs/tokio", "dates": "2022", "technologies": ["Rust"]}
+        ],
+        "authoring": [
+            {"title": "Payments in Rust", "publisher": "Leanpub", "date": "2023", "link": "https://leanpub.example/payments", "description": "A short book"}
+        ],
+        "patents": [
+            {"title": "Retry-safe transfers", "patent_number": "US1234567", "date": "Jul 2020", "link": "https://patents.example/US1234567", "inventors": ["Jane Doe", "John Roe"]}
+        ]
+    }))
+    .unwrap()
+}
@@ -0,0 +1,517 @@
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use futures::{future::join_all, StreamExt};
+use handlebars::Handlebars;
+use log::{debug, error, info, warn};
+use mongodb::Client;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::{
+    features::resumes::{
+        entities::{
+            job::{JobEntity, JobKind, JobResult, JobState},
+            job_description::{JobDescriptionEntity, ParsedJobDescription},
+            resume::{ParsedResume, ResumeEntity, SourceFile},
+            resume_score::{ResumeScore, ScoreGuidance},
+        },
+        routes::{
+            bullet_proof::{save_rewrite, save_score, WorkRewrite},
+            resume::save_resume,
+        },
+        rubric::RubricStore,
+        verification::{PendingRewrite, Verification},
+    },
+    html_renderer::render_fragment_html,
+    shared::ops::{
+        cache_ops::Cache,
+        date_ops,
+        environ_ops::QueueConfig,
+        openai::{completion_error::CompletionError, parse_completion, provider::LlmProvider, stream::DeltaStream},
+        queue_ops::{Consumer, Delivery, PartialPublisher, Partials, Producer, RedisConnectionManager},
+        quota_ops::spend_quota,
+        repository_ops::Repository,
+    },
+};
+
+/// Received characters are reported at most this often while a completion streams in
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(1);
+
+/// LLM work taken off the request by a POST route and done by `definite worker`
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum LlmJob {
+    ParseResume { resume_text: String, source_file: Option<SourceFile> },
+    ParseJobDescription { jd_text: String },
+    ScoreResume { resume_id: String },
+    RewriteWork { resume_id: String },
+}
+
+impl LlmJob {
+    /// Every kind of job has its own queue, a backlog of one does not hold up the others
+    pub const QUEUES: [&'static str; 4] = ["jobs:parse_resume", "jobs:parse_jd", "jobs:score_resume", "jobs:rewrite_work"];
+
+    pub fn queue(&self) -> &'static str {
+        match self {
+            LlmJob::ParseResume { .. } => Self::QUEUES[0],
+            LlmJob::ParseJobDescription { .. } => Self::QUEUES[1],
+            LlmJob::ScoreResume { .. } => Self::QUEUES[2],
+            LlmJob::RewriteWork { .. } => Self::QUEUES[3],
+        }
+    }
+
+    pub fn kind(&self) -> JobKind {
+        match self {
+            LlmJob::ParseResume { .. } => JobKind::ParseResume,
+            LlmJob::ParseJobDescription { .. } => JobKind::ParseJobDescription,
+            LlmJob::ScoreResume { .. } => JobKind::ScoreResume,
+            LlmJob::RewriteWork { .. } => JobKind::RewriteWork,
+        }
+    }
+
+    /// What a failed job was doing, for the error fragment
+    fn action(&self) -> &'static str {
+        match self {
+            LlmJob::ParseResume { .. } => "parsing résumé",
+            LlmJob::ParseJobDescription { .. } => "parsing job description",
+            LlmJob::ScoreResume { .. } => "scoring résumé",
+            LlmJob::RewriteWork { .. } => "rewriting work experience",
+        }
+    }
+}
+
+/// A job as it is queued, the worker acts for `user_id`
+#[derive(Debug, Serialize, Deserialize)]
+pub struct QueuedJob {
+    pub user_id: String,
+    pub job: LlmJob,
+}
+
+/// Saves where the job is, `updated` moves with every save
+async fn save_job(
+    mongoc: &Client,
+    job: &mut JobEntity,
+) -> bool {
+    job.updated = date_ops::to_timestamp();
+    job.save(mongoc).await.is_ok()
+}
+
+/// Records the job for the user and queues it, returns its id
+pub async fn enqueue(
+    producer: &Producer,
+    mongoc: &Client,
+    user_id: &str,
+    job: LlmJob,
+) -> Option<String> {
+    let entity = JobEntity {
+        user_id: user_id.to_string(),
+        kind: job.kind(),
+        ..Default::default()
+    };
+    // the record goes first, a fast worker must not find its job without one
+    let job_id = entity.create(mongoc).await.ok()?.to_hex();
+    let queue = job.queue();
+    match producer.produce(queue, &job_id, &QueuedJob { user_id: user_id.to_string(), job }).await {
+        Ok(()) => Some(job_id),
+        Err(e) => {
+            error!("Error queueing job {} on {}: {:?}", job_id, queue, e);
+            let mut entity = entity;
+            entity.state = JobState::Failed;
+            entity.message = "Failed".to_string();
+            entity.error = Some("The job could not be queued".to_string());
+            save_job(mongoc, &mut entity).await;
+            None
+        }
+    }
+}
+
+/// What a job made: the fragment that shows it and, when it saved something, where that is
+struct JobOutcome {
+    html: String,
+    result: Option<JobResult>,
+    message: &'static str,
+}
+
+/// Why a job did not produce its fragment
+enum JobFailure {
+    /// The provider failed, a transient failure is tried again
+    Completion(CompletionError),
+    /// Nothing another attempt would change, the context is for the error fragment
+    Failed(Value),
+}
+
+impl From<CompletionError> for JobFailure {
+    fn from(e: CompletionError) -> Self {
+        JobFailure::Completion(e)
+    }
+}
+
+fn dashboard_error(message: &str) -> JobFailure {
+    JobFailure::Failed(json!({
+        "error_message": message,
+        "navigate_url": "/resumes/dashboard",
+        "navigate_text": "Go to dashboard",
+    }))
+}
+
+/// What a worker needs to do any job
+pub struct Worker {
+    pub handlebars: Handlebars<'static>,
+    pub mongoc: Client,
+    pub cache: Cache,
+    pub rubric_store: RubricStore,
+    pub provider: Arc<dyn LlmProvider>,
+    pub partials: Partials,
+}
+
+impl Worker {
+    /// Records the step the job is at, `progress` in percent
+    async fn progress(
+        &self,
+        job: &mut JobEntity,
+        progress: u32,
+        message: &str,
+    ) {
+        job.state = JobState::Running;
+        job.progress = progress;
+        job.message = message.to_string();
+        if !save_job(&self.mongoc, job).await {
+            debug!("Error saving progress of job {}", job._id.to_hex());
+        }
+    }
+
+    /// Reads the whole completion, publishing every delta for the page and reporting how much has arrived as it streams in
+    async fn collect<T: DeserializeOwned>(
+        &self,
+        job: &mut JobEntity,
+        deltas: Result<DeltaStream, CompletionError>,
+    ) -> Result<T, CompletionError> {
+        let mut deltas = deltas?;
+        let mut publisher = self.publisher(job).await;
+        let mut content = String::new();
+        let mut received = 0;
+        let mut reported = Instant::now();
+        while let Some(delta) = deltas.next().await {
+            let delta = delta?;
+            received += delta.chars().count();
+            content.push_str(&delta);
+            if let Some(p) = publisher.as_mut() {
+                if let Err(e) = p.publish(&json!({"delta": delta, "received": received})).await {
+                    // the page still gets the progress and the result from the job's record
+                    debug!("Error publishing partial output of job {}, no longer publishing it: {:?}", job._id.to_hex(), e);
+                    publisher = None;
+                }
+            }
+            if reported.elapsed() >= PROGRESS_INTERVAL {
+                let progress = job.progress;
+                self.progress(job, progress, &format!("Received {} characters", received)).await;
+                reported = Instant::now();
+            }
+        }
+        parse_completion::<T>(&content)
+    }
+
+    async fn publisher(
+        &self,
+        job: &JobEntity,
+    ) -> Option<PartialPublisher> {
+        match self.partials.publisher(&job._id.to_hex()).await {
+            Ok(p) => Some(p),
+            Err(e) => {
+                debug!("Error connecting to publish partial output of job {}: {:?}", job._id.to_hex(), e);
+                None
+            }
+        }
+    }
+
+    /// Spends the user's quota right before the provider is called. Only the first attempt pays,
+    /// a retry after a provider failure is not the user's doing.
+    async fn spend_quota(
+        &self,
+        entity: &JobEntity,
+    ) -> Result<(), JobFailure> {
+        if entity.attempts > 1 {
+            return Ok(());
+        }
+        spend_quota(&self.cache, &self.mongoc, &entity.user_id).await.map_err(|e| dashboard_error(&e.message()))
+    }
+
+    async fn find_resume(
+        &self,
+        user_id: &str,
+        resume_id: &str,
+    ) -> Result<ResumeEntity, JobFailure> {
+        ResumeEntity::find_hex(&self.mongoc, user_id, resume_id).await.map_err(|_| dashboard_error("Résumé not found"))
+    }
+
+    /// Does the job and returns the fragment that shows its result
+    async fn run(
+        &self,
+        entity: &mut JobEntity,
+        job: LlmJob,
+    ) -> Result<JobOutcome, JobFailure> {
+        let user_id = entity.user_id.clone();
+        match job {
+            LlmJob::ParseResume { resume_text, source_file } => {
+                self.progress(entity, 10, "Reading your résumé").await;
+                let parsed_resume = match ParsedResume::cached(&self.cache, &resume_text).await {
+                    Some(r) => r,
+                    None => {
+                        self.spend_quota(entity).await?;
+                        let r = self
+                            .collect::<ParsedResume>(entity, ParsedResume::parse_stream(&resume_text, Some(user_id.clone()), self.provider.as_ref()).await)
+                            .await?;
+                        r.cache(&self.cache, &resume_text).await;
+                        r
+                    }
+                };
+                self.progress(entity, 90, "Saving your résumé").await;
+                let resume_id = save_resume(&self.mongoc, resume_text, user_id, parsed_resume, source_file).await.map_err(dashboard_error)?;
+                let url = format!("/resumes/resume/{}", resume_id);
+                Ok(JobOutcome {
+                    html: render_fragment_html(
+                        &self.handlebars,
+                        "resume-added",
+                        json!({
+                            "message": "Your résumé has been added!",
+                            "navigate_url": "/resumes/dashboard",
+                            "navigate_text": "Go to dashboard",
+                            "ats_friendly_url": url,
+                        }),
+                    ),
+                    result: Some(JobResult { id: resume_id, url }),
+                    message: "Your résumé has been added",
+                })
+            }
+            LlmJob::ParseJobDescription { jd_text } => {
+                self.progress(entity, 10, "Reading the job description").await;
+                let parsed_jd = match ParsedJobDescription::cached(&self.cache, &jd_text).await {
+                    Some(r) => r,
+                    None => {
+                        self.spend_quota(entity).await?;
+                        ParsedJobDescription::parse(&jd_text, Some(user_id.clone()), &self.cache, self.provider.as_ref()).await?
+                    }
+                };
+                self.progress(entity, 90, "Saving the job description").await;
+                let jd_entity = JobDescriptionEntity {
+                    jd_text,
+                    user_id,
+                    name: parsed_jd.name_slug.clone().unwrap_or_default(),
+                    parsed_jd: Some(parsed_jd),
+                    ..Default::default()
+                };
+                let jd_id = jd_entity.create(&self.mongoc).await.map_err(|_| dashboard_error("Error saving job description"))?;
+                Ok(JobOutcome {
+                    html: render_fragment_html(
+                        &self.handlebars,
+                        "jd-added",
+                        json!({
+                            "message": "Your job description has been added!",
+                            "navigate_url": "/resumes/dashboard",
+                            "navigate_text": "Go to dashboard",
+                        }),
+                    ),
+                    result: Some(JobResult {
+                        url: format!("/resumes/job-description/{}", jd_id),
+                        id: jd_id.to_hex(),
+                    }),
+                    message: "Your job description has been added",
+                })
+            }
+            LlmJob::ScoreResume { resume_id } => {
+                self.progress(entity, 10, "Scoring your résumé").await;
+                let parsed_resume = self
+                    .find_resume(&user_id, &resume_id)
+                    .await?
+                    .parsed_resume
+                    .ok_or_else(|| dashboard_error("Résumé has not been parsed"))?;
+                let (rubric, rubric_version) = self.rubric_store.active(&self.mongoc).await;
+                let rubric_hash = rubric.content_hash();
+                let rubric = rubric.for_persona(&parsed_resume.persona).ok_or_else(|| dashboard_error("No scoring rubric for this persona"))?;
+                let mut score_with_gaps = ResumeScore::compute(&parsed_resume, rubric);
+                if !score_with_gaps.gaps().is_empty() {
+                    self.spend_quota(entity).await?;
+                    self.progress(entity, 40, "Writing guidance for the gaps").await;
+                    // the score stands without guidance, a failed completion only loses the advice
+                    match self
+                        .collect::<ScoreGuidance>(entity, score_with_gaps.guide_stream(&parsed_resume, user_id.clone(), self.provider.as_ref()).await)
+                        .await
+                    {
+                        Ok(guidance) => score_with_gaps.apply_guidance(guidance),
+                        Err(e) => error!("Error writing score guidance: {}", e),
+                    }
+                }
+                self.progress(entity, 90, "Saving your score").await;
+                let score = save_score(&self.mongoc, resume_id, user_id, score_with_gaps, rubric_version, rubric_hash)
+                    .await
+                    .map_err(dashboard_error)?;
+                let url = format!("/resumes/bullet-proof/score/{}", score);
+                Ok(JobOutcome {
+                    html: render_fragment_html(
+                        &self.handlebars,
+                        "scoring-complete",
+                        json!({
+                            "navigate_url": url,
+                            "navigate_text": "See your score",
+                        }),
+                    ),
+                    result: Some(JobResult { id: score, url }),
+                    message: "Your résumé has been scored",
+                })
+            }
+            LlmJob::RewriteWork { resume_id } => {
+                self.progress(entity, 10, "Rewriting your work experience").await;
+                let resume = self.find_resume(&user_id, &resume_id).await?;
+                let mut parsed_resume = resume.parsed_resume.clone().ok_or_else(|| dashboard_error("Résumé has not been parsed"))?;
+                self.spend_quota(entity).await?;
+                let optimized = ParsedResume::optimize_work(&mut parsed_resume, Some(user_id.clone()), self.provider.as_ref()).await?;
+                self.progress(entity, 80, "Checking the rewrite against your résumé").await;
+                let rewritten = optimized.work_experience_diff.as_ref().map(|d| d.rewritten()).unwrap_or_default();
+                let verification = Verification::of(&resume.resume_text, &rewritten);
+                if verification.is_supported() {
+                    let context = save_rewrite(&self.mongoc, resume, optimized, &rewritten).await.map_err(dashboard_error)?;
+                    return Ok(JobOutcome {
+                        html: render_fragment_html(&self.handlebars, "work-optimized", context),
+                        result: Some(JobResult {
+                            url: format!("/resumes/bullet-proof/review/{}", resume_id),
+                            id: resume_id,
+                        }),
+                        message: "Your work experience has been rewritten",
+                    });
+                }
+                let pending = PendingRewrite {
+                    verification,
+                    rewrite: WorkRewrite {
+                        resume_id,
+                        work_experience: rewritten,
+                    },
+                };
+                let pending_id = pending.park(&self.cache, &user_id).await.ok_or_else(|| dashboard_error("Error keeping the rewrite for review"))?;
+                // nothing is saved until the user confirms, there is no result to point at yet
+                Ok(JobOutcome {
+                    html: render_fragment_html(
+                        &self.handlebars,
+                        "rewrite-verification",
+                        json!({
+                            "message": format!("The rewrite says {} things your résumé does not, check them before it is saved", pending.verification.claims.len()),
+                            "claims": pending.verification.claims.iter().map(|c| json!({"label": c.kind.label(), "claim": c})).collect::<Vec<_>>(),
+                            "work_diff": optimized.work_experience_diff,
+                            "confirm_url": "/resumes/bullet-proof/optimize/confirm",
+                            "confirm_text": "They are true, save the rewrite",
+                            "pending_id": pending_id,
+                            "navigate_url": "/resumes/bullet-proof",
+                            "navigate_text": "Discard the rewrite",
+                        }),
+                    ),
+                    result: None,
+                    message: "The rewrite was not saved, it said things your résumé does not",
+                })
+            }
+        }
+    }
+
+    /// Records how the job ended, `context` is the error fragment's for a failed job
+    async fn fail(
+        &self,
+        job: &mut JobEntity,
+        context: Value,
+    ) {
+        job.state = JobState::Failed;
+        job.message = "Failed".to_string();
+        job.error = Some(context["error_detail"].as_str().or(context["error_message"].as_str()).unwrap_or("Failed").to_string());
+        job.html = Some(render_fragment_html(&self.handlebars, "error-fragment", context));
+        if !save_job(&self.mongoc, job).await {
+            error!("Error saving the failure of job {}", job._id.to_hex());
+        }
+    }
+
+    /// Runs one delivery. A transient provider failure goes back to the queue until it is out of attempts,
+    /// every other failure is final and shown to the user right away.
+    async fn process(
+        &self,
+        delivery: Delivery<QueuedJob>,
+    ) -> Result<(), String> {
+        let Delivery {
+            id: job_id,
+            attempt,
+            last_attempt,
+            payload: QueuedJob { user_id, job },
+            lease,
+        } = delivery;
+        info!("Running job {} ({}) attempt {}", job_id, job.queue(), attempt);
+        let mut entity = match JobEntity::find_hex(&self.mongoc, &user_id, &job_id).await {
+            Ok(e) => e,
+            Err(_) => {
+                // the record is only there to report on, the job is still done
+                error!("No record for job {}, it runs without progress", job_id);
+                JobEntity {
+                    user_id: user_id.clone(),
+                    kind: job.kind(),
+                    ..Default::default()
+                }
+            }
+        };
+        entity.attempts = attempt;
+        let action = job.action();
+        let outcome = self.run(&mut entity, job).await;
+        if !lease.is_held().await {
+            // the job was handed back while this attempt stalled, the attempt that has it now records the result
+            warn!("Lost the lease on job {}, its result is not recorded", job_id);
+            return Ok(());
+        }
+        match outcome {
+            Ok(outcome) => {
+                entity.state = JobState::Succeeded;
+                entity.progress = 100;
+                entity.message = outcome.message.to_string();
+                entity.error = None;
+                entity.result = outcome.result;
+                entity.html = Some(outcome.html);
+                if !save_job(&self.mongoc, &mut entity).await {
+                    error!("Error saving the result of job {}", job_id);
+                }
+                Ok(())
+            }
+            Err(JobFailure::Completion(e)) if e.is_transient() && !last_attempt => {
+                entity.state = JobState::Queued;
+                entity.message = "The AI is busy, trying again shortly".to_string();
+                entity.error = Some(e.user_message().to_string());
+                save_job(&self.mongoc, &mut entity).await;
+                Err(e.to_string())
+            }
+            Err(JobFailure::Completion(e)) => {
+                self.fail(&mut entity, e.to_fragment_context(action)).await;
+                // out of attempts, the dead-letter queue keeps it
+                match e.is_transient() {
+                    true => Err(e.to_string()),
+                    false => Ok(()),
+                }
+            }
+            Err(JobFailure::Failed(context)) => {
+                self.fail(&mut entity, context).await;
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Takes jobs off every queue until the process stops, `concurrency` jobs of each kind at a time
+pub async fn work(
+    worker: Arc<Worker>,
+    connection_manager: Arc<RedisConnectionManager>,
+    queue_config: &QueueConfig,
+    concurrency: usize,
+) {
+    let consumers: Vec<Consumer> = LlmJob::QUEUES
+        .iter()
+        .flat_map(|queue| (0..concurrency).map(|_| Consumer::new(connection_manager.clone(), queue, queue_config)))
+        .collect();
+    join_all(consumers.iter().map(|consumer| {
+        let worker = worker.clone();
+        async move { consumer.run(|delivery: Delivery<QueuedJob>| worker.process(delivery)).await }
+    }))
+    .await;
+}
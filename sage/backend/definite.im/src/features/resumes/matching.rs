@@ -0,0 +1,547 @@
+use serde::{Deserialize, Serialize};
+
+use super::entities::{job_description::ParsedJobDescription, resume::ParsedResume};
+
+/// Words that say nothing about a requirement on their own
+const STOP_WORDS: [&str; 58] = [
+    "a",
+    "about",
+    "across",
+    "an",
+    "and",
+    "any",
+    "are",
+    "as",
+    "at",
+    "be",
+    "being",
+    "both",
+    "by",
+    "can",
+    "demonstrated",
+    "e.g",
+    "etc",
+    "experience",
+    "for",
+    "from",
+    "have",
+    "in",
+    "including",
+    "into",
+    "is",
+    "it",
+    "its",
+    "least",
+    "of",
+    "on",
+    "or",
+    "other",
+    "our",
+    "plus",
+    "proven",
+    "related",
+    "relevant",
+    "similar",
+    "skills",
+    "strong",
+    "such",
+    "that",
+    "the",
+    "their",
+    "this",
+    "through",
+    "to",
+    "using",
+    "we",
+    "well",
+    "what",
+    "when",
+    "who",
+    "will",
+    "with",
+    "within",
+    "you",
+    "your",
+];
+
+/// Keyword contexts the job description parser uses for skills rather than general keywords
+const SKILL_CONTEXTS: [&str; 6] = ["skill", "technical", "tool", "technolog", "language", "framework"];
+
+/// Share of a requirement's terms the résumé must mention to cover it, or to cover it in part
+const COVERED_SHARE: f64 = 0.7;
+const PARTIAL_SHARE: f64 = 0.3;
+
+/// Résumé passages quoted as evidence for one requirement
+const MAX_EVIDENCE: usize = 3;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum RequirementKind {
+    MinimumQualification,
+    Skill,
+    Keyword,
+    PreferredQualification,
+    Responsibility,
+}
+
+impl RequirementKind {
+    /// How much the requirement counts towards the match score
+    pub fn weight(&self) -> u32 {
+        match self {
+            RequirementKind::MinimumQualification => 3,
+            RequirementKind::Skill => 2,
+            RequirementKind::Keyword | RequirementKind::PreferredQualification | RequirementKind::Responsibility => 1,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            RequirementKind::MinimumQualification => "Minimum qualification",
+            RequirementKind::Skill => "Skill",
+            RequirementKind::Keyword => "Keyword",
+            RequirementKind::PreferredQualification => "Preferred qualification",
+            RequirementKind::Responsibility => "Responsibility",
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Coverage {
+    Covered,
+    Partial,
+    Missing,
+}
+
+/// Résumé text that meets a requirement, quoted as it appears in the résumé
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Evidence {
+    /// Where the text is, e.g. "Work experience: Acme, bullet 3"
+    pub source: String,
+    pub text: String,
+    /// The requirement's terms this text mentions
+    pub matched_terms: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct MatchItem {
+    pub kind: RequirementKind,
+    /// Where the requirement is in the job description, e.g. "Minimum qualification 2"
+    pub jd_source: String,
+    /// The requirement as the job description words it
+    pub jd_text: String,
+    pub coverage: Coverage,
+    /// Terms of the requirement the résumé does not mention
+    pub missing_terms: Vec<String>,
+    /// Best match first
+    pub evidence: Vec<Evidence>,
+    pub note: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct MatchReport {
+    /// Weighted share of the requirements covered out of 100, partial ones count half
+    pub score: u32,
+    pub covered: u32,
+    pub partial: u32,
+    pub missing: u32,
+    /// Grouped by kind in the order of `RequirementKind`, then in job description order
+    pub items: Vec<MatchItem>,
+}
+
+/// A piece of the résumé with its terms
+struct Passage {
+    source: String,
+    text: String,
+    /// Listed in the skills or keywords section rather than mentioned in passing
+    is_skill: bool,
+    terms: Vec<String>,
+}
+
+struct Requirement {
+    kind: RequirementKind,
+    jd_source: String,
+    jd_text: String,
+}
+
+/// Lowercase words with stop words dropped and plurals folded, keeping `c++`, `c#` and `node.js` whole
+pub fn terms(text: &str) -> Vec<String> {
+    let mut terms: Vec<String> = vec![];
+    for word in text.to_lowercase().split(|c: char| !(c.is_alphanumeric() || "+#.".contains(c))) {
+        let word = word.trim_matches('.');
+        if word.chars().count() < 2 || STOP_WORDS.contains(&word) {
+            continue;
+        }
+        let term = if word.len() > 4 && word.ends_with("ies") {
+            format!("{}y", &word[..word.len() - 3])
+        } else if word.len() > 3 && word.ends_with('s') && !word.ends_with("ss") && !word.ends_with("us") && !word.contains('.') {
+            word[..word.len() - 1].to_string()
+        } else {
+            word.to_string()
+        };
+        if !terms.contains(&term) {
+            terms.push(term);
+        }
+    }
+    terms
+}
+
+/// The parts that are there, e.g. a degree without a field of study
+fn joined(
+    parts: &[Option<&str>],
+    separator: &str,
+) -> String {
+    parts.iter().flatten().copied().collect::<Vec<_>>().join(separator)
+}
+
+/// The same word in another form, e.g. "design" and "designed" or "mentor" and "mentoring"
+pub fn same_term(
+    a: &str,
+    b: &str,
+) -> bool {
+    let (short, long) = if a.len() <= b.len() { (a, b) } else { (b, a) };
+    short == long || (short.len() >= 4 && long.len() - short.len() <= 3 && long.starts_with(short))
+}
+
+impl Passage {
+    fn mentions(
+        &self,
+        term: &str,
+    ) -> bool {
+        self.terms.iter().any(|t| same_term(t, term))
+    }
+}
+
+fn passages(resume: &ParsedResume) -> Vec<Passage> {
+    let mut passages = vec![];
+    let mut add = |source: String, text: String, is_skill: bool| {
+        let terms = terms(&text);
+        if !terms.is_empty() {
+            passages.push(Passage { source, text, is_skill, terms });
+        }
+    };
+    if let Some(skills) = &resume.skills {
+        for (label, list) in [("Technical skill", &skills.technical), ("Soft skill", &skills.soft_skills), ("Other skill", &skills.other_skills)] {
+            list.iter().for_each(|skill| add(label.to_string(), skill.clone(), true));
+        }
+    }
+    for keyword in resume.keywords.iter().flatten() {
+        add("Keyword".to_string(), keyword.keyword.clone(), true);
+    }
+    if let Some(summary) = &resume.summary {
+        add("Summary".to_string(), summary.clone(), false);
+    }
+    for work in &resume.work_experience {
+        add(format!("Work experience: {}", work.company), format!("{}, {}", work.title, work.company), false);
+        for (i, bullet) in work.responsibilities.iter().enumerate() {
+            add(format!("Work experience: {}, bullet {}", work.company, i + 1), bullet.clone(), false);
+        }
+    }
+    for project in resume.projects.iter().flatten() {
+        add(
+            format!("Project: {}", project.name),
+            joined(&[Some(project.name.as_str()), project.description.as_deref()], ": "),
+            false,
+        );
+        if !project.technologies.is_empty() {
+            add(format!("Project: {}, technologies", project.name), project.technologies.join(", "), false);
+        }
+    }
+    for contribution in resume.open_source_contributions.iter().flatten() {
+        let source = format!("Open source: {}", contribution.project_name);
+        add(source.clone(), joined(&[Some(contribution.project_name.as_str()), contribution.description.as_deref()], ": "), false);
+        if !contribution.technologies.is_empty() {
+            add(format!("{}, technologies", source), contribution.technologies.join(", "), false);
+        }
+    }
+    for education in &resume.education {
+        let text = joined(&[education.degree.as_deref(), education.field_of_study.as_deref(), Some(education.institution.as_str())], ", ");
+        add(format!("Education: {}", education.institution), text, false);
+    }
+    for certification in resume.certifications.iter().flatten() {
+        let text = joined(&[Some(certification.name.as_str()), certification.issuer.as_deref()], ", ");
+        add("Certification".to_string(), text, false);
+    }
+    for achievement in resume.achievements.iter().flatten() {
+        let text = joined(&[Some(achievement.title.as_str()), achievement.details.as_deref()], ": ");
+        add("Achievement".to_string(), text, false);
+    }
+    for volunteer in resume.volunteer_experience.iter().flatten() {
+        let text = joined(&[volunteer.role.as_deref(), volunteer.organization.as_deref(), volunteer.description.as_deref()], ", ");
+        add("Volunteer experience".to_string(), text, false);
+    }
+    for publication in resume.publications.iter().flatten() {
+        let text = joined(&[publication.title.as_deref(), publication.journal_or_conference.as_deref()], ", ");
+        add("Publication".to_string(), text, false);
+    }
+    for blog in resume.blogs.iter().flatten() {
+        let text = joined(&[Some(blog.title.as_str()), blog.summary.as_deref()], ": ");
+        add("Blog".to_string(), text, false);
+    }
+    for authoring in resume.authoring.iter().flatten() {
+        let text = joined(&[Some(authoring.title.as_str()), authoring.description.as_deref()], ": ");
+        add("Authoring".to_string(), text, false);
+    }
+    for patent in resume.patents.iter().flatten() {
+        add("Patent".to_string(), patent.title.clone(), false);
+    }
+    passages
+}
+
+fn requirements(jd: &ParsedJobDescription) -> Vec<Requirement> {
+    let mut requirements = vec![];
+    let mut add = |kind: RequirementKind, jd_source: String, jd_text: &str| {
+        let jd_text = jd_text.trim();
+        let duplicate = requirements.iter().any(|r: &Requirement| r.kind == kind && r.jd_text.eq_ignore_ascii_case(jd_text));
+        if !jd_text.is_empty() && !duplicate {
+            requirements.push(Requirement {
+                kind,
+                jd_source,
+                jd_text: jd_text.to_string(),
+            });
+        }
+    };
+    if let Some(qualifications) = &jd.qualifications {
+        for (i, q) in qualifications.minimum_qualifications.iter().flatten().enumerate() {
+            add(RequirementKind::MinimumQualification, format!("Minimum qualification {}", i + 1), q);
+        }
+        for (i, q) in qualifications.preferred_qualifications.iter().flatten().enumerate() {
+            add(RequirementKind::PreferredQualification, format!("Preferred qualification {}", i + 1), q);
+        }
+    }
+    for (i, r) in jd.responsibilities.iter().flatten().enumerate() {
+        add(RequirementKind::Responsibility, format!("Responsibility {}", i + 1), r);
+    }
+    for (i, keyword) in jd.keywords.iter().flatten().enumerate() {
+        let context = keyword.context.to_lowercase();
+        let kind = if SKILL_CONTEXTS.iter().any(|c| context.contains(c)) {
+            RequirementKind::Skill
+        } else {
+            RequirementKind::Keyword
+        };
+        add(kind, format!("Keyword {} ({})", i + 1, keyword.context), &keyword.keyword);
+    }
+    requirements
+}
+
+/// Picks passages greedily, each one adding the most terms not matched yet
+fn best_evidence(
+    wanted: &[String],
+    passages: &[Passage],
+) -> (Vec<Evidence>, Vec<String>) {
+    let mut evidence = vec![];
+    let mut matched: Vec<String> = vec![];
+    while evidence.len() < MAX_EVIDENCE {
+        let best = passages
+            .iter()
+            .map(|p| (p, wanted.iter().filter(|t| !matched.contains(t) && p.mentions(t)).cloned().collect::<Vec<_>>()))
+            .filter(|(_, new)| !new.is_empty())
+            // the most new terms, the first passage on a tie
+            .fold(None, |best: Option<(&Passage, Vec<String>)>, candidate| match &best {
+                Some((_, most)) if most.len() >= candidate.1.len() => best,
+                _ => Some(candidate),
+            });
+        let (passage, new) = match best {
+            Some(b) => b,
+            None => break,
+        };
+        matched.extend(new);
+        evidence.push(Evidence {
+            source: passage.source.clone(),
+            text: passage.text.clone(),
+            matched_terms: wanted.iter().filter(|t| passage.mentions(t)).cloned().collect(),
+        });
+    }
+    (evidence, matched)
+}
+
+fn match_item(
+    requirement: Requirement,
+    passages: &[Passage],
+) -> Option<MatchItem> {
+    let wanted = terms(&requirement.jd_text);
+    if wanted.is_empty() {
+        return None;
+    }
+    let is_phrase = matches!(requirement.kind, RequirementKind::Skill | RequirementKind::Keyword);
+    let names_all = |p: &&Passage| wanted.iter().all(|t| p.mentions(t));
+    // a skill or keyword is met by one passage that names all of it, a qualification can be met across several
+    let whole = if is_phrase {
+        passages.iter().filter(|p| p.is_skill).find(names_all).or(passages.iter().find(names_all))
+    } else {
+        None
+    };
+    let (evidence, matched) = match whole {
+        Some(p) => (
+            vec![Evidence {
+                source: p.source.clone(),
+                text: p.text.clone(),
+                matched_terms: wanted.clone(),
+            }],
+            wanted.clone(),
+        ),
+        None => best_evidence(&wanted, passages),
+    };
+    let missing_terms: Vec<String> = wanted.iter().filter(|t| !matched.contains(t)).cloned().collect();
+    let share = matched.len() as f64 / wanted.len() as f64;
+    let mut note = None;
+    let coverage = match whole {
+        Some(p) if requirement.kind == RequirementKind::Skill && !p.is_skill => {
+            note = Some("Mentioned in the résumé but not listed under skills".to_string());
+            Coverage::Partial
+        }
+        Some(_) => Coverage::Covered,
+        None if is_phrase && !matched.is_empty() => Coverage::Partial,
+        None if is_phrase => Coverage::Missing,
+        None if share >= COVERED_SHARE => Coverage::Covered,
+        None if share >= PARTIAL_SHARE => Coverage::Partial,
+        None => Coverage::Missing,
+    };
+    Some(MatchItem {
+        kind: requirement.kind,
+        jd_source: requirement.jd_source,
+        jd_text: requirement.jd_text,
+        coverage,
+        missing_terms,
+        evidence: if coverage == Coverage::Missing { vec![] } else { evidence },
+        note,
+    })
+}
+
+impl MatchReport {
+    /// Matches the résumé against the job description's qualifications, responsibilities, skills and keywords.
+    /// The same pair always gives the same report.
+    pub fn between(
+        resume: &ParsedResume,
+        jd: &ParsedJobDescription,
+    ) -> Self {
+        let passages = passages(resume);
+        let mut items: Vec<MatchItem> = requirements(jd).into_iter().filter_map(|r| match_item(r, &passages)).collect();
+        items.sort_by_key(|i| i.kind);
+        let count = |coverage: Coverage| items.iter().filter(|i| i.coverage == coverage).count() as u32;
+        let total: u32 = items.iter().map(|i| i.kind.weight() * 2).sum();
+        let earned: u32 = items
+            .iter()
+            .map(|i| match i.coverage {
+                Coverage::Covered => i.kind.weight() * 2,
+                Coverage::Partial => i.kind.weight(),
+                Coverage::Missing => 0,
+            })
+            .sum();
+        MatchReport {
+            score: (earned * 100 + total / 2).checked_div(total).unwrap_or_default(),
+            covered: count(Coverage::Covered),
+            partial: count(Coverage::Partial),
+            missing: count(Coverage::Missing),
+            items,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::features::resumes::interchange::full_resume;
+
+    fn job_description() -> ParsedJobDescription {
+        serde_json::from_value(serde_json::json!({
+            "qualifications": {
+                "minimum_qualifications": ["Built payment systems in Rust", "Terraform cluster operations"],
+                "preferred_qualifications": ["Mentoring engineers and hiring managers"]
+            },
+            "keywords": [
+                {"keyword": "Go", "context": "technical"},
+                {"keyword": "Postgres", "context": "technical"},
+                {"keyword": "Terraform", "context": "tools"},
+                {"keyword": "fintech", "context": "domain"},
+                {"keyword": "go", "context": "technical"}
+            ]
+        }))
+        .unwrap()
+    }
+
+    fn item<'a>(
+        report: &'a MatchReport,
+        jd_text: &str,
+    ) -> &'a MatchItem {
+        report.items.iter().find(|i| i.jd_text == jd_text).unwrap()
+    }
+
+    #[test]
+    fn terms_drop_stop_words_and_fold_plurals() {
+        assert_eq!(terms("Experience with the payment systems and libraries"), vec!["payment", "system", "library"]);
+        assert_eq!(terms("C++, C# and Node.js."), vec!["c++", "c#", "node.js"]);
+        assert_eq!(terms("Business process status"), vec!["business", "process", "status"]);
+    }
+
+    #[test]
+    fn other_forms_of_a_word_are_the_same_term() {
+        assert!(same_term("design", "designed"));
+        assert!(same_term("mentoring", "mentor"));
+        assert!(!same_term("go", "google"));
+        assert!(!same_term("test", "testament"));
+    }
+
+    #[test]
+    fn requirements_are_scored_by_weight() {
+        let report = MatchReport::between(&full_resume(), &job_description());
+        let coverage: Vec<_> = report.items.iter().map(|i| (i.kind, i.jd_text.as_str(), i.coverage)).collect();
+        assert_eq!(
+            coverage,
+            vec![
+                (RequirementKind::MinimumQualification, "Built payment systems in Rust", Coverage::Covered),
+                (RequirementKind::MinimumQualification, "Terraform cluster operations", Coverage::Missing),
+                (RequirementKind::Skill, "Go", Coverage::Covered),
+                (RequirementKind::Skill, "Postgres", Coverage::Partial),
+                (RequirementKind::Skill, "Terraform", Coverage::Missing),
+                (RequirementKind::Keyword, "fintech", Coverage::Covered),
+                (RequirementKind::PreferredQualification, "Mentoring engineers and hiring managers", Coverage::Partial),
+            ]
+        );
+        assert_eq!((report.covered, report.partial, report.missing), (3, 2, 2));
+        // 15 of 28 weighted points, partial ones count half
+        assert_eq!(report.score, 54);
+    }
+
+    #[test]
+    fn a_qualification_is_met_across_passages() {
+        let report = MatchReport::between(&full_resume(), &job_description());
+        let built = item(&report, "Built payment systems in Rust");
+        assert!(built.missing_terms.is_empty());
+        let sources: Vec<_> = built.evidence.iter().map(|e| e.source.as_str()).collect();
+        assert_eq!(sources, vec!["Summary", "Technical skill", "Work experience: Initech, bullet 1"]);
+        assert_eq!(built.evidence[0].matched_terms, vec!["payment", "system"]);
+    }
+
+    #[test]
+    fn gaps_name_the_missing_terms() {
+        let report = MatchReport::between(&full_resume(), &job_description());
+        let operations = item(&report, "Terraform cluster operations");
+        assert_eq!(operations.missing_terms, vec!["terraform", "cluster", "operation"]);
+        assert!(operations.evidence.is_empty());
+        assert_eq!(item(&report, "Mentoring engineers and hiring managers").missing_terms, vec!["hiring", "manager"]);
+    }
+
+    #[test]
+    fn a_skill_outside_the_skills_section_is_partial() {
+        let report = MatchReport::between(&full_resume(), &job_description());
+        let postgres = item(&report, "Postgres");
+        assert_eq!(postgres.note.as_deref(), Some("Mentioned in the résumé but not listed under skills"));
+        assert_eq!(postgres.evidence[0].source, "Project: ledger, technologies");
+    }
+
+    #[test]
+    fn the_same_pair_gives_the_same_report() {
+        let (resume, jd) = (full_resume(), job_description());
+        let first = serde_json::to_value(MatchReport::between(&resume, &jd)).unwrap();
+        assert_eq!(serde_json::to_value(MatchReport::between(&resume, &jd)).unwrap(), first);
+    }
+
+    #[test]
+    fn a_job_description_without_requirements_scores_nothing() {
+        let jd: ParsedJobDescription = serde_json::from_value(serde_json::json!({})).unwrap();
+        let report = MatchReport::between(&full_resume(), &jd);
+        assert!(report.items.is_empty());
+        assert_eq!(report.score, 0);
+    }
+}
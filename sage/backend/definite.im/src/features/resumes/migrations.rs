@@ -0,0 +1,232 @@
+use mongodb::{
+    bson::{self, doc, Bson, Document},
+    Client,
+};
+
+use crate::shared::ops::{
+    db_ops::Database,
+    migration_ops::{migrate_collection, Migration, MigrationReport, Versioned},
+};
+
+use super::entities::{
+    job::JobEntity,
+    job_description::JobDescriptionEntity,
+    match_report::MatchReportEntity,
+    resume::{ResumeEntity, WorkExperience},
+    resume_diff::WorkExperienceDiff,
+    resume_score::ScoreEntity,
+    resume_version::ResumeVersionEntity,
+    rubric_version::RubricVersionEntity,
+};
+
+fn is_missing(
+    document: &Document,
+    key: &str,
+) -> bool {
+    matches!(document.get(key), None | Some(Bson::Null))
+}
+
+/// `kind` and `parsed_resume.work_experience_diff` predate the first schema version
+fn resume_v0_defaults(document: &mut Document) -> Result<(), String> {
+    if is_missing(document, "kind") {
+        document.insert("kind", "Uploaded");
+    }
+    if let Ok(parsed_resume) = document.get_document_mut("parsed_resume") {
+        if !parsed_resume.contains_key("work_experience_diff") {
+            parsed_resume.insert("work_experience_diff", Bson::Null);
+        }
+    }
+    Ok(())
+}
+
+/// `work_experience_diff` used to hold both whole lists as `added` and `removed`
+fn parsed_resume_v1_structural_diff(document: &mut Document) -> Result<(), String> {
+    let parsed_resume = match document.get_document_mut("parsed_resume") {
+        Ok(p) => p,
+        Err(_) => return Ok(()),
+    };
+    let legacy = match parsed_resume.get_document("work_experience_diff") {
+        Ok(d) if d.contains_key("added") || d.contains_key("removed") => d.clone(),
+        _ => return Ok(()),
+    };
+    let list = |key: &str| -> Result<Vec<WorkExperience>, String> {
+        match legacy.get(key) {
+            Some(value) => bson::from_bson(value.clone()).map_err(|e| format!("bad {}: {}", key, e)),
+            None => Ok(vec![]),
+        }
+    };
+    let work_diff = WorkExperienceDiff::between(&list("removed")?, &list("added")?);
+    let work_diff = bson::to_bson(&work_diff).map_err(|e| format!("cannot store diff: {}", e))?;
+    parsed_resume.insert("work_experience_diff", work_diff);
+    Ok(())
+}
+
+fn job_description_v0_name(document: &mut Document) -> Result<(), String> {
+    if is_missing(document, "name") {
+        let name = document.get_document("parsed_jd").ok().and_then(|p| p.get_str("name_slug").ok()).unwrap_or("not-set").to_string();
+        document.insert("name", name);
+    }
+    Ok(())
+}
+
+/// Early scores only stored the detail, the totals were copied out of it later
+fn score_v0_totals(document: &mut Document) -> Result<(), String> {
+    let detail = document.get_document("detail").map_err(|e| format!("no detail: {}", e))?.clone();
+    if is_missing(document, "score") {
+        document.insert("score", detail.get("overall_score").cloned().unwrap_or(Bson::Int32(0)));
+    }
+    if is_missing(document, "max_score") {
+        document.insert("max_score", detail.get("maximum_possible_score").cloned().unwrap_or(Bson::Int32(0)));
+    }
+    Ok(())
+}
+
+/// `published_at` was stored in seconds, two publishes within a second could not be told apart
+fn rubric_version_v1_published_millis(document: &mut Document) -> Result<(), String> {
+    let seconds = match document.get("published_at") {
+        Some(Bson::Int64(s)) => *s,
+        Some(Bson::Int32(s)) => *s as i64,
+        _ => return Ok(()),
+    };
+    document.insert("published_at", seconds * 1000);
+    Ok(())
+}
+
+// When a stored struct changes, bump its `SCHEMA_VERSION` and add a migration from the previous version here
+pub static RESUME_MIGRATIONS: &[Migration] = &[
+    Migration {
+        from_version: 0,
+        description: "default kind and work_experience_diff",
+        apply: resume_v0_defaults,
+    },
+    Migration {
+        from_version: 1,
+        description: "structural work_experience_diff",
+        apply: parsed_resume_v1_structural_diff,
+    },
+];
+
+pub static RESUME_VERSION_MIGRATIONS: &[Migration] = &[Migration {
+    from_version: 1,
+    description: "structural work_experience_diff",
+    apply: parsed_resume_v1_structural_diff,
+}];
+
+pub static JOB_DESCRIPTION_MIGRATIONS: &[Migration] = &[Migration {
+    from_version: 0,
+    description: "default name from the parsed name slug",
+    apply: job_description_v0_name,
+}];
+
+pub static SCORE_MIGRATIONS: &[Migration] = &[Migration {
+    from_version: 0,
+    description: "copy score totals out of the detail",
+    apply: score_v0_totals,
+}];
+
+// rubric versions were created at version 1
+pub static RUBRIC_VERSION_MIGRATIONS: &[Migration] = &[Migration {
+    from_version: 1,
+    description: "published_at in milliseconds",
+    apply: rubric_version_v1_published_millis,
+}];
+
+// match reports were created at version 1
+pub static MATCH_REPORT_MIGRATIONS: &[Migration] = &[];
+
+// jobs were created at version 1
+pub static JOB_MIGRATIONS: &[Migration] = &[];
+
+/// Indexes the code relies on, for `collection` (or `all`). Created at server and worker startup,
+/// and before the documents of `collection` are migrated.
+pub async fn ensure_indexes(
+    mongoc: &Client,
+    collection: &str,
+) -> Result<(), String> {
+    if collection == "all" || collection == ResumeVersionEntity::COLLECTION {
+        // `append_version` retries when a concurrent save took the next number
+        let versions = Database::get_collection::<Document>(mongoc, ResumeVersionEntity::COLLECTION);
+        Database::ensure_unique_index(&versions, "resume_id_version", doc! {"resume_id": 1, "version": 1}).await?;
+    }
+    if collection == "all" || collection == RubricVersionEntity::COLLECTION {
+        // `post_rubric` retries when a concurrent save took the next number
+        let versions = Database::get_collection::<Document>(mongoc, RubricVersionEntity::COLLECTION);
+        Database::ensure_unique_index(&versions, "version", doc! {"version": 1}).await?;
+        // `RubricVersionEntity::published` sorts on it
+        Database::ensure_index(&versions, "published_at_version", doc! {"published_at": -1, "version": -1}).await?;
+    }
+    Ok(())
+}
+
+async fn migrate_one<T: Versioned>(
+    mongoc: &Client,
+    batch_size: u32,
+    dry_run: bool,
+) -> Result<MigrationReport, String> {
+    let collection = Database::get_collection::<Document>(mongoc, T::COLLECTION);
+    migrate_collection::<T>(collection, batch_size, dry_run).await
+}
+
+/// Batch-migrates `collection` (or `all`), used by `definite migrate`
+pub async fn migrate(
+    mongoc: &Client,
+    collection: &str,
+    batch_size: u32,
+    dry_run: bool,
+) -> Result<Vec<MigrationReport>, String> {
+    if !dry_run {
+        ensure_indexes(mongoc, collection).await?;
+    }
+    let mut reports = vec![];
+    if collection == "all" || collection == ResumeEntity::COLLECTION {
+        reports.push(migrate_one::<ResumeEntity>(mongoc, batch_size, dry_run).await?);
+    }
+    if collection == "all" || collection == ResumeVersionEntity::COLLECTION {
+        reports.push(migrate_one::<ResumeVersionEntity>(mongoc, batch_size, dry_run).await?);
+    }
+    if collection == "all" || collection == JobDescriptionEntity::COLLECTION {
+        reports.push(migrate_one::<JobDescriptionEntity>(mongoc, batch_size, dry_run).await?);
+    }
+    if collection == "all" || collection == ScoreEntity::COLLECTION {
+        reports.push(migrate_one::<ScoreEntity>(mongoc, batch_size, dry_run).await?);
+    }
+    if collection == "all" || collection == RubricVersionEntity::COLLECTION {
+        reports.push(migrate_one::<RubricVersionEntity>(mongoc, batch_size, dry_run).await?);
+    }
+    if collection == "all" || collection == MatchReportEntity::COLLECTION {
+        reports.push(migrate_one::<MatchReportEntity>(mongoc, batch_size, dry_run).await?);
+    }
+    if collection == "all" || collection == JobEntity::COLLECTION {
+        reports.push(migrate_one::<JobEntity>(mongoc, batch_size, dry_run).await?);
+    }
+    if reports.is_empty() {
+        return Err(format!(
+            "Unknown collection {}, expected one of all, {}, {}, {}, {}, {}, {}, {}",
+            collection,
+            ResumeEntity::COLLECTION,
+            ResumeVersionEntity::COLLECTION,
+            JobDescriptionEntity::COLLECTION,
+            ScoreEntity::COLLECTION,
+            RubricVersionEntity::COLLECTION,
+            MatchReportEntity::COLLECTION,
+            JobEntity::COLLECTION
+        ));
+    }
+    Ok(reports)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shared::ops::migration_ops::upgrade;
+
+    #[test]
+    fn rubric_versions_are_published_in_milliseconds() {
+        let mut published = doc! {"version": 3, "published_at": 1_700_000_000_i64, "schema_version": 1};
+        let mut draft = doc! {"version": 4, "published_at": Bson::Null, "schema_version": 1};
+        assert!(upgrade::<RubricVersionEntity>(&mut published).unwrap());
+        assert!(upgrade::<RubricVersionEntity>(&mut draft).unwrap());
+        assert_eq!(published.get_i64("published_at").unwrap(), 1_700_000_000_000);
+        assert_eq!(draft.get("published_at"), Some(&Bson::Null));
+    }
+}
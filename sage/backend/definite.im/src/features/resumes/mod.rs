@@ -1,4 +1,11 @@
 pub mod entities;
+pub mod export;
+pub mod interchange;
+pub mod jobs;
+pub mod matching;
+pub mod migrations;
 pub mod models;
 pub mod routes;
+pub mod rubric;
 pub mod schemas;
+pub mod verification;
@@ -0,0 +1,7 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Deserialize, Debug, Serialize)]
+pub struct AlignmentFormData {
+    pub resume_id: String,
+    pub jd_id: String,
+}
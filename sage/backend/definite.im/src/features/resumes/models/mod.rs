@@ -1,2 +1,4 @@
+pub mod alignment;
 pub mod job_description;
+pub mod matching;
 pub mod resume;
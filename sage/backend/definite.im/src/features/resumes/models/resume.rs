@@ -1,5 +1,7 @@
 use serde::{Deserialize, Serialize};
 
+use crate::shared::ops::extract_ops::DocumentFormat;
+
 #[derive(Deserialize, Debug, Serialize)]
 pub struct ResumeFormData {
     pub resume_text: String,
@@ -9,3 +11,26 @@ pub struct ResumeFormData {
 pub struct ScoreFormData {
     pub resume_id: String,
 }
+
+/// A résumé file read from a multipart upload, not yet checked beyond its size and extension.
+/// `F` is a `DocumentFormat` for text extraction or an `ImportFormat` for structured imports.
+pub struct ResumeUpload<F = DocumentFormat> {
+    pub filename: String,
+    pub content_type: String,
+    pub format: F,
+    pub bytes: Vec<u8>,
+}
+
+#[derive(Deserialize, Debug, Serialize)]
+pub struct ConfirmRewriteFormData {
+    pub pending_id: String,
+}
+
+/// One bullet's decision, `choice` is "accepted", "rejected" or "edited" with the new `text`
+#[derive(Deserialize, Debug, Serialize)]
+pub struct BulletDecisionFormData {
+    pub entry: usize,
+    pub bullet: usize,
+    pub choice: String,
+    pub text: Option<String>,
+}
@@ -1,278 +1,229 @@
-// use actix_web::{web, HttpRequest, Responder};
-// use handlebars::Handlebars;
-// use log::{debug, error};
-// use mongodb::bson::oid::ObjectId;
-// use mongodb::Client;
-// use serde_json::json;
-
-// use crate::features::resumes::entities::resume::ResumeEntity;
-// use crate::features::resumes::schemas::alignment_schema::{AlignmentFormData, ResumeAlignmentSchema};
-// use crate::html_renderer::render_handlebars;
-// use crate::shared::auth::user::UserAuth;
-// use crate::shared::ops::openai::completion_request::{ResponseFormat, ResponseFormatType};
-// use crate::shared::ops::openai::{
-//     completion_request::{Content, ContentType, Message},
-//     post_chat_completion,
-// };
-// use crate::shared::ops::schema_ops;
-
-// pub async fn get_aligned_page(
-//     req: HttpRequest,
-//     mongoc: web::Data<Client>,
-//     handlebars: web::Data<Handlebars<'_>>,
-//     session: actix_session::Session,
-//     slug: web::Path<String>,
-// ) -> impl Responder {
-//     let resume_id = slug.into_inner();
-
-//     let resume_id = match ObjectId::parse_str(resume_id.clone()) {
-//         Ok(r) => r,
-//         Err(e) => {
-//             error!("Failed to parse resume id: {} because: {}", resume_id, e);
-
-//             return render_handlebars(
-//                 req,
-//                 &handlebars,
-//                 "aligned-resume-viewer",
-//                 json!({
-//                     "title": "Aligned Resume",
-//                     "error": true,
-//                     "is_retry": false,
-//                     "is_startover": true
-//                 }),
-//                 session,
-//             )
-//             .await;
-//         }
-//     };
-
-//     let resume_entity = ResumeEntity { _id: resume_id, ..Default::default() };
-
-//     let resume = match resume_entity.find(&mongoc).await {
-//         Some(r) => r,
-//         None => {
-//             error!("Failed to find resume for resume_id: {}", resume_id.to_hex());
-//             return render_handlebars(
-//                 req,
-//                 &handlebars,
-//                 "aligned-resume-viewer",
-//                 json!({
-//                     "title": "Aligned Resume",
-//                     "error": true,
-//                     "is_retry": false,
-//                     "is_startover": true
-//                 }),
-//                 session,
-//             )
-//             .await;
-//         }
-//     };
-
-//     // Parse and preprocess the resume data
-//     // let result = match serde_json::from_str::<ResumeAlignmentSchema>(resume.optimized_resume.unwrap_or_default().as_str()) {
-//     //     Ok(parsed) => parsed,
-//     //     Err(e) => {
-//     //         error!("Failed to parse optimized resume id: {}, because: {}", resume_id.to_hex(), e);
-//     //         return render_handlebars(
-//     //             req,
-//     //             &handlebars,
-//     //             "aligned-resume-viewer",
-//     //             json!({
-//     //                 "title": "Aligned Resume",
-//     //                 "error": true,
-//     //                 "is_retry": true,
-//     //                 "is_startover": false,
-//     //                 "resume_id": resume_id.to_hex(),
-//     //             }),
-//     //             session,
-//     //         )
-//     //         .await;
-//     //     }
-//     // };
-
-//     return render_handlebars(
-//         req,
-//         &handlebars,
-//         "aligned-resume-viewer",
-//         json!({
-//             "title": "Aligned Resume",
-//             "description": "Your optimized resume is ready for download.",
-//             "error": false,
-//             // "result": result,
-//             "resume_id": resume_id.to_hex(),
-//         }),
-//         session,
-//     )
-//     .await;
-// }
-
-// /// Accepts a POST request to optimize a resume.
-// pub async fn post_align(
-//     req: HttpRequest,
-//     mongoc: web::Data<Client>,
-//     handlebars: web::Data<Handlebars<'_>>,
-//     session: actix_session::Session,
-//     form: web::Form<AlignmentFormData>,
-// ) -> impl Responder {
-//     // let resume_id = form.resume_id.clone();
-
-//     // let resume_id = match ObjectId::parse_str(resume_id.clone()) {
-//     //     Ok(r) => r,
-//     //     Err(e) => {
-//     //         error!("Failed to parse resume id: {} because: {}", resume_id, e);
-//     //         return render_handlebars(
-//     //             req,
-//     //             &handlebars,
-//     //             "alignment-complete",
-//     //             json!({
-//     //                 "is_startover": true,
-//     //                 "is_retry": false,
-//     //                 "error": true
-//     //             }),
-//     //             session,
-//     //         )
-//     //         .await;
-//     //     }
-//     // };
-
-//     // let mut resume_entity = ResumeEntity { _id: resume_id, ..Default::default() };
-
-//     // let resume = match resume_entity.find(&mongoc).await {
-//     //     Some(r) => r,
-//     //     None => {
-//     //         error!("Failed to find resume for resume_id: {}", resume_id.to_hex());
-//     //         return render_handlebars(
-//     //             req,
-//     //             &handlebars,
-//     //             "alignment-complete",
-//     //             json!({
-//     //                 "is_startover": true,
-//     //                 "is_retry": false,
-//     //                 "error": true
-//     //             }),
-//     //             session,
-//     //         )
-//     //         .await;
-//     //     }
-//     // };
-
-//     // let messages = vec![Message {
-//     //     role: "user".to_string(),
-//     //     content: vec![Content {
-//     //         content_type: ContentType::Text,
-//     //         text: Some(format!(
-//     //             "I am giving you a resume, job description, and gap analysis. \
-//     //             Rewrite the resume to match the job description by filling in the gaps.\
-//     //             \
-//     //             ### Guidelines for Optimization:\
-//     //             1. **Be creative to address Gaps from `bad_parts`:**\
-//     //             - Use the `bad_parts` field in the provided gap analysis to fill missing or insufficient work experience or skills in the resume.\
-//     //             - For each missing or insufficient item, include a specific new achievement or responsibility that directly addresses the gap.\
-//     //             - Example: If the gap analysis says: \"Lack of experience with C++ and PHP,\" add achievements such as:\
-//     //                 - \"Developed high-performance transaction processing modules using C++, improving latency by 20%.\"\
-//     //                 - \"Implemented server-side scripting in PHP to optimize API response times, reducing load times by 15%.\"\
-//     //             \
-//     //             2. **Enhance Work Experience:**\
-//     //             - Ensure each work experience entry includes specific numbers, percentages, or dollar values to quantify impact.\
-//     //             - Example:\
-//     //                 - Bad: \"Improved scalability by 5%.\"\
-//     //                 - Good: \"Redesigned distributed systems to handle 25% more traffic, improving scalability by 5%.\"\
-//     //             \
-//     //             3. **Refine Content:**\
-//     //             - Replace vague or generic phrases with clear, concise, and action-oriented statements.\
-//     //             - Focus on outcomes (e.g., cost savings, performance improvements) rather than just tasks.\
-//     //             \
-//     //             4. **Structure and Readability:**\
-//     //             - Simplify sentences for clarity and precision.\
-//     //             - Use the past tense for completed roles and present tense for current roles.\
-//     //             \
-//     //             5. **Metrics Integration:**\
-//     //             - Ensure measurable results are included wherever applicable (e.g., percentages, improvements, dollar values).\
-//     //             \
-//     //             6. **Handle Optional Sections:**\
-//     //             - Include all optional sections, using placeholders or defaults if data is unavailable:\
-//     //                 - Example: Awards = [], Publications = [], Projects = [].\
-//     //             \
-//     //             7. **Validation Checklist:**\
-//     //             - Ensure items in the `bad_parts` field from the gap analysis is addressed in the work experience.\
-//     //             - Retain all original work experience entries but reorganize them with the most relevant at the top.\
-//     //             \
-//     //             ### Output Instructions:\
-//     //             - Follow the provided resume schema exactly.\
-//     //             - Use the job description and technical skills as guides for adding content.\
-//     //             - Include measurable outcomes and metrics for all achievements.\
-//     //             - If data is unavailable, use placeholders (e.g., '[n/a]', [], etc.).\
-//     //             \
-//     //             ***Resume***: ==={}=== \
-//     //             ***Job Description***: ==={}=== \
-//     //             ***Gap Analysis***: ==={}===",
-//     //             resume.resume_text,
-//     //             // resume.job_description,
-//     //             // resume.analysis.unwrap()
-//     //         )),
-//     //         image_url: None,
-//     //     }],
-//     // }];
-
-//     // let response_format = ResponseFormat {
-//     //     format_type: ResponseFormatType::JsonSchema,
-//     //     json_schema: Some(json!(schema_ops::to_openai_schema::<ResumeAlignmentSchema>().unwrap())),
-//     // };
-
-//     // let user_auth = UserAuth::from(session.clone());
-
-//     // let response = match post_chat_completion(messages, Some(response_format), Some(user_auth.google_model.unwrap().id)).await {
-//     //     Some(r) => r,
-//     //     None => {
-//     //         error!("Failed to post chat completion for resume_id: {}", resume_id.to_hex());
-//     //         return render_handlebars(
-//     //             req,
-//     //             &handlebars,
-//     //             "alignment-complete",
-//     //             json!({
-//     //                 "resume_id": resume_entity._id.to_hex(),
-//     //                 "error": true,
-//     //                 "is_retry": true,
-//     //                 "is_startover": false
-//     //             }),
-//     //             session,
-//     //         )
-//     //         .await;
-//     //     }
-//     // };
-
-//     // save the response in the database
-//     // resume_entity.optimized_resume = Some(response);
-//     // match resume_entity.update_optimized(&mongoc).await {
-//     //     Some(_) => debug!("Resume optimization saved"),
-//     //     None => {
-//     //         error!("Failed to save resume optimization for resume_id: {}", resume_id.to_hex());
-
-//     //         return render_handlebars(
-//     //             req,
-//     //             &handlebars,
-//     //             "alignment-complete",
-//     //             json!({
-//     //                 "resume_id": resume_entity._id.to_hex(),
-//     //                 "error": true,
-//     //                 "is_retry": false,
-//     //                 "is_startover": true,
-//     //             }),
-//     //             session,
-//     //         )
-//     //         .await;
-//     //     }
-//     // }
-
-//     return render_handlebars(
-//         req,
-//         &handlebars,
-//         "alignment-complete",
-//         json!({
-//             // "resume_id": resume_entity._id.to_hex(),
-//             "error": false,
-//         }),
-//         session,
-//     )
-//     .await;
-// }
+use actix_web::{web, HttpResponse, Responder};
+use handlebars::Handlebars;
+use log::error;
+use mongodb::Client;
+use serde_json::json;
+
+use crate::{
+    features::resumes::{
+        entities::{
+            job_description::JobDescriptionEntity,
+            resume::{ParsedResume, ResumeEntity, ResumeKind, TailoredFrom},
+            resume_diff::{ResumeDiff, WorkExperienceDiff},
+            resume_version::ResumeVersionEntity,
+        },
+        export::{layout, text, ExportTemplate},
+        matching::MatchReport,
+        models::{alignment::AlignmentFormData, resume::ConfirmRewriteFormData},
+        verification::{PendingRewrite, Verification},
+    },
+    html_renderer::{render_error_fragment, render_fragment, render_page, render_page_with_status},
+    shared::{
+        auth::{
+            access::{self, Action, Principal},
+            user::UserAuth,
+        },
+        ops::{cache_ops::Cache, diff_ops::diff_lines, openai::provider::LlmProvider, quota_ops::spend_quota, repository_ops::Repository},
+    },
+};
+
+use super::{matching::dashboard_error, resume::append_version};
+
+/// Saves the tailored résumé with its first version
+async fn save_tailored(
+    handlebars: &Handlebars<'_>,
+    mongoc: &Client,
+    resume_entity: &ResumeEntity,
+    message: String,
+) -> HttpResponse {
+    if resume_entity.create(mongoc).await.is_err() {
+        return render_error_fragment(handlebars, dashboard_error("Error saving the tailored résumé")).await;
+    }
+    // the tailored résumé gets its own history, starting here
+    if let Err(message) = append_version(mongoc, resume_entity, None, ResumeKind::Edited, None).await {
+        error!("Error saving first version of tailored resume {}: {}", resume_entity._id.to_hex(), message);
+    }
+    render_fragment(
+        handlebars,
+        "alignment-complete",
+        json!({
+            "message": message,
+            "navigate_url": format!("/resumes/align/{}", resume_entity._id.to_hex()),
+            "navigate_text": "Review the changes",
+        }),
+    )
+    .await
+}
+
+/// Tailors a résumé to a job description as a new `Edited` résumé, the source stays as it is.
+/// A tailored résumé that says things the source does not waits for confirmation.
+pub async fn post_align(
+    handlebars: web::Data<Handlebars<'_>>,
+    session: actix_session::Session,
+    form: web::Form<AlignmentFormData>,
+    mongoc: web::Data<Client>,
+    cache: web::Data<Cache>,
+    provider: web::Data<dyn LlmProvider>,
+) -> impl Responder {
+    let principal = Principal::from(session);
+    let user_id = principal.user_id.clone();
+    let resume = access::fetch::<ResumeEntity>(&mongoc, &principal, &form.resume_id, Action::Read).await;
+    let jd = access::fetch::<JobDescriptionEntity>(&mongoc, &principal, &form.jd_id, Action::Read).await;
+    let (resume, parsed_resume, parsed_jd) = match (resume, jd) {
+        (Ok(mut r), Ok(j)) => match (r.parsed_resume.take(), j.parsed_jd) {
+            (Some(p), Some(pj)) => (r, p, pj),
+            _ => return render_error_fragment(&handlebars, dashboard_error("The résumé or job description has not been parsed yet")).await,
+        },
+        _ => return render_error_fragment(&handlebars, dashboard_error("Résumé or job description not found")).await,
+    };
+    if let Err(exceeded) = spend_quota(&cache, &mongoc, &user_id).await {
+        return render_fragment(
+            &handlebars,
+            "quota-exceeded",
+            json!({
+                "message": exceeded.message(),
+                "retry_after_secs": exceeded.retry_after_secs(),
+                "navigate_url": "/resumes/dashboard",
+                "navigate_text": "Go to dashboard",
+            }),
+        )
+        .await;
+    }
+    let tailored = match ParsedResume::align(&parsed_resume, &parsed_jd, Some(user_id.clone()), provider.as_ref()).await {
+        Ok(t) => parsed_resume.with_tailoring_of(t),
+        Err(e) => return render_error_fragment(&handlebars, e.to_fragment_context("tailoring your résumé")).await,
+    };
+    let metadata = parsed_jd.metadata.as_ref();
+    let tailored_from = TailoredFrom {
+        resume_id: form.resume_id.clone(),
+        resume_version: ResumeVersionEntity::latest(&mongoc, &form.resume_id, &user_id).await.ok().flatten().map(|v| v.version),
+        jd_id: form.jd_id.clone(),
+        job_title: metadata.and_then(|m| m.job_title.clone()),
+        company: metadata.and_then(|m| m.company.clone()),
+    };
+    let resume_entity = ResumeEntity {
+        user_id: user_id.clone(),
+        resume_text: text::render(&layout(&tailored, ExportTemplate::default())),
+        name: match &parsed_jd.name_slug {
+            Some(jd_slug) => format!("{}-{}", tailored.name_slug, jd_slug),
+            None => tailored.name_slug.clone(),
+        },
+        parsed_resume: Some(tailored.clone()),
+        kind: Some(ResumeKind::Edited),
+        tailored_from: Some(tailored_from),
+        ..Default::default()
+    };
+    let verification = Verification::of_tailored(&resume.resume_text, &tailored);
+    if verification.is_supported() {
+        let before = MatchReport::between(&parsed_resume, &parsed_jd).score;
+        let after = MatchReport::between(&tailored, &parsed_jd).score;
+        let message = format!("Your tailored résumé matches {}% of the job description, the original matched {}%", after, before);
+        return save_tailored(&handlebars, &mongoc, &resume_entity, message).await;
+    }
+    let pending = PendingRewrite { verification, rewrite: resume_entity };
+    let pending_id = match pending.park(&cache, &user_id).await {
+        Some(id) => id,
+        None => return render_error_fragment(&handlebars, dashboard_error("Error keeping the tailored résumé for review")).await,
+    };
+    render_fragment(
+        &handlebars,
+        "rewrite-verification",
+        json!({
+            "message": format!("The tailored résumé says {} things your résumé does not, check them before it is saved", pending.verification.claims.len()),
+            "claims": pending.verification.claims.iter().map(|c| json!({"label": c.kind.label(), "claim": c})).collect::<Vec<_>>(),
+            "work_diff": WorkExperienceDiff::between(&parsed_resume.work_experience, &tailored.work_experience),
+            "confirm_url": "/resumes/align/confirm",
+            "confirm_text": "They are true, save the tailored résumé",
+            "pending_id": pending_id,
+            "navigate_url": "/resumes/dashboard",
+            "navigate_text": "Discard the tailored résumé",
+        }),
+    )
+    .await
+}
+
+/// Saves a tailored résumé the user confirmed
+pub async fn post_align_confirm(
+    handlebars: web::Data<Handlebars<'_>>,
+    session: actix_session::Session,
+    form: web::Form<ConfirmRewriteFormData>,
+    mongoc: web::Data<Client>,
+    cache: web::Data<Cache>,
+) -> impl Responder {
+    let user_id = UserAuth::from(session).google_model.unwrap().id;
+    match PendingRewrite::<ResumeEntity>::find(&cache, &user_id, &form.pending_id).await {
+        Some(pending) if pending.rewrite.user_id == user_id => {
+            // a second click finds the résumé the first one saved
+            if ResumeEntity::find(&mongoc, &user_id, &pending.rewrite._id).await.is_ok() {
+                return render_fragment(
+                    &handlebars,
+                    "alignment-complete",
+                    json!({
+                        "message": "Your tailored résumé is saved",
+                        "navigate_url": format!("/resumes/align/{}", pending.rewrite._id.to_hex()),
+                        "navigate_text": "Review the changes",
+                    }),
+                )
+                .await;
+            }
+            save_tailored(&handlebars, &mongoc, &pending.rewrite, "Your tailored résumé is saved".to_string()).await
+        }
+        _ => render_error_fragment(&handlebars, dashboard_error("The tailored résumé has expired, please tailor it again")).await,
+    }
+}
+
+/// What tailoring changed, against the source résumé as it was when it was tailored
+pub async fn get_aligned_page(
+    req: actix_web::HttpRequest,
+    handlebars: web::Data<Handlebars<'_>>,
+    session: actix_session::Session,
+    resume_id: web::Path<String>,
+    mongoc: web::Data<Client>,
+) -> impl Responder {
+    let principal = Principal::from(session.clone());
+    let user_id = principal.user_id.clone();
+    let (resume, tailored_from) = match access::fetch::<ResumeEntity>(&mongoc, &principal, &resume_id, Action::Read).await {
+        Ok(mut r) => match r.tailored_from.take() {
+            Some(t) => (r, t),
+            None => return render_page(req, &handlebars, "resume-view", dashboard_error("This résumé was not tailored to a job description"), session).await,
+        },
+        Err(e) => return render_page_with_status(req, &handlebars, "resume-view", dashboard_error("Résumé not found"), session, access::status_of(&e)).await,
+    };
+    let source = match tailored_from.resume_version {
+        Some(version) => ResumeVersionEntity::find_version(&mongoc, &tailored_from.resume_id, &user_id, version)
+            .await
+            .ok()
+            .flatten()
+            .and_then(|v| v.parsed_resume),
+        None => access::fetch::<ResumeEntity>(&mongoc, &principal, &tailored_from.resume_id, Action::Read)
+            .await
+            .ok()
+            .and_then(|r| r.parsed_resume),
+    };
+    // both sides rendered the same way, the source's uploaded text would differ on every line
+    let (changes, structure) = match (&source, &resume.parsed_resume) {
+        (Some(s), Some(t)) => (
+            diff_lines(&text::render(&layout(s, ExportTemplate::default())), &text::render(&layout(t, ExportTemplate::default()))),
+            Some(ResumeDiff::between(s, t)),
+        ),
+        _ => (vec![], None),
+    };
+    render_page(
+        req,
+        &handlebars,
+        "aligned-resume",
+        json!({
+            "title": "Tailored Résumé",
+            "description": "What changed when your résumé was tailored to the job description.",
+            "resume_id": resume._id.to_hex(),
+            "resume": resume,
+            "tailored_from": tailored_from,
+            "source_found": source.is_some(),
+            "changes": changes,
+            "structure": structure,
+            "export_templates": ExportTemplate::ALL.iter().map(|t| json!({"value": t, "label": t.label()})).collect::<Vec<_>>(),
+        }),
+        session,
+    )
+    .await
+}
@@ -1,24 +1,36 @@
 use actix_web::{web, Responder};
 use handlebars::Handlebars;
-use log::debug;
-use mongodb::{
-    bson::{doc, oid::ObjectId},
-    Client,
-};
-use serde_json::json;
+use mongodb::Client;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
 
 use crate::{
     features::resumes::{
         entities::{
-            resume::ResumeEntity,
+            resume::{ParsedResume, ResumeEntity, WorkExperience},
+            resume_diff::WorkExperienceDiff,
             resume_score::{ResumeScore, ScoreEntity},
         },
-        models::resume::ScoreFormData,
+        jobs::LlmJob,
+        models::resume::{ConfirmRewriteFormData, ScoreFormData},
+        verification::PendingRewrite,
+    },
+    html_renderer::{render_error_fragment, render_fragment, render_page, render_page_with_status},
+    shared::{
+        auth::{
+            access::{self, Action, Principal},
+            user::UserAuth,
+        },
+        ops::{
+            cache_ops::Cache,
+            queue_ops::Producer,
+            repository_ops::{Query, Repository, RepositoryError},
+        },
     },
-    html_renderer::{render_error_fragment, render_fragment, render_page},
-    shared::auth::user::UserAuth,
 };
 
+use super::stream::queue_job;
+
 pub async fn get_bullet_proof(
     req: actix_web::HttpRequest,
     handlebars: web::Data<Handlebars<'_>>,
@@ -27,10 +39,9 @@ pub async fn get_bullet_proof(
 ) -> impl Responder {
     let user_auth = UserAuth::from(session.clone());
     let user_id = user_auth.google_model.unwrap().id;
-    let resume_entity = ResumeEntity { ..Default::default() };
-    let resumes = match resume_entity.filter(&mongoc, doc! {"user_id": user_id.clone()}).await {
-        Some(r) => r,
-        None => {
+    let resumes = match ResumeEntity::list(&mongoc, &user_id, &Query::new()).await {
+        Ok(r) => r,
+        Err(_) => {
             return render_page(
                 req,
                 &handlebars,
@@ -45,6 +56,23 @@ pub async fn get_bullet_proof(
             .await
         }
     };
+    let resume = match resumes.first() {
+        Some(r) => r,
+        None => {
+            return render_page(
+                req,
+                &handlebars,
+                "resume-view",
+                json!({
+                    "error_message": "Upload a résumé first",
+                    "navigate_url": "/resumes/resume/add",
+                    "navigate_text": "Upload a résumé",
+                }),
+                session,
+            )
+            .await
+        }
+    };
     render_page(
         req,
         &handlebars,
@@ -52,95 +80,170 @@ pub async fn get_bullet_proof(
         json!({
             "title": "Bullet-proof your Résumé",
             "description": "Analyze and rewrite your résumé to make it bullet-proof. Preview before and after.",
-            "resume": resumes.first().unwrap().parsed_resume,
-            "resume_id": resumes.first().unwrap()._id.to_hex(),
+            "resume": resume.parsed_resume,
+            "resume_id": resume._id.to_hex(),
+            "export_url": format!("/resumes/resume/{}/export", resume._id.to_hex()),
         }),
         session,
     )
     .await
 }
 
+/// Queues the score, the worker streams it back over `/resumes/stream/{job_id}`
 pub async fn post_bullet_score(
     req: actix_web::HttpRequest,
     handlebars: web::Data<Handlebars<'_>>,
     session: actix_session::Session,
     form: web::Form<ScoreFormData>,
     mongoc: web::Data<Client>,
+    producer: web::Data<Producer>,
 ) -> impl Responder {
     let id = form.resume_id.clone();
+    let principal = Principal::from(session.clone());
 
-    // retrieve the parsed resume
-    let resume_entity = ResumeEntity {
-        _id: ObjectId::parse_str(id.as_str()).unwrap(),
-        ..Default::default()
-    };
-    let resume = match resume_entity.find(&mongoc).await {
-        Some(r) => r,
-        None => {
-            return render_page(
-                req,
-                &handlebars,
-                "resume-view",
-                json!({
-                    "error_message": "Résumé not found",
-                    "navigate_url": "/resumes/dashboard",
-                    "navigate_text": "Go to dashboard",
-                }),
-                session,
-            )
-            .await
-        }
-    };
-    // read the rubric
-    let rubric_text = include_str!("../../../../assets/data/rubric.json");
-    debug!("Rubric: {}", rubric_text);
-    let user_auth = UserAuth::from(session.clone());
-    let user_id = user_auth.google_model.unwrap().id;
-    // calculate the score by calling openai
-    let score_with_gaps = match ResumeScore::evaluate(serde_json::to_string(&resume.parsed_resume.unwrap()).unwrap(), rubric_text.to_string(), user_id.clone()).await {
-        Some(s) => s,
-        None => {
-            return render_error_fragment(
-                &handlebars,
-                json!({
-                    "error_message": "Error scoring résumé"
-                }),
-            )
-            .await
-        }
+    // the worker reads the résumé again, this only turns away ones that are not there
+    if let Err(e) = access::fetch::<ResumeEntity>(&mongoc, &principal, &id, Action::Read).await {
+        return render_page_with_status(
+            req,
+            &handlebars,
+            "resume-view",
+            json!({
+                "error_message": "Résumé not found",
+                "navigate_url": "/resumes/dashboard",
+                "navigate_text": "Go to dashboard",
+            }),
+            session,
+            access::status_of(&e),
+        )
+        .await;
+    }
+    queue_job(
+        &handlebars,
+        &producer,
+        &mongoc,
+        &principal.user_id,
+        LlmJob::ScoreResume { resume_id: id },
+        "Scoring your résumé against the rubric. You can leave this page, the score is saved when it is done.",
+    )
+    .await
+}
+
+fn optimize_error(message: &str) -> serde_json::Value {
+    json!({
+        "error_message": message,
+        "navigate_url": "/resumes/bullet-proof",
+        "navigate_text": "Back to Bullet-proof",
+    })
+}
+
+/// Rewritten work experience waiting for the user to confirm what the original résumé does not say
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WorkRewrite {
+    pub resume_id: String,
+    pub work_experience: Vec<WorkExperience>,
+}
+
+/// Records the rewrite on the résumé next to the original work experience, returns the `work-optimized` context
+pub async fn save_rewrite(
+    mongoc: &Client,
+    mut resume: ResumeEntity,
+    mut parsed_resume: ParsedResume,
+    rewritten: &[WorkExperience],
+) -> Result<Value, &'static str> {
+    parsed_resume.work_experience_diff = Some(WorkExperienceDiff::between(&parsed_resume.work_experience, rewritten));
+    resume.parsed_resume = Some(parsed_resume.clone());
+    match resume.save(mongoc).await {
+        Ok(()) => {}
+        Err(RepositoryError::Conflict { .. }) => return Err("The résumé changed while it was being rewritten, please rewrite it again"),
+        Err(_) => return Err("Error saving the rewrite"),
+    }
+    let resume_id = resume._id.to_hex();
+    Ok(json!({
+        "resume_id": resume_id,
+        "work_diff": parsed_resume.work_experience_diff,
+        "export_url": format!("/resumes/resume/{}/export", resume_id),
+    }))
+}
+
+/// Queues a rewrite of the work experience bullets, kept next to the originals as `work_experience_diff`.
+/// The résumé itself is unchanged until the rewrite is downloaded or applied, and a rewrite that says
+/// things the résumé does not waits for confirmation.
+pub async fn post_bullet_optimize(
+    handlebars: web::Data<Handlebars<'_>>,
+    session: actix_session::Session,
+    form: web::Form<ScoreFormData>,
+    mongoc: web::Data<Client>,
+    producer: web::Data<Producer>,
+) -> impl Responder {
+    let principal = Principal::from(session);
+    match access::fetch::<ResumeEntity>(&mongoc, &principal, &form.resume_id, Action::Write).await.ok() {
+        Some(r) if r.parsed_resume.is_some() => {}
+        Some(_) => return render_error_fragment(&handlebars, optimize_error("Résumé has not been parsed")).await,
+        None => return render_error_fragment(&handlebars, optimize_error("Résumé not found")).await,
+    }
+    queue_job(
+        &handlebars,
+        &producer,
+        &mongoc,
+        &principal.user_id,
+        LlmJob::RewriteWork { resume_id: form.resume_id.clone() },
+        "Rewriting your work experience. You can leave this page, the rewrite is kept when it is done.",
+    )
+    .await
+}
+
+/// Saves a rewrite the user confirmed, against the résumé as it is now
+pub async fn post_bullet_optimize_confirm(
+    handlebars: web::Data<Handlebars<'_>>,
+    session: actix_session::Session,
+    form: web::Form<ConfirmRewriteFormData>,
+    mongoc: web::Data<Client>,
+    cache: web::Data<Cache>,
+) -> impl Responder {
+    let principal = Principal::from(session);
+    let pending = match PendingRewrite::<WorkRewrite>::find(&cache, &principal.user_id, &form.pending_id).await {
+        Some(p) => p,
+        None => return render_error_fragment(&handlebars, optimize_error("The rewrite has expired, please rewrite again")).await,
     };
+    match access::fetch::<ResumeEntity>(&mongoc, &principal, &pending.rewrite.resume_id, Action::Write).await.ok() {
+        Some(r) => match r.parsed_resume.clone() {
+            Some(p) => match save_rewrite(&mongoc, r, p, &pending.rewrite.work_experience).await {
+                Ok(context) => {
+                    PendingRewrite::<WorkRewrite>::discard(&cache, &principal.user_id, &form.pending_id).await;
+                    render_fragment(&handlebars, "work-optimized", context).await
+                }
+                Err(message) => render_error_fragment(&handlebars, optimize_error(message)).await,
+            },
+            None => render_error_fragment(&handlebars, optimize_error("Résumé has not been parsed")).await,
+        },
+        None => render_error_fragment(&handlebars, optimize_error("Résumé not found")).await,
+    }
+}
 
+/// Stores the score for the résumé and returns its id
+pub async fn save_score(
+    mongoc: &Client,
+    resume_id: String,
+    user_id: String,
+    score_with_gaps: ResumeScore,
+    rubric_version: Option<u32>,
+    rubric_hash: String,
+) -> Result<String, &'static str> {
     let score_entity = ScoreEntity {
-        resume_id: id,
+        resume_id,
         score: score_with_gaps.overall_score,
         max_score: score_with_gaps.maximum_possible_score,
         detail: score_with_gaps,
+        rubric_version,
+        rubric_hash: Some(rubric_hash),
         user_id,
         ..Default::default()
     };
 
-    let score = match score_entity.upsert(&mongoc).await {
-        Some(s) => s,
-        None => {
-            return render_error_fragment(
-                &handlebars,
-                json!({
-                    "error_message": "Error saving score"
-                }),
-            )
-            .await
-        }
-    };
-
-    render_fragment(
-        &handlebars,
-        "scoring-complete",
-        json!({
-            "navigate_url": format!("/resumes/bullet-proof/score/{}", score),
-            "navigate_text": "See your scrore",
-        }),
-    )
-    .await
+    match score_entity.create(mongoc).await {
+        Ok(id) => Ok(id.to_hex()),
+        Err(_) => Err("Error saving score"),
+    }
 }
 
 pub async fn get_bullet_score(
@@ -150,15 +253,11 @@ pub async fn get_bullet_score(
     score_id: web::Path<String>,
     mongoc: web::Data<Client>,
 ) -> impl Responder {
-    let id = score_id.into_inner();
-    let score_entity = ScoreEntity {
-        _id: ObjectId::parse_str(id.as_str()).unwrap(),
-        ..Default::default()
-    };
-    let score = match score_entity.find(&mongoc).await {
-        Some(s) => s,
-        None => {
-            return render_page(
+    let principal = Principal::from(session.clone());
+    let score = match access::fetch::<ScoreEntity>(&mongoc, &principal, &score_id, Action::Read).await {
+        Ok(s) => s,
+        Err(e) => {
+            return render_page_with_status(
                 req,
                 &handlebars,
                 "resume-view",
@@ -168,6 +267,7 @@ pub async fn get_bullet_score(
                     "navigate_text": "Go to dashboard",
                 }),
                 session,
+                access::status_of(&e),
             )
             .await
         }
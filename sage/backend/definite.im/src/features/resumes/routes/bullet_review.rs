@@ -0,0 +1,189 @@
+use actix_web::{http::StatusCode, web, Responder};
+use handlebars::Handlebars;
+use mongodb::Client;
+use serde_json::json;
+
+use crate::{
+    features::resumes::{
+        entities::{
+            resume::{EditedFrom, ResumeEntity, ResumeKind},
+            resume_diff::{BulletDecision, BulletSource, DiffOp, TextDiff, WorkExperienceDiff},
+            resume_version::ResumeVersionEntity,
+        },
+        export::{layout, text, ExportTemplate},
+        models::resume::BulletDecisionFormData,
+    },
+    html_renderer::{render_error_fragment, render_fragment, render_page, render_page_with_status},
+    shared::{
+        auth::access::{self, Action, Principal},
+        ops::repository_ops::{Repository, RepositoryError},
+    },
+};
+
+use super::resume::append_version;
+
+fn review_error(message: &str) -> serde_json::Value {
+    json!({
+        "error_message": message,
+        "navigate_url": "/resumes/bullet-proof",
+        "navigate_text": "Back to Bullet-proof",
+    })
+}
+
+/// The résumé with `resume_id` and its rewrite if `principal` may `action` it, `None` for a bad id, someone else's résumé or no rewrite
+async fn find_rewritten(
+    mongoc: &Client,
+    resume_id: &str,
+    principal: &Principal,
+    action: Action,
+) -> Option<(ResumeEntity, WorkExperienceDiff)> {
+    let resume = access::fetch::<ResumeEntity>(mongoc, principal, resume_id, action).await.ok()?;
+    let work_diff = resume.parsed_resume.as_ref()?.work_experience_diff.clone()?;
+    Some((resume, work_diff))
+}
+
+fn bullet_context(
+    resume_id: &str,
+    entry: usize,
+    bullet: usize,
+    diff: &TextDiff,
+) -> serde_json::Value {
+    json!({
+        "resume_id": resume_id,
+        "entry": entry,
+        "bullet": bullet,
+        "diff": diff,
+        "rewritten": diff.op != DiffOp::Unchanged,
+    })
+}
+
+/// Every rewritten bullet with its original, to accept, reject or edit one at a time
+pub async fn get_bullet_review(
+    req: actix_web::HttpRequest,
+    handlebars: web::Data<Handlebars<'_>>,
+    session: actix_session::Session,
+    resume_id: web::Path<String>,
+    mongoc: web::Data<Client>,
+) -> impl Responder {
+    let principal = Principal::from(session.clone());
+    let (_, work_diff) = match find_rewritten(&mongoc, &resume_id, &principal, Action::Read).await {
+        Some(r) => r,
+        None => {
+            let context = review_error("This résumé has no rewritten work experience to review");
+            return render_page_with_status(req, &handlebars, "resume-view", context, session, StatusCode::NOT_FOUND).await;
+        }
+    };
+    let entries: Vec<_> = work_diff
+        .entries
+        .iter()
+        .enumerate()
+        .map(|(i, entry)| {
+            json!({
+                "entry": entry,
+                "bullets": entry.bullets.iter().enumerate().map(|(j, b)| bullet_context(&resume_id, i, j, b)).collect::<Vec<_>>(),
+            })
+        })
+        .collect();
+    render_page(
+        req,
+        &handlebars,
+        "bullet-review",
+        json!({
+            "title": "Review Rewritten Bullets",
+            "description": "Take the rewrites you like, keep your originals where you prefer them and edit the rest.",
+            "resume_id": resume_id.into_inner(),
+            "entries": entries,
+        }),
+        session,
+    )
+    .await
+}
+
+pub async fn post_bullet_decision(
+    handlebars: web::Data<Handlebars<'_>>,
+    session: actix_session::Session,
+    resume_id: web::Path<String>,
+    form: web::Form<BulletDecisionFormData>,
+    mongoc: web::Data<Client>,
+) -> impl Responder {
+    let principal = Principal::from(session);
+    let decision = match form.choice.as_str() {
+        "accepted" => BulletDecision::Accepted,
+        "rejected" => BulletDecision::Rejected,
+        "edited" => BulletDecision::Edited {
+            text: form.text.clone().unwrap_or_default(),
+        },
+        _ => return render_error_fragment(&handlebars, review_error("Unknown decision")).await,
+    };
+    let (mut resume, mut work_diff) = match find_rewritten(&mongoc, &resume_id, &principal, Action::Write).await {
+        Some(r) => r,
+        None => return render_error_fragment(&handlebars, review_error("This résumé has no rewritten work experience to review")).await,
+    };
+    if !work_diff.decide(form.entry, form.bullet, decision) {
+        return render_error_fragment(&handlebars, review_error("The rewrite has changed, please reload the page")).await;
+    }
+    let diff = work_diff.entries[form.entry].bullets[form.bullet].clone();
+    if let Some(parsed_resume) = resume.parsed_resume.as_mut() {
+        parsed_resume.work_experience_diff = Some(work_diff);
+    }
+    match resume.save(&mongoc).await {
+        Ok(()) => {}
+        // another decision was saved since this one was read
+        Err(RepositoryError::Conflict { .. }) => return render_error_fragment(&handlebars, review_error("The rewrite has changed, please reload the page")).await,
+        Err(_) => return render_error_fragment(&handlebars, review_error("Error saving your decision")).await,
+    }
+    render_fragment(&handlebars, "bullet-decision", bullet_context(&resume_id, form.entry, form.bullet, &diff)).await
+}
+
+/// Saves the reviewed work experience as a new `Edited` résumé, the reviewed one stays as it is
+pub async fn post_bullet_review(
+    handlebars: web::Data<Handlebars<'_>>,
+    session: actix_session::Session,
+    resume_id: web::Path<String>,
+    mongoc: web::Data<Client>,
+) -> impl Responder {
+    let principal = Principal::from(session);
+    let user_id = principal.user_id.clone();
+    let (resume, work_diff) = match find_rewritten(&mongoc, &resume_id, &principal, Action::Read).await {
+        Some(r) => r,
+        None => return render_error_fragment(&handlebars, review_error("This résumé has no rewritten work experience to review")).await,
+    };
+    let mut parsed_resume = match resume.parsed_resume.clone() {
+        Some(p) => p,
+        None => return render_error_fragment(&handlebars, review_error("Résumé has not been parsed")).await,
+    };
+    let (work_experience, bullets) = work_diff.reviewed();
+    let from_ai = bullets.iter().filter(|b| b.source == BulletSource::Ai).count();
+    let from_user = bullets.iter().filter(|b| b.source == BulletSource::User).count();
+    parsed_resume.work_experience = work_experience;
+    parsed_resume.work_experience_diff = None;
+    let edited = ResumeEntity {
+        user_id: user_id.clone(),
+        resume_text: text::render(&layout(&parsed_resume, ExportTemplate::default())),
+        name: format!("{}-reviewed", resume.name),
+        parsed_resume: Some(parsed_resume),
+        kind: Some(ResumeKind::Edited),
+        edited_from: Some(EditedFrom {
+            resume_id: resume_id.to_string(),
+            resume_version: ResumeVersionEntity::latest(&mongoc, &resume_id, &user_id).await.ok().flatten().map(|v| v.version),
+            bullets,
+        }),
+        ..Default::default()
+    };
+    if edited.create(&mongoc).await.is_err() {
+        return render_error_fragment(&handlebars, review_error("Error saving the reviewed résumé")).await;
+    }
+    if let Err(message) = append_version(&mongoc, &edited, None, ResumeKind::Edited, None).await {
+        return render_error_fragment(&handlebars, review_error(message)).await;
+    }
+    render_fragment(
+        &handlebars,
+        "review-saved",
+        json!({
+            "message": format!("Saved as a new résumé with {} bullets from the AI and {} written by you", from_ai, from_user),
+            "navigate_url": format!("/resumes/resume/{}", edited._id.to_hex()),
+            "navigate_text": "See the new résumé",
+        }),
+    )
+    .await
+}
@@ -0,0 +1,72 @@
+use actix_web::{http::header, web, HttpResponse, Responder};
+use log::{error, warn};
+use mongodb::Client;
+use serde::Deserialize;
+
+use crate::{
+    features::resumes::{
+        entities::resume::ResumeEntity,
+        export::{export, ExportError, ExportFormat, ExportTemplate},
+    },
+    shared::auth::access::{self, Action, Principal},
+};
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportVariant {
+    /// The résumé as saved
+    #[default]
+    Current,
+    /// With the work experience proposed by `optimize_work`
+    Rewritten,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ExportQuery {
+    pub format: ExportFormat,
+    #[serde(default)]
+    pub template: ExportTemplate,
+    #[serde(default)]
+    pub variant: ExportVariant,
+}
+
+/// Downloads the résumé as an ATS-friendly file
+pub async fn get_resume_export(
+    session: actix_session::Session,
+    resume_id: web::Path<String>,
+    query: web::Query<ExportQuery>,
+    mongoc: web::Data<Client>,
+) -> impl Responder {
+    let principal = Principal::from(session);
+    let mut parsed_resume = match access::fetch::<ResumeEntity>(&mongoc, &principal, &resume_id, Action::Read).await.ok().and_then(|r| r.parsed_resume) {
+        Some(p) => p,
+        None => return HttpResponse::NotFound().finish(),
+    };
+    if query.variant == ExportVariant::Rewritten {
+        match &parsed_resume.work_experience_diff {
+            Some(work_diff) => parsed_resume.work_experience = work_diff.rewritten(),
+            None => return HttpResponse::NotFound().body("This résumé has no rewritten work experience yet"),
+        }
+    }
+    let bytes = match export(&parsed_resume, query.format, query.template) {
+        Ok(b) => b,
+        Err(e @ ExportError::UnsupportedCharacters { .. }) => {
+            warn!("Resume {} not exported: {}", resume_id, e);
+            return HttpResponse::UnprocessableEntity().content_type("text/plain; charset=utf-8").body(e.to_string());
+        }
+        Err(e) => {
+            error!("Error exporting resume {}: {}", resume_id, e);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+    let filename = if query.format.uses_template() {
+        format!("{}-{}.{}", parsed_resume.name_slug, query.template.label().to_lowercase(), query.format.extension())
+    } else {
+        format!("{}.{}", parsed_resume.name_slug, query.format.extension())
+    }
+    .replace('"', "");
+    HttpResponse::Ok()
+        .content_type(query.format.content_type())
+        .insert_header((header::CONTENT_DISPOSITION, format!("attachment; filename=\"{}\"", filename)))
+        .body(bytes)
+}
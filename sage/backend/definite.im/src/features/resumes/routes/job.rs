@@ -0,0 +1,47 @@
+use actix_web::{web, HttpResponse, Responder};
+use handlebars::Handlebars;
+use mongodb::Client;
+use serde_json::{json, Value};
+
+use crate::{
+    features::resumes::entities::job::JobEntity,
+    html_renderer::render_fragment,
+    shared::auth::access::{self, Action, Principal},
+};
+
+/// What the dashboard and `/resumes/jobs/{job_id}` show of a job, the finished fragment is left out
+pub fn job_view(job: &JobEntity) -> Value {
+    json!({
+        "id": job._id.to_hex(),
+        "kind": job.kind,
+        "label": job.kind.label(),
+        "state": job.state,
+        "finished": job.is_finished(),
+        "progress": job.progress,
+        "message": job.message,
+        "error": job.error,
+        "result": job.result,
+        "attempts": job.attempts,
+        "created": job.created,
+        "updated": job.updated,
+    })
+}
+
+/// A job's status as json, or as the dashboard row for htmx, which polls it until the job is finished
+pub async fn get_job(
+    req: actix_web::HttpRequest,
+    handlebars: web::Data<Handlebars<'_>>,
+    session: actix_session::Session,
+    job_id: web::Path<String>,
+    mongoc: web::Data<Client>,
+) -> impl Responder {
+    let principal = Principal::from(session);
+    let job = match access::fetch::<JobEntity>(&mongoc, &principal, &job_id, Action::Read).await {
+        Ok(j) => j,
+        Err(e) => return HttpResponse::build(access::status_of(&e)).json(json!({"error": "Job not found"})),
+    };
+    match req.headers().contains_key("HX-Request") {
+        true => render_fragment(&handlebars, "job-row", job_view(&job)).await,
+        false => HttpResponse::Ok().json(job_view(&job)),
+    }
+}
@@ -4,14 +4,13 @@ use mongodb::Client;
 use serde_json::json;
 
 use crate::{
-    features::resumes::{
-        entities::job_description::{JobDescriptionEntity, ParsedJobDescription},
-        models::job_description::JobDesriptionFormData,
-    },
-    html_renderer::{render_error_fragment, render_fragment, render_page},
-    shared::auth::user::UserAuth,
+    features::resumes::{jobs::LlmJob, models::job_description::JobDesriptionFormData},
+    html_renderer::render_page,
+    shared::{auth::user::UserAuth, ops::queue_ops::Producer},
 };
 
+use super::stream::queue_job;
+
 pub async fn get_jd_add(
     req: actix_web::HttpRequest,
     handlebars: web::Data<Handlebars<'_>>,
@@ -30,58 +29,24 @@ pub async fn get_jd_add(
     .await
 }
 
+/// Queues the job description, the worker parses and saves it
 pub async fn post_jd(
     handlebars: web::Data<Handlebars<'_>>,
     session: actix_session::Session,
     form: web::Form<JobDesriptionFormData>,
     mongoc: web::Data<Client>,
+    producer: web::Data<Producer>,
 ) -> impl Responder {
-    let jd_text = form.jd_text.clone();
     let user_auth = UserAuth::from(session.clone());
     let user_id = user_auth.google_model.unwrap().id;
-    let parsed_jd = match ParsedJobDescription::parse(&jd_text, Some(user_id.clone())).await {
-        Some(r) => r,
-        None => {
-            return render_error_fragment(
-                &handlebars,
-                json!({
-                    "error_message": "Error parsing job description",
-                    "navigate_url": "/resumes/dashboard",
-                    "navigate_text": "Go to dashboard",
-                }),
-            )
-            .await
-        }
-    };
-    let resume_entity = JobDescriptionEntity {
-        jd_text,
-        user_id,
-        parsed_jd: Some(parsed_jd.clone()),
-        name: parsed_jd.name_slug.unwrap(),
-        ..Default::default()
-    };
-    match resume_entity.create(&mongoc).await {
-        Some(r) => r,
-        None => {
-            return render_error_fragment(
-                &handlebars,
-                json!({
-                    "error_message": "Error saving job description",
-                    "navigate_url": "/resumes/dashboard",
-                    "navigate_text": "Go to dashboard",
-                }),
-            )
-            .await
-        }
-    };
-    render_fragment(
+    let job = LlmJob::ParseJobDescription { jd_text: form.jd_text.clone() };
+    queue_job(
         &handlebars,
-        "jd-added",
-        json!({
-            "message": "Your job description has been added!",
-            "navigate_url": "/resumes/dashboard",
-            "navigate_text": "Go to dashboard",
-        }),
+        &producer,
+        &mongoc,
+        &user_id,
+        job,
+        "Parsing the job description. You can leave this page, it is saved when it is done.",
     )
     .await
 }
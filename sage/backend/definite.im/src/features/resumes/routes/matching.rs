@@ -0,0 +1,116 @@
+use actix_web::{web, Responder};
+use handlebars::Handlebars;
+use mongodb::Client;
+use serde_json::json;
+
+use crate::{
+    features::resumes::{
+        entities::{job_description::JobDescriptionEntity, match_report::MatchReportEntity, resume::ResumeEntity, resume_version::ResumeVersionEntity},
+        matching::{Coverage, MatchReport},
+        models::matching::MatchFormData,
+    },
+    html_renderer::{render_error_fragment, render_fragment, render_page, render_page_with_status},
+    shared::{
+        auth::access::{self, Action, Principal},
+        ops::repository_ops::Repository,
+    },
+};
+
+pub(super) fn dashboard_error(message: &str) -> serde_json::Value {
+    json!({
+        "error_message": message,
+        "navigate_url": "/resumes/dashboard",
+        "navigate_text": "Go to dashboard",
+    })
+}
+
+/// Matches a résumé against a job description and saves the report
+pub async fn post_match(
+    handlebars: web::Data<Handlebars<'_>>,
+    session: actix_session::Session,
+    form: web::Form<MatchFormData>,
+    mongoc: web::Data<Client>,
+) -> impl Responder {
+    let principal = Principal::from(session);
+    let user_id = principal.user_id.clone();
+    let resume = access::fetch::<ResumeEntity>(&mongoc, &principal, &form.resume_id, Action::Read).await;
+    let jd = access::fetch::<JobDescriptionEntity>(&mongoc, &principal, &form.jd_id, Action::Read).await;
+    let (resume, parsed_resume, parsed_jd) = match (resume, jd) {
+        (Ok(mut r), Ok(j)) => match (r.parsed_resume.take(), j.parsed_jd) {
+            (Some(p), Some(pj)) => (r, p, pj),
+            _ => return render_error_fragment(&handlebars, dashboard_error("The résumé or job description has not been parsed yet")).await,
+        },
+        _ => return render_error_fragment(&handlebars, dashboard_error("Résumé or job description not found")).await,
+    };
+    let report = MatchReport::between(&parsed_resume, &parsed_jd);
+    let resume_version = ResumeVersionEntity::latest(&mongoc, &form.resume_id, &user_id).await.ok().flatten().map(|v| v.version);
+    let metadata = parsed_jd.metadata.as_ref();
+    let match_entity = MatchReportEntity {
+        user_id,
+        resume_id: resume._id.to_hex(),
+        jd_id: form.jd_id.clone(),
+        resume_version,
+        resume_name: parsed_resume.name,
+        job_title: metadata.and_then(|m| m.job_title.clone()),
+        company: metadata.and_then(|m| m.company.clone()),
+        report,
+        ..Default::default()
+    };
+    if match_entity.create(&mongoc).await.is_err() {
+        return render_error_fragment(&handlebars, dashboard_error("Error saving the match report")).await;
+    }
+    render_fragment(
+        &handlebars,
+        "match-complete",
+        json!({
+            "message": format!("Your résumé matches {}% of the job description", match_entity.report.score),
+            "navigate_url": format!("/resumes/match/{}", match_entity._id.to_hex()),
+            "navigate_text": "See the match report",
+        }),
+    )
+    .await
+}
+
+pub async fn get_match(
+    req: actix_web::HttpRequest,
+    handlebars: web::Data<Handlebars<'_>>,
+    session: actix_session::Session,
+    match_id: web::Path<String>,
+    mongoc: web::Data<Client>,
+) -> impl Responder {
+    let principal = Principal::from(session.clone());
+    let match_report = match access::fetch::<MatchReportEntity>(&mongoc, &principal, &match_id, Action::Read).await {
+        Ok(m) => m,
+        Err(e) => return render_page_with_status(req, &handlebars, "resume-view", dashboard_error("Match report not found"), session, access::status_of(&e)).await,
+    };
+    let groups: Vec<_> = [
+        (Coverage::Covered, "Covered", "The résumé meets these"),
+        (Coverage::Partial, "Partly covered", "The résumé touches on these, the missing terms show what to add"),
+        (Coverage::Missing, "Missing", "Nothing in the résumé speaks to these"),
+    ]
+    .iter()
+    .map(|(coverage, title, description)| {
+        let items: Vec<_> = match_report
+            .report
+            .items
+            .iter()
+            .filter(|i| i.coverage == *coverage)
+            .map(|i| json!({"kind": i.kind.label(), "item": i}))
+            .collect();
+        json!({"coverage": coverage, "title": title, "description": description, "count": items.len(), "items": items})
+    })
+    .collect();
+    render_page(
+        req,
+        &handlebars,
+        "match-view",
+        json!({
+            "title": "Résumé Match",
+            "description": "How your résumé meets the job description, item by item.",
+            "match": match_report,
+            "groups": groups,
+        }),
+        session,
+    )
+    .await
+}
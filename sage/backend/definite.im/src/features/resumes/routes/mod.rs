@@ -3,15 +3,32 @@ use handlebars::Handlebars;
 use mongodb::{bson::doc, Client};
 use serde_json::json;
 
-use crate::{html_renderer::render_page, shared::auth::user::UserAuth};
+use crate::{
+    html_renderer::render_page,
+    shared::{
+        auth::user::UserAuth,
+        entities::llm_usage::{LlmUsageEntity, PriceTable, UsageSummary},
+        ops::repository_ops::{Query, Repository, SortOrder},
+    },
+};
 
-use super::entities::{job_description::JobDescriptionEntity, resume::ResumeEntity, resume_score::ScoreEntity};
+use super::entities::{job::JobEntity, job_description::JobDescriptionEntity, match_report::MatchReportEntity, resume::ResumeEntity, resume_score::ScoreEntity};
 
 pub mod align;
 pub mod bullet_proof;
+pub mod bullet_review;
+pub mod export;
 pub mod gaps;
+pub mod job;
 pub mod job_description;
+pub mod matching;
 pub mod resume;
+pub mod resume_version;
+pub mod rubric;
+pub mod stream;
+
+/// Jobs listed on the dashboard
+const RECENT_JOBS: u64 = 10;
 
 pub async fn get_resumes_index_page(
     req: actix_web::HttpRequest,
@@ -39,12 +56,9 @@ pub async fn get_resume_dashboard_page(
 ) -> impl Responder {
     let user_auth = UserAuth::from(session.clone());
     let user_id = user_auth.google_model.unwrap().id;
-    let resume_entity = ResumeEntity { ..Default::default() };
-    let jd_entity = JobDescriptionEntity { ..Default::default() };
-    let score_entity = ScoreEntity { ..Default::default() };
-    let resumes = match resume_entity.filter(&mongoc, doc! {"user_id": user_id.clone()}).await {
-        Some(r) => r,
-        None => {
+    let resumes = match ResumeEntity::list(&mongoc, &user_id, &Query::new()).await {
+        Ok(r) => r,
+        Err(_) => {
             return render_page(
                 req,
                 &handlebars,
@@ -59,9 +73,9 @@ pub async fn get_resume_dashboard_page(
             .await
         }
     };
-    let job_descriptions = match jd_entity.filter(&mongoc, doc! {"user_id": user_id.clone()}).await {
-        Some(r) => r,
-        None => {
+    let job_descriptions = match JobDescriptionEntity::list(&mongoc, &user_id, &Query::new()).await {
+        Ok(r) => r,
+        Err(_) => {
             return render_page(
                 req,
                 &handlebars,
@@ -76,9 +90,9 @@ pub async fn get_resume_dashboard_page(
             .await
         }
     };
-    let scores = match score_entity.filter(&mongoc, doc! {"user_id": user_id}).await {
-        Some(r) => r,
-        None => {
+    let scores = match ScoreEntity::list(&mongoc, &user_id, &Query::new()).await {
+        Ok(r) => r,
+        Err(_) => {
             return render_page(
                 req,
                 &handlebars,
@@ -93,6 +107,16 @@ pub async fn get_resume_dashboard_page(
             .await
         }
     };
+    let tailored: Vec<&ResumeEntity> = resumes.iter().filter(|r| r.tailored_from.is_some()).collect();
+    // jobs are informational, the dashboard still renders without them
+    let jobs = JobEntity::recent(&mongoc, &user_id, RECENT_JOBS).await.unwrap_or_default();
+    // match reports can be made again, the dashboard still renders without them
+    let matches = MatchReportEntity::list(&mongoc, &user_id, &Query::new().sort("timestamp", SortOrder::Descending))
+        .await
+        .unwrap_or_default();
+    // usage is informational, the dashboard still renders without it
+    let usage = LlmUsageEntity::summarize(&mongoc, doc! {"user_id": user_id}).await.unwrap_or_default();
+    let (usage_total, usage_by_operation) = UsageSummary::from_groups(&usage);
     render_page(
         req,
         &handlebars,
@@ -100,11 +124,269 @@ pub async fn get_resume_dashboard_page(
         json!({
             "title": "Manage your Résumés",
             "body": "Upload and analyze your résumé, job descriptions, and cover letters.",
+            "usage_total": usage_total,
+            "usage_by_operation": usage_by_operation,
+            "usage_currency": PriceTable::get().currency,
             "resumes": resumes,
+            "tailored": tailored,
             "job_descriptions": job_descriptions,
             "scores": scores,
+            "matches": matches,
+            "jobs": jobs.iter().map(job::job_view).collect::<Vec<_>>(),
         }),
         session,
     )
     .await
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Once;
+
+    use actix_session::SessionExt;
+    use actix_web::{dev::Service, http::StatusCode, test, web, App};
+    use mongodb::bson::Document;
+
+    use super::*;
+    use crate::{
+        configure_handlebars,
+        features::resumes::{
+            entities::{resume::ResumeKind, resume_diff::WorkExperienceDiff, resume_version::ResumeVersionEntity},
+            interchange::full_resume,
+        },
+        shared::{models::google::GoogleUserModel, ops::db_ops::Database},
+    };
+
+    const OWNER: &str = "route-test-alice";
+    const VISITOR: &str = "route-test-mallory";
+
+    /// `UserAuth` reads the web and auth config on every request, the database settings are left to the caller
+    fn configure_env() {
+        static ENV: Once = Once::new();
+        ENV.call_once(|| {
+            for (key, value) in [
+                ("WEB_APP_PORT", "8080"),
+                ("WEB_APP_IP", "127.0.0.1"),
+                ("LOG_LEVEL", "error"),
+                ("GOOGLE_CLIENT_ID", "test"),
+                ("GOOGLE_CLIENT_SECRET", "test"),
+                ("GOOGLE_AUTH_URI", "http://localhost/auth"),
+                ("GOOGLE_TOKEN_URI", "http://localhost/token"),
+                ("GOOGLE_CALLBACK_URI", "http://localhost/callback"),
+                ("JWT_SECRET", "test"),
+            ] {
+                if std::env::var(key).is_err() {
+                    std::env::set_var(key, value);
+                }
+            }
+            // the debug user would stand in for whoever is signed in
+            std::env::set_var("ALLOW_DEBUG", "false");
+        });
+    }
+
+    /// Sends `request` as the signed in `user_id`, the status and the body it got back
+    async fn call_as(
+        user_id: &str,
+        request: test::TestRequest,
+    ) -> (StatusCode, String) {
+        let google_model = GoogleUserModel {
+            id: user_id.to_string(),
+            ..Default::default()
+        };
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(configure_handlebars()))
+                .app_data(web::Data::new(Database::get_client().await))
+                .wrap_fn(move |req, srv| {
+                    req.get_session().insert("google_model", google_model.clone()).unwrap();
+                    srv.call(req)
+                })
+                .route("/resume/{resume_id}", web::get().to(resume::get_resume))
+                .route("/resume/{resume_id}/original", web::get().to(resume::get_resume_original))
+                .route("/resume/{resume_id}/export", web::get().to(export::get_resume_export))
+                .route("/resume/{resume_id}/versions", web::get().to(resume_version::get_resume_versions))
+                .route("/resume/{resume_id}/versions/{version}", web::get().to(resume_version::get_resume_version))
+                .route("/resume/{resume_id}/compare", web::get().to(resume_version::get_resume_compare))
+                .route("/match", web::post().to(matching::post_match))
+                .route("/match/{match_id}", web::get().to(matching::get_match))
+                .route("/bullet-proof/score/{score_id}", web::get().to(bullet_proof::get_bullet_score))
+                .route("/bullet-proof/review/{resume_id}", web::get().to(bullet_review::get_bullet_review))
+                .route("/bullet-proof/review/{resume_id}", web::post().to(bullet_review::post_bullet_review))
+                .route("/jobs/{job_id}", web::get().to(job::get_job)),
+        )
+        .await;
+        let response = test::call_service(&app, request.to_request()).await;
+        let status = response.status();
+        let body = test::read_body(response).await;
+        (status, String::from_utf8_lossy(&body).to_string())
+    }
+
+    async fn status_as(
+        user_id: &str,
+        uri: &str,
+    ) -> StatusCode {
+        call_as(user_id, test::TestRequest::get().uri(uri)).await.0
+    }
+
+    /// `uri`'s status for `VISITOR` and for `OWNER`
+    async fn statuses(uri: &str) -> (StatusCode, StatusCode) {
+        (status_as(VISITOR, uri).await, status_as(OWNER, uri).await)
+    }
+
+    /// Drops what a test stored for `OWNER` in `collection`
+    async fn clean_up(
+        mongoc: &Client,
+        collection: &str,
+    ) {
+        Database::get_collection::<Document>(mongoc, collection).delete_many(doc! {"user_id": OWNER}).await.unwrap();
+    }
+
+    fn assert_only_owner_finds(results: &[(String, (StatusCode, StatusCode))]) {
+        for (uri, (visitor, owner)) in results {
+            assert_eq!(*visitor, StatusCode::NOT_FOUND, "{} of someone else's document must look missing", uri);
+            assert_ne!(*owner, StatusCode::NOT_FOUND, "the owner must still find {}", uri);
+        }
+    }
+
+    fn parsed_resume(owner: &str) -> ResumeEntity {
+        let mut parsed_resume = full_resume();
+        let mut rewritten = parsed_resume.work_experience.clone();
+        rewritten[0].responsibilities[0] = "Rewrote the payments service".to_string();
+        parsed_resume.work_experience_diff = Some(WorkExperienceDiff::between(&parsed_resume.work_experience, &rewritten));
+        ResumeEntity {
+            user_id: owner.to_string(),
+            parsed_resume: Some(parsed_resume),
+            ..Default::default()
+        }
+    }
+
+    #[actix_web::test]
+    #[ignore = "needs MongoDB: set DB_CONNECTION_STRING and DB_NAME"]
+    async fn other_users_resume_is_not_found() {
+        configure_env();
+        let mongoc = Database::get_client().await;
+        let resume = parsed_resume(OWNER);
+        resume.create(&mongoc).await.unwrap();
+        ResumeVersionEntity::next(&resume, None, ResumeKind::Uploaded, None).create(&mongoc).await.unwrap();
+        let id = resume._id.to_hex();
+
+        let mut results = vec![];
+        for uri in [
+            format!("/resume/{}", id),
+            format!("/resume/{}/export?format=txt", id),
+            format!("/resume/{}/versions", id),
+            format!("/resume/{}/versions/1", id),
+            format!("/resume/{}/compare?from=1&to=1", id),
+            format!("/bullet-proof/review/{}", id),
+        ] {
+            let result = statuses(&uri).await;
+            results.push((uri, result));
+        }
+        // there is no uploaded file, so the owner is not found either
+        let original = status_as(VISITOR, &format!("/resume/{}/original", id)).await;
+        let (review, _) = call_as(VISITOR, test::TestRequest::post().uri(&format!("/bullet-proof/review/{}", id))).await;
+        let visitor_resumes = ResumeEntity::count(&mongoc, VISITOR, doc! {}).await.unwrap();
+        clean_up(&mongoc, ResumeEntity::COLLECTION).await;
+        clean_up(&mongoc, ResumeVersionEntity::COLLECTION).await;
+
+        assert_only_owner_finds(&results);
+        assert_eq!(original, StatusCode::NOT_FOUND);
+        assert_eq!(review, StatusCode::OK, "the review answers with an error fragment");
+        assert_eq!(visitor_resumes, 0, "reviewing someone else's résumé must not copy it");
+    }
+
+    #[actix_web::test]
+    #[ignore = "needs MongoDB: set DB_CONNECTION_STRING and DB_NAME"]
+    async fn unparsed_resume_shows_an_error_to_its_owner() {
+        configure_env();
+        let mongoc = Database::get_client().await;
+        let resume = ResumeEntity {
+            user_id: OWNER.to_string(),
+            ..Default::default()
+        };
+        resume.create(&mongoc).await.unwrap();
+
+        let (status, body) = call_as(OWNER, test::TestRequest::get().uri(&format!("/resume/{}", resume._id.to_hex()))).await;
+        clean_up(&mongoc, ResumeEntity::COLLECTION).await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert!(body.contains("Résumé has not been parsed"));
+    }
+
+    #[actix_web::test]
+    #[ignore = "needs MongoDB: set DB_CONNECTION_STRING and DB_NAME"]
+    async fn other_users_job_description_cannot_be_matched() {
+        configure_env();
+        let mongoc = Database::get_client().await;
+        let resume = parsed_resume(VISITOR);
+        let jd = JobDescriptionEntity {
+            user_id: OWNER.to_string(),
+            ..Default::default()
+        };
+        resume.create(&mongoc).await.unwrap();
+        jd.create(&mongoc).await.unwrap();
+
+        let form = [("resume_id", resume._id.to_hex()), ("jd_id", jd._id.to_hex())];
+        let (_, body) = call_as(VISITOR, test::TestRequest::post().uri("/match").set_form(form)).await;
+        let reports = MatchReportEntity::count(&mongoc, VISITOR, doc! {}).await.unwrap();
+        resume.delete(&mongoc).await.unwrap();
+        clean_up(&mongoc, JobDescriptionEntity::COLLECTION).await;
+
+        assert!(body.contains("Résumé or job description not found"));
+        assert_eq!(reports, 0);
+    }
+
+    #[actix_web::test]
+    #[ignore = "needs MongoDB: set DB_CONNECTION_STRING and DB_NAME"]
+    async fn other_users_score_is_not_found() {
+        configure_env();
+        let mongoc = Database::get_client().await;
+        let score = ScoreEntity {
+            user_id: OWNER.to_string(),
+            ..Default::default()
+        };
+        score.create(&mongoc).await.unwrap();
+        let uri = format!("/bullet-proof/score/{}", score._id.to_hex());
+
+        let result = statuses(&uri).await;
+        clean_up(&mongoc, ScoreEntity::COLLECTION).await;
+
+        assert_only_owner_finds(&[(uri, result)]);
+    }
+
+    #[actix_web::test]
+    #[ignore = "needs MongoDB: set DB_CONNECTION_STRING and DB_NAME"]
+    async fn other_users_job_is_not_found() {
+        configure_env();
+        let mongoc = Database::get_client().await;
+        let job = JobEntity {
+            user_id: OWNER.to_string(),
+            ..Default::default()
+        };
+        job.create(&mongoc).await.unwrap();
+        let uri = format!("/jobs/{}", job._id.to_hex());
+
+        let result = statuses(&uri).await;
+        clean_up(&mongoc, JobEntity::COLLECTION).await;
+
+        assert_eq!(result, (StatusCode::NOT_FOUND, StatusCode::OK));
+    }
+
+    #[actix_web::test]
+    #[ignore = "needs MongoDB: set DB_CONNECTION_STRING and DB_NAME"]
+    async fn other_users_match_report_is_not_found() {
+        configure_env();
+        let mongoc = Database::get_client().await;
+        let report = MatchReportEntity {
+            user_id: OWNER.to_string(),
+            ..Default::default()
+        };
+        report.create(&mongoc).await.unwrap();
+        let uri = format!("/match/{}", report._id.to_hex());
+
+        let result = statuses(&uri).await;
+        clean_up(&mongoc, MatchReportEntity::COLLECTION).await;
+
+        assert_eq!(result, (StatusCode::NOT_FOUND, StatusCode::OK));
+    }
+}
@@ -1,18 +1,42 @@
-use actix_web::{web, Responder};
+use actix_multipart::Multipart;
+use actix_web::{http::header, web, HttpResponse, Responder};
+use futures::StreamExt;
 use handlebars::Handlebars;
-use log::debug;
-use mongodb::{bson::oid::ObjectId, Client};
+use log::{debug, error};
+use mongodb::{bson::doc, Client};
 use serde_json::json;
 
 use crate::{
     features::resumes::{
-        entities::resume::{ParsedResume, ResumeEntity},
-        models::resume::ResumeFormData,
+        entities::{
+            resume::{ParsedResume, ResumeEntity, ResumeKind, SourceFile},
+            resume_version::ResumeVersionEntity,
+        },
+        export::{layout, text, ExportTemplate},
+        interchange::{import, ImportError, ImportFormat},
+        jobs::LlmJob,
+        models::resume::{ResumeFormData, ResumeUpload},
+    },
+    html_renderer::{render_error_fragment, render_fragment, render_page, render_page_with_status},
+    shared::{
+        auth::{
+            access::{self, Action, Principal},
+            user::UserAuth,
+        },
+        ops::{
+            db_ops::Database,
+            environ_ops::{Environ, UploadConfig},
+            extract_ops::{extract_text, DocumentFormat, ExtractError},
+            queue_ops::Producer,
+            repository_ops::{Repository, RepositoryError},
+        },
     },
-    html_renderer::{render_error_fragment, render_fragment, render_page},
-    shared::auth::user::UserAuth,
 };
 
+use super::stream::queue_job;
+
+const PARSE_MESSAGE: &str = "Parsing and creating an ATS-friendly version. You can leave this page, the résumé is saved when it is done.";
+
 pub async fn get_resume_add(
     req: actix_web::HttpRequest,
     handlebars: web::Data<Handlebars<'_>>,
@@ -25,99 +49,164 @@ pub async fn get_resume_add(
         json!({
             "title": "Workout for your Résumé",
             "description": "Welcome to Résumés! Align, rewrite, and improve your résumé to get the job you want.",
+            "accepted_extensions": DocumentFormat::ACCEPTED_EXTENSIONS,
+            "import_extensions": ImportFormat::ACCEPTED_EXTENSIONS,
+            "upload_max_mb": Environ::init::<UploadConfig>().upload_max_bytes / 1_000_000,
         }),
         session,
     )
     .await
 }
 
-pub async fn post_resume(
+/// Reads the `resume_file` field, giving up as soon as it grows past `max_bytes`.
+/// `format_of` picks the format from the filename, files it returns `None` for are rejected.
+async fn read_upload<F>(
+    mut payload: Multipart,
+    max_bytes: usize,
+    format_of: fn(&str) -> Option<F>,
+) -> Result<ResumeUpload<F>, ExtractError> {
+    while let Some(field) = payload.next().await {
+        let mut field = field.map_err(|e| ExtractError::Interrupted { message: e.to_string() })?;
+        if field.name() != Some("resume_file") {
+            continue;
+        }
+        let filename = field.content_disposition().and_then(|d| d.get_filename()).unwrap_or_default().to_string();
+        if filename.is_empty() {
+            return Err(ExtractError::NoFile);
+        }
+        let format = format_of(&filename).ok_or(ExtractError::UnsupportedType { filename: filename.clone() })?;
+        let content_type = field.content_type().map(|m| m.to_string()).unwrap_or("application/octet-stream".to_string());
+        let mut bytes = vec![];
+        while let Some(chunk) = field.next().await {
+            let chunk = chunk.map_err(|e| ExtractError::Interrupted { message: e.to_string() })?;
+            if bytes.len() + chunk.len() > max_bytes {
+                return Err(ExtractError::TooLarge { max_bytes });
+            }
+            bytes.extend_from_slice(&chunk);
+        }
+        if bytes.is_empty() {
+            return Err(ExtractError::NoFile);
+        }
+        return Ok(ResumeUpload {
+            filename,
+            content_type,
+            format,
+            bytes,
+        });
+    }
+    Err(ExtractError::NoFile)
+}
+
+/// Takes a résumé file instead of pasted text. The file is checked and its text extracted here,
+/// the original goes to GridFS and the text is parsed like a pasted résumé.
+pub async fn post_resume_upload(
     handlebars: web::Data<Handlebars<'_>>,
     session: actix_session::Session,
-    form: web::Form<ResumeFormData>,
+    payload: Multipart,
     mongoc: web::Data<Client>,
+    producer: web::Data<Producer>,
 ) -> impl Responder {
-    // if the resume exists then replace it
-    let resume_text = form.resume_text.clone();
-    let user_auth = UserAuth::from(session.clone());
-    let user_id = user_auth.google_model.unwrap().id;
-    let parsed_resume = match ParsedResume::parse(&resume_text, Some(user_id.clone())).await {
-        Some(r) => r,
-        None => {
-            return render_error_fragment(
-                &handlebars,
-                json!({
-                    "error_message": "Error parsing résumé",
-                    "navigate_url": "/resumes/dashboard",
-                    "navigate_text": "Go to dashboard",
-                }),
-            )
-            .await
+    let user_id = UserAuth::from(session.clone()).google_model.unwrap().id;
+    let upload_config: UploadConfig = Environ::init();
+    let error = |message: String| {
+        json!({
+            "error_message": message,
+            "navigate_url": "/resumes/resume/add",
+            "navigate_text": "Try again",
+        })
+    };
+    let upload = match read_upload(payload, upload_config.upload_max_bytes, DocumentFormat::from_filename).await {
+        Ok(u) => u,
+        Err(e) => {
+            debug!("Rejected resume upload: {:?}", e);
+            return render_error_fragment(&handlebars, error(e.to_string())).await;
         }
     };
-    debug!("Parsed resume");
-
-    let mut resume_entity = ResumeEntity {
+    let ResumeUpload {
+        filename,
+        content_type,
+        format,
+        bytes,
+    } = upload;
+    let (bytes, extracted) = match web::block(move || {
+        let extracted = extract_text(format, &bytes);
+        (bytes, extracted)
+    })
+    .await
+    {
+        Ok(r) => r,
+        Err(e) => {
+            error!("Error extracting text from {}: {}", filename, e);
+            return render_error_fragment(&handlebars, error("Error reading the file".to_string())).await;
+        }
+    };
+    let resume_text = match extracted {
+        Ok(t) => t,
+        Err(e) => {
+            debug!("Could not extract text from {}: {:?}", filename, e);
+            return render_error_fragment(&handlebars, error(e.to_string())).await;
+        }
+    };
+    let metadata = doc! {"user_id": user_id.clone(), "content_type": content_type.clone()};
+    let file_id = match Database::upload_file(&mongoc, &upload_config.upload_bucket, &filename, &bytes, metadata).await {
+        Some(id) => id,
+        None => return render_error_fragment(&handlebars, error("Error saving the file".to_string())).await,
+    };
+    let job = LlmJob::ParseResume {
         resume_text,
-        user_id,
-        parsed_resume: Some(parsed_resume.clone()),
-        name: parsed_resume.name_slug,
-        ..Default::default()
+        source_file: Some(SourceFile {
+            file_id,
+            filename,
+            content_type,
+            format,
+            size: bytes.len() as u64,
+        }),
     };
+    queue_job(&handlebars, &producer, &mongoc, &user_id, job, PARSE_MESSAGE).await
+}
 
-    match resume_entity.find_by(&mongoc).await {
-        Some(r) => {
-            resume_entity._id = r._id;
-            match resume_entity.delete(&mongoc).await {
-                Some(_) => r,
-                None => {
-                    return render_error_fragment(
-                        &handlebars,
-                        json!({
-                            "error_message": "Error deleting résumé",
-                            "navigate_url": "/resumes/dashboard",
-                            "navigate_text": "Go to dashboard",
-                        }),
-                    )
-                    .await
-                }
-            }
-        }
-        None => {
-            return render_error_fragment(
-                &handlebars,
-                json!({
-                    "error_message": "Error finding résumé",
-                    "navigate_url": "/resumes/dashboard",
-                    "navigate_text": "Go to dashboard",
-                }),
-            )
-            .await
+/// Takes a JSON Resume or Europass file. It is converted directly, the model is not asked to parse it.
+pub async fn post_resume_import(
+    handlebars: web::Data<Handlebars<'_>>,
+    session: actix_session::Session,
+    payload: Multipart,
+    mongoc: web::Data<Client>,
+) -> impl Responder {
+    let user_id = UserAuth::from(session.clone()).google_model.unwrap().id;
+    let upload_config: UploadConfig = Environ::init();
+    let error = |message: String| {
+        json!({
+            "error_message": message,
+            "navigate_url": "/resumes/resume/add",
+            "navigate_text": "Try again",
+        })
+    };
+    let upload = match read_upload(payload, upload_config.upload_max_bytes, ImportFormat::from_filename).await {
+        Ok(u) => u,
+        Err(ExtractError::UnsupportedType { filename }) => return render_error_fragment(&handlebars, error(ImportError::UnsupportedType { filename }.to_string())).await,
+        Err(e) => {
+            debug!("Rejected resume import: {:?}", e);
+            return render_error_fragment(&handlebars, error(e.to_string())).await;
         }
     };
-
-    let resume_id = match resume_entity.create(&mongoc).await {
-        Some(r) => r,
-        None => {
-            return render_error_fragment(
-                &handlebars,
-                json!({
-                    "error_message": "Error saving résumé",
-                    "navigate_url": "/resumes/dashboard",
-                    "navigate_text": "Go to dashboard",
-                }),
-            )
-            .await
+    let parsed_resume = match import(upload.format, &upload.bytes) {
+        Ok(r) => r,
+        Err(e) => {
+            debug!("Could not import {}: {:?}", upload.filename, e);
+            return render_error_fragment(&handlebars, error(e.to_string())).await;
         }
     };
-
-    debug!("Saved resume");
-
+    // the plain text export stands in for the pasted text, versions are compared on it
+    let resume_text = text::render(&layout(&parsed_resume, ExportTemplate::default()));
+    let resume_id = match save_resume(&mongoc, resume_text, user_id, parsed_resume, None).await {
+        Ok(id) => id,
+        Err(message) => return render_error_fragment(&handlebars, error(message.to_string())).await,
+    };
     render_fragment(
         &handlebars,
         "resume-added",
         json!({
-            "message": "Your résumé has been added!",
+            "message": format!("Your {} has been imported!", upload.format.label()),
             "navigate_url": "/resumes/dashboard",
             "navigate_text": "Go to dashboard",
             "ats_friendly_url": format!("/resumes/resume/{}", resume_id),
@@ -126,6 +215,117 @@ pub async fn post_resume(
     .await
 }
 
+/// The file a résumé was uploaded as
+pub async fn get_resume_original(
+    session: actix_session::Session,
+    resume_id: web::Path<String>,
+    mongoc: web::Data<Client>,
+) -> impl Responder {
+    let principal = Principal::from(session);
+    let source_file = match access::fetch::<ResumeEntity>(&mongoc, &principal, &resume_id, Action::Read).await.ok().and_then(|r| r.source_file) {
+        Some(f) => f,
+        None => return HttpResponse::NotFound().finish(),
+    };
+    let upload_config: UploadConfig = Environ::init();
+    match Database::download_file(&mongoc, &upload_config.upload_bucket, &source_file.file_id).await {
+        Some(bytes) => HttpResponse::Ok()
+            .content_type(source_file.content_type)
+            .insert_header((header::CONTENT_DISPOSITION, format!("attachment; filename=\"{}\"", source_file.filename.replace('"', ""))))
+            .body(bytes),
+        None => HttpResponse::NotFound().finish(),
+    }
+}
+
+pub async fn post_resume(
+    handlebars: web::Data<Handlebars<'_>>,
+    session: actix_session::Session,
+    form: web::Form<ResumeFormData>,
+    mongoc: web::Data<Client>,
+    producer: web::Data<Producer>,
+) -> impl Responder {
+    let user_auth = UserAuth::from(session.clone());
+    let user_id = user_auth.google_model.unwrap().id;
+    let job = LlmJob::ParseResume {
+        resume_text: form.resume_text.clone(),
+        source_file: None,
+    };
+    queue_job(&handlebars, &producer, &mongoc, &user_id, job, PARSE_MESSAGE).await
+}
+
+/// How often `append_version` reads the latest version again after a concurrent save took its number
+const APPEND_VERSION_ATTEMPTS: usize = 5;
+
+/// Appends `resume` to its history. Résumés saved before versioning get `previous` recorded as version 1 first.
+/// Version numbers are unique per résumé, a concurrent save that took the next number first is retried on top of it.
+pub async fn append_version(
+    mongoc: &Client,
+    resume: &ResumeEntity,
+    previous: Option<&ResumeEntity>,
+    source: ResumeKind,
+    restored_from: Option<u32>,
+) -> Result<ResumeVersionEntity, &'static str> {
+    let resume_id = resume._id.to_hex();
+    for _ in 0..APPEND_VERSION_ATTEMPTS {
+        let mut parent = match ResumeVersionEntity::latest(mongoc, &resume_id, &resume.user_id).await {
+            Ok(p) => p,
+            Err(e) => {
+                error!("Error finding the latest version of résumé {}: {}", resume_id, e);
+                return Err("Error saving résumé history");
+            }
+        };
+        if let (None, Some(previous)) = (&parent, previous) {
+            let first = ResumeVersionEntity::next(previous, None, previous.kind.clone().unwrap_or(ResumeKind::Uploaded), None);
+            match first.create(mongoc).await {
+                Ok(_) => parent = Some(first),
+                Err(RepositoryError::Conflict { .. }) => continue,
+                Err(e) => {
+                    error!("Error saving the first version of résumé {}: {}", resume_id, e);
+                    return Err("Error saving résumé history");
+                }
+            }
+        }
+        let version = ResumeVersionEntity::next(resume, parent.as_ref(), source.clone(), restored_from);
+        match version.create(mongoc).await {
+            Ok(_) => return Ok(version),
+            Err(RepositoryError::Conflict { .. }) => debug!("Version {} of résumé {} was taken, trying again", version.version, resume_id),
+            Err(e) => {
+                error!("Error saving version {} of résumé {}: {}", version.version, resume_id, e);
+                return Err("Error saving résumé history");
+            }
+        }
+    }
+    error!("Gave up appending a version to résumé {} after {} attempts", resume_id, APPEND_VERSION_ATTEMPTS);
+    Err("Error saving résumé history")
+}
+
+/// Makes the parsed résumé the user's current one and returns its id.
+/// The id stays the same across uploads so scores keep pointing at it, earlier uploads stay in `resume_versions`.
+pub async fn save_resume(
+    mongoc: &Client,
+    resume_text: String,
+    user_id: String,
+    parsed_resume: ParsedResume,
+    source_file: Option<SourceFile>,
+) -> Result<String, &'static str> {
+    let mut resume_entity = ResumeEntity {
+        resume_text,
+        user_id,
+        parsed_resume: Some(parsed_resume.clone()),
+        source_file,
+        name: parsed_resume.name_slug,
+        ..Default::default()
+    };
+
+    let previous = match resume_entity.upsert(mongoc, doc! {"kind": &resume_entity.kind}).await {
+        Ok(p) => p,
+        Err(RepositoryError::Conflict { .. }) => return Err("Your résumé was changed while this one was saved, please upload it again"),
+        Err(_) => return Err("Error saving résumé"),
+    };
+    append_version(mongoc, &resume_entity, previous.as_ref(), ResumeKind::Uploaded, None).await?;
+    debug!("Saved resume");
+    Ok(resume_entity._id.to_hex())
+}
+
 pub async fn get_resume(
     req: actix_web::HttpRequest,
     handlebars: web::Data<Handlebars<'_>>,
@@ -134,19 +334,34 @@ pub async fn get_resume(
     mongoc: web::Data<Client>,
 ) -> impl Responder {
     let id = resume_id.into_inner();
-    let resume_entity = ResumeEntity {
-        _id: ObjectId::parse_str(id.as_str()).unwrap(),
-        ..Default::default()
+    let principal = Principal::from(session.clone());
+    let resume = match access::fetch::<ResumeEntity>(&mongoc, &principal, &id, Action::Read).await {
+        Ok(r) => r,
+        Err(e) => {
+            return render_page_with_status(
+                req,
+                &handlebars,
+                "resume-view",
+                json!({
+                    "error_message": "Résumé not found",
+                    "navigate_url": "/resumes/dashboard",
+                    "navigate_text": "Go to dashboard",
+                }),
+                session,
+                access::status_of(&e),
+            )
+            .await
+        }
     };
-    let resume = match resume_entity.find(&mongoc).await {
-        Some(r) => r,
+    let parsed_resume = match resume.parsed_resume {
+        Some(p) => p,
         None => {
             return render_page(
                 req,
                 &handlebars,
                 "resume-view",
                 json!({
-                    "error_message": "Résumé not found",
+                    "error_message": "Résumé has not been parsed",
                     "navigate_url": "/resumes/dashboard",
                     "navigate_text": "Go to dashboard",
                 }),
@@ -162,8 +377,12 @@ pub async fn get_resume(
         json!({
             "title": "Workout for your Résumé",
             "description": "Welcome to Résumés! Align, rewrite, and improve your résumé to get the job you want.",
-            "resume": resume.parsed_resume.unwrap(),
+            "resume": parsed_resume,
             "resume_id": id,
+            "source_file": resume.source_file,
+            "tailored_from": resume.tailored_from,
+            "edited_from": resume.edited_from,
+            "export_templates": ExportTemplate::ALL.iter().map(|t| json!({"value": t, "label": t.label()})).collect::<Vec<_>>(),
         }),
         session,
     )
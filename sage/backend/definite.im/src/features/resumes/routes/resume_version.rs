@@ -0,0 +1,216 @@
+use actix_web::{http::StatusCode, web, HttpResponse, Responder};
+use handlebars::Handlebars;
+use mongodb::Client;
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::{
+    features::resumes::entities::{
+        resume::{ResumeEntity, ResumeKind},
+        resume_diff::ResumeDiff,
+        resume_version::ResumeVersionEntity,
+    },
+    html_renderer::{render_error_fragment, render_fragment, render_page, render_page_with_status},
+    shared::{
+        auth::access::{self, Action, Principal},
+        ops::{
+            diff_ops::diff_lines,
+            repository_ops::{Repository, RepositoryError},
+        },
+    },
+};
+
+use super::resume::append_version;
+
+#[derive(Debug, Deserialize)]
+pub struct CompareQuery {
+    pub from: u32,
+    pub to: u32,
+}
+
+/// The résumé with `resume_id` if `principal` may `action` it, `None` for a bad id or someone else's résumé
+async fn find_owned_resume(
+    mongoc: &Client,
+    resume_id: &str,
+    principal: &Principal,
+    action: Action,
+) -> Option<ResumeEntity> {
+    access::fetch::<ResumeEntity>(mongoc, principal, resume_id, action).await.ok()
+}
+
+async fn not_found(
+    req: actix_web::HttpRequest,
+    handlebars: &Handlebars<'_>,
+    session: actix_session::Session,
+    message: &str,
+) -> HttpResponse {
+    render_page_with_status(
+        req,
+        handlebars,
+        "resume-view",
+        json!({
+            "error_message": message,
+            "navigate_url": "/resumes/dashboard",
+            "navigate_text": "Go to dashboard",
+        }),
+        session,
+        StatusCode::NOT_FOUND,
+    )
+    .await
+}
+
+pub async fn get_resume_versions(
+    req: actix_web::HttpRequest,
+    handlebars: web::Data<Handlebars<'_>>,
+    session: actix_session::Session,
+    resume_id: web::Path<String>,
+    mongoc: web::Data<Client>,
+) -> impl Responder {
+    let principal = Principal::from(session.clone());
+    let user_id = principal.user_id.clone();
+    let resume = match find_owned_resume(&mongoc, &resume_id, &principal, Action::Read).await {
+        Some(r) => r,
+        None => return not_found(req, &handlebars, session, "Résumé not found").await,
+    };
+    let versions = match ResumeVersionEntity::history(&mongoc, &resume_id, &user_id).await {
+        Ok(v) => v,
+        Err(_) => return not_found(req, &handlebars, session, "Error fetching résumé history").await,
+    };
+    let latest = versions.first().map(|v| v.version);
+    render_page(
+        req,
+        &handlebars,
+        "resume-versions",
+        json!({
+            "title": "Résumé History",
+            "description": "Every version of your résumé. View, compare or restore any of them.",
+            "resume_id": resume_id.into_inner(),
+            "name": resume.parsed_resume.map(|p| p.name).unwrap_or(resume.name),
+            "versions": versions,
+            "latest": latest,
+        }),
+        session,
+    )
+    .await
+}
+
+pub async fn get_resume_version(
+    req: actix_web::HttpRequest,
+    handlebars: web::Data<Handlebars<'_>>,
+    session: actix_session::Session,
+    path: web::Path<(String, u32)>,
+    mongoc: web::Data<Client>,
+) -> impl Responder {
+    let (resume_id, version) = path.into_inner();
+    let user_id = Principal::from(session.clone()).user_id;
+    let resume_version = match ResumeVersionEntity::find_version(&mongoc, &resume_id, &user_id, version).await {
+        Ok(Some(v)) => v,
+        _ => return not_found(req, &handlebars, session, "Résumé version not found").await,
+    };
+    render_page(
+        req,
+        &handlebars,
+        "resume-view",
+        json!({
+            "title": format!("Résumé version {}", version),
+            "description": "An earlier version of your résumé.",
+            "resume": resume_version.parsed_resume,
+            "resume_id": resume_id,
+            "version": resume_version,
+        }),
+        session,
+    )
+    .await
+}
+
+/// Copies an earlier version into a new one, so restoring never loses history either
+pub async fn post_restore_resume_version(
+    handlebars: web::Data<Handlebars<'_>>,
+    session: actix_session::Session,
+    path: web::Path<(String, u32)>,
+    mongoc: web::Data<Client>,
+) -> impl Responder {
+    let (resume_id, version) = path.into_inner();
+    let principal = Principal::from(session.clone());
+    let user_id = principal.user_id.clone();
+    let error = |message: &str| {
+        json!({
+            "error_message": message,
+            "navigate_url": format!("/resumes/resume/{}/versions", resume_id),
+            "navigate_text": "Back to history",
+        })
+    };
+    let (resume, restored) = match (
+        find_owned_resume(&mongoc, &resume_id, &principal, Action::Write).await,
+        ResumeVersionEntity::find_version(&mongoc, &resume_id, &user_id, version).await,
+    ) {
+        (Some(r), Ok(Some(v))) => (r, v),
+        _ => return render_error_fragment(&handlebars, error("Résumé version not found")).await,
+    };
+    let mut resume_entity = ResumeEntity {
+        resume_text: restored.resume_text,
+        parsed_resume: restored.parsed_resume,
+        source_file: restored.source_file,
+        name: restored.name,
+        ..resume.clone()
+    };
+    match resume_entity.save(&mongoc).await {
+        Ok(()) => {}
+        Err(RepositoryError::Conflict { .. }) => return render_error_fragment(&handlebars, error("The résumé changed while it was being restored, please try again")).await,
+        Err(_) => return render_error_fragment(&handlebars, error("Error restoring résumé")).await,
+    }
+    let new_version = match append_version(&mongoc, &resume_entity, Some(&resume), ResumeKind::Edited, Some(version)).await {
+        Ok(v) => v,
+        Err(message) => return render_error_fragment(&handlebars, error(message)).await,
+    };
+    render_fragment(
+        &handlebars,
+        "version-restored",
+        json!({
+            "message": format!("Version {} restored as version {}", version, new_version.version),
+            "navigate_url": format!("/resumes/resume/{}/versions", resume_id),
+            "navigate_text": "Back to history",
+            "resume_url": format!("/resumes/resume/{}", resume_id),
+        }),
+    )
+    .await
+}
+
+pub async fn get_resume_compare(
+    req: actix_web::HttpRequest,
+    handlebars: web::Data<Handlebars<'_>>,
+    session: actix_session::Session,
+    resume_id: web::Path<String>,
+    query: web::Query<CompareQuery>,
+    mongoc: web::Data<Client>,
+) -> impl Responder {
+    let user_id = Principal::from(session.clone()).user_id;
+    let (from, to) = match (
+        ResumeVersionEntity::find_version(&mongoc, &resume_id, &user_id, query.from).await,
+        ResumeVersionEntity::find_version(&mongoc, &resume_id, &user_id, query.to).await,
+    ) {
+        (Ok(Some(f)), Ok(Some(t))) => (f, t),
+        _ => return not_found(req, &handlebars, session, "Résumé version not found").await,
+    };
+    let changes = diff_lines(&from.resume_text, &to.resume_text);
+    let structure = match (&from.parsed_resume, &to.parsed_resume) {
+        (Some(f), Some(t)) => Some(ResumeDiff::between(f, t)),
+        _ => None,
+    };
+    render_page(
+        req,
+        &handlebars,
+        "resume-compare",
+        json!({
+            "title": format!("Compare versions {} and {}", from.version, to.version),
+            "description": "What changed between the older version and the newer one.",
+            "resume_id": resume_id.into_inner(),
+            "from": from,
+            "to": to,
+            "changes": changes,
+            "structure": structure,
+        }),
+        session,
+    )
+    .await
+}
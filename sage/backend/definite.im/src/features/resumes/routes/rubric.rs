@@ -0,0 +1,220 @@
+use actix_web::{web, Responder};
+use handlebars::Handlebars;
+use log::debug;
+use mongodb::{bson::doc, Client};
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::{
+    features::resumes::{
+        entities::rubric_version::{RubricVersionEntity, RUBRIC_OWNER},
+        rubric::{Rubric, RubricStore},
+    },
+    html_renderer::{render_error_fragment, render_fragment, render_page},
+    shared::{
+        auth::user::UserAuth,
+        ops::repository_ops::{Query, Repository, RepositoryError, SortOrder},
+    },
+};
+
+#[derive(Debug, Deserialize)]
+pub struct RubricForm {
+    /// The whole rubric as json, in the layout of `assets/data/rubric.json`
+    pub rubric: String,
+    pub parent_version: Option<u32>,
+    pub note: Option<String>,
+}
+
+/// The rubric in the form, or every reason it cannot score résumés
+fn check(json: &str) -> Result<Rubric, Vec<String>> {
+    let rubric: Rubric = serde_json::from_str(json).map_err(|e| vec![format!("Not a valid rubric: {}", e)])?;
+    let problems = rubric.problems();
+    if !problems.is_empty() {
+        return Err(problems);
+    }
+    Ok(rubric)
+}
+
+fn to_pretty_json(rubric: &Rubric) -> String {
+    serde_json::to_string_pretty(rubric).unwrap_or_default()
+}
+
+pub async fn get_rubric_versions(
+    req: actix_web::HttpRequest,
+    handlebars: web::Data<Handlebars<'_>>,
+    session: actix_session::Session,
+    mongoc: web::Data<Client>,
+) -> impl Responder {
+    let versions = RubricVersionEntity::list(&mongoc, RUBRIC_OWNER, &Query::new().sort("version", SortOrder::Descending)).await.ok();
+    let published = versions
+        .as_ref()
+        .and_then(|v| v.iter().filter(|v| v.published_at.is_some()).max_by_key(|v| (v.published_at, v.version)))
+        .map(|v| v.version);
+    render_page(
+        req,
+        &handlebars,
+        "rubric-versions",
+        json!({
+            "title": "Scoring Rubrics",
+            "description": "Edit, validate and publish the weights résumés are scored with.",
+            "error_message": versions.is_none().then_some("Error fetching rubric versions"),
+            "versions": versions.unwrap_or_default(),
+            "published": published,
+        }),
+        session,
+    )
+    .await
+}
+
+/// The editor, starting from the rubric in use
+pub async fn get_rubric_new(
+    req: actix_web::HttpRequest,
+    handlebars: web::Data<Handlebars<'_>>,
+    session: actix_session::Session,
+    mongoc: web::Data<Client>,
+    rubric_store: web::Data<RubricStore>,
+) -> impl Responder {
+    let (rubric, version) = rubric_store.active(&mongoc).await;
+    render_page(
+        req,
+        &handlebars,
+        "rubric-edit",
+        json!({
+            "title": "New Rubric Version",
+            "description": "Starts from the rubric that scores résumés today.",
+            "rubric_json": to_pretty_json(&rubric),
+            "parent_version": version,
+        }),
+        session,
+    )
+    .await
+}
+
+/// The editor, starting from a saved version that can also be published from here
+pub async fn get_rubric_version(
+    req: actix_web::HttpRequest,
+    handlebars: web::Data<Handlebars<'_>>,
+    session: actix_session::Session,
+    version: web::Path<u32>,
+    mongoc: web::Data<Client>,
+) -> impl Responder {
+    let rubric_version = match RubricVersionEntity::find_one(&mongoc, RUBRIC_OWNER, doc! {"version": *version as i64}).await {
+        Ok(Some(v)) => v,
+        _ => {
+            return render_page(
+                req,
+                &handlebars,
+                "rubric-versions",
+                json!({
+                    "error_message": "Rubric version not found",
+                    "versions": [],
+                }),
+                session,
+            )
+            .await
+        }
+    };
+    render_page(
+        req,
+        &handlebars,
+        "rubric-edit",
+        json!({
+            "title": format!("Rubric Version {}", rubric_version.version),
+            "description": "Saving an edit adds a new version, this one stays as it is.",
+            "rubric_json": to_pretty_json(&rubric_version.rubric),
+            "parent_version": rubric_version.version,
+            "version": rubric_version,
+        }),
+        session,
+    )
+    .await
+}
+
+pub async fn post_rubric_validate(
+    handlebars: web::Data<Handlebars<'_>>,
+    form: web::Form<RubricForm>,
+) -> impl Responder {
+    match check(&form.rubric) {
+        Ok(_) => render_fragment(&handlebars, "rubric-validated", json!({"valid": true})).await,
+        Err(problems) => render_fragment(&handlebars, "rubric-validated", json!({"problems": problems})).await,
+    }
+}
+
+/// How often `post_rubric` reads the latest version again after a concurrent save took its number
+const SAVE_ATTEMPTS: usize = 5;
+
+/// Saves the edit as a draft with the next version number
+pub async fn post_rubric(
+    handlebars: web::Data<Handlebars<'_>>,
+    session: actix_session::Session,
+    form: web::Form<RubricForm>,
+    mongoc: web::Data<Client>,
+) -> impl Responder {
+    let form = form.into_inner();
+    let rubric = match check(&form.rubric) {
+        Ok(r) => r,
+        Err(problems) => return render_fragment(&handlebars, "rubric-validated", json!({"problems": problems})).await,
+    };
+    let author = UserAuth::from(session).google_model.unwrap().email;
+    let note = form.note.map(|n| n.trim().to_string()).filter(|n| !n.is_empty());
+    let mut saved = None;
+    for _ in 0..SAVE_ATTEMPTS {
+        let latest = match RubricVersionEntity::latest(&mongoc).await {
+            Ok(l) => l,
+            Err(_) => break,
+        };
+        let rubric_version = RubricVersionEntity::next(rubric.clone(), latest.as_ref(), form.parent_version, note.clone(), author.clone());
+        match rubric_version.create(&mongoc).await {
+            Ok(_) => {
+                saved = Some(rubric_version);
+                break;
+            }
+            Err(RepositoryError::Conflict { .. }) => debug!("Rubric version {} was taken, trying again", rubric_version.version),
+            Err(_) => break,
+        }
+    }
+    let rubric_version = match saved {
+        Some(v) => v,
+        None => return render_error_fragment(&handlebars, json!({"error_message": "Error saving the rubric"})).await,
+    };
+    render_fragment(
+        &handlebars,
+        "rubric-saved",
+        json!({
+            "message": format!("Saved as draft version {}", rubric_version.version),
+            "version": rubric_version.version,
+        }),
+    )
+    .await
+}
+
+/// Makes the version score new résumés, earlier scores keep the version they were scored with
+pub async fn post_rubric_publish(
+    handlebars: web::Data<Handlebars<'_>>,
+    version: web::Path<u32>,
+    mongoc: web::Data<Client>,
+) -> impl Responder {
+    let mut rubric_version = match RubricVersionEntity::find_one(&mongoc, RUBRIC_OWNER, doc! {"version": *version as i64}).await {
+        Ok(Some(v)) => v,
+        _ => return render_error_fragment(&handlebars, json!({"error_message": "Rubric version not found"})).await,
+    };
+    // the résumé fields may have changed since it was saved
+    let problems = rubric_version.rubric.problems();
+    if !problems.is_empty() {
+        return render_fragment(&handlebars, "rubric-validated", json!({"problems": problems})).await;
+    }
+    match rubric_version.publish(&mongoc).await {
+        Ok(()) => {
+            render_fragment(
+                &handlebars,
+                "rubric-saved",
+                json!({
+                    "message": format!("Version {} now scores new résumés", rubric_version.version),
+                    "version": rubric_version.version,
+                }),
+            )
+            .await
+        }
+        Err(_) => render_error_fragment(&handlebars, json!({"error_message": "Error publishing the rubric"})).await,
+    }
+}
@@ -0,0 +1,182 @@
+use std::time::{Duration, Instant};
+
+use actix_web::{web, web::Bytes, HttpResponse, Responder};
+use futures::{
+    channel::mpsc::{unbounded, UnboundedSender},
+    StreamExt,
+};
+use handlebars::Handlebars;
+use log::{debug, warn};
+use mongodb::Client;
+use serde_json::{json, Value};
+
+use crate::{
+    features::resumes::{
+        entities::job::JobEntity,
+        jobs::{enqueue, LlmJob},
+    },
+    html_renderer::{render_error_fragment, render_fragment, render_fragment_html},
+    shared::{
+        auth::access::{self, Action, Principal},
+        ops::{
+            queue_ops::{PartialStream, Partials, Producer},
+            stream_ops::sse_event,
+        },
+    },
+};
+
+/// How often the stream looks at the job's record
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// A stream is closed after this long, the job keeps running and its result is saved
+const STREAM_LIMIT: Duration = Duration::from_secs(600);
+
+type EventSender = UnboundedSender<Result<Bytes, actix_web::Error>>;
+
+fn send(
+    tx: &EventSender,
+    event: &str,
+    data: &str,
+) -> bool {
+    // the browser went away, the worker still finishes and saves the work
+    if tx.unbounded_send(Ok(sse_event(event, data))).is_err() {
+        debug!("Event stream closed, dropping {} event", event);
+        return false;
+    }
+    true
+}
+
+fn send_error(
+    tx: &EventSender,
+    handlebars: &Handlebars<'_>,
+    context: Value,
+) {
+    send(tx, "complete", &render_fragment_html(handlebars, "error-fragment", context));
+}
+
+fn dashboard_error(message: &str) -> Value {
+    json!({
+        "error_message": message,
+        "navigate_url": "/resumes/dashboard",
+        "navigate_text": "Go to dashboard",
+    })
+}
+
+/// Queues `job` and answers with the progress fragment that streams its status
+pub async fn queue_job(
+    handlebars: &Handlebars<'_>,
+    producer: &Producer,
+    mongoc: &Client,
+    user_id: &str,
+    job: LlmJob,
+    message: &str,
+) -> HttpResponse {
+    match enqueue(producer, mongoc, user_id, job).await {
+        Some(job_id) => {
+            render_fragment(
+                handlebars,
+                "stream-progress",
+                json!({
+                    "message": message,
+                    "stream_id": job_id,
+                    "stream_url": format!("/resumes/stream/{}", job_id),
+                }),
+            )
+            .await
+        }
+        None => render_error_fragment(handlebars, dashboard_error("Error queueing your request, please try again")).await,
+    }
+}
+
+/// The next partial output of the job, never for a job whose partial output could not be subscribed to
+async fn next_partial(partials: &mut Option<PartialStream>) -> Option<String> {
+    match partials {
+        Some(p) => p.next().await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Sends the job's progress as it changes and its partial output as it arrives, until it is finished
+async fn follow(
+    job_id: String,
+    principal: Principal,
+    tx: EventSender,
+    handlebars: web::Data<Handlebars<'static>>,
+    mongoc: web::Data<Client>,
+    partials: web::Data<Partials>,
+) {
+    let started = Instant::now();
+    let mut last_message = String::new();
+    let mut partial_stream = None;
+    loop {
+        // the browser went away between events, stop polling for it
+        if tx.is_closed() {
+            debug!("Event stream for job {} closed, no longer following it", job_id);
+            return;
+        }
+        let job = match access::fetch::<JobEntity>(&mongoc, &principal, &job_id, Action::Read).await {
+            Ok(j) => j,
+            Err(_) => return send_error(&tx, &handlebars, dashboard_error("This request was not found, please submit it again")),
+        };
+        if job.is_finished() {
+            send(&tx, "complete", &job.html.unwrap_or_default());
+            return;
+        }
+        // subscribed once the job is known to be the principal's, output published before that is not shown
+        if partial_stream.is_none() {
+            partial_stream = match partials.subscribe(&job_id).await {
+                Ok(p) => Some(p),
+                Err(e) => {
+                    warn!("Error subscribing to partial output of job {}, following its progress only: {:?}", job_id, e);
+                    None
+                }
+            };
+        }
+        if job.message != last_message {
+            if !send(&tx, "progress", &json!({"message": job.message, "progress": job.progress}).to_string()) {
+                return;
+            }
+            last_message = job.message;
+        }
+        if started.elapsed() >= STREAM_LIMIT {
+            return send_error(&tx, &handlebars, dashboard_error("This is taking longer than usual, the result will be on your dashboard"));
+        }
+        let next_poll = actix_web::rt::time::sleep(POLL_INTERVAL);
+        tokio::pin!(next_poll);
+        loop {
+            tokio::select! {
+                _ = &mut next_poll => break,
+                partial = next_partial(&mut partial_stream) => match partial {
+                    Some(p) => {
+                        if !send(&tx, "partial", &p) {
+                            return;
+                        }
+                    }
+                    None => {
+                        debug!("Partial output of job {} ended, following its progress only", job_id);
+                        partial_stream = None;
+                    }
+                },
+            }
+        }
+    }
+}
+
+/// Server-sent events for a job queued by `post_resume` / `post_jd` / `post_bullet_score` / `post_bullet_optimize`:
+/// `progress` (status text), `partial` (the completion as it streams in) and a final `complete` with the fragment html
+pub async fn get_stream(
+    handlebars: web::Data<Handlebars<'static>>,
+    session: actix_session::Session,
+    stream_id: web::Path<String>,
+    mongoc: web::Data<Client>,
+    partials: web::Data<Partials>,
+) -> impl Responder {
+    let principal = Principal::from(session);
+    let (tx, rx) = unbounded();
+    actix_web::rt::spawn(follow(stream_id.into_inner(), principal, tx, handlebars, mongoc, partials));
+    HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header(("Cache-Control", "no-cache"))
+        .insert_header(("X-Accel-Buffering", "no"))
+        .streaming(rx)
+}
@@ -0,0 +1,399 @@
+use std::{
+    collections::BTreeMap,
+    fmt,
+    sync::{Arc, RwLock},
+    time::SystemTime,
+};
+
+use log::{error, info};
+use mongodb::Client;
+use schemars::schema_for;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use sha2::{Digest, Sha256};
+
+use super::entities::{
+    resume::{ParsedResume, Persona},
+    rubric_version::RubricVersionEntity,
+};
+
+/// Points for a top-level `ParsedResume` field, or points per key for an object such as `contact`
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(untagged)]
+pub enum Weight {
+    Points(i32),
+    Keys(BTreeMap<String, i32>),
+}
+
+/// The weights for one persona, as laid out in `assets/data/rubric.json`
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct PersonaRubric {
+    pub required_fields: BTreeMap<String, Weight>,
+    pub optional_fields: BTreeMap<String, Weight>,
+    /// Extra points per field of an entry, e.g. `education.gpa`, on top of the field's own weight
+    #[serde(default)]
+    pub subfield_details: BTreeMap<String, BTreeMap<String, i32>>,
+}
+
+/// `assets/data/rubric.json`: the persona rubrics and which of them scores each `Persona`
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Rubric {
+    /// Rubric key for every `Persona` variant, the keys are the variant names
+    pub personas: BTreeMap<Persona, String>,
+    pub rubrics: BTreeMap<String, PersonaRubric>,
+}
+
+#[derive(Debug)]
+pub enum RubricError {
+    Read {
+        path: String,
+        message: String,
+    },
+    Parse {
+        path: String,
+        message: String,
+    },
+    /// Every problem found, so a bad edit can be fixed in one go
+    Invalid {
+        path: String,
+        problems: Vec<String>,
+    },
+}
+
+impl fmt::Display for RubricError {
+    fn fmt(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+    ) -> fmt::Result {
+        match self {
+            RubricError::Read { path, message } => write!(f, "Error reading rubric {}: {}", path, message),
+            RubricError::Parse { path, message } => write!(f, "Error parsing rubric {}: {}", path, message),
+            RubricError::Invalid { path, problems } => write!(f, "Invalid rubric {}: {}", path, problems.join("; ")),
+        }
+    }
+}
+
+impl Rubric {
+    /// Reads and validates the rubric file
+    pub fn load(path: &str) -> Result<Self, RubricError> {
+        let json = std::fs::read_to_string(path).map_err(|e| RubricError::Read {
+            path: path.to_string(),
+            message: e.to_string(),
+        })?;
+        let rubric: Rubric = serde_json::from_str(&json).map_err(|e| RubricError::Parse {
+            path: path.to_string(),
+            message: e.to_string(),
+        })?;
+        let problems = rubric.problems();
+        if !problems.is_empty() {
+            return Err(RubricError::Invalid { path: path.to_string(), problems });
+        }
+        Ok(rubric)
+    }
+
+    /// Sha256 of the rubric's json, which names the rubric file's content on a score the way a version number
+    /// names a saved one. The maps are ordered, so equal rubrics hash the same.
+    pub fn content_hash(&self) -> String {
+        let json = serde_json::to_string(self).unwrap_or_default();
+        format!("{:x}", Sha256::digest(json.as_bytes()))
+    }
+
+    /// The persona's rubric. Validation guarantees every persona has one.
+    pub fn for_persona(
+        &self,
+        persona: &Persona,
+    ) -> Option<&PersonaRubric> {
+        self.personas.get(persona).and_then(|key| self.rubrics.get(key))
+    }
+
+    /// Checks the mapping covers every persona, weights are positive and every field is on `ParsedResume`
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = vec![];
+        for persona in Persona::ALL {
+            match self.personas.get(&persona) {
+                None => problems.push(format!("persona {:?} has no rubric", persona)),
+                Some(key) if !self.rubrics.contains_key(key) => problems.push(format!("persona {:?} maps to {}, which is not defined", persona, key)),
+                Some(_) => {}
+            }
+        }
+        let schema = serde_json::to_value(schema_for!(ParsedResume)).unwrap_or_default();
+        let definitions = schema.get("definitions").and_then(Value::as_object).cloned().unwrap_or_default();
+        let resume_fields = schema.get("properties").and_then(Value::as_object).cloned().unwrap_or_default();
+        for (key, rubric) in &self.rubrics {
+            let mut positive = |name: String, points: i32| {
+                if points <= 0 {
+                    problems.push(format!("{}: {} must weigh more than 0, not {}", key, name, points));
+                }
+            };
+            for (field, weight) in rubric.required_fields.iter().chain(&rubric.optional_fields) {
+                match weight {
+                    Weight::Points(points) => positive(field.clone(), *points),
+                    Weight::Keys(keys) => keys.iter().for_each(|(k, points)| positive(format!("{}.{}", field, k), *points)),
+                }
+            }
+            for (field, subfields) in &rubric.subfield_details {
+                subfields.iter().for_each(|(subfield, points)| positive(format!("{}.{}", field, subfield), *points));
+            }
+
+            for field in rubric.required_fields.keys().filter(|f| rubric.optional_fields.contains_key(*f)) {
+                problems.push(format!("{}: {} is both required and optional", key, field));
+            }
+            for (field, weight) in rubric.required_fields.iter().chain(&rubric.optional_fields) {
+                let field_schema = match resume_fields.get(field) {
+                    Some(s) => s,
+                    None => {
+                        problems.push(format!("{}: {} is not a résumé field", key, field));
+                        continue;
+                    }
+                };
+                if let Weight::Keys(keys) = weight {
+                    let properties = entry_properties(field_schema, &definitions);
+                    for k in keys.keys().filter(|k| !properties.is_some_and(|p| p.contains_key(*k))) {
+                        problems.push(format!("{}: {}.{} is not a résumé field", key, field, k));
+                    }
+                }
+            }
+            for (field, subfields) in &rubric.subfield_details {
+                if !rubric.required_fields.contains_key(field) && !rubric.optional_fields.contains_key(field) {
+                    problems.push(format!("{}: subfieldDetails.{} is not a required or optional field", key, field));
+                }
+                let properties = resume_fields.get(field).and_then(|s| entry_properties(s, &definitions));
+                for subfield in subfields.keys().filter(|s| !properties.is_some_and(|p| p.contains_key(*s))) {
+                    problems.push(format!("{}: {}.{} is not a résumé field", key, field, subfield));
+                }
+            }
+        }
+        problems
+    }
+}
+
+/// The properties of an object field, or of the entries of a list field, following `$ref`s and `Option`s
+fn entry_properties<'a>(
+    schema: &'a Value,
+    definitions: &'a Map<String, Value>,
+) -> Option<&'a Map<String, Value>> {
+    if let Some(properties) = schema.get("properties").and_then(Value::as_object) {
+        return Some(properties);
+    }
+    if let Some(reference) = schema.get("$ref").and_then(Value::as_str) {
+        let name = reference.strip_prefix("#/definitions/")?;
+        return entry_properties(definitions.get(name)?, definitions);
+    }
+    if let Some(items) = schema.get("items") {
+        return entry_properties(items, definitions);
+    }
+    ["anyOf", "allOf", "oneOf"]
+        .iter()
+        .filter_map(|k| schema.get(*k).and_then(Value::as_array))
+        .flatten()
+        .find_map(|s| entry_properties(s, definitions))
+}
+
+struct Loaded {
+    rubric: Arc<Rubric>,
+    /// Modification time of the file last read, valid or not
+    modified: Option<SystemTime>,
+}
+
+/// The rubric in use, re-read when the file changes on disk. An edit that does not
+/// validate is logged and the last good rubric keeps scoring.
+pub struct RubricStore {
+    path: String,
+    loaded: RwLock<Loaded>,
+}
+
+fn modified(path: &str) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+impl RubricStore {
+    pub fn load(path: &str) -> Result<Self, RubricError> {
+        let modified = modified(path);
+        let rubric = Rubric::load(path)?;
+        Ok(RubricStore {
+            path: path.to_string(),
+            loaded: RwLock::new(Loaded { rubric: Arc::new(rubric), modified }),
+        })
+    }
+
+    /// The current rubric, reloaded first if the file changed since it was last read
+    pub fn current(&self) -> Arc<Rubric> {
+        let modified = modified(&self.path);
+        {
+            let loaded = self.loaded.read().unwrap_or_else(|e| e.into_inner());
+            if modified.is_none() || loaded.modified == modified {
+                return loaded.rubric.clone();
+            }
+        }
+        let mut loaded = self.loaded.write().unwrap_or_else(|e| e.into_inner());
+        if loaded.modified != modified {
+            loaded.modified = modified;
+            match Rubric::load(&self.path) {
+                Ok(rubric) => {
+                    info!("Reloaded rubric {}", self.path);
+                    loaded.rubric = Arc::new(rubric);
+                }
+                Err(e) => error!("{}, still scoring with the previous rubric", e),
+            }
+        }
+        loaded.rubric.clone()
+    }
+
+    /// The rubric new scores use and its version: the latest published version,
+    /// or the rubric file until an admin publishes one
+    pub async fn active(
+        &self,
+        mongoc: &Client,
+    ) -> (Arc<Rubric>, Option<u32>) {
+        match RubricVersionEntity::published(mongoc).await {
+            Ok(Some(published)) => (Arc::new(published.rubric), Some(published.version)),
+            Ok(None) => (self.current(), None),
+            Err(e) => {
+                error!("{}, scoring with the rubric file", e);
+                (self.current(), None)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs::File,
+        time::{Duration, SystemTime},
+    };
+
+    use super::*;
+
+    const BUNDLED: &str = include_str!("../../../assets/data/rubric.json");
+
+    fn bundled() -> Rubric {
+        serde_json::from_str(BUNDLED).unwrap()
+    }
+
+    fn software_engineer(rubric: &mut Rubric) -> &mut PersonaRubric {
+        rubric.rubrics.get_mut("software_engineer").unwrap()
+    }
+
+    /// A rubric file of its own, removed when the test is done
+    struct TempRubric(std::path::PathBuf);
+
+    impl TempRubric {
+        fn new(json: &str) -> Self {
+            let path = std::env::temp_dir().join(format!("rubric-{}.json", mongodb::bson::oid::ObjectId::new().to_hex()));
+            std::fs::write(&path, json).unwrap();
+            TempRubric(path)
+        }
+
+        fn path(&self) -> &str {
+            self.0.to_str().unwrap()
+        }
+
+        /// Rewrites the file with a later modification time, so it is seen as changed even within the clock's resolution
+        fn rewrite(
+            &self,
+            json: &str,
+        ) {
+            std::fs::write(&self.0, json).unwrap();
+            File::options().write(true).open(&self.0).unwrap().set_modified(SystemTime::now() + Duration::from_secs(60)).unwrap();
+        }
+    }
+
+    impl Drop for TempRubric {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.0);
+        }
+    }
+
+    #[test]
+    fn the_bundled_rubric_has_no_problems() {
+        assert_eq!(bundled().problems(), Vec::<String>::new());
+    }
+
+    #[test]
+    fn every_persona_needs_a_defined_rubric() {
+        let mut rubric = bundled();
+        rubric.personas.remove(&Persona::ProductManager);
+        rubric.personas.insert(Persona::SoftwareEngineer, "astronaut".to_string());
+        let problems = rubric.problems();
+        assert!(problems.contains(&"persona ProductManager has no rubric".to_string()), "{:?}", problems);
+        assert!(problems.contains(&"persona SoftwareEngineer maps to astronaut, which is not defined".to_string()), "{:?}", problems);
+    }
+
+    #[test]
+    fn weights_must_be_positive() {
+        let mut rubric = bundled();
+        let persona_rubric = software_engineer(&mut rubric);
+        persona_rubric.required_fields.insert("name".to_string(), Weight::Points(0));
+        persona_rubric.required_fields.insert("contact".to_string(), Weight::Keys(BTreeMap::from([("email".to_string(), -5)])));
+        persona_rubric.subfield_details.get_mut("education").unwrap().insert("gpa".to_string(), -1);
+        let problems = rubric.problems();
+        assert!(problems.contains(&"software_engineer: name must weigh more than 0, not 0".to_string()), "{:?}", problems);
+        assert!(problems.contains(&"software_engineer: contact.email must weigh more than 0, not -5".to_string()), "{:?}", problems);
+        assert!(problems.contains(&"software_engineer: education.gpa must weigh more than 0, not -1".to_string()), "{:?}", problems);
+    }
+
+    #[test]
+    fn a_field_is_either_required_or_optional() {
+        let mut rubric = bundled();
+        software_engineer(&mut rubric).optional_fields.insert("skills".to_string(), Weight::Points(5));
+        assert_eq!(rubric.problems(), vec!["software_engineer: skills is both required and optional".to_string()]);
+    }
+
+    #[test]
+    fn fields_must_be_on_the_resume() {
+        let mut rubric = bundled();
+        let persona_rubric = software_engineer(&mut rubric);
+        persona_rubric.optional_fields.insert("horoscope".to_string(), Weight::Points(1));
+        persona_rubric.required_fields.insert("contact".to_string(), Weight::Keys(BTreeMap::from([("fax".to_string(), 1)])));
+        persona_rubric.subfield_details.get_mut("education").unwrap().insert("mascot".to_string(), 1);
+        persona_rubric.subfield_details.insert("hobbies".to_string(), BTreeMap::from([("name".to_string(), 1)]));
+        let problems = rubric.problems();
+        for problem in [
+            "software_engineer: horoscope is not a résumé field",
+            "software_engineer: contact.fax is not a résumé field",
+            "software_engineer: education.mascot is not a résumé field",
+            "software_engineer: subfieldDetails.hobbies is not a required or optional field",
+        ] {
+            assert!(problems.contains(&problem.to_string()), "{} missing from {:?}", problem, problems);
+        }
+    }
+
+    #[test]
+    fn an_invalid_file_is_reported_with_every_problem() {
+        let mut rubric = bundled();
+        software_engineer(&mut rubric).required_fields.insert("name".to_string(), Weight::Points(0));
+        software_engineer(&mut rubric).optional_fields.insert("horoscope".to_string(), Weight::Points(1));
+        let file = TempRubric::new(&serde_json::to_string(&rubric).unwrap());
+        match Rubric::load(file.path()) {
+            Err(RubricError::Invalid { problems, .. }) => assert_eq!(problems.len(), 2, "{:?}", problems),
+            other => panic!("expected an invalid rubric, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn a_bad_edit_keeps_the_previous_rubric() {
+        let file = TempRubric::new(BUNDLED);
+        let store = RubricStore::load(file.path()).unwrap();
+        let before = store.current().content_hash();
+
+        file.rewrite("{ not json");
+        assert_eq!(store.current().content_hash(), before);
+
+        let mut rubric = bundled();
+        software_engineer(&mut rubric).required_fields.insert("name".to_string(), Weight::Points(-1));
+        file.rewrite(&serde_json::to_string(&rubric).unwrap());
+        assert_eq!(store.current().content_hash(), before);
+    }
+
+    #[test]
+    fn a_good_edit_is_picked_up() {
+        let file = TempRubric::new(BUNDLED);
+        let store = RubricStore::load(file.path()).unwrap();
+        let mut rubric = bundled();
+        software_engineer(&mut rubric).required_fields.insert("name".to_string(), Weight::Points(20));
+        file.rewrite(&serde_json::to_string(&rubric).unwrap());
+        assert_eq!(store.current().content_hash(), rubric.content_hash());
+    }
+}
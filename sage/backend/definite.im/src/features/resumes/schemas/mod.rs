@@ -1,2 +1 @@
-pub mod alignment_schema;
 pub mod find_gaps_schema;
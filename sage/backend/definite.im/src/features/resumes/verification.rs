@@ -0,0 +1,392 @@
+use std::time::Duration;
+
+use log::error;
+use mongodb::bson::oid::ObjectId;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::shared::ops::cache_ops::Cache;
+
+use super::{
+    entities::resume::{ParsedResume, WorkExperience},
+    matching::{same_term, terms},
+};
+
+/// A rewritten bullet with less than this share of its words in the original résumé says something new
+const SUPPORTED_SHARE: f64 = 0.5;
+
+/// Bullets shorter than this many words are all numbers and tools, those are checked on their own
+const MIN_CLAIM_TERMS: usize = 3;
+
+/// Unconfirmed rewrites are dropped after a day
+const PENDING_REWRITE_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum ClaimKind {
+    Number,
+    Tool,
+    Employer,
+    Claim,
+}
+
+impl ClaimKind {
+    pub fn label(&self) -> &'static str {
+        match self {
+            ClaimKind::Number => "Number",
+            ClaimKind::Tool => "Tool or technology",
+            ClaimKind::Employer => "Employer",
+            ClaimKind::Claim => "Claim",
+        }
+    }
+}
+
+/// Something a rewrite says that the original résumé text does not
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct UnsupportedClaim {
+    pub kind: ClaimKind,
+    /// The number, tool or employer, the whole bullet for a claim
+    pub text: String,
+    /// Empty for the summary and skills, whose `title` is the section
+    pub company: String,
+    pub title: String,
+    /// The rewritten bullet it is in, `None` for an employer
+    pub bullet: Option<String>,
+}
+
+/// Everything in rewritten work experience that needs the user's word before it is saved
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct Verification {
+    pub claims: Vec<UnsupportedClaim>,
+}
+
+/// Numbers as written, e.g. "$1.2M" or "40%", with the digits they compare by ("1.2", "40")
+fn numbers(text: &str) -> Vec<(String, String)> {
+    text.split_whitespace()
+        .map(|word| word.trim_matches(|c: char| ",;:()[]\"'".contains(c)).trim_end_matches('.'))
+        .filter(|word| word.chars().any(|c| c.is_ascii_digit()))
+        .map(|word| {
+            let digits: String = word
+                .chars()
+                .skip_while(|c| !c.is_ascii_digit())
+                .take_while(|c| c.is_ascii_digit() || *c == '.' || *c == ',')
+                .filter(|c| *c != ',')
+                .collect();
+            (word.to_string(), digits.trim_end_matches('.').to_string())
+        })
+        .collect()
+}
+
+/// Words that name something, e.g. "Kubernetes", "AWS", "C++" or "Node.js". The first word of a bullet
+/// is capitalized anyway, so it only counts when it is written like a name.
+fn tools(bullet: &str) -> Vec<String> {
+    bullet
+        .split_whitespace()
+        .enumerate()
+        .filter_map(|(i, word)| {
+            let word = word.trim_matches(|c: char| !(c.is_alphanumeric() || "+#".contains(c)));
+            let letters = word.chars().filter(|c| c.is_alphabetic()).count();
+            let looks_named = word.chars().skip(1).any(|c| c.is_uppercase()) || word.contains(['+', '#', '.']);
+            let capitalized = word.chars().next().is_some_and(|c| c.is_uppercase());
+            let amount = word.chars().next().is_some_and(|c| c.is_ascii_digit());
+            (letters > 0 && !amount && (looks_named || (i > 0 && capitalized))).then(|| word.to_string())
+        })
+        .collect()
+}
+
+/// What the original résumé text says, that a rewrite is checked against
+struct Known {
+    terms: Vec<String>,
+    numbers: Vec<String>,
+}
+
+impl Known {
+    fn of(resume_text: &str) -> Self {
+        Known {
+            terms: terms(resume_text),
+            numbers: numbers(resume_text).into_iter().map(|(_, digits)| digits).collect(),
+        }
+    }
+
+    fn has(
+        &self,
+        term: &str,
+    ) -> bool {
+        self.terms.iter().any(|t| same_term(t, term))
+    }
+
+    /// The numbers, tools and wording of a bullet or summary sentence the résumé does not have.
+    /// `header` is the company and title, whose names are checked with the employer.
+    fn check_bullet(
+        &self,
+        bullet: &str,
+        header: &str,
+    ) -> Vec<(ClaimKind, String)> {
+        let header_terms = terms(header);
+        let mut claims = vec![];
+        let mut flagged: Vec<String> = vec![];
+        for (written, digits) in numbers(bullet) {
+            if !digits.is_empty() && !self.numbers.contains(&digits) && !flagged.contains(&written) {
+                claims.push((ClaimKind::Number, written.clone()));
+                flagged.push(written);
+            }
+        }
+        for tool in tools(bullet) {
+            let tool_terms = terms(&tool);
+            let unknown = tool_terms.iter().any(|t| !self.has(t) && !header_terms.contains(t));
+            if unknown && !tool_terms.is_empty() && !flagged.contains(&tool) {
+                claims.push((ClaimKind::Tool, tool.clone()));
+                flagged.push(tool);
+            }
+        }
+        // numbers and tools flagged above are not held against the rest of the bullet
+        let flagged_terms = terms(&flagged.join(" "));
+        let words: Vec<String> = terms(bullet).into_iter().filter(|t| !t.chars().any(|c| c.is_ascii_digit()) && !flagged_terms.contains(t)).collect();
+        let supported = words.iter().filter(|t| self.has(t)).count();
+        if words.len() >= MIN_CLAIM_TERMS && (supported as f64) < SUPPORTED_SHARE * words.len() as f64 {
+            claims.push((ClaimKind::Claim, bullet.to_string()));
+        }
+        claims
+    }
+}
+
+/// The sentences of a summary, each checked like a bullet
+fn sentences(text: &str) -> impl Iterator<Item = &str> {
+    text.split_inclusive(". ").map(str::trim).filter(|s| !s.is_empty())
+}
+
+impl Verification {
+    /// Checks rewritten work experience against the résumé text the rewrite was made from
+    pub fn of(
+        resume_text: &str,
+        rewritten: &[WorkExperience],
+    ) -> Self {
+        Self::of_work(&Known::of(resume_text), rewritten)
+    }
+
+    /// Checks a tailored résumé against the résumé text it was tailored from: the work experience,
+    /// the summary and the skills, the parts tailoring rewrites
+    pub fn of_tailored(
+        resume_text: &str,
+        tailored: &ParsedResume,
+    ) -> Self {
+        let known = Known::of(resume_text);
+        let mut verification = Self::of_work(&known, &tailored.work_experience);
+        let section = |kind: ClaimKind, text: String, bullet: Option<&str>, title: &str| UnsupportedClaim {
+            kind,
+            text,
+            company: String::new(),
+            title: title.to_string(),
+            bullet: bullet.map(str::to_string),
+        };
+        for sentence in tailored.summary.iter().flat_map(|s| sentences(s)) {
+            for (kind, text) in known.check_bullet(sentence, "") {
+                verification.claims.push(section(kind, text, Some(sentence), "Summary"));
+            }
+        }
+        let skills = tailored.skills.iter().flat_map(|s| s.technical.iter().chain(&s.soft_skills).chain(&s.other_skills));
+        for skill in skills {
+            let skill_terms = terms(skill);
+            if !skill_terms.is_empty() && !skill_terms.iter().all(|t| known.has(t)) {
+                verification.claims.push(section(ClaimKind::Tool, skill.clone(), None, "Skills"));
+            }
+        }
+        verification
+    }
+
+    fn of_work(
+        known: &Known,
+        rewritten: &[WorkExperience],
+    ) -> Self {
+        let mut claims = vec![];
+        for entry in rewritten {
+            let claim = |kind: ClaimKind, text: &str, bullet: Option<&String>| UnsupportedClaim {
+                kind,
+                text: text.to_string(),
+                company: entry.company.clone(),
+                title: entry.title.clone(),
+                bullet: bullet.cloned(),
+            };
+            if !terms(&entry.company).iter().all(|t| known.has(t)) {
+                claims.push(claim(ClaimKind::Employer, &entry.company, None));
+            }
+            let header = format!("{} {}", entry.company, entry.title);
+            for bullet in &entry.responsibilities {
+                for (kind, text) in known.check_bullet(bullet, &header) {
+                    claims.push(claim(kind, &text, Some(bullet)));
+                }
+            }
+        }
+        Verification { claims }
+    }
+
+    pub fn is_supported(&self) -> bool {
+        self.claims.is_empty()
+    }
+}
+
+/// A rewrite with unsupported claims, kept out of the résumé until the user confirms them
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PendingRewrite<T> {
+    pub verification: Verification,
+    pub rewrite: T,
+}
+
+impl<T: Serialize + DeserializeOwned> PendingRewrite<T> {
+    fn cache_key(
+        user_id: &str,
+        pending_id: &str,
+    ) -> String {
+        format!("pending_rewrite:{}:{}", user_id, pending_id)
+    }
+
+    /// Keeps the rewrite for the user and returns the id it is confirmed with
+    pub async fn park(
+        &self,
+        cache: &Cache,
+        user_id: &str,
+    ) -> Option<String> {
+        let pending_id = ObjectId::new().to_hex();
+        match cache.set(&Self::cache_key(user_id, &pending_id), self, PENDING_REWRITE_TTL).await {
+            Ok(()) => Some(pending_id),
+            Err(e) => {
+                error!("Error parking rewrite for user {}: {}", user_id, e);
+                None
+            }
+        }
+    }
+
+    /// Drops the user's parked rewrite once it is saved, so confirming it again does not save it twice
+    pub async fn discard(
+        cache: &Cache,
+        user_id: &str,
+        pending_id: &str,
+    ) {
+        if let Err(e) = cache.remove(&Self::cache_key(user_id, pending_id)).await {
+            error!("Error discarding rewrite {} for user {}: {}", pending_id, user_id, e);
+        }
+    }
+
+    /// The user's parked rewrite, `None` once it has expired
+    pub async fn find(
+        cache: &Cache,
+        user_id: &str,
+        pending_id: &str,
+    ) -> Option<Self> {
+        match cache.get(&Self::cache_key(user_id, pending_id)).await {
+            Ok(pending) => pending,
+            Err(e) => {
+                error!("Error finding rewrite {} for user {}: {}", pending_id, user_id, e);
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::features::resumes::{
+        export::{layout, text, ExportTemplate},
+        interchange::full_resume,
+    };
+
+    fn texts(
+        verification: &Verification,
+        title: &str,
+    ) -> Vec<String> {
+        verification.claims.iter().filter(|c| c.title == title).map(|c| c.text.clone()).collect()
+    }
+
+    #[test]
+    fn the_same_resume_is_supported() {
+        let resume = full_resume();
+        let resume_text = text::render(&layout(&resume, ExportTemplate::default()));
+        assert!(Verification::of_tailored(&resume_text, &resume).is_supported());
+    }
+
+    #[test]
+    fn tailored_summary_and_skills_are_checked() {
+        let resume = full_resume();
+        let resume_text = text::render(&layout(&resume, ExportTemplate::default()));
+        let mut tailored = resume.clone();
+        tailored.summary = Some("Backend engineer who ships payment systems. Scaled Kafka clusters to 500 brokers.".to_string());
+        let skills = tailored.skills.as_mut().unwrap();
+        skills.technical = vec!["Go".to_string(), "Rust".to_string(), "Kubernetes".to_string()];
+
+        let verification = Verification::of_tailored(&resume_text, &tailored);
+        assert_eq!(texts(&verification, "Summary"), vec!["500", "Kafka", "Scaled Kafka clusters to 500 brokers."]);
+        // reordering is fine, an added skill is not
+        assert_eq!(texts(&verification, "Skills"), vec!["Kubernetes"]);
+        assert!(verification.claims.iter().filter(|c| c.title == "Summary" || c.title == "Skills").all(|c| c.company.is_empty()));
+    }
+
+    fn work_experience() -> Vec<WorkExperience> {
+        full_resume().work_experience
+    }
+
+    fn resume_text() -> String {
+        text::render(&layout(&full_resume(), ExportTemplate::default()))
+    }
+
+    #[test]
+    fn rewritten_work_experience_as_it_was_is_supported() {
+        assert!(Verification::of(&resume_text(), &work_experience()).is_supported());
+    }
+
+    #[test]
+    fn rewording_a_bullet_is_supported() {
+        let mut rewritten = work_experience();
+        let bullet = &mut rewritten[0].responsibilities[0];
+        let mut words: Vec<&str> = bullet.split_whitespace().collect();
+        // the same words in another order, with the verb first
+        words.rotate_left(1);
+        *bullet = words.join(" ");
+        assert!(Verification::of(&resume_text(), &rewritten).is_supported());
+    }
+
+    #[test]
+    fn an_invented_metric_is_flagged() {
+        let mut rewritten = work_experience();
+        let bullet = format!("{} by 73%", rewritten[0].responsibilities[0].trim_end_matches('.'));
+        rewritten[0].responsibilities[0] = bullet.clone();
+        let verification = Verification::of(&resume_text(), &rewritten);
+        let claims: Vec<_> = verification.claims.iter().map(|c| (c.kind, c.text.as_str(), c.bullet.as_deref())).collect();
+        assert_eq!(claims, vec![(ClaimKind::Number, "73%", Some(bullet.as_str()))]);
+        assert_eq!(verification.claims[0].company, rewritten[0].company);
+    }
+
+    #[test]
+    fn an_invented_employer_is_flagged() {
+        let mut rewritten = work_experience();
+        rewritten[0].company = "Globex Corporation".to_string();
+        let verification = Verification::of(&resume_text(), &rewritten);
+        let employers: Vec<_> = verification
+            .claims
+            .iter()
+            .filter(|c| c.kind == ClaimKind::Employer)
+            .map(|c| (c.text.as_str(), c.bullet.is_none()))
+            .collect();
+        assert_eq!(employers, vec![("Globex Corporation", true)]);
+        // the bullets under it are not held against the new name
+        assert!(verification.claims.iter().all(|c| c.kind == ClaimKind::Employer));
+    }
+
+    #[test]
+    fn an_invented_tool_is_flagged() {
+        let mut rewritten = work_experience();
+        let bullet = format!("{} on Kubernetes", rewritten[0].responsibilities[0].trim_end_matches('.'));
+        rewritten[0].responsibilities[0] = bullet;
+        let verification = Verification::of(&resume_text(), &rewritten);
+        assert_eq!(texts(&verification, &rewritten[0].title), vec!["Kubernetes"]);
+        assert_eq!(verification.claims[0].kind, ClaimKind::Tool);
+    }
+
+    #[test]
+    fn a_new_claim_is_flagged() {
+        let mut rewritten = work_experience();
+        let bullet = "Negotiated vendor contracts and chaired the procurement committee".to_string();
+        rewritten[0].responsibilities.push(bullet.clone());
+        let verification = Verification::of(&resume_text(), &rewritten);
+        let claims: Vec<_> = verification.claims.iter().map(|c| (c.kind, c.text.clone())).collect();
+        assert_eq!(claims, vec![(ClaimKind::Claim, bullet)]);
+    }
+}
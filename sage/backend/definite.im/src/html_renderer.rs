@@ -1,5 +1,5 @@
 use actix_session::Session;
-use actix_web::HttpResponse;
+use actix_web::{http::StatusCode, HttpResponse};
 use handlebars::Handlebars;
 use log::{error, warn};
 use serde_json::{json, Value};
@@ -80,6 +80,22 @@ pub async fn render_page(
     }
 }
 
+/// `render_page` answered with `status` instead of 200, e.g. a 404 page for a document that is not there
+pub async fn render_page_with_status(
+    req: actix_web::HttpRequest,
+    handlebars: &Handlebars<'_>,
+    template_name: &str,
+    handlebars_context: Value,
+    session: Session,
+    status: StatusCode,
+) -> HttpResponse {
+    let mut response = render_page(req, handlebars, template_name, handlebars_context, session).await;
+    if response.status().is_success() {
+        *response.status_mut() = status;
+    }
+    response
+}
+
 pub async fn render_fragment(
     handlebars: &Handlebars<'_>,
     template_name: &str,
@@ -113,3 +129,19 @@ pub async fn render_error_fragment(
         }
     }
 }
+
+/// Renders a fragment to a string, for responses that are not a single `HttpResponse` (e.g. event streams)
+pub fn render_fragment_html(
+    handlebars: &Handlebars<'_>,
+    template_name: &str,
+    handlebars_context: Value,
+) -> String {
+    let context = handlebars_context.as_object().cloned().unwrap_or_default();
+    match handlebars.render(template_name, &context) {
+        Ok(body) => body,
+        Err(e) => {
+            error!("Error rendering {} template: {:?}", template_name, e);
+            format!("<p class=\"text-base font-bold text-orange-600\">Error rendering {}</p>", template_name)
+        }
+    }
+}
@@ -1,4 +1,4 @@
-use std::sync::{Arc, Mutex};
+use std::{sync::Arc, time::Duration};
 
 use actix_files as fs;
 
@@ -9,26 +9,43 @@ use actix_web::{
     web, App, HttpServer,
 };
 use features::resumes::routes::{
-    bullet_proof::{get_bullet_proof, get_bullet_score, post_bullet_score},
+    align::{get_aligned_page, post_align, post_align_confirm},
+    bullet_proof::{get_bullet_proof, get_bullet_score, post_bullet_optimize, post_bullet_optimize_confirm, post_bullet_score},
+    bullet_review::{get_bullet_review, post_bullet_decision, post_bullet_review},
+    export::get_resume_export,
     get_resume_dashboard_page,
+    job::get_job,
     job_description::{get_jd, get_jd_add, post_jd},
-    resume::{get_resume, get_resume_add, post_resume},
+    matching::{get_match, post_match},
+    resume::{get_resume, get_resume_add, get_resume_original, post_resume, post_resume_import, post_resume_upload},
+    resume_version::{get_resume_compare, get_resume_version, get_resume_versions, post_restore_resume_version},
+    rubric::{get_rubric_new, get_rubric_version, get_rubric_versions, post_rubric, post_rubric_publish, post_rubric_validate},
+    stream::get_stream,
+};
+use features::resumes::{
+    jobs::{self, Worker},
+    migrations,
+    rubric::RubricStore,
 };
 use handlebars::{handlebars_helper, Handlebars};
-use log::{error, info};
+use log::{error, info, warn};
 use mongodb::{bson::oid::ObjectId, Client};
 use oauth2::{basic::BasicClient, AuthUrl, ClientId, ClientSecret, RedirectUrl, TokenUrl};
 use shared::{
     auth,
-    interceptors::login_interceptor::LoginInterceptor,
+    interceptors::{admin_interceptor::AdminInterceptor, login_interceptor::LoginInterceptor, quota_interceptor::QuotaInterceptor},
     ops::date_ops,
+    routes::admin::get_llm_usage,
     routes::index::{get_login_page, get_privacy_page, get_resume_page, get_tos_page, get_under_construction_page},
 };
 use shared::{
     ops::{
         cache_ops::Cache,
-        environ_ops::{AuthConfig, DatabaseConfig, Environ, Environment, RedisConfig, WebConfig},
-        queue_ops::{Producer, RedisConnectionManager},
+        db_ops::Database,
+        environ_ops::{AuthConfig, DatabaseConfig, Environ, Environment, QueueConfig, RedisConfig, RubricConfig, WebConfig},
+        openai::provider::provider_from_env,
+        queue_ops::{Partials, Producer, RedisConnectionManager},
+        quota_ops::QuotaPlans,
     },
     routes::index::get_index_page,
 };
@@ -44,6 +61,7 @@ pub struct FileInfo {
 
 /// read all files of a given extension from a directory and all its subdirectories
 /// then return a vector of each file name without its extension and its path
+#[allow(clippy::double_ended_iterator_last)]
 pub fn read_files_from_dir(
     dir: &str,
     ext: &str,
@@ -73,8 +91,10 @@ pub fn configure_handlebars() -> Handlebars<'static> {
     // Register the custom helper
     handlebars.register_helper("persona_label", Box::new(persona_label_helper));
     handlebars.register_helper("format_timestamp", Box::new(format_timestamp));
+    handlebars.register_helper("format_timestamp_millis", Box::new(format_timestamp_millis));
     handlebars.register_helper("to_hex", Box::new(to_hex));
     handlebars.register_helper("is_greater_than", Box::new(is_greater_than));
+    handlebars.register_helper("format_cost", Box::new(format_cost));
 
     read_files_from_dir("./src", ".hbs")
         .iter()
@@ -82,6 +102,28 @@ pub fn configure_handlebars() -> Handlebars<'static> {
     handlebars
 }
 
+/// Sessions need Redis, a Redis that is still starting gets a few tries before the server gives up
+async fn connect_session_store(redis_url: &str) -> std::io::Result<RedisSessionStore> {
+    const ATTEMPTS: u32 = 5;
+    let mut backoff = Duration::from_secs(1);
+    let mut attempt = 1;
+    loop {
+        match RedisSessionStore::new(redis_url).await {
+            Ok(store) => return Ok(store),
+            Err(e) if attempt >= ATTEMPTS => {
+                error!("Redis not reachable for sessions: {}", e);
+                return Err(std::io::Error::other(e.to_string()));
+            }
+            Err(e) => {
+                warn!("Redis not reachable for sessions (attempt {} of {}), retrying in {}s: {}", attempt, ATTEMPTS, backoff.as_secs(), e);
+                actix_web::rt::time::sleep(backoff).await;
+                backoff *= 2;
+                attempt += 1;
+            }
+        }
+    }
+}
+
 pub fn get_session_middleware(
     redis_store: RedisSessionStore,
     secret_key: Key,
@@ -108,41 +150,150 @@ handlebars_helper!(persona_label_helper: |val: str| {
 
 handlebars_helper!(is_greater_than: |a: f64, b: f64| a > b);
 
+handlebars_helper!(format_cost: |cost: f64| {
+    format!("{:.4}", cost)
+});
+
 // Define the helper function
 handlebars_helper!(format_timestamp: |timestamp: u64| {
     date_ops::days_ago(timestamp)
 });
 
+handlebars_helper!(format_timestamp_millis: |timestamp: u64| {
+    date_ops::days_ago(timestamp / 1000)
+});
+
 handlebars_helper!(to_hex: |id: ObjectId| {
     id.to_hex()
 });
 
+/// `definite migrate <collection|all> [--dry-run] [--batch-size N]`, also creates the indexes the code relies on
+async fn run_migrate(args: &[String]) -> std::io::Result<()> {
+    let usage = "usage: definite migrate <collection|all> [--dry-run] [--batch-size N]";
+    let collection = match args.first() {
+        Some(c) if !c.starts_with("--") => c.as_str(),
+        _ => return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, usage)),
+    };
+    let dry_run = args.iter().any(|a| a == "--dry-run");
+    let batch_size = match args.iter().position(|a| a == "--batch-size") {
+        Some(i) => args
+            .get(i + 1)
+            .and_then(|b| b.parse::<u32>().ok())
+            .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidInput, usage))?,
+        None => 100,
+    };
+    let mongoc = Database::get_client().await;
+    match migrations::migrate(&mongoc, collection, batch_size, dry_run).await {
+        Ok(reports) => {
+            for report in reports {
+                println!(
+                    "{}{}: {} scanned, {} migrated, {} skipped, {} failed",
+                    report.collection,
+                    if dry_run { " (dry run)" } else { "" },
+                    report.scanned,
+                    report.migrated,
+                    report.skipped,
+                    report.failed
+                );
+            }
+            Ok(())
+        }
+        Err(e) => Err(std::io::Error::other(e)),
+    }
+}
+
+/// `definite worker [--concurrency N]`, takes the LLM jobs the web server queues
+async fn run_worker(args: &[String]) -> std::io::Result<()> {
+    let usage = "usage: definite worker [--concurrency N]";
+    let concurrency = match args.iter().position(|a| a == "--concurrency") {
+        Some(i) => args
+            .get(i + 1)
+            .and_then(|c| c.parse::<usize>().ok())
+            .filter(|c| *c > 0)
+            .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidInput, usage))?,
+        None => 1,
+    };
+    let redis_config: RedisConfig = Environ::init();
+    let rubric_config: RubricConfig = Environ::init();
+    let queue_config: QueueConfig = Environ::init();
+    let rubric_store = RubricStore::load(&rubric_config.rubric_path).map_err(|e| std::io::Error::other(e.to_string()))?;
+    let provider = provider_from_env().map_err(std::io::Error::other)?;
+    let connection_manager = Arc::new(RedisConnectionManager::new(&redis_config.redis_server, &redis_config.redis_key_prefix));
+    let mongoc = Database::get_client().await;
+    // saves retry on a duplicate version number and the published rubric is looked up by index
+    migrations::ensure_indexes(&mongoc, "all").await.map_err(std::io::Error::other)?;
+    let worker = Worker {
+        handlebars: configure_handlebars(),
+        mongoc,
+        cache: Cache::new(&redis_config).map_err(|e| std::io::Error::other(e.to_string()))?,
+        rubric_store,
+        provider,
+        partials: Partials::new(connection_manager.clone()),
+    };
+    info!("Starting worker, {} job(s) of each kind at a time", concurrency);
+    jobs::work(Arc::new(worker), connection_manager, &queue_config, concurrency).await;
+    Ok(())
+}
+
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     Environ::load_env_file();
-    let auth_config: AuthConfig = Environ::init();
-    let db_config: DatabaseConfig = Environ::init();
     let web_config: WebConfig = Environ::init();
-    let redis_config: RedisConfig = Environ::init();
     let log_level = web_config.log_level;
     env_logger::init_from_env(env_logger::Env::new().default_filter_or(log_level));
 
+    let args: Vec<String> = std::env::args().collect();
+    if args.get(1).map(String::as_str) == Some("migrate") {
+        return run_migrate(&args[2..]).await;
+    }
+    if args.get(1).map(String::as_str) == Some("worker") {
+        return run_worker(&args[2..]).await;
+    }
+
+    let auth_config: AuthConfig = Environ::init();
+    let db_config: DatabaseConfig = Environ::init();
+    let redis_config: RedisConfig = Environ::init();
+    let rubric_config: RubricConfig = Environ::init();
+
     let client_id = ClientId::new(auth_config.google_client_id);
     let client_secret = ClientSecret::new(auth_config.google_client_secret);
     let auth_url = AuthUrl::new(auth_config.google_auth_uri).expect("Invalid authorization endpoint URL");
     let token_url = TokenUrl::new(auth_config.google_token_uri).expect("Invalid token endpoint URL");
     let oauth_client = BasicClient::new(client_id, Some(client_secret), auth_url, Some(token_url)).set_redirect_uri(RedirectUrl::new(auth_config.google_callback_uri).expect("Invalid redirect URL"));
     let mongoc = Client::with_uri_str(db_config.db_connection_string).await.unwrap();
+    // saves retry on a duplicate version number and the published rubric is looked up by index
+    if let Err(e) = migrations::ensure_indexes(&mongoc, "all").await {
+        error!("{}", e);
+        return Err(std::io::Error::other(e));
+    }
     let ip = web_config.web_app_ip;
     let port = web_config.web_app_port;
     let handlebars = configure_handlebars();
-    let cache = Cache::default();
+    let cache = Cache::new(&redis_config).map_err(|e| std::io::Error::other(e.to_string()))?;
+    // fail at startup rather than on the first limited request
+    QuotaPlans::get();
+    let rubric_store = match RubricStore::load(&rubric_config.rubric_path) {
+        Ok(store) => web::Data::new(store),
+        Err(e) => {
+            error!("{}", e);
+            return Err(std::io::Error::other(e.to_string()));
+        }
+    };
 
-    let connection_manager = Arc::new(RedisConnectionManager::new(redis_config.redis_server.as_str()));
-    let producer = Arc::new(Mutex::new(Producer::new(connection_manager.clone())));
+    let provider = match provider_from_env() {
+        Ok(p) => web::Data::from(p),
+        Err(e) => {
+            error!("{}", e);
+            return Err(std::io::Error::other(e));
+        }
+    };
+
+    let connection_manager = Arc::new(RedisConnectionManager::new(&redis_config.redis_server, &redis_config.redis_key_prefix));
+    let producer = Producer::new(connection_manager.clone());
+    let partials = Partials::new(connection_manager);
 
     let secret_key = Key::generate();
-    let redis_store = RedisSessionStore::new(redis_config.redis_server).await.unwrap();
+    let redis_store = connect_session_store(&redis_config.redis_server).await?;
 
     let server = HttpServer::new(move || {
         App::new()
@@ -153,6 +304,9 @@ async fn main() -> std::io::Result<()> {
             .app_data(web::Data::new(handlebars.clone()))
             .app_data(web::Data::new(mongoc.clone()))
             .app_data(web::Data::new(producer.clone()))
+            .app_data(web::Data::new(partials.clone()))
+            .app_data(rubric_store.clone())
+            .app_data(provider.clone())
             .app_data(web::FormConfig::default().limit(2_000_000)) // 2MB
             .service(fs::Files::new("/assets", "./assets").show_files_listing())
             .service(
@@ -167,15 +321,56 @@ async fn main() -> std::io::Result<()> {
                 web::scope("/resumes")
                     .wrap(LoginInterceptor)
                     .route("/dashboard", web::get().to(get_resume_dashboard_page))
-                    .route("/resume/add", web::get().to(get_resume_add))
-                    .route("/resume/add", web::post().to(post_resume))
+                    .service(
+                        web::resource("/resume/add")
+                            .wrap(QuotaInterceptor)
+                            .route(web::get().to(get_resume_add))
+                            .route(web::post().to(post_resume)),
+                    )
+                    .service(web::resource("/resume/add/upload").wrap(QuotaInterceptor).route(web::post().to(post_resume_upload)))
+                    .route("/resume/add/import", web::post().to(post_resume_import))
                     .route("/resume/{resume_id}", web::get().to(get_resume))
-                    .route("/job-description/add", web::get().to(get_jd_add))
-                    .route("/job-description/add", web::post().to(post_jd))
+                    .route("/resume/{resume_id}/original", web::get().to(get_resume_original))
+                    .route("/resume/{resume_id}/export", web::get().to(get_resume_export))
+                    .route("/resume/{resume_id}/versions", web::get().to(get_resume_versions))
+                    .route("/resume/{resume_id}/versions/{version}", web::get().to(get_resume_version))
+                    .route("/resume/{resume_id}/versions/{version}/restore", web::post().to(post_restore_resume_version))
+                    .route("/resume/{resume_id}/compare", web::get().to(get_resume_compare))
+                    .service(
+                        web::resource("/job-description/add")
+                            .wrap(QuotaInterceptor)
+                            .route(web::get().to(get_jd_add))
+                            .route(web::post().to(post_jd)),
+                    )
                     .route("/job-description/{resume_id}", web::get().to(get_jd))
+                    .route("/match", web::post().to(post_match))
+                    .route("/match/{match_id}", web::get().to(get_match))
+                    .service(web::resource("/align").wrap(QuotaInterceptor).route(web::post().to(post_align)))
+                    .route("/align/confirm", web::post().to(post_align_confirm))
+                    .route("/align/{resume_id}", web::get().to(get_aligned_page))
                     .route("/bullet-proof", web::get().to(get_bullet_proof))
                     .route("/bullet-proof/score/{score_id}", web::get().to(get_bullet_score))
-                    .route("/bullet-proof/score", web::post().to(post_bullet_score)),
+                    .service(web::resource("/bullet-proof/score").wrap(QuotaInterceptor).route(web::post().to(post_bullet_score)))
+                    .service(web::resource("/bullet-proof/optimize").wrap(QuotaInterceptor).route(web::post().to(post_bullet_optimize)))
+                    .route("/bullet-proof/optimize/confirm", web::post().to(post_bullet_optimize_confirm))
+                    .route("/bullet-proof/review/{resume_id}", web::get().to(get_bullet_review))
+                    .route("/bullet-proof/review/{resume_id}", web::post().to(post_bullet_review))
+                    .route("/bullet-proof/review/{resume_id}/bullet", web::post().to(post_bullet_decision))
+                    .route("/stream/{stream_id}", web::get().to(get_stream))
+                    .route("/jobs/{job_id}", web::get().to(get_job)),
+            )
+            .service(
+                web::scope("/admin")
+                    // the last wrap runs first, so the login check happens before the admin check
+                    .wrap(AdminInterceptor)
+                    .wrap(LoginInterceptor)
+                    .route("/llm-usage", web::get().to(get_llm_usage))
+                    .route("/rubrics", web::get().to(get_rubric_versions))
+                    .route("/rubrics", web::post().to(post_rubric))
+                    .route("/rubrics/new", web::get().to(get_rubric_new))
+                    .route("/rubrics/validate", web::post().to(post_rubric_validate))
+                    .route("/rubrics/{version}", web::get().to(get_rubric_version))
+                    .route("/rubrics/{version}/publish", web::post().to(post_rubric_publish)),
             )
             .route("/branding", web::get().to(get_under_construction_page))
             .route("/problems", web::get().to(get_under_construction_page))
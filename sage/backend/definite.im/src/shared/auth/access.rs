@@ -0,0 +1,295 @@
+use actix_session::Session;
+use actix_web::http::StatusCode;
+use log::warn;
+use mongodb::{bson::oid::ObjectId, Client};
+
+use crate::shared::{
+    auth::user::UserAuth,
+    ops::repository_ops::{Repository, RepositoryError},
+};
+
+/// Who is asking for a document
+#[derive(Debug, Clone)]
+pub struct Principal {
+    pub user_id: String,
+    pub is_admin: bool,
+}
+
+impl From<&UserAuth> for Principal {
+    fn from(user_auth: &UserAuth) -> Self {
+        Principal {
+            // a session without a user owns nothing
+            user_id: user_auth.google_model.as_ref().map(|g| g.id.clone()).unwrap_or_default(),
+            is_admin: user_auth.is_admin(),
+        }
+    }
+}
+
+impl From<Session> for Principal {
+    fn from(session: Session) -> Self {
+        Principal::from(&UserAuth::from(session))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Action {
+    Read,
+    Write,
+}
+
+/// A stored document as a policy sees it
+#[derive(Debug)]
+pub struct Resource<'a> {
+    pub collection: &'static str,
+    pub id: ObjectId,
+    pub owner: &'a str,
+}
+
+/// Decides who may read or change a document. Sharing and admin roles are added as policies
+/// that allow more than `OwnerPolicy`, and switched on in `policy`.
+pub trait AccessPolicy: Send + Sync {
+    fn allows(
+        &self,
+        principal: &Principal,
+        resource: &Resource<'_>,
+        action: Action,
+    ) -> bool;
+}
+
+/// Only the owner may read or change a document
+pub struct OwnerPolicy;
+
+impl AccessPolicy for OwnerPolicy {
+    fn allows(
+        &self,
+        principal: &Principal,
+        resource: &Resource<'_>,
+        _action: Action,
+    ) -> bool {
+        !principal.user_id.is_empty() && principal.user_id == resource.owner
+    }
+}
+
+/// `inner`, and admins may read everything, e.g. to look into a support request
+pub struct AdminReadPolicy<P: AccessPolicy>(pub P);
+
+impl<P: AccessPolicy> AccessPolicy for AdminReadPolicy<P> {
+    fn allows(
+        &self,
+        principal: &Principal,
+        resource: &Resource<'_>,
+        action: Action,
+    ) -> bool {
+        (principal.is_admin && action == Action::Read) || self.0.allows(principal, resource, action)
+    }
+}
+
+/// The policy the app runs with
+pub fn policy() -> &'static dyn AccessPolicy {
+    static POLICY: OwnerPolicy = OwnerPolicy;
+    &POLICY
+}
+
+/// Hands out `entity` if `policy` allows it. A document the principal may not see is reported
+/// as not found, so an id from someone else's url does not even confirm that it exists.
+pub fn authorize<T: Repository>(
+    policy: &dyn AccessPolicy,
+    principal: &Principal,
+    entity: T,
+    action: Action,
+) -> Result<T, RepositoryError> {
+    let resource = Resource {
+        collection: T::COLLECTION,
+        id: entity.id(),
+        owner: entity.owner(),
+    };
+    if policy.allows(principal, &resource, action) {
+        return Ok(entity);
+    }
+    warn!("User {} denied {:?} of {} in {}", principal.user_id, action, resource.id, resource.collection);
+    Err(RepositoryError::NotFound {
+        collection: T::COLLECTION,
+        id: resource.id.to_hex(),
+    })
+}
+
+/// The document `id` from a url or a form, if `principal` may `action` it
+pub async fn fetch<T: Repository>(
+    mongoc: &Client,
+    principal: &Principal,
+    id: &str,
+    action: Action,
+) -> Result<T, RepositoryError> {
+    let oid = ObjectId::parse_str(id).map_err(|_| RepositoryError::InvalidId { id: id.to_string() })?;
+    let entity = T::find_unscoped(mongoc, &oid).await?;
+    authorize(policy(), principal, entity, action)
+}
+
+/// The status for a page that could not load its document: 404 for a bad id, a missing document or someone else's
+pub fn status_of(e: &RepositoryError) -> StatusCode {
+    match e {
+        RepositoryError::InvalidId { .. } | RepositoryError::NotFound { .. } => StatusCode::NOT_FOUND,
+        RepositoryError::Conflict { .. } => StatusCode::CONFLICT,
+        RepositoryError::Database { .. } | RepositoryError::Serialization { .. } => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::features::resumes::entities::{job::JobEntity, job_description::JobDescriptionEntity, match_report::MatchReportEntity, resume::ResumeEntity, resume_score::ScoreEntity};
+
+    fn user(user_id: &str) -> Principal {
+        Principal {
+            user_id: user_id.to_string(),
+            is_admin: false,
+        }
+    }
+
+    fn admin(user_id: &str) -> Principal {
+        Principal {
+            user_id: user_id.to_string(),
+            is_admin: true,
+        }
+    }
+
+    fn resume(owner: &str) -> ResumeEntity {
+        ResumeEntity {
+            user_id: owner.to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn is_not_found<T>(result: &Result<T, RepositoryError>) -> bool {
+        matches!(result, Err(RepositoryError::NotFound { .. }))
+    }
+
+    #[test]
+    fn owner_can_read_and_write_their_resume() {
+        let entity = resume("alice");
+        let id = entity._id;
+        let read = authorize(&OwnerPolicy, &user("alice"), entity.clone(), Action::Read);
+        assert_eq!(read.map(|r| r._id).ok(), Some(id));
+        assert!(authorize(&OwnerPolicy, &user("alice"), entity, Action::Write).is_ok());
+    }
+
+    #[test]
+    fn other_user_gets_not_found_for_a_resume() {
+        for action in [Action::Read, Action::Write] {
+            let result = authorize(&OwnerPolicy, &user("mallory"), resume("alice"), action);
+            assert!(is_not_found(&result), "{:?} of someone else's résumé must look missing", action);
+        }
+    }
+
+    #[test]
+    fn other_user_gets_not_found_for_a_job_description() {
+        let jd = JobDescriptionEntity {
+            user_id: "alice".to_string(),
+            ..Default::default()
+        };
+        assert!(authorize(&OwnerPolicy, &user("alice"), jd.clone(), Action::Read).is_ok());
+        assert!(is_not_found(&authorize(&OwnerPolicy, &user("mallory"), jd, Action::Read)));
+    }
+
+    #[test]
+    fn other_user_gets_not_found_for_a_score() {
+        let score = ScoreEntity {
+            user_id: "alice".to_string(),
+            ..Default::default()
+        };
+        assert!(authorize(&OwnerPolicy, &user("alice"), score.clone(), Action::Read).is_ok());
+        assert!(is_not_found(&authorize(&OwnerPolicy, &user("mallory"), score, Action::Read)));
+    }
+
+    #[test]
+    fn other_user_gets_not_found_for_a_job() {
+        let job = JobEntity {
+            user_id: "alice".to_string(),
+            ..Default::default()
+        };
+        assert!(authorize(&OwnerPolicy, &user("alice"), job.clone(), Action::Read).is_ok());
+        assert!(is_not_found(&authorize(&OwnerPolicy, &user("mallory"), job, Action::Read)));
+    }
+
+    #[test]
+    fn other_user_gets_not_found_for_a_match_report() {
+        let report = MatchReportEntity {
+            user_id: "alice".to_string(),
+            ..Default::default()
+        };
+        assert!(authorize(&OwnerPolicy, &user("alice"), report.clone(), Action::Read).is_ok());
+        assert!(is_not_found(&authorize(&OwnerPolicy, &user("mallory"), report, Action::Read)));
+    }
+
+    #[test]
+    fn denied_and_missing_documents_answer_the_same() {
+        let entity = resume("alice");
+        let id = entity._id.to_hex();
+        let denied = authorize(&OwnerPolicy, &user("mallory"), entity, Action::Read).unwrap_err();
+        let missing = RepositoryError::NotFound {
+            collection: ResumeEntity::COLLECTION,
+            id: id.clone(),
+        };
+        assert_eq!(denied.to_string(), missing.to_string());
+        assert_eq!(status_of(&denied), StatusCode::NOT_FOUND);
+    }
+
+    #[test]
+    fn user_without_an_id_owns_nothing() {
+        let unowned = resume("");
+        assert!(is_not_found(&authorize(&OwnerPolicy, &user(""), unowned, Action::Read)));
+    }
+
+    #[test]
+    fn owner_policy_ignores_the_admin_role() {
+        assert!(is_not_found(&authorize(&OwnerPolicy, &admin("root"), resume("alice"), Action::Read)));
+    }
+
+    #[test]
+    fn admin_policy_lets_admins_read_but_not_write() {
+        let policy = AdminReadPolicy(OwnerPolicy);
+        assert!(authorize(&policy, &admin("root"), resume("alice"), Action::Read).is_ok());
+        assert!(is_not_found(&authorize(&policy, &admin("root"), resume("alice"), Action::Write)));
+        assert!(is_not_found(&authorize(&policy, &user("mallory"), resume("alice"), Action::Read)));
+        assert!(authorize(&policy, &user("alice"), resume("alice"), Action::Write).is_ok());
+    }
+
+    /// The hook for sharing: a policy that allows more than the owner
+    struct SharedWith(&'static str);
+
+    impl AccessPolicy for SharedWith {
+        fn allows(
+            &self,
+            principal: &Principal,
+            resource: &Resource<'_>,
+            action: Action,
+        ) -> bool {
+            (principal.user_id == self.0 && action == Action::Read) || OwnerPolicy.allows(principal, resource, action)
+        }
+    }
+
+    #[test]
+    fn sharing_policy_opens_reads_to_the_shared_user_only() {
+        let policy = SharedWith("bob");
+        assert!(authorize(&policy, &user("bob"), resume("alice"), Action::Read).is_ok());
+        assert!(is_not_found(&authorize(&policy, &user("bob"), resume("alice"), Action::Write)));
+        assert!(is_not_found(&authorize(&policy, &user("mallory"), resume("alice"), Action::Read)));
+    }
+
+    #[test]
+    fn bad_ids_are_not_found() {
+        let e = RepositoryError::InvalidId { id: "not-an-id".to_string() };
+        assert_eq!(status_of(&e), StatusCode::NOT_FOUND);
+    }
+
+    #[test]
+    fn app_runs_with_the_owner_policy() {
+        let resource = Resource {
+            collection: ResumeEntity::COLLECTION,
+            id: ObjectId::new(),
+            owner: "alice",
+        };
+        assert!(policy().allows(&user("alice"), &resource, Action::Write));
+        assert!(!policy().allows(&admin("root"), &resource, Action::Read));
+    }
+}
@@ -69,7 +69,9 @@ pub async fn logout(
     let user_auth = UserAuth::from(session.clone());
     debug!("Logging out user: {}", user_key);
     info!("Logging out user and redirecting to /");
-    cache.remove(user_key.as_str());
+    if let Err(e) = cache.remove(user_key.as_str()).await {
+        warn!("Error removing cached data for user {}: {}", user_key, e);
+    }
     user_auth.logout();
     HttpResponse::Found()
         .append_header(("Location", "/")) // Redirect to the home page or login page
@@ -151,9 +153,9 @@ pub async fn auth_callback(
 
         // insert user into database
         let user = UserEntity::from(user_model);
-        match user.create(&client).await {
-            Some(_) => info!("User inserted into database: {:?}", user),
-            None => error!("Failed to insert user into database"),
+        match user.find_or_create(&client).await {
+            Ok(_) => info!("User inserted into database: {:?}", user),
+            Err(e) => error!("Failed to insert user into database: {}", e),
         }
     } else {
         warn!("Failed to get user info from Google");
@@ -2,7 +2,7 @@ use actix_session::Session;
 use serde_json::Value;
 
 use crate::shared::models::google::GoogleUserModel;
-use crate::shared::ops::environ_ops::{Environ, Environment, WebConfig};
+use crate::shared::ops::environ_ops::{AuthConfig, Environ, Environment, WebConfig};
 
 pub struct UserAuth {
     pub access_token: Option<String>,
@@ -94,6 +94,15 @@ impl From<Session> for UserAuth {
 }
 
 impl UserAuth {
+    /// True when the signed-in Google account is listed in `ADMIN_EMAILS`
+    pub fn is_admin(&self) -> bool {
+        let auth_config: AuthConfig = Environ::init();
+        match &self.google_model {
+            Some(g) => auth_config.admin_emails.contains(&g.email.to_lowercase()),
+            None => false,
+        }
+    }
+
     pub fn set_access_token(
         &mut self,
         access_token: &String,
@@ -0,0 +1,394 @@
+use std::{collections::HashMap, sync::OnceLock};
+
+use futures::stream::TryStreamExt;
+use log::{debug, error};
+use mongodb::{
+    bson::{self, doc, oid::ObjectId, Bson, Document},
+    Client,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::shared::ops::{
+    date_ops,
+    db_ops::Database,
+    environ_ops::{Environ, UsageConfig},
+    openai::completion_response::Usage,
+};
+
+const LLM_USAGE_COLLECTION: &str = "llm_usage";
+
+/// What a completion was spent on
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LlmOperation {
+    ParseResume,
+    ParseJobDescription,
+    ScoreResume,
+    OptimizeWork,
+    AlignResume,
+}
+
+impl LlmOperation {
+    pub fn label(&self) -> &'static str {
+        match self {
+            LlmOperation::ParseResume => "Parse résumé",
+            LlmOperation::ParseJobDescription => "Parse job description",
+            LlmOperation::ScoreResume => "Score résumé",
+            LlmOperation::OptimizeWork => "Optimize work experience",
+            LlmOperation::AlignResume => "Tailor résumé to a job",
+        }
+    }
+}
+
+impl From<LlmOperation> for Bson {
+    fn from(val: LlmOperation) -> Self {
+        match val {
+            LlmOperation::ParseResume => Bson::String("ParseResume".to_string()),
+            LlmOperation::ParseJobDescription => Bson::String("ParseJobDescription".to_string()),
+            LlmOperation::ScoreResume => Bson::String("ScoreResume".to_string()),
+            LlmOperation::OptimizeWork => Bson::String("OptimizeWork".to_string()),
+            LlmOperation::AlignResume => Bson::String("AlignResume".to_string()),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct ModelPrice {
+    pub prompt: f64,
+    pub completion: f64,
+}
+
+/// Token prices per model, loaded once from `UsageConfig.llm_price_table`
+#[derive(Debug, Deserialize, Clone)]
+pub struct PriceTable {
+    pub currency: String,
+    /// Prices are quoted per this many tokens (usually one million)
+    pub per_tokens: f64,
+    pub models: HashMap<String, ModelPrice>,
+}
+
+impl PriceTable {
+    pub fn get() -> &'static PriceTable {
+        static PRICES: OnceLock<PriceTable> = OnceLock::new();
+        PRICES.get_or_init(|| {
+            let usage_config: UsageConfig = Environ::init();
+            let table = std::fs::read_to_string(&usage_config.llm_price_table)
+                .map_err(|e| e.to_string())
+                .and_then(|t| serde_json::from_str::<PriceTable>(&t).map_err(|e| e.to_string()));
+            match table {
+                Ok(t) => t,
+                Err(e) => {
+                    error!("Error loading price table {}, costs will be recorded as 0: {}", usage_config.llm_price_table, e);
+                    PriceTable {
+                        currency: "USD".to_string(),
+                        per_tokens: 1_000_000.0,
+                        models: HashMap::new(),
+                    }
+                }
+            }
+        })
+    }
+
+    /// Providers answer with dated model names (gpt-4o-2024-08-06), so the longest matching prefix wins
+    pub fn price_for(
+        &self,
+        model: &str,
+    ) -> Option<&ModelPrice> {
+        self.models
+            .iter()
+            .filter(|(name, _)| model.starts_with(name.as_str()))
+            .max_by_key(|(name, _)| name.len())
+            .map(|(_, price)| price)
+    }
+
+    pub fn cost(
+        &self,
+        model: &str,
+        usage: &Usage,
+    ) -> f64 {
+        match self.price_for(model) {
+            Some(price) => (usage.prompt_tokens as f64 * price.prompt + usage.completion_tokens as f64 * price.completion) / self.per_tokens,
+            None => {
+                debug!("No price for model {}", model);
+                0.0
+            }
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct LlmUsageEntity {
+    pub _id: ObjectId,
+    pub user_id: String,
+    pub operation: LlmOperation,
+    pub provider: String,
+    pub model: String,
+    pub prompt_tokens: u32,
+    pub completion_tokens: u32,
+    pub reasoning_tokens: u32,
+    pub total_tokens: u32,
+    pub cost: f64,
+    pub currency: String,
+    /// False when the tokens were spent on a truncated or refused completion
+    pub succeeded: bool,
+    pub timestamp: i64,
+}
+
+/// Totals for one operation (or all of them) over a set of usage records
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct UsageSummary {
+    pub calls: u64,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    pub reasoning_tokens: u64,
+    pub total_tokens: u64,
+    pub cost: f64,
+}
+
+impl UsageSummary {
+    fn add(
+        &mut self,
+        other: &UsageSummary,
+    ) {
+        self.calls += other.calls;
+        self.prompt_tokens += other.prompt_tokens;
+        self.completion_tokens += other.completion_tokens;
+        self.reasoning_tokens += other.reasoning_tokens;
+        self.total_tokens += other.total_tokens;
+        self.cost += other.cost;
+    }
+
+    /// Totals over all `groups`, and per operation ordered by cost
+    pub fn from_groups<'a>(groups: impl IntoIterator<Item = &'a UsageGroup>) -> (UsageSummary, Vec<OperationUsage>) {
+        let mut total = UsageSummary::default();
+        let mut by_operation: HashMap<LlmOperation, UsageSummary> = HashMap::new();
+        for group in groups {
+            total.add(&group.summary);
+            by_operation.entry(group.operation).or_default().add(&group.summary);
+        }
+        let mut by_operation: Vec<OperationUsage> = by_operation
+            .into_iter()
+            .map(|(operation, summary)| OperationUsage {
+                operation,
+                label: operation.label(),
+                summary,
+            })
+            .collect();
+        by_operation.sort_by(|a, b| b.summary.cost.total_cmp(&a.summary.cost).then(b.summary.total_tokens.cmp(&a.summary.total_tokens)));
+        (total, by_operation)
+    }
+}
+
+/// One user's usage of one operation, summed in the database by `LlmUsageEntity::summarize`
+#[derive(Debug, Clone)]
+pub struct UsageGroup {
+    pub user_id: String,
+    pub operation: LlmOperation,
+    pub summary: UsageSummary,
+}
+
+/// A `$group` result as the database returns it
+#[derive(Debug, Deserialize)]
+struct UsageGroupRow {
+    _id: UsageGroupKey,
+    calls: u64,
+    prompt_tokens: u64,
+    completion_tokens: u64,
+    reasoning_tokens: u64,
+    total_tokens: u64,
+    cost: f64,
+}
+
+#[derive(Debug, Deserialize)]
+struct UsageGroupKey {
+    user_id: String,
+    operation: LlmOperation,
+}
+
+impl From<UsageGroupRow> for UsageGroup {
+    fn from(row: UsageGroupRow) -> Self {
+        UsageGroup {
+            user_id: row._id.user_id,
+            operation: row._id.operation,
+            summary: UsageSummary {
+                calls: row.calls,
+                prompt_tokens: row.prompt_tokens,
+                completion_tokens: row.completion_tokens,
+                reasoning_tokens: row.reasoning_tokens,
+                total_tokens: row.total_tokens,
+                cost: row.cost,
+            },
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct OperationUsage {
+    pub operation: LlmOperation,
+    pub label: &'static str,
+    #[serde(flatten)]
+    pub summary: UsageSummary,
+}
+
+impl LlmUsageEntity {
+    pub fn new(
+        user_id: Option<String>,
+        operation: LlmOperation,
+        provider: &str,
+        model: &str,
+        usage: &Usage,
+        succeeded: bool,
+    ) -> Self {
+        let prices = PriceTable::get();
+        LlmUsageEntity {
+            _id: ObjectId::new(),
+            user_id: user_id.unwrap_or("anonymous".to_string()),
+            operation,
+            provider: provider.to_string(),
+            model: model.to_string(),
+            prompt_tokens: usage.prompt_tokens,
+            completion_tokens: usage.completion_tokens,
+            reasoning_tokens: usage.completion_tokens_details.reasoning_tokens,
+            total_tokens: usage.total_tokens,
+            cost: prices.cost(model, usage),
+            currency: prices.currency.clone(),
+            succeeded,
+            timestamp: date_ops::to_timestamp(),
+        }
+    }
+
+    pub async fn create(
+        &self,
+        mongoc: &Client,
+    ) -> Option<String> {
+        let collection = Database::get_collection::<LlmUsageEntity>(mongoc, LLM_USAGE_COLLECTION);
+        Database::create(&collection, self).await
+    }
+
+    /// Saves the record without holding up the caller, usage accounting must never fail a request
+    pub fn record(self) {
+        tokio::spawn(async move {
+            let mongoc = Database::shared_client().await;
+            if self.create(mongoc).await.is_none() {
+                error!("Error recording LLM usage: {:?}", self);
+            }
+        });
+    }
+
+    /// Sums the usage matching `filter` per user and operation. The sums are made by the database,
+    /// only one row per user and operation is read however many calls there were.
+    pub async fn summarize(
+        mongoc: &Client,
+        filter: Document,
+    ) -> Option<Vec<UsageGroup>> {
+        let collection = Database::get_collection::<Document>(mongoc, LLM_USAGE_COLLECTION);
+        let pipeline = vec![
+            doc! {"$match": filter},
+            doc! {"$group": {
+                "_id": {"user_id": "$user_id", "operation": "$operation"},
+                "calls": {"$sum": 1},
+                "prompt_tokens": {"$sum": "$prompt_tokens"},
+                "completion_tokens": {"$sum": "$completion_tokens"},
+                "reasoning_tokens": {"$sum": "$reasoning_tokens"},
+                "total_tokens": {"$sum": "$total_tokens"},
+                "cost": {"$sum": "$cost"},
+            }},
+        ];
+        let rows: Vec<Document> = match collection.aggregate(pipeline).await {
+            Ok(cursor) => match cursor.try_collect().await {
+                Ok(r) => r,
+                Err(e) => {
+                    error!("Error reading LLM usage totals: {}", e);
+                    return None;
+                }
+            },
+            Err(e) => {
+                error!("Error summing LLM usage: {}", e);
+                return None;
+            }
+        };
+        rows.into_iter()
+            .map(|row| bson::from_document::<UsageGroupRow>(row).map(UsageGroup::from))
+            .collect::<Result<Vec<_>, _>>()
+            .inspect_err(|e| error!("Error reading LLM usage totals: {}", e))
+            .ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn prices() -> PriceTable {
+        serde_json::from_str(include_str!("../../../assets/data/llm_prices.json")).unwrap()
+    }
+
+    fn usage(
+        prompt_tokens: u32,
+        completion_tokens: u32,
+    ) -> Usage {
+        Usage {
+            prompt_tokens,
+            completion_tokens,
+            total_tokens: prompt_tokens + completion_tokens,
+            ..Default::default()
+        }
+    }
+
+    fn group(
+        operation: LlmOperation,
+        calls: u64,
+        total_tokens: u64,
+        cost: f64,
+    ) -> UsageGroup {
+        UsageGroup {
+            user_id: "user".to_string(),
+            operation,
+            summary: UsageSummary {
+                calls,
+                total_tokens,
+                cost,
+                ..Default::default()
+            },
+        }
+    }
+
+    #[test]
+    fn the_longest_matching_prefix_prices_a_model() {
+        let prices = prices();
+        assert_eq!(prices.price_for("gpt-4o").unwrap().prompt, 2.5);
+        assert_eq!(prices.price_for("gpt-4o-2024-08-06").unwrap().prompt, 2.5);
+        assert_eq!(prices.price_for("gpt-4o-mini-2024-07-18").unwrap().prompt, 0.15);
+        assert_eq!(prices.price_for("o1-mini").unwrap().completion, 4.4);
+    }
+
+    #[test]
+    fn an_unknown_model_has_no_price_and_costs_nothing() {
+        let prices = prices();
+        assert!(prices.price_for("llama3.1").is_none());
+        assert!(prices.price_for("my-gpt-4o").is_none());
+        assert_eq!(prices.cost("llama3.1", &usage(1_000, 1_000)), 0.0);
+    }
+
+    #[test]
+    fn cost_is_priced_per_token_kind() {
+        let prices = prices();
+        // (2000 * 2.5 + 500 * 10.0) / 1 000 000
+        assert!((prices.cost("gpt-4o-2024-08-06", &usage(2_000, 500)) - 0.01).abs() < 1e-12);
+        assert!((prices.cost("gpt-4o-mini", &usage(1_000_000, 1_000_000)) - 0.75).abs() < 1e-12);
+        assert_eq!(prices.cost("gpt-4o", &usage(0, 0)), 0.0);
+    }
+
+    #[test]
+    fn groups_are_totalled_per_operation_by_cost() {
+        let groups = [
+            group(LlmOperation::ParseResume, 2, 300, 0.02),
+            group(LlmOperation::ScoreResume, 1, 900, 0.05),
+            group(LlmOperation::ParseResume, 1, 100, 0.01),
+        ];
+        let (total, by_operation) = UsageSummary::from_groups(&groups);
+        assert_eq!((total.calls, total.total_tokens), (4, 1_300));
+        assert!((total.cost - 0.08).abs() < 1e-12);
+        let order: Vec<_> = by_operation.iter().map(|o| (o.operation, o.summary.calls)).collect();
+        assert_eq!(order, vec![(LlmOperation::ScoreResume, 1), (LlmOperation::ParseResume, 3)]);
+    }
+}
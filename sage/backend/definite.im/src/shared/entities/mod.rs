@@ -1 +1,2 @@
+pub mod llm_usage;
 pub mod user;
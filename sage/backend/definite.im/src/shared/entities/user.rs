@@ -1,16 +1,17 @@
+use async_trait::async_trait;
 use log::{error, info};
 use mongodb::{
     bson::{doc, oid::ObjectId},
-    Client, Collection,
+    Client,
 };
 use serde::{Deserialize, Serialize};
 
 use crate::shared::models::google::GoogleUserModel;
-use crate::shared::ops::db_ops::Database;
+use crate::shared::ops::repository_ops::{Repository, RepositoryError};
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct UserEntity {
-    pub _id: Option<ObjectId>,
+    pub _id: ObjectId,
     pub google_id: String,
     pub email: String,
     pub verified_email: bool,
@@ -18,12 +19,18 @@ pub struct UserEntity {
     pub given_name: String,
     pub family_name: String,
     pub picture: String,
+    /// Quota plan, see `QuotaPlans`, `None` is the default plan
+    #[serde(default)]
+    pub plan: Option<String>,
+    /// Bumped by every `Repository::save`
+    #[serde(default)]
+    pub revision: u32,
 }
 
 impl Default for UserEntity {
     fn default() -> Self {
         UserEntity {
-            _id: None,
+            _id: ObjectId::new(),
             google_id: "not-set".to_string(),
             email: "not-set".to_string(),
             verified_email: false,
@@ -31,18 +38,49 @@ impl Default for UserEntity {
             given_name: "not-set".to_string(),
             family_name: "not-set".to_string(),
             picture: "not-set".to_string(),
+            plan: None,
+            revision: 0,
         }
     }
 }
 
-impl UserEntity {
-    pub fn get_collection(mongoc: &Client) -> Collection<UserEntity> {
-        Database::get_collection::<UserEntity>(mongoc, "users")
+#[async_trait]
+impl Repository for UserEntity {
+    const COLLECTION: &'static str = "users";
+    /// A user owns their own record
+    const OWNER_FIELD: &'static str = "google_id";
+
+    fn id(&self) -> ObjectId {
+        self._id
+    }
+
+    fn set_id(
+        &mut self,
+        id: ObjectId,
+    ) {
+        self._id = id;
+    }
+
+    fn owner(&self) -> &str {
+        &self.google_id
+    }
+
+    fn revision(&self) -> u32 {
+        self.revision
+    }
+
+    fn set_revision(
+        &mut self,
+        revision: u32,
+    ) {
+        self.revision = revision;
     }
+}
 
+impl UserEntity {
     pub fn from(user: GoogleUserModel) -> UserEntity {
         UserEntity {
-            _id: ObjectId::new().into(),
+            _id: ObjectId::new(),
             google_id: user.id,
             email: user.email.clone(),
             verified_email: user.verified_email,
@@ -50,30 +88,31 @@ impl UserEntity {
             given_name: user.given_name,
             family_name: user.family_name,
             picture: user.picture,
+            plan: None,
+            revision: 0,
         }
     }
 
-    pub async fn create(
+    /// Stores a user seen for the first time, a returning user's record (and plan) is left as it is
+    pub async fn find_or_create(
         &self,
         mongoc: &Client,
-    ) -> Option<String> {
-        let collection = Self::get_collection(mongoc);
-        let user = match Database::filter(collection.clone(), doc! {"google_id": self.google_id.clone()}).await {
-            Some(r) => Some(r),
-            None => {
-                error!("Failed to get user: {:?}", self);
-                None
-            }
-        };
-
-        if user.is_some() {
+    ) -> Result<ObjectId, RepositoryError> {
+        if let Some(user) = Self::find_one(mongoc, &self.google_id, doc! {}).await? {
             info!("User already exists: {:?}", user);
-            return Some(user.unwrap()._id.unwrap().to_hex());
+            return Ok(user._id);
         }
-        match Database::create(&collection, self).await {
-            Some(r) => Some(r),
-            None => {
-                error!("Failed to create user: {:?}", self);
+        self.create(mongoc).await.inspect_err(|e| error!("Failed to create user {:?}: {}", self, e))
+    }
+
+    pub async fn find_plan(
+        mongoc: &Client,
+        google_id: &str,
+    ) -> Option<String> {
+        match Self::find_one(mongoc, google_id, doc! {}).await {
+            Ok(user) => user.and_then(|u| u.plan),
+            Err(e) => {
+                error!("Error finding plan for user {}: {}", google_id, e);
                 None
             }
         }
@@ -0,0 +1,79 @@
+use actix_service::{Service, Transform};
+use actix_session::SessionExt;
+use actix_web::{
+    body::BoxBody,
+    dev::{ServiceRequest, ServiceResponse},
+    Error, HttpResponse,
+};
+use futures::future::{ok, LocalBoxFuture, Ready};
+use futures::FutureExt;
+use log::{debug, info};
+
+use crate::shared::{
+    auth::user::UserAuth,
+    ops::environ_ops::{Environ, Environment, WebConfig},
+};
+
+/// Lets only `ADMIN_EMAILS` through, wrap it inside `LoginInterceptor` so the session is already verified
+pub struct AdminInterceptor;
+
+impl<S> Transform<S, ServiceRequest> for AdminInterceptor
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<BoxBody>, Error = Error> + 'static,
+{
+    type Response = ServiceResponse<BoxBody>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = AdminMiddlewareService<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(
+        &self,
+        service: S,
+    ) -> Self::Future {
+        ok(AdminMiddlewareService { service })
+    }
+}
+
+pub struct AdminMiddlewareService<S> {
+    service: S,
+}
+
+impl<S> Service<ServiceRequest> for AdminMiddlewareService<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<BoxBody>, Error = Error> + 'static,
+{
+    type Response = ServiceResponse<BoxBody>;
+    type Error = S::Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(
+        &self,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(
+        &self,
+        service_req: ServiceRequest,
+    ) -> Self::Future {
+        info!("AdminVerifier");
+        let web_config: WebConfig = Environ::init();
+        if Environment::get_env() == Environment::Dev && web_config.allow_debug {
+            debug!("Dev environment AND debug is allowed, proceeding with admin request");
+            return self.service.call(service_req).boxed_local();
+        }
+        let user_auth = UserAuth::from(service_req.get_session());
+        if user_auth.is_admin() {
+            debug!("Admin verified, proceeding with request");
+            self.service.call(service_req).boxed_local()
+        } else {
+            debug!("Not an admin, rejecting request");
+            let response = HttpResponse::Forbidden().finish().map_into_boxed_body();
+            let (request, _payload) = service_req.into_parts();
+            let service_response = ServiceResponse::new(request, response);
+            async { Ok(service_response) }.boxed_local()
+        }
+    }
+}
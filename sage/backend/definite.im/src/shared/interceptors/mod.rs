@@ -1 +1,3 @@
+pub mod admin_interceptor;
 pub mod login_interceptor;
+pub mod quota_interceptor;
@@ -0,0 +1,126 @@
+use std::rc::Rc;
+
+use actix_service::{Service, Transform};
+use actix_session::SessionExt;
+use actix_web::{
+    body::BoxBody,
+    dev::{ServiceRequest, ServiceResponse},
+    http::Method,
+    web, Error, HttpResponse,
+};
+use futures::future::{ok, LocalBoxFuture, Ready};
+use futures::FutureExt;
+use handlebars::Handlebars;
+use log::{debug, error, info};
+use mongodb::Client;
+use serde_json::json;
+
+use crate::{
+    html_renderer::render_fragment_html,
+    shared::{
+        auth::user::UserAuth,
+        ops::{
+            cache_ops::Cache,
+            environ_ops::{Environ, QuotaConfig},
+            quota_ops::{check_quota, QuotaExceeded},
+        },
+    },
+};
+
+/// Turns away non-GET requests to the wrapped resource once the user is out of quota. Nothing is spent here,
+/// the quota is spent where the provider is called so an answer from the completion cache is free.
+/// Wrap only resources whose POST triggers an LLM call, inside `LoginInterceptor`.
+pub struct QuotaInterceptor;
+
+impl<S> Transform<S, ServiceRequest> for QuotaInterceptor
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<BoxBody>, Error = Error> + 'static,
+{
+    type Response = ServiceResponse<BoxBody>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = QuotaMiddlewareService<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(
+        &self,
+        service: S,
+    ) -> Self::Future {
+        ok(QuotaMiddlewareService { service: Rc::new(service) })
+    }
+}
+
+pub struct QuotaMiddlewareService<S> {
+    // the plan lookup is async, so the service is moved into the returned future
+    service: Rc<S>,
+}
+
+impl<S> Service<ServiceRequest> for QuotaMiddlewareService<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<BoxBody>, Error = Error> + 'static,
+{
+    type Response = ServiceResponse<BoxBody>;
+    type Error = S::Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(
+        &self,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(
+        &self,
+        service_req: ServiceRequest,
+    ) -> Self::Future {
+        info!("QuotaVerifier");
+        let quota_config: QuotaConfig = Environ::init();
+        if !quota_config.quota_enabled || service_req.method() == Method::GET {
+            return self.service.call(service_req).boxed_local();
+        }
+        let service = self.service.clone();
+        async move {
+            let user_id = match UserAuth::from(service_req.get_session()).google_model {
+                Some(g) => g.id,
+                None => {
+                    debug!("No user in session, skipping quota");
+                    return service.call(service_req).await;
+                }
+            };
+            let (cache, mongoc) = match (service_req.app_data::<web::Data<Cache>>(), service_req.app_data::<web::Data<Client>>()) {
+                (Some(c), Some(m)) => (c.clone(), m.clone()),
+                _ => {
+                    error!("Cache or Mongo client not registered, skipping quota");
+                    return service.call(service_req).await;
+                }
+            };
+            match check_quota(&cache, &mongoc, &user_id).await {
+                Ok(()) => service.call(service_req).await,
+                Err(exceeded) => Ok(quota_exceeded(service_req, exceeded)),
+            }
+        }
+        .boxed_local()
+    }
+}
+
+fn quota_exceeded(
+    service_req: ServiceRequest,
+    exceeded: QuotaExceeded,
+) -> ServiceResponse<BoxBody> {
+    let (message, retry_after_secs) = (exceeded.message(), exceeded.retry_after_secs());
+    let context = json!({
+        "message": message,
+        "retry_after_secs": retry_after_secs,
+        "navigate_url": "/resumes/dashboard",
+        "navigate_text": "Go to dashboard",
+    });
+    let body = match service_req.app_data::<web::Data<Handlebars<'static>>>() {
+        Some(handlebars) => render_fragment_html(handlebars, "quota-exceeded", context),
+        None => message,
+    };
+    // htmx only swaps 2xx responses, so like the other error fragments this is a 200
+    let response = HttpResponse::Ok().insert_header(("Retry-After", retry_after_secs.to_string())).body(body).map_into_boxed_body();
+    let (request, _payload) = service_req.into_parts();
+    ServiceResponse::new(request, response)
+}
@@ -1,45 +1,95 @@
 extern crate redis;
 
-use log::{debug, info};
-use redis::{Commands, FromRedisValue, ToRedisArgs};
+use std::{
+    fmt,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use log::{debug, info, warn};
+use redis::{aio::MultiplexedConnection, AsyncCommands, AsyncConnectionConfig, FromRedisValue, RedisError};
 use serde::de::DeserializeOwned;
 use serde::Serialize;
 use serde_json;
-use std::sync::{Arc, Mutex};
+use tokio::sync::Mutex;
+
+use crate::shared::ops::environ_ops::RedisConfig;
+
+/// A request waits at most this long for Redis to accept a connection
+const CONNECTION_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// ...and this long for an answer, a slow Redis is treated like a missing one
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// After a failed connect, calls fail fast for this long instead of each waiting on Redis
+const RECONNECT_AFTER: Duration = Duration::from_secs(5);
+
+/// Why a cache call failed. Callers treat the cache as optional and carry on without it.
+#[derive(Debug)]
+pub enum CacheError {
+    /// Redis could not be reached, the next call tries to connect again
+    Unavailable {
+        message: String,
+    },
+    Command {
+        message: String,
+    },
+    Serialize {
+        message: String,
+    },
+    /// The stored value is not a `T`, usually written before a change to `T`
+    Deserialize {
+        key: String,
+        message: String,
+    },
+}
 
-use crate::shared::ops::environ_ops::{Environ, RedisConfig};
+impl fmt::Display for CacheError {
+    fn fmt(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+    ) -> fmt::Result {
+        match self {
+            CacheError::Unavailable { message } => write!(f, "cache unavailable: {}", message),
+            CacheError::Command { message } => write!(f, "cache command failed: {}", message),
+            CacheError::Serialize { message } => write!(f, "value could not be serialized for the cache: {}", message),
+            CacheError::Deserialize { key, message } => write!(f, "cached value for {} could not be deserialized: {}", key, message),
+        }
+    }
+}
 
+impl std::error::Error for CacheError {}
+
+/// Redis behind one multiplexed connection shared by every request. Commands run concurrently on it,
+/// the lock is only held to hand out a clone. The connection is made on first use and again after it breaks,
+/// so the app starts while Redis is down and recovers once it is back.
 #[derive(Clone)]
 pub struct Cache {
-    redis_conn: Arc<Mutex<redis::Connection>>, // Reuse the connection
+    client: redis::Client,
+    connection: Arc<Mutex<ConnectionSlot>>,
     cache_key_prefix: String,
 }
 
-impl Default for Cache {
-    fn default() -> Self {
-        let redis_config: RedisConfig = Environ::init();
-        let connection_str = redis_config.redis_server.clone();
-        println!("Connecting to Redis at: {}", connection_str);
-        let client = match redis::Client::open(connection_str.clone()) {
-            Ok(c) => c,
-            Err(e) => {
-                panic!("Error creating Redis client at {}: {:?}", connection_str, e)
-            }
-        };
-        let conn = match client.get_connection() {
-            Ok(c) => c,
-            Err(e) => {
-                panic!("Error connecting to Redis: {:?}", e)
-            }
-        };
-        Cache {
-            redis_conn: Arc::new(Mutex::new(conn)), // Store the connection in an Arc<Mutex<>> for sharing and safety
-            cache_key_prefix: redis_config.redis_key_prefix.clone(),
-        }
-    }
+#[derive(Default)]
+struct ConnectionSlot {
+    connection: Option<MultiplexedConnection>,
+    failed_at: Option<Instant>,
 }
 
 impl Cache {
+    /// Only fails for a malformed `redis_server` url, nothing is connected yet
+    pub fn new(redis_config: &RedisConfig) -> Result<Self, CacheError> {
+        info!("Using Redis at: {}", redis_config.redis_server);
+        let client = redis::Client::open(redis_config.redis_server.as_str()).map_err(|e| CacheError::Unavailable {
+            message: format!("invalid Redis url {}: {}", redis_config.redis_server, e),
+        })?;
+        Ok(Cache {
+            client,
+            connection: Arc::new(Mutex::new(ConnectionSlot::default())),
+            cache_key_prefix: redis_config.redis_key_prefix.clone(),
+        })
+    }
+
     fn build_key(
         &self,
         key: &str,
@@ -47,116 +97,114 @@ impl Cache {
         format!("{}{}", self.cache_key_prefix, key)
     }
 
-    pub fn get_scalar<T: FromRedisValue>(
-        &self,
-        key: &str,
-    ) -> Option<T> {
-        let mut conn = match self.redis_conn.lock() {
-            Ok(c) => c,
-            Err(e) => {
-                println!("Error getting Redis connection: {:?}", e);
-                return None;
+    async fn connection(&self) -> Result<MultiplexedConnection, CacheError> {
+        let mut slot = self.connection.lock().await;
+        if let Some(c) = slot.connection.as_ref() {
+            return Ok(c.clone());
+        }
+        if slot.failed_at.is_some_and(|t| t.elapsed() < RECONNECT_AFTER) {
+            return Err(CacheError::Unavailable {
+                message: "waiting to reconnect".to_string(),
+            });
+        }
+        let config = AsyncConnectionConfig::new().set_connection_timeout(CONNECTION_TIMEOUT).set_response_timeout(RESPONSE_TIMEOUT);
+        match self.client.get_multiplexed_async_connection_with_config(&config).await {
+            Ok(c) => {
+                info!("Connected to Redis");
+                *slot = ConnectionSlot {
+                    connection: Some(c.clone()),
+                    failed_at: None,
+                };
+                Ok(c)
             }
-        }; // Reuse the connection
-        let redis_key = self.build_key(key);
-        debug!("Getting key: {}", redis_key);
-        match conn.get(redis_key) {
-            Ok(value) => Some(value),
             Err(e) => {
-                println!("Error getting value from Redis: {:?}", e);
-                None
+                warn!("Error connecting to Redis: {}", e);
+                slot.failed_at = Some(Instant::now());
+                Err(CacheError::Unavailable { message: e.to_string() })
             }
         }
     }
 
-    // Removed the specific set_scalar method for String values
+    /// Drops a connection that broke so the next call makes a new one
+    async fn command_error(
+        &self,
+        e: RedisError,
+    ) -> CacheError {
+        if e.is_io_error() || e.is_connection_dropped() || e.is_connection_refusal() || e.is_timeout() {
+            warn!("Lost the Redis connection: {}", e);
+            self.connection.lock().await.connection = None;
+            return CacheError::Unavailable { message: e.to_string() };
+        }
+        CacheError::Command { message: e.to_string() }
+    }
 
-    pub fn set_scalar<T: ToRedisArgs>(
+    /// The json value stored under `key`, `None` if there is none or it has expired
+    pub async fn get<T: DeserializeOwned>(
         &self,
         key: &str,
-        value: T,
-    ) {
-        let mut conn = match self.redis_conn.lock() {
-            Ok(c) => c,
-            Err(e) => {
-                println!("Error getting Redis connection: {:?}", e);
-                return;
-            }
-        }; // Reuse the connection
+    ) -> Result<Option<T>, CacheError> {
         let redis_key = self.build_key(key);
-        let _: () = conn.set::<String, T, ()>(redis_key, value).unwrap();
+        debug!("Getting key: {}", redis_key);
+        let mut conn = self.connection().await?;
+        let json_str = match conn.get::<&str, Option<String>>(&redis_key).await {
+            Ok(Some(s)) => s,
+            Ok(None) => return Ok(None),
+            Err(e) => return Err(self.command_error(e).await),
+        };
+        serde_json::from_str(&json_str).map(Some).map_err(|e| CacheError::Deserialize {
+            key: redis_key,
+            message: e.to_string(),
+        })
     }
 
-    pub fn set_list<T: Serialize>(
+    /// Stores `value` as json, expiring after `ttl` (at least a second)
+    pub async fn set<T: Serialize>(
         &self,
         key: &str,
-        value: &Vec<T>,
-    ) -> bool {
-        let mut conn = match self.redis_conn.lock() {
-            Ok(c) => c,
-            Err(e) => {
-                println!("Error getting Redis connection: {:?}", e);
-                return false;
-            }
-        };
+        value: &T,
+        ttl: Duration,
+    ) -> Result<(), CacheError> {
+        let json_str = serde_json::to_string(value).map_err(|e| CacheError::Serialize { message: e.to_string() })?;
         let redis_key = self.build_key(key);
-        info!("Setting list key: {}", key);
-        let _: () = conn.del(redis_key.clone()).unwrap(); // Clear the list
-        for item in value {
-            if let Ok(json_str) = serde_json::to_string(item) {
-                let _: () = conn.rpush(redis_key.clone(), json_str).unwrap();
-            }
+        debug!("Setting key: {} for {}s", redis_key, ttl.as_secs());
+        let mut conn = self.connection().await?;
+        match conn.set_ex::<String, String, ()>(redis_key, json_str, ttl.as_secs().max(1)).await {
+            Ok(()) => Ok(()),
+            Err(e) => Err(self.command_error(e).await),
         }
-        true
     }
 
-    pub fn get_range<T: DeserializeOwned>(
+    /// Runs a Lua script atomically, `keys` are prefixed like every other cache key
+    pub async fn eval_script<T: FromRedisValue>(
         &self,
-        key: &str,
-        start: isize,
-        stop: isize,
-    ) -> Option<Vec<T>> {
-        info!("Getting range for key: {}", key);
-        let mut conn = match self.redis_conn.lock() {
-            Ok(c) => c,
-            Err(e) => {
-                println!("Error getting Redis connection: {:?}", e);
-                return None;
-            }
-        }; // Reuse the connection
-        let redis_key = self.build_key(key);
-        debug!("Using Redis key: {}", redis_key);
-
-        if let Ok(json_strs) = conn.lrange::<_, Vec<String>>(redis_key, start, stop) {
-            let mut items: Vec<T> = vec![];
-            for json_str in json_strs {
-                if let Ok(item) = serde_json::from_str(&json_str) {
-                    items.push(item);
-                }
-            }
-            if items.is_empty() {
-                None
-            } else {
-                Some(items)
-            }
-        } else {
-            None
+        script: &redis::Script,
+        keys: &[String],
+        args: &[String],
+    ) -> Result<T, CacheError> {
+        let mut conn = self.connection().await?;
+        let mut invocation = script.prepare_invoke();
+        for key in keys {
+            invocation.key(self.build_key(key));
+        }
+        for arg in args {
+            invocation.arg(arg);
+        }
+        match invocation.invoke_async(&mut conn).await {
+            Ok(value) => Ok(value),
+            Err(e) => Err(self.command_error(e).await),
         }
     }
 
-    pub fn remove(
+    /// `true` if there was a value to remove
+    pub async fn remove(
         &self,
         key: &str,
-    ) -> bool {
+    ) -> Result<bool, CacheError> {
         info!("Removing key: {}", key);
-        let mut conn = match self.redis_conn.lock() {
-            Ok(c) => c,
-            Err(e) => {
-                println!("Error getting Redis connection: {:?}", e);
-                return false;
-            }
-        }; // Reuse the connection
-        let redis_key = self.build_key(key);
-        conn.del(redis_key).unwrap_or(0) > 0
+        let mut conn = self.connection().await?;
+        match conn.del::<String, i64>(self.build_key(key)).await {
+            Ok(removed) => Ok(removed > 0),
+            Err(e) => Err(self.command_error(e).await),
+        }
     }
 }
@@ -39,6 +39,11 @@ pub fn to_timestamp() -> i64 {
     Local::now().timestamp()
 }
 
+/// For timestamps that order events, two of them in the same second still compare
+pub fn to_timestamp_millis() -> i64 {
+    Local::now().timestamp_millis()
+}
+
 pub fn to_year_only() -> String {
     let date = from(to_timestamp());
     let target_format = "%Y";
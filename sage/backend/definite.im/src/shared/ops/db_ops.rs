@@ -1,13 +1,19 @@
 use std::fmt::{Debug, Error};
 
-use futures::stream::TryStreamExt;
+use futures::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    stream::TryStreamExt,
+};
 use log::{debug, error};
 use mongodb::{
     bson::{self, doc, oid::ObjectId, Document},
-    Client, Collection,
+    error::{ErrorKind, WriteFailure},
+    options::{GridFsBucketOptions, IndexOptions},
+    Client, Collection, IndexModel,
 };
 
 use serde::{de::DeserializeOwned, Serialize};
+use tokio::sync::OnceCell;
 
 use crate::shared::ops::environ_ops::{DatabaseConfig, Environ};
 
@@ -28,6 +34,60 @@ impl Database {
         }
     }
 
+    /// A client shared by code that has no `web::Data<Client>` at hand (e.g. the completion pipeline)
+    pub async fn shared_client() -> &'static Client {
+        static CLIENT: OnceCell<Client> = OnceCell::const_new();
+        CLIENT.get_or_init(Self::get_client).await
+    }
+
+    /// Whether a write failed because a unique index already has its key, e.g. two saves racing for the same number
+    pub fn is_duplicate_key(e: &mongodb::error::Error) -> bool {
+        matches!(e.kind.as_ref(), ErrorKind::Write(WriteFailure::WriteError(w)) if w.code == 11000)
+    }
+
+    /// Creates the unique index `name` on `keys` unless it is there. Fails while the collection holds duplicates.
+    pub async fn ensure_unique_index<T>(
+        collection: &Collection<T>,
+        name: &str,
+        keys: Document,
+    ) -> Result<(), String>
+    where
+        T: Send + Sync,
+    {
+        Self::create_index(collection, name, keys, true).await
+    }
+
+    /// Creates the index `name` on `keys` unless it is there, for lookups that sort or filter on them
+    pub async fn ensure_index<T>(
+        collection: &Collection<T>,
+        name: &str,
+        keys: Document,
+    ) -> Result<(), String>
+    where
+        T: Send + Sync,
+    {
+        Self::create_index(collection, name, keys, false).await
+    }
+
+    async fn create_index<T>(
+        collection: &Collection<T>,
+        name: &str,
+        keys: Document,
+        unique: bool,
+    ) -> Result<(), String>
+    where
+        T: Send + Sync,
+    {
+        let index = IndexModel::builder().keys(keys).options(IndexOptions::builder().name(name.to_string()).unique(unique).build()).build();
+        match collection.create_index(index).await {
+            Ok(_) => {
+                debug!("Index {} on {} is in place", name, collection.name());
+                Ok(())
+            }
+            Err(e) => Err(format!("Error creating index {} on {}: {}", name, collection.name(), e)),
+        }
+    }
+
     pub fn get_collection<T>(
         client: &Client,
         collection_name: &str,
@@ -189,4 +249,61 @@ impl Database {
             }
         }
     }
+
+    /// Stores a file in the GridFS `bucket` and returns its id
+    pub async fn upload_file(
+        client: &Client,
+        bucket: &str,
+        filename: &str,
+        bytes: &[u8],
+        metadata: Document,
+    ) -> Option<ObjectId> {
+        let db_config: DatabaseConfig = Environ::init();
+        let bucket = client
+            .database(db_config.db_name.as_str())
+            .gridfs_bucket(GridFsBucketOptions::builder().bucket_name(bucket.to_string()).build());
+        let mut stream = match bucket.open_upload_stream(filename).metadata(metadata).await {
+            Ok(s) => s,
+            Err(e) => {
+                error!("Error opening upload stream for {}: {}", filename, e);
+                return None;
+            }
+        };
+        if let Err(e) = stream.write_all(bytes).await {
+            error!("Error writing {} to GridFS: {}", filename, e);
+            return None;
+        }
+        if let Err(e) = stream.close().await {
+            error!("Error closing upload stream for {}: {}", filename, e);
+            return None;
+        }
+        stream.id().as_object_id()
+    }
+
+    /// Reads a whole file back from the GridFS `bucket`
+    pub async fn download_file(
+        client: &Client,
+        bucket: &str,
+        id: &ObjectId,
+    ) -> Option<Vec<u8>> {
+        let db_config: DatabaseConfig = Environ::init();
+        let bucket = client
+            .database(db_config.db_name.as_str())
+            .gridfs_bucket(GridFsBucketOptions::builder().bucket_name(bucket.to_string()).build());
+        let mut stream = match bucket.open_download_stream((*id).into()).await {
+            Ok(s) => s,
+            Err(e) => {
+                error!("Error opening download stream for {}: {}", id, e);
+                return None;
+            }
+        };
+        let mut bytes = vec![];
+        match stream.read_to_end(&mut bytes).await {
+            Ok(_) => Some(bytes),
+            Err(e) => {
+                error!("Error reading {} from GridFS: {}", id, e);
+                None
+            }
+        }
+    }
 }
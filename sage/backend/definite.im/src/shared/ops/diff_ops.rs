@@ -0,0 +1,105 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeKind {
+    Equal,
+    Added,
+    Removed,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Change<T> {
+    pub kind: ChangeKind,
+    pub value: T,
+}
+
+/// Longest-common-subsequence diff of two sequences, removals are listed before additions at each change
+pub fn diff<T: PartialEq + Clone>(
+    old: &[T],
+    new: &[T],
+) -> Vec<Change<T>> {
+    // lcs[i][j] is the length of the common subsequence of old[i..] and new[j..]
+    let mut lcs = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = if old[i] == new[j] { lcs[i + 1][j + 1] + 1 } else { lcs[i + 1][j].max(lcs[i][j + 1]) };
+        }
+    }
+    let (mut i, mut j) = (0, 0);
+    let mut changes = Vec::with_capacity(old.len().max(new.len()));
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            changes.push(Change {
+                kind: ChangeKind::Equal,
+                value: old[i].clone(),
+            });
+            i += 1;
+            j += 1;
+        } else if i < old.len() && (j == new.len() || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            changes.push(Change {
+                kind: ChangeKind::Removed,
+                value: old[i].clone(),
+            });
+            i += 1;
+        } else {
+            changes.push(Change {
+                kind: ChangeKind::Added,
+                value: new[j].clone(),
+            });
+            j += 1;
+        }
+    }
+    changes
+}
+
+/// Line diff of two texts, ignoring trailing whitespace and blank lines
+pub fn diff_lines(
+    old: &str,
+    new: &str,
+) -> Vec<Change<String>> {
+    let lines = |text: &str| text.lines().map(|l| l.trim_end().to_string()).filter(|l| !l.is_empty()).collect::<Vec<String>>();
+    diff(&lines(old), &lines(new))
+}
+
+/// Word diff of two texts, consecutive words of the same kind are joined into one change
+pub fn diff_words(
+    old: &str,
+    new: &str,
+) -> Vec<Change<String>> {
+    let old: Vec<&str> = old.split_whitespace().collect();
+    let new: Vec<&str> = new.split_whitespace().collect();
+    let mut merged: Vec<Change<String>> = vec![];
+    for change in diff(&old, &new) {
+        match merged.last_mut() {
+            Some(last) if last.kind == change.kind => {
+                last.value.push(' ');
+                last.value.push_str(change.value);
+            }
+            _ => merged.push(Change {
+                kind: change.kind,
+                value: change.value.to_string(),
+            }),
+        }
+    }
+    merged
+}
+
+/// Share of distinct lowercase words the two texts have in common, 0.0 to 1.0
+pub fn word_similarity(
+    a: &str,
+    b: &str,
+) -> f64 {
+    let words = |text: &str| {
+        text.split_whitespace()
+            .map(|w| w.trim_matches(|c: char| !c.is_alphanumeric()).to_lowercase())
+            .filter(|w| !w.is_empty())
+            .collect::<std::collections::HashSet<String>>()
+    };
+    let (a, b) = (words(a), words(b));
+    let union = a.union(&b).count();
+    if union == 0 {
+        return 1.0;
+    }
+    a.intersection(&b).count() as f64 / union as f64
+}
@@ -13,6 +13,72 @@ pub struct OpenAIConfig {
     pub api_key: String,
     pub model: String,
     pub temperature: f64,
+    /// Attempts after the first one for transient failures (network, 408, 429, 5xx)
+    pub max_retries: u32,
+    pub initial_backoff_ms: u64,
+    pub max_backoff_ms: u64,
+    /// Upper bound for a single attempt, including reading the body
+    pub request_timeout_secs: u64,
+    /// Upper bound for all attempts of one call together, backoff included
+    pub request_deadline_secs: u64,
+    /// Consecutive failed attempts before the circuit opens
+    pub breaker_failure_threshold: u32,
+    /// How long an open circuit rejects calls before letting a trial call through
+    pub breaker_cooldown_secs: u64,
+}
+
+/// The backend that serves chat completions.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub enum LlmProviderKind {
+    OpenAI,
+    AzureOpenAI,
+    /// Ollama, llama.cpp server or any other OpenAI-compatible local endpoint
+    Ollama,
+    /// Deterministic in-process provider for tests and offline development
+    Fake,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct LlmConfig {
+    pub llm_provider: LlmProviderKind,
+    pub llm_base_url: Option<String>,
+    pub azure_deployment: Option<String>,
+    pub azure_api_version: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct UsageConfig {
+    /// Json file with per-model token prices, see `assets/data/llm_prices.json`
+    pub llm_price_table: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct QuotaConfig {
+    /// Json file with per-plan request quotas, see `assets/data/quota_plans.json`
+    pub quota_plans: String,
+    pub quota_enabled: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RubricConfig {
+    /// Json file with the scoring rubrics, see `assets/data/rubric.json`. Edits are picked up without a restart.
+    pub rubric_path: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct UploadConfig {
+    /// Largest résumé file accepted, checked while the upload streams in
+    pub upload_max_bytes: usize,
+    /// GridFS bucket that keeps the original files
+    pub upload_bucket: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct QueueConfig {
+    /// How long a worker may hold a job before it is handed to another worker
+    pub queue_visibility_timeout_secs: u64,
+    /// Attempts before a job is moved to its dead-letter queue
+    pub queue_max_attempts: u32,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -38,6 +104,8 @@ pub struct AuthConfig {
     pub google_callback_uri: String,
     pub jwt_secret: String,
     pub auth_disabled: bool,
+    /// Google account emails allowed into `/admin`
+    pub admin_emails: Vec<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -78,6 +146,12 @@ impl Config for AuthConfig {
             google_callback_uri: env::var("GOOGLE_CALLBACK_URI").expect("Missing GOOGLE_CALLBACK_URI"),
             jwt_secret: env::var("JWT_SECRET").expect("Missing JWT_SECRET"),
             auth_disabled: env::var("AUTH_DISABLED").unwrap_or("false".to_string()).parse::<bool>().expect("AUTH_DISABLED must be a boolean"),
+            admin_emails: env::var("ADMIN_EMAILS")
+                .unwrap_or_default()
+                .split(',')
+                .map(|e| e.trim().to_lowercase())
+                .filter(|e| !e.is_empty())
+                .collect(),
         }
     }
 }
@@ -95,12 +169,105 @@ impl Config for RedisConfig {
 impl Config for OpenAIConfig {
     fn from_env() -> Self {
         OpenAIConfig {
-            api_key: env::var("OPENAI_API_KEY").expect("Missing OPENAI_API_KEY"),
+            // local and fake providers do not need a key
+            api_key: env::var("OPENAI_API_KEY").unwrap_or_default(),
             model: env::var("OPENAI_MODEL").expect("Missing OPENAI_MODEL"),
             temperature: env::var("OPENAI_TEMPERATURE")
                 .expect("Missing OPENAI_TEMPERATURE")
                 .parse::<f64>()
                 .expect("OPENAI_TEMPERATURE must be a number"),
+            max_retries: env::var("OPENAI_MAX_RETRIES").unwrap_or("3".to_string()).parse::<u32>().expect("OPENAI_MAX_RETRIES must be a number"),
+            initial_backoff_ms: env::var("OPENAI_INITIAL_BACKOFF_MS")
+                .unwrap_or("500".to_string())
+                .parse::<u64>()
+                .expect("OPENAI_INITIAL_BACKOFF_MS must be a number"),
+            max_backoff_ms: env::var("OPENAI_MAX_BACKOFF_MS")
+                .unwrap_or("8000".to_string())
+                .parse::<u64>()
+                .expect("OPENAI_MAX_BACKOFF_MS must be a number"),
+            request_timeout_secs: env::var("OPENAI_REQUEST_TIMEOUT_SECS")
+                .unwrap_or("120".to_string())
+                .parse::<u64>()
+                .expect("OPENAI_REQUEST_TIMEOUT_SECS must be a number"),
+            request_deadline_secs: env::var("OPENAI_REQUEST_DEADLINE_SECS")
+                .unwrap_or("300".to_string())
+                .parse::<u64>()
+                .expect("OPENAI_REQUEST_DEADLINE_SECS must be a number"),
+            breaker_failure_threshold: env::var("OPENAI_BREAKER_FAILURE_THRESHOLD")
+                .unwrap_or("5".to_string())
+                .parse::<u32>()
+                .expect("OPENAI_BREAKER_FAILURE_THRESHOLD must be a number"),
+            breaker_cooldown_secs: env::var("OPENAI_BREAKER_COOLDOWN_SECS")
+                .unwrap_or("30".to_string())
+                .parse::<u64>()
+                .expect("OPENAI_BREAKER_COOLDOWN_SECS must be a number"),
+        }
+    }
+}
+
+impl Config for LlmConfig {
+    fn from_env() -> Self {
+        let llm_provider = match env::var("LLM_PROVIDER").unwrap_or("openai".to_string()).to_lowercase().as_str() {
+            "openai" => LlmProviderKind::OpenAI,
+            "azure" | "azure_openai" => LlmProviderKind::AzureOpenAI,
+            "ollama" | "llamacpp" | "llama.cpp" => LlmProviderKind::Ollama,
+            "fake" => LlmProviderKind::Fake,
+            other => panic!("LLM_PROVIDER must be one of openai, azure, ollama or fake, got {}", other),
+        };
+        LlmConfig {
+            llm_provider,
+            llm_base_url: env::var("LLM_BASE_URL").ok(),
+            azure_deployment: env::var("AZURE_OPENAI_DEPLOYMENT").ok(),
+            azure_api_version: env::var("AZURE_OPENAI_API_VERSION").ok(),
+        }
+    }
+}
+
+impl Config for UsageConfig {
+    fn from_env() -> Self {
+        UsageConfig {
+            llm_price_table: env::var("LLM_PRICE_TABLE").unwrap_or("./assets/data/llm_prices.json".to_string()),
+        }
+    }
+}
+
+impl Config for QuotaConfig {
+    fn from_env() -> Self {
+        QuotaConfig {
+            quota_plans: env::var("QUOTA_PLANS").unwrap_or("./assets/data/quota_plans.json".to_string()),
+            quota_enabled: env::var("QUOTA_ENABLED").unwrap_or("true".to_string()).parse::<bool>().expect("QUOTA_ENABLED must be a boolean"),
+        }
+    }
+}
+
+impl Config for RubricConfig {
+    fn from_env() -> Self {
+        RubricConfig {
+            rubric_path: env::var("RUBRIC_PATH").unwrap_or("./assets/data/rubric.json".to_string()),
+        }
+    }
+}
+
+impl Config for UploadConfig {
+    fn from_env() -> Self {
+        UploadConfig {
+            upload_max_bytes: env::var("UPLOAD_MAX_BYTES")
+                .unwrap_or("5000000".to_string())
+                .parse::<usize>()
+                .expect("UPLOAD_MAX_BYTES must be a number"),
+            upload_bucket: env::var("UPLOAD_BUCKET").unwrap_or("resume_files".to_string()),
+        }
+    }
+}
+
+impl Config for QueueConfig {
+    fn from_env() -> Self {
+        QueueConfig {
+            queue_visibility_timeout_secs: env::var("QUEUE_VISIBILITY_TIMEOUT_SECS")
+                .unwrap_or("600".to_string())
+                .parse::<u64>()
+                .expect("QUEUE_VISIBILITY_TIMEOUT_SECS must be a number"),
+            queue_max_attempts: env::var("QUEUE_MAX_ATTEMPTS").unwrap_or("3".to_string()).parse::<u32>().expect("QUEUE_MAX_ATTEMPTS must be a number"),
         }
     }
 }
@@ -0,0 +1,472 @@
+use std::{
+    fmt,
+    io::{Cursor, Read},
+    panic::{self, AssertUnwindSafe},
+};
+
+use quick_xml::{events::Event, Reader};
+use serde::{Deserialize, Serialize};
+
+/// Upper bound for a single xml part unpacked from a docx or odt archive
+const MAX_UNPACKED_BYTES: u64 = 20_000_000;
+
+/// Document formats a résumé can be uploaded in
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub enum DocumentFormat {
+    Pdf,
+    Docx,
+    Odt,
+    Rtf,
+    Text,
+}
+
+impl DocumentFormat {
+    pub const ACCEPTED_EXTENSIONS: &'static str = ".pdf,.docx,.odt,.rtf,.txt,.md";
+
+    pub fn from_filename(filename: &str) -> Option<Self> {
+        let extension = filename.rsplit_once('.')?.1.to_lowercase();
+        match extension.as_str() {
+            "pdf" => Some(DocumentFormat::Pdf),
+            "docx" => Some(DocumentFormat::Docx),
+            "odt" => Some(DocumentFormat::Odt),
+            "rtf" => Some(DocumentFormat::Rtf),
+            "txt" | "md" => Some(DocumentFormat::Text),
+            _ => None,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            DocumentFormat::Pdf => "PDF",
+            DocumentFormat::Docx => "Word document",
+            DocumentFormat::Odt => "OpenDocument text",
+            DocumentFormat::Rtf => "RTF document",
+            DocumentFormat::Text => "text file",
+        }
+    }
+
+    /// Whether the bytes start the way a file of this format does, so a renamed file is caught before parsing
+    pub fn matches(
+        &self,
+        bytes: &[u8],
+    ) -> bool {
+        match self {
+            DocumentFormat::Pdf => bytes.starts_with(b"%PDF-"),
+            DocumentFormat::Docx | DocumentFormat::Odt => bytes.starts_with(b"PK\x03\x04"),
+            DocumentFormat::Rtf => bytes.starts_with(b"{\\rtf"),
+            DocumentFormat::Text => std::str::from_utf8(bytes).is_ok() && !bytes.contains(&0),
+        }
+    }
+}
+
+/// Why an uploaded file could not be turned into text. The messages are shown to the user.
+#[derive(Debug)]
+pub enum ExtractError {
+    NoFile,
+    /// The multipart body broke off or was malformed
+    Interrupted {
+        message: String,
+    },
+    TooLarge {
+        max_bytes: usize,
+    },
+    UnsupportedType {
+        filename: String,
+    },
+    ContentMismatch {
+        format: DocumentFormat,
+    },
+    Corrupt {
+        format: DocumentFormat,
+        message: String,
+    },
+    /// Parsed fine but holds no text, usually a scanned pdf
+    NoText {
+        format: DocumentFormat,
+    },
+}
+
+impl fmt::Display for ExtractError {
+    fn fmt(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+    ) -> fmt::Result {
+        match self {
+            ExtractError::NoFile => write!(f, "Choose a résumé file to upload"),
+            ExtractError::Interrupted { .. } => write!(f, "The upload did not complete, try again"),
+            ExtractError::TooLarge { max_bytes } => write!(f, "The file is larger than {} MB", max_bytes / 1_000_000),
+            ExtractError::UnsupportedType { filename } => write!(f, "{} is not a PDF, DOCX, ODT, RTF or text file", filename),
+            ExtractError::ContentMismatch { format } => write!(f, "The file is not a valid {}", format.label()),
+            ExtractError::Corrupt { format, .. } => write!(f, "The {} could not be read", format.label()),
+            ExtractError::NoText { format } => write!(f, "No text found in the {}. Scanned documents are not supported, paste the text instead.", format.label()),
+        }
+    }
+}
+
+/// Extracts plain text from a document, one paragraph per line. CPU bound, call it off the async runtime.
+pub fn extract_text(
+    format: DocumentFormat,
+    bytes: &[u8],
+) -> Result<String, ExtractError> {
+    if !format.matches(bytes) {
+        return Err(ExtractError::ContentMismatch { format });
+    }
+    let corrupt = |message: String| ExtractError::Corrupt { format, message };
+    let text = match format {
+        DocumentFormat::Pdf => extract_pdf(bytes).map_err(corrupt)?,
+        DocumentFormat::Docx => extract_xml(&unzip_part(bytes, "word/document.xml").map_err(corrupt)?, &[b"w:p"], b"w:t", &[b"w:tab"], &[b"w:br", b"w:cr"]).map_err(corrupt)?,
+        DocumentFormat::Odt => extract_xml(&unzip_part(bytes, "content.xml").map_err(corrupt)?, &[b"text:p", b"text:h"], b"", &[b"text:tab"], &[b"text:line-break"]).map_err(corrupt)?,
+        DocumentFormat::Rtf => extract_rtf(bytes),
+        DocumentFormat::Text => String::from_utf8_lossy(bytes).to_string(),
+    };
+    let text = tidy(&text);
+    if text.is_empty() {
+        return Err(ExtractError::NoText { format });
+    }
+    Ok(text)
+}
+
+fn extract_pdf(bytes: &[u8]) -> Result<String, String> {
+    // pdf-extract panics on some malformed files instead of returning an error
+    match panic::catch_unwind(AssertUnwindSafe(|| pdf_extract::extract_text_from_mem(bytes))) {
+        Ok(Ok(text)) => Ok(text),
+        Ok(Err(e)) => Err(e.to_string()),
+        Err(_) => Err("pdf parser panicked".to_string()),
+    }
+}
+
+fn unzip_part(
+    bytes: &[u8],
+    name: &str,
+) -> Result<Vec<u8>, String> {
+    let mut archive = zip::ZipArchive::new(Cursor::new(bytes)).map_err(|e| e.to_string())?;
+    let part = archive.by_name(name).map_err(|e| format!("{}: {}", name, e))?;
+    let mut xml = vec![];
+    part.take(MAX_UNPACKED_BYTES).read_to_end(&mut xml).map_err(|e| e.to_string())?;
+    Ok(xml)
+}
+
+/// Text of an office xml part, a line per paragraph. An empty `text_element` takes text anywhere inside a paragraph.
+fn extract_xml(
+    xml: &[u8],
+    paragraphs: &[&[u8]],
+    text_element: &[u8],
+    tabs: &[&[u8]],
+    breaks: &[&[u8]],
+) -> Result<String, String> {
+    let mut reader = Reader::from_reader(xml);
+    let mut buf = vec![];
+    let mut text = String::new();
+    let (mut in_paragraph, mut in_text) = (0usize, false);
+    loop {
+        match reader.read_event_into(&mut buf).map_err(|e| e.to_string())? {
+            Event::Start(e) => {
+                let name = e.name();
+                if paragraphs.contains(&name.as_ref()) {
+                    in_paragraph += 1;
+                } else if name.as_ref() == text_element {
+                    in_text = true;
+                }
+            }
+            Event::End(e) => {
+                let name = e.name();
+                if paragraphs.contains(&name.as_ref()) {
+                    in_paragraph = in_paragraph.saturating_sub(1);
+                    text.push('\n');
+                } else if name.as_ref() == text_element {
+                    in_text = false;
+                }
+            }
+            Event::Empty(e) => {
+                let name = e.name();
+                if tabs.contains(&name.as_ref()) {
+                    text.push('\t');
+                } else if breaks.contains(&name.as_ref()) || paragraphs.contains(&name.as_ref()) {
+                    text.push('\n');
+                } else if name.as_ref() == b"text:s" {
+                    text.push(' ');
+                }
+            }
+            Event::Text(e) if in_text || (text_element.is_empty() && in_paragraph > 0) => {
+                text.push_str(&e.unescape().map_err(|e| e.to_string())?);
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+        buf.clear();
+    }
+    Ok(text)
+}
+
+/// Plain text of an rtf document: control words are dropped, `\par` and `\line` become newlines
+/// and groups that hold no body text (font tables, styles, pictures) are skipped
+fn extract_rtf(bytes: &[u8]) -> String {
+    const SKIPPED_DESTINATIONS: [&str; 10] = ["fonttbl", "colortbl", "stylesheet", "info", "pict", "header", "footer", "themedata", "listtable", "listoverridetable"];
+    let mut text = String::new();
+    // per open group, whether its text is skipped
+    let mut skipping = vec![false];
+    // `\ucN` replacement characters still to drop after a `\uN`
+    let mut skip_chars = 0usize;
+    let mut i = 0;
+    while i < bytes.len() {
+        let skipped = *skipping.last().unwrap_or(&false);
+        match bytes[i] {
+            b'{' => {
+                skipping.push(skipped);
+                i += 1;
+            }
+            b'}' => {
+                if skipping.len() > 1 {
+                    skipping.pop();
+                }
+                i += 1;
+            }
+            b'\\' => {
+                i += 1;
+                let start = i;
+                while i < bytes.len() && bytes[i].is_ascii_alphabetic() {
+                    i += 1;
+                }
+                let word = String::from_utf8_lossy(&bytes[start..i]).to_string();
+                if word.is_empty() {
+                    // control symbol: \\ \{ \} \~ \'hh \*
+                    match bytes.get(i) {
+                        Some(b'\'') => {
+                            let hex = bytes.get(i + 1..i + 3).and_then(|h| std::str::from_utf8(h).ok()).and_then(|h| u8::from_str_radix(h, 16).ok());
+                            if let (Some(byte), false) = (hex, skipped) {
+                                if skip_chars > 0 {
+                                    skip_chars -= 1;
+                                } else {
+                                    // windows-1252 and latin-1 agree on letters
+                                    text.push(byte as char);
+                                }
+                            }
+                            i += 3;
+                        }
+                        Some(b'*') => {
+                            if let Some(last) = skipping.last_mut() {
+                                *last = true;
+                            }
+                            i += 1;
+                        }
+                        Some(b'~') => {
+                            if !skipped {
+                                text.push(' ');
+                            }
+                            i += 1;
+                        }
+                        Some(c) => {
+                            if !skipped && matches!(c, b'\\' | b'{' | b'}') {
+                                text.push(*c as char);
+                            }
+                            i += 1;
+                        }
+                        None => {}
+                    }
+                    continue;
+                }
+                let number_start = i;
+                if i < bytes.len() && bytes[i] == b'-' {
+                    i += 1;
+                }
+                while i < bytes.len() && bytes[i].is_ascii_digit() {
+                    i += 1;
+                }
+                let number = std::str::from_utf8(&bytes[number_start..i]).ok().and_then(|n| n.parse::<i32>().ok());
+                // a single space after a control word belongs to it
+                if i < bytes.len() && bytes[i] == b' ' {
+                    i += 1;
+                }
+                if SKIPPED_DESTINATIONS.contains(&word.as_str()) {
+                    if let Some(last) = skipping.last_mut() {
+                        *last = true;
+                    }
+                    continue;
+                }
+                if skipped {
+                    continue;
+                }
+                match word.as_str() {
+                    "par" | "line" | "sect" | "page" => text.push('\n'),
+                    "tab" => text.push('\t'),
+                    "u" => {
+                        if let Some(c) = number.map(|n| if n < 0 { n + 65536 } else { n }).and_then(|n| char::from_u32(n as u32)) {
+                            text.push(c);
+                        }
+                        skip_chars = 1;
+                    }
+                    _ => {}
+                }
+            }
+            b'\r' | b'\n' => i += 1,
+            c => {
+                if !skipped {
+                    if skip_chars > 0 {
+                        skip_chars -= 1;
+                    } else {
+                        text.push(c as char);
+                    }
+                }
+                i += 1;
+            }
+        }
+    }
+    text
+}
+
+/// Trims every line and collapses runs of blank lines, the parsers leave plenty of both
+fn tidy(text: &str) -> String {
+    let mut lines: Vec<&str> = vec![];
+    for line in text.lines().map(str::trim) {
+        if line.is_empty() && lines.last().is_none_or(|l| l.is_empty()) {
+            continue;
+        }
+        lines.push(line);
+    }
+    while lines.last().is_some_and(|l| l.is_empty()) {
+        lines.pop();
+    }
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+
+    /// A zip archive holding `parts`, the shape of a docx or odt
+    fn archive(parts: &[(&str, &str)]) -> Vec<u8> {
+        let mut zip = zip::ZipWriter::new(Cursor::new(vec![]));
+        for (name, content) in parts {
+            zip.start_file(*name, zip::write::SimpleFileOptions::default()).unwrap();
+            zip.write_all(content.as_bytes()).unwrap();
+        }
+        zip.finish().unwrap().into_inner()
+    }
+
+    /// A one page pdf that says `text` in Helvetica
+    fn pdf(text: &str) -> Vec<u8> {
+        use pdf_writer::{Content, Finish, Name, Pdf, Rect, Ref, Str};
+        let (catalog, tree, page, font, content) = (Ref::new(1), Ref::new(2), Ref::new(3), Ref::new(4), Ref::new(5));
+        let mut pdf = Pdf::new();
+        pdf.catalog(catalog).pages(tree);
+        pdf.pages(tree).kids([page]).count(1);
+        let mut p = pdf.page(page);
+        p.media_box(Rect::new(0.0, 0.0, 595.0, 842.0)).parent(tree).contents(content);
+        p.resources().fonts().pair(Name(b"F1"), font);
+        p.finish();
+        pdf.type1_font(font).base_font(Name(b"Helvetica"));
+        let mut ops = Content::new();
+        ops.begin_text().set_font(Name(b"F1"), 12.0).next_line(72.0, 770.0).show(Str(text.as_bytes())).end_text();
+        pdf.stream(content, &ops.finish());
+        pdf.finish()
+    }
+
+    const DOCX_XML: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<w:document xmlns:w="http://schemas.openxmlformats.org/wordprocessingml/2006/main"><w:body>
+<w:p><w:r><w:t>Jane Doe</w:t></w:r></w:p>
+<w:p><w:r><w:t>Engineer</w:t><w:tab/><w:t>2020 &amp; on</w:t></w:r></w:p>
+<w:p></w:p><w:p></w:p>
+<w:p><w:r><w:t xml:space="preserve">  Rust  </w:t><w:br/><w:t>Go</w:t></w:r></w:p>
+</w:body></w:document>"#;
+
+    const ODT_XML: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<office:document-content xmlns:office="urn:oasis:names:tc:opendocument:xmlns:office:1.0" xmlns:text="urn:oasis:names:tc:opendocument:xmlns:text:1.0"><office:body><office:text>
+<text:h>Jane Doe</text:h>
+<text:p>Engineer<text:s/>at <text:span>Acme</text:span></text:p>
+<text:p>Rust<text:line-break/>Go</text:p>
+</office:text></office:body></office:document-content>"#;
+
+    const RTF: &str = r"{\rtf1\ansi{\fonttbl{\f0 Helvetica;}}{\colortbl;\red0\green0\blue0;}{\*\generator Word;}
+\f0 Jane Doe\par
+Caf\'e9 \u8212? Engineer\tab 2020\par
+\{braces\} and \\ backslash\line done}";
+
+    #[test]
+    fn formats_come_from_the_extension() {
+        assert_eq!(DocumentFormat::from_filename("Resume.PDF"), Some(DocumentFormat::Pdf));
+        assert_eq!(DocumentFormat::from_filename("cv.final.docx"), Some(DocumentFormat::Docx));
+        assert_eq!(DocumentFormat::from_filename("notes.md"), Some(DocumentFormat::Text));
+        assert_eq!(DocumentFormat::from_filename("resume.pages"), None);
+        assert_eq!(DocumentFormat::from_filename("resume"), None);
+    }
+
+    #[test]
+    fn magic_bytes_match_their_format() {
+        assert!(DocumentFormat::Pdf.matches(b"%PDF-1.7\n"));
+        assert!(DocumentFormat::Docx.matches(&archive(&[("word/document.xml", DOCX_XML)])));
+        assert!(DocumentFormat::Rtf.matches(RTF.as_bytes()));
+        assert!(DocumentFormat::Text.matches("Jane Doe, Café".as_bytes()));
+        assert!(!DocumentFormat::Text.matches(b"Jane\0Doe"));
+        assert!(!DocumentFormat::Text.matches(&[0xff, 0xfe, 0x41]));
+    }
+
+    #[test]
+    fn renamed_files_are_a_content_mismatch() {
+        let docx = archive(&[("word/document.xml", DOCX_XML)]);
+        assert!(matches!(extract_text(DocumentFormat::Pdf, &docx), Err(ExtractError::ContentMismatch { format: DocumentFormat::Pdf })));
+        assert!(matches!(
+            extract_text(DocumentFormat::Docx, RTF.as_bytes()),
+            Err(ExtractError::ContentMismatch { format: DocumentFormat::Docx })
+        ));
+        assert!(matches!(
+            extract_text(DocumentFormat::Rtf, b"%PDF-1.4"),
+            Err(ExtractError::ContentMismatch { format: DocumentFormat::Rtf })
+        ));
+    }
+
+    #[test]
+    fn docx_paragraphs_become_lines() {
+        let docx = archive(&[("word/document.xml", DOCX_XML)]);
+        assert_eq!(extract_text(DocumentFormat::Docx, &docx).unwrap(), "Jane Doe\nEngineer\t2020 & on\n\nRust\nGo");
+    }
+
+    #[test]
+    fn odt_paragraphs_and_headings_become_lines() {
+        let odt = archive(&[("mimetype", "application/vnd.oasis.opendocument.text"), ("content.xml", ODT_XML)]);
+        assert_eq!(extract_text(DocumentFormat::Odt, &odt).unwrap(), "Jane Doe\nEngineer at Acme\nRust\nGo");
+    }
+
+    #[test]
+    fn archives_without_the_document_part_are_corrupt() {
+        let odt_as_docx = archive(&[("content.xml", ODT_XML)]);
+        assert!(matches!(
+            extract_text(DocumentFormat::Docx, &odt_as_docx),
+            Err(ExtractError::Corrupt { format: DocumentFormat::Docx, .. })
+        ));
+        assert!(matches!(extract_text(DocumentFormat::Docx, b"PK\x03\x04 not really a zip"), Err(ExtractError::Corrupt { .. })));
+    }
+
+    #[test]
+    fn rtf_drops_control_words_and_skipped_groups() {
+        assert_eq!(
+            extract_text(DocumentFormat::Rtf, RTF.as_bytes()).unwrap(),
+            "Jane Doe\nCafé — Engineer\t2020\n{braces} and \\ backslash\ndone"
+        );
+    }
+
+    #[test]
+    fn pdf_text_is_extracted() {
+        let text = extract_text(DocumentFormat::Pdf, &pdf("Jane Doe")).unwrap();
+        assert!(text.contains("Jane Doe"), "{:?}", text);
+    }
+
+    #[test]
+    fn malformed_pdfs_are_corrupt_instead_of_panicking() {
+        let valid = pdf("Jane Doe");
+        for malformed in [b"%PDF-1.4\nnot a pdf at all".to_vec(), valid[..valid.len() / 2].to_vec()] {
+            assert!(matches!(extract_text(DocumentFormat::Pdf, &malformed), Err(ExtractError::Corrupt { format: DocumentFormat::Pdf, .. })));
+        }
+    }
+
+    #[test]
+    fn empty_documents_have_no_text() {
+        assert!(matches!(extract_text(DocumentFormat::Text, b" \n\n \t"), Err(ExtractError::NoText { format: DocumentFormat::Text })));
+    }
+
+    #[test]
+    fn tidy_trims_lines_and_collapses_blank_runs() {
+        assert_eq!(tidy("\n\n  Jane Doe  \n\n\n\n Engineer\n \n"), "Jane Doe\n\nEngineer");
+    }
+}
@@ -0,0 +1,273 @@
+use std::fmt::Debug;
+
+use futures::stream::TryStreamExt;
+use log::{error, info, warn};
+use mongodb::{
+    bson::{self, doc, Bson, Document},
+    Collection,
+};
+use serde::{de::DeserializeOwned, Serialize};
+
+/// Documents written before versioning have no `schema_version` and count as version 0
+pub const SCHEMA_VERSION_FIELD: &str = "schema_version";
+
+/// Upgrades a raw document from `from_version` to `from_version + 1`
+pub struct Migration {
+    pub from_version: u32,
+    pub description: &'static str,
+    pub apply: fn(&mut Document) -> Result<(), String>,
+}
+
+/// An entity stored with a `schema_version`, upgraded by its migrations when read
+pub trait Versioned: DeserializeOwned + Serialize + Debug + Unpin + Send + Sync {
+    const COLLECTION: &'static str;
+    const SCHEMA_VERSION: u32;
+
+    /// One migration per version below `SCHEMA_VERSION`, in any order
+    fn migrations() -> &'static [Migration];
+}
+
+pub fn schema_version(document: &Document) -> u32 {
+    match document.get(SCHEMA_VERSION_FIELD) {
+        Some(Bson::Int32(v)) => *v as u32,
+        Some(Bson::Int64(v)) => *v as u32,
+        _ => 0,
+    }
+}
+
+/// Applies every migration between the document's version and `T::SCHEMA_VERSION`.
+/// Returns whether the document changed.
+pub fn upgrade<T: Versioned>(document: &mut Document) -> Result<bool, String> {
+    let mut version = schema_version(document);
+    if version > T::SCHEMA_VERSION {
+        return Err(format!("{} document is at version {}, newer than {}", T::COLLECTION, version, T::SCHEMA_VERSION));
+    }
+    let changed = version < T::SCHEMA_VERSION;
+    while version < T::SCHEMA_VERSION {
+        let migration = match T::migrations().iter().find(|m| m.from_version == version) {
+            Some(m) => m,
+            None => return Err(format!("No {} migration from version {}", T::COLLECTION, version)),
+        };
+        (migration.apply)(document).map_err(|e| format!("{} migration from version {} ({}) failed: {}", T::COLLECTION, version, migration.description, e))?;
+        version += 1;
+    }
+    document.insert(SCHEMA_VERSION_FIELD, T::SCHEMA_VERSION as i32);
+    Ok(changed)
+}
+
+/// Upgrades and deserializes a raw document
+pub fn try_decode<T: Versioned>(mut document: Document) -> Result<T, String> {
+    let id = document.get("_id").cloned();
+    upgrade::<T>(&mut document).map_err(|e| format!("Error upgrading {:?}: {}", id, e))?;
+    bson::from_document::<T>(document).map_err(|e| format!("Error deserializing {:?} from {}: {}", id, T::COLLECTION, e))
+}
+
+/// Upgrades and deserializes a raw document, `None` (logged) when either fails
+pub fn decode<T: Versioned>(document: Document) -> Option<T> {
+    match try_decode::<T>(document) {
+        Ok(entity) => Some(entity),
+        Err(e) => {
+            error!("{}", e);
+            None
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct MigrationReport {
+    pub collection: &'static str,
+    pub scanned: u64,
+    pub migrated: u64,
+    /// Written by the app between the read and the write, which already stored it at the current version
+    pub skipped: u64,
+    pub failed: u64,
+}
+
+/// Matches `document` only as it was read. The app saves the current version with a new revision,
+/// so a save in between makes the migration's write miss instead of overwriting it.
+fn unchanged(document: &Document) -> Document {
+    let field = |key: &str| document.get(key).cloned().unwrap_or(Bson::Null);
+    // null also matches a missing field
+    doc! {
+        "_id": field("_id"),
+        SCHEMA_VERSION_FIELD: field(SCHEMA_VERSION_FIELD),
+        "revision": field("revision"),
+    }
+}
+
+/// Rewrites every document below `T::SCHEMA_VERSION` in place. With `dry_run` nothing is written.
+pub async fn migrate_collection<T: Versioned>(
+    collection: Collection<Document>,
+    batch_size: u32,
+    dry_run: bool,
+) -> Result<MigrationReport, String> {
+    let filter = doc! {
+        "$or": [
+            { SCHEMA_VERSION_FIELD: { "$exists": false } },
+            { SCHEMA_VERSION_FIELD: { "$lt": T::SCHEMA_VERSION as i32 } },
+        ]
+    };
+    let mut report = MigrationReport {
+        collection: T::COLLECTION,
+        ..Default::default()
+    };
+    let mut cursor = collection
+        .find(filter)
+        .batch_size(batch_size)
+        .await
+        .map_err(|e| format!("Error finding documents in {}: {}", T::COLLECTION, e))?;
+    while let Some(mut document) = cursor.try_next().await.map_err(|e| format!("Error iterating documents in {}: {}", T::COLLECTION, e))? {
+        report.scanned += 1;
+        let id = document.get("_id").cloned().unwrap_or(Bson::Null);
+        let filter = unchanged(&document);
+        if let Err(e) = upgrade::<T>(&mut document) {
+            warn!("Skipping {}: {}", id, e);
+            report.failed += 1;
+            continue;
+        }
+        // never write back a document the current structs cannot read
+        if let Err(e) = bson::from_document::<T>(document.clone()) {
+            warn!("Skipping {}, still unreadable after upgrade: {}", id, e);
+            report.failed += 1;
+            continue;
+        }
+        if dry_run {
+            report.migrated += 1;
+            continue;
+        }
+        match collection.replace_one(filter, document).await {
+            Ok(r) if r.matched_count == 0 => {
+                info!("Skipping {}, it was saved while being migrated", id);
+                report.skipped += 1;
+            }
+            Ok(_) => report.migrated += 1,
+            Err(e) => {
+                warn!("Error writing {}: {}", id, e);
+                report.failed += 1;
+            }
+        }
+    }
+    info!("{:?}", report);
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+
+    use super::*;
+
+    #[derive(Debug, Serialize, Deserialize)]
+    struct Note {
+        title: String,
+        tags: Vec<String>,
+        schema_version: u32,
+    }
+
+    fn v0_title(document: &mut Document) -> Result<(), String> {
+        let name = document.get_str("name").map_err(|e| e.to_string())?.to_string();
+        document.remove("name");
+        document.insert("title", name);
+        Ok(())
+    }
+
+    fn v1_tags(document: &mut Document) -> Result<(), String> {
+        // the title must already be there, the migrations run in version order
+        let title = document.get_str("title").map_err(|e| e.to_string())?.to_lowercase();
+        document.insert("tags", vec![title]);
+        Ok(())
+    }
+
+    // listed out of order on purpose
+    static NOTE_MIGRATIONS: &[Migration] = &[
+        Migration {
+            from_version: 1,
+            description: "tags from the title",
+            apply: v1_tags,
+        },
+        Migration {
+            from_version: 0,
+            description: "name renamed to title",
+            apply: v0_title,
+        },
+    ];
+
+    impl Versioned for Note {
+        const COLLECTION: &'static str = "notes";
+        const SCHEMA_VERSION: u32 = 2;
+
+        fn migrations() -> &'static [Migration] {
+            NOTE_MIGRATIONS
+        }
+    }
+
+    /// Like `Note`, but a version was bumped without adding its migration
+    #[derive(Debug, Serialize, Deserialize)]
+    struct Gap {
+        schema_version: u32,
+    }
+
+    impl Versioned for Gap {
+        const COLLECTION: &'static str = "gaps";
+        const SCHEMA_VERSION: u32 = 3;
+
+        fn migrations() -> &'static [Migration] {
+            NOTE_MIGRATIONS
+        }
+    }
+
+    #[test]
+    fn a_document_without_a_version_runs_the_whole_chain() {
+        let mut document = doc! {"name": "Payments"};
+        assert!(upgrade::<Note>(&mut document).unwrap());
+        let note = bson::from_document::<Note>(document).unwrap();
+        assert_eq!(note.title, "Payments");
+        assert_eq!(note.tags, vec!["payments"]);
+        assert_eq!(note.schema_version, 2);
+    }
+
+    #[test]
+    fn the_chain_starts_at_the_documents_version() {
+        let mut document = doc! {"title": "Kept", "tags": ["old"], "schema_version": 1};
+        assert!(upgrade::<Note>(&mut document).unwrap());
+        assert_eq!(try_decode::<Note>(document).unwrap().tags, vec!["kept"]);
+    }
+
+    #[test]
+    fn a_current_document_is_left_alone() {
+        let mut document = doc! {"title": "Current", "tags": ["mine"], "schema_version": 2};
+        let before = document.clone();
+        assert!(!upgrade::<Note>(&mut document).unwrap());
+        assert_eq!(document, before);
+    }
+
+    #[test]
+    fn a_newer_document_is_not_downgraded() {
+        let mut document = doc! {"title": "Future", "tags": [], "schema_version": 3};
+        assert!(upgrade::<Note>(&mut document).unwrap_err().contains("newer"));
+    }
+
+    #[test]
+    fn a_missing_migration_is_an_error() {
+        let mut document = doc! {"title": "Gap", "tags": [], "schema_version": 2};
+        assert!(upgrade::<Gap>(&mut document).unwrap_err().contains("No gaps migration from version 2"));
+    }
+
+    #[test]
+    fn a_failing_migration_names_itself() {
+        let mut document = doc! {"schema_version": 0};
+        let error = upgrade::<Note>(&mut document).unwrap_err();
+        assert!(error.contains("name renamed to title"), "{}", error);
+        assert!(decode::<Note>(doc! {"schema_version": 0}).is_none());
+    }
+
+    #[test]
+    fn the_write_back_only_matches_the_document_as_it_was_read() {
+        let id = bson::oid::ObjectId::new();
+        let filter = unchanged(&doc! {"_id": id, "name": "Old", "schema_version": 0, "revision": 4_i64});
+        assert_eq!(filter, doc! {"_id": id, "schema_version": 0, "revision": 4_i64});
+        // documents from before versioning have neither field, null matches that
+        let filter = unchanged(&doc! {"_id": id, "name": "Old"});
+        assert_eq!(filter, doc! {"_id": id, "schema_version": Bson::Null, "revision": Bson::Null});
+    }
+}
@@ -1,8 +1,14 @@
 pub mod cache_ops;
 pub mod date_ops;
 pub mod db_ops;
+pub mod diff_ops;
 pub mod environ_ops;
+pub mod extract_ops;
 pub mod jwt_ops;
+pub mod migration_ops;
 pub mod openai;
 pub mod queue_ops;
+pub mod quota_ops;
+pub mod repository_ops;
 pub mod schema_ops;
+pub mod stream_ops;
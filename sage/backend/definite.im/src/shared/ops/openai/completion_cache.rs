@@ -0,0 +1,104 @@
+use std::{fmt::Debug, time::Duration};
+
+use log::{debug, info, warn};
+use schemars::JsonSchema;
+use serde::{de::DeserializeOwned, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::shared::ops::{
+    cache_ops::Cache,
+    environ_ops::{Environ, OpenAIConfig, RedisConfig},
+    schema_ops,
+};
+
+/// Whitespace and line endings vary between copy-pastes of the same document
+fn normalize(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<&str>>().join(" ")
+}
+
+/// Key for a structured completion of `text` into `T` following `prompt`.
+/// Covers the model, the prompt and `T`'s schema, so a model switch, a reworded prompt or a schema change never returns stale results.
+pub fn cache_key<T>(
+    kind: &str,
+    prompt: &str,
+    text: &str,
+) -> String
+where
+    T: JsonSchema + Serialize + Debug,
+{
+    let openai_config: OpenAIConfig = Environ::init();
+    let schema = schema_ops::to_openai_schema::<T>().map(|s| s.to_string()).unwrap_or_default();
+    key_of(kind, &[&openai_config.model, prompt, &schema, &normalize(text)])
+}
+
+fn key_of(
+    kind: &str,
+    parts: &[&str],
+) -> String {
+    let mut hasher = Sha256::new();
+    for part in parts {
+        hasher.update(part.as_bytes());
+        hasher.update([0]);
+    }
+    format!("completion:{}:{:x}", kind, hasher.finalize())
+}
+
+/// A cache that is down or holds something unreadable counts as a miss
+pub async fn get<T: DeserializeOwned>(
+    cache: &Cache,
+    key: &str,
+) -> Option<T> {
+    match cache.get::<T>(key).await {
+        Ok(Some(value)) => {
+            info!("Completion cache hit for {}", key);
+            Some(value)
+        }
+        Ok(None) => {
+            debug!("Completion cache miss for {}", key);
+            None
+        }
+        Err(e) => {
+            warn!("Completion cache skipped for {}: {}", key, e);
+            None
+        }
+    }
+}
+
+/// Keeps the result for `RedisConfig.redis_ttl` seconds
+pub async fn set<T: Serialize>(
+    cache: &Cache,
+    key: &str,
+    value: &T,
+) {
+    let redis_config: RedisConfig = Environ::init();
+    if let Err(e) = cache.set(key, value, Duration::from_secs(redis_config.redis_ttl)).await {
+        debug!("Completion not cached for {}: {}", key, e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(
+        prompt: &str,
+        text: &str,
+    ) -> String {
+        key_of("resume", &["gpt", prompt, "{}", &normalize(text)])
+    }
+
+    #[test]
+    fn reworded_prompts_do_not_share_a_key() {
+        assert_ne!(key("Parse the following resume:", "Jane Doe"), key("Parse the following resume, keep every bullet:", "Jane Doe"));
+    }
+
+    #[test]
+    fn copy_paste_whitespace_shares_a_key() {
+        assert_eq!(key("Parse:", "Jane  Doe\r\nEngineer"), key("Parse:", "Jane Doe\nEngineer"));
+    }
+
+    #[test]
+    fn parts_do_not_run_into_each_other() {
+        assert_ne!(key("Parse: Jane", "Doe"), key("Parse:", "Jane Doe"));
+    }
+}
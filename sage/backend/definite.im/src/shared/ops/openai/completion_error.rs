@@ -0,0 +1,246 @@
+use std::fmt;
+
+use serde::Serialize;
+use serde_json::{json, Value};
+
+use super::completion_response::Usage;
+
+/// What the user can do about a failed completion
+#[derive(Debug, Serialize, Clone, Copy, PartialEq)]
+pub enum CompletionRemedy {
+    /// Transient failure, trying again later is likely to work
+    Retry,
+    /// The input (or the output it produced) did not fit in the model's context
+    ShortenInput,
+    /// Nothing the user can do, we need to look at it
+    ContactSupport,
+}
+
+/// Everything that can go wrong between building a chat completion request and
+/// holding a typed, deserialized result.
+#[derive(Debug)]
+pub enum CompletionError {
+    /// The request never got a response (DNS, TLS, connection reset)
+    Network { message: String },
+    /// A single attempt took longer than `OpenAIConfig.request_timeout_secs`
+    Timeout { seconds: u64 },
+    /// The circuit breaker is open after repeated provider failures
+    CircuitOpen { retry_in_secs: u64 },
+    /// The provider answered with a non-2xx status, `retry_after_ms` comes from `Retry-After`
+    Status { status: u16, retry_after_ms: Option<u64>, body: String },
+    /// The provider answered 2xx but the body is not a chat completion
+    InvalidResponse { message: String, body: String },
+    /// The completion had no choices
+    Empty { usage: Option<Usage>, body: String },
+    /// The model stopped early because it ran out of tokens (`finish_reason == "length"`)
+    Truncated { finish_reason: String, usage: Option<Usage>, body: String },
+    /// The model refused, or the provider's content filter stopped the completion
+    Refused { finish_reason: String, refusal: Option<String>, usage: Option<Usage> },
+    /// The completion content does not match the requested schema
+    Schema { message: String, body: String },
+}
+
+impl CompletionError {
+    pub fn status(&self) -> Option<u16> {
+        match self {
+            CompletionError::Status { status, .. } => Some(*status),
+            _ => None,
+        }
+    }
+
+    /// Whether another attempt against the same provider may succeed
+    pub fn is_transient(&self) -> bool {
+        match self {
+            CompletionError::Network { .. } | CompletionError::Timeout { .. } => true,
+            // 429 is also used for an exhausted billing quota, retrying that only burns time
+            CompletionError::Status { status: 429, body, .. } => !body.contains("insufficient_quota"),
+            CompletionError::Status { status, .. } => matches!(status, 408 | 500..=599),
+            _ => false,
+        }
+    }
+
+    pub fn retry_after_ms(&self) -> Option<u64> {
+        match self {
+            CompletionError::Status { retry_after_ms, .. } => *retry_after_ms,
+            _ => None,
+        }
+    }
+
+    pub fn finish_reason(&self) -> Option<&str> {
+        match self {
+            CompletionError::Truncated { finish_reason, .. } | CompletionError::Refused { finish_reason, .. } => Some(finish_reason),
+            _ => None,
+        }
+    }
+
+    pub fn usage(&self) -> Option<&Usage> {
+        match self {
+            CompletionError::Empty { usage, .. } | CompletionError::Truncated { usage, .. } | CompletionError::Refused { usage, .. } => usage.as_ref(),
+            _ => None,
+        }
+    }
+
+    pub fn body(&self) -> Option<&str> {
+        match self {
+            CompletionError::Status { body, .. }
+            | CompletionError::InvalidResponse { body, .. }
+            | CompletionError::Empty { body, .. }
+            | CompletionError::Truncated { body, .. }
+            | CompletionError::Schema { body, .. } => Some(body),
+            _ => None,
+        }
+    }
+
+    pub fn remedy(&self) -> CompletionRemedy {
+        match self {
+            CompletionError::Network { .. } | CompletionError::Timeout { .. } | CompletionError::CircuitOpen { .. } | CompletionError::Empty { .. } => CompletionRemedy::Retry,
+            CompletionError::Status { .. } if self.is_transient() => CompletionRemedy::Retry,
+            CompletionError::Status { status, .. } => match status {
+                413 => CompletionRemedy::ShortenInput,
+                // a 400 on an oversized prompt is reported by OpenAI as context_length_exceeded
+                400 if self.body().is_some_and(|b| b.contains("context_length_exceeded")) => CompletionRemedy::ShortenInput,
+                _ => CompletionRemedy::ContactSupport,
+            },
+            CompletionError::Truncated { .. } => CompletionRemedy::ShortenInput,
+            CompletionError::InvalidResponse { .. } | CompletionError::Refused { .. } | CompletionError::Schema { .. } => CompletionRemedy::ContactSupport,
+        }
+    }
+
+    /// A sentence that can be shown to the user as-is
+    pub fn user_message(&self) -> &'static str {
+        match self.remedy() {
+            CompletionRemedy::Retry => "Our AI provider is temporarily unavailable. Please try again in a minute.",
+            CompletionRemedy::ShortenInput => "The text is too long for us to process in one go. Please shorten it and try again.",
+            CompletionRemedy::ContactSupport => match self {
+                CompletionError::Refused { .. } => "The AI declined to process this text. If you think this is a mistake, please contact support.",
+                _ => "Something went wrong while processing your text. Please contact support if this keeps happening.",
+            },
+        }
+    }
+
+    /// Handlebars context for `error-fragment`, `action` is what we were doing (e.g. "parsing résumé")
+    pub fn to_fragment_context(
+        &self,
+        action: &str,
+    ) -> Value {
+        json!({
+            "error_message": format!("Error {}", action),
+            "error_detail": self.user_message(),
+            "remedy": self.remedy(),
+            "can_retry": self.remedy() == CompletionRemedy::Retry,
+            "navigate_url": "/resumes/dashboard",
+            "navigate_text": "Go to dashboard",
+        })
+    }
+}
+
+impl fmt::Display for CompletionError {
+    fn fmt(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+    ) -> fmt::Result {
+        match self {
+            CompletionError::Network { message } => write!(f, "completion request failed: {}", message),
+            CompletionError::Timeout { seconds } => write!(f, "completion request timed out after {}s", seconds),
+            CompletionError::CircuitOpen { retry_in_secs } => write!(f, "completion circuit open, retry in {}s", retry_in_secs),
+            CompletionError::Status { status, body, .. } => write!(f, "completion response NOT OK: {} Details: [{}]", status, body),
+            CompletionError::InvalidResponse { message, body } => write!(f, "completion response could not be deserialized: {} Body: [{}]", message, body),
+            CompletionError::Empty { .. } => write!(f, "completion response had no choices"),
+            CompletionError::Truncated { finish_reason, usage, .. } => write!(f, "completion truncated, finish reason: {}, usage: {:?}", finish_reason, usage),
+            CompletionError::Refused { finish_reason, refusal, .. } => write!(f, "completion refused, finish reason: {}, refusal: {:?}", finish_reason, refusal),
+            CompletionError::Schema { message, body } => write!(f, "completion does not match schema: {} Body: [{}]", message, body),
+        }
+    }
+}
+
+impl std::error::Error for CompletionError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn status(
+        status: u16,
+        body: &str,
+    ) -> CompletionError {
+        CompletionError::Status {
+            status,
+            retry_after_ms: None,
+            body: body.to_string(),
+        }
+    }
+
+    #[test]
+    fn every_failure_maps_to_a_remedy() {
+        let cases = [
+            (CompletionError::Network { message: "reset".to_string() }, CompletionRemedy::Retry, true),
+            (CompletionError::Timeout { seconds: 30 }, CompletionRemedy::Retry, true),
+            (CompletionError::CircuitOpen { retry_in_secs: 10 }, CompletionRemedy::Retry, false),
+            (status(408, ""), CompletionRemedy::Retry, true),
+            (status(429, "rate_limit_exceeded"), CompletionRemedy::Retry, true),
+            (status(429, "insufficient_quota"), CompletionRemedy::ContactSupport, false),
+            (status(500, ""), CompletionRemedy::Retry, true),
+            (status(503, ""), CompletionRemedy::Retry, true),
+            (status(413, ""), CompletionRemedy::ShortenInput, false),
+            (status(400, "{\"code\": \"context_length_exceeded\"}"), CompletionRemedy::ShortenInput, false),
+            (status(400, "invalid_request_error"), CompletionRemedy::ContactSupport, false),
+            (status(401, ""), CompletionRemedy::ContactSupport, false),
+            (
+                CompletionError::InvalidResponse {
+                    message: "expected value".to_string(),
+                    body: "<html>".to_string(),
+                },
+                CompletionRemedy::ContactSupport,
+                false,
+            ),
+            (CompletionError::Empty { usage: None, body: "{}".to_string() }, CompletionRemedy::Retry, false),
+            (
+                CompletionError::Truncated {
+                    finish_reason: "length".to_string(),
+                    usage: None,
+                    body: "{\"name\":".to_string(),
+                },
+                CompletionRemedy::ShortenInput,
+                false,
+            ),
+            (
+                CompletionError::Refused {
+                    finish_reason: "content_filter".to_string(),
+                    refusal: None,
+                    usage: None,
+                },
+                CompletionRemedy::ContactSupport,
+                false,
+            ),
+            (
+                CompletionError::Schema {
+                    message: "missing field".to_string(),
+                    body: "{}".to_string(),
+                },
+                CompletionRemedy::ContactSupport,
+                false,
+            ),
+        ];
+        for (error, remedy, transient) in cases {
+            assert_eq!(error.remedy(), remedy, "{}", error);
+            assert_eq!(error.is_transient(), transient, "{}", error);
+        }
+    }
+
+    #[test]
+    fn the_user_message_follows_the_remedy() {
+        assert!(CompletionError::Timeout { seconds: 1 }.user_message().contains("try again in a minute"));
+        assert!(status(413, "").user_message().contains("shorten it"));
+        let refused = CompletionError::Refused {
+            finish_reason: "stop".to_string(),
+            refusal: Some("I can't help with that".to_string()),
+            usage: None,
+        };
+        assert!(refused.user_message().starts_with("The AI declined"));
+        assert!(status(401, "").user_message().starts_with("Something went wrong"));
+        let context = status(503, "").to_fragment_context("parsing résumé");
+        assert_eq!(context["error_message"], "Error parsing résumé");
+        assert_eq!(context["remedy"], "Retry");
+        assert_eq!(context["can_retry"], true);
+    }
+}
@@ -6,9 +6,15 @@ pub struct ChatCompletionResponse {
     pub object: String,
     pub created: u64,
     pub model: String,
-    pub system_fingerprint: String,
+    /// Not returned by every provider (e.g. Azure, llama.cpp)
+    #[serde(default)]
+    pub system_fingerprint: Option<String>,
     pub choices: Vec<Choice>,
+    #[serde(default)]
     pub usage: Usage,
+    /// The body as the provider sent it, kept for errors about the response
+    #[serde(skip)]
+    pub raw_body: String,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -22,18 +28,53 @@ pub struct Choice {
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Message {
     pub role: String,
-    pub content: String,
+    /// `null` when the model refuses
+    #[serde(default)]
+    pub content: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub refusal: Option<String>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
 pub struct Usage {
     pub prompt_tokens: u32,
     pub completion_tokens: u32,
     pub total_tokens: u32,
+    #[serde(default)]
     pub completion_tokens_details: CompletionTokensDetails,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
 pub struct CompletionTokensDetails {
+    #[serde(default)]
     pub reasoning_tokens: u32,
 }
+
+/// One `data:` event of a streamed chat completion
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ChatCompletionChunk {
+    pub id: String,
+    #[serde(default)]
+    pub model: String,
+    #[serde(default)]
+    pub choices: Vec<ChunkChoice>,
+    /// Only present on the last chunk, and only when `stream_options.include_usage` is set
+    #[serde(default)]
+    pub usage: Option<Usage>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ChunkChoice {
+    pub index: u32,
+    pub delta: Delta,
+    #[serde(default)]
+    pub finish_reason: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct Delta {
+    #[serde(default)]
+    pub content: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub refusal: Option<String>,
+}
@@ -1,13 +1,21 @@
-use completion_request::{ChatCompletionRequest, Message, ResponseFormat};
-use completion_response::ChatCompletionResponse;
+use completion_error::CompletionError;
+use completion_request::{ChatCompletionRequest, Message, ResponseFormat, StreamOptions};
+use futures::StreamExt;
 use log::debug;
-use reqwest::Client as HttpClient;
-use serde::{Deserialize, Serialize};
+use provider::LlmProvider;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use stream::{to_delta_stream, DeltaStream};
 
 use super::environ_ops::{Environ, Environment, OpenAIConfig};
+use crate::shared::entities::llm_usage::{LlmOperation, LlmUsageEntity};
 
+pub mod completion_cache;
+pub mod completion_error;
 pub mod completion_request;
 pub mod completion_response;
+pub mod provider;
+pub mod resilience;
+pub mod stream;
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct PostChatCompletionRequest {
@@ -16,11 +24,12 @@ pub struct PostChatCompletionRequest {
     pub response_format: Option<ResponseFormat>,
 }
 
-pub async fn post_chat_completion(
+fn build_request(
     messages: Vec<Message>,
     response_format: Option<ResponseFormat>,
     user_id: Option<String>,
-) -> Option<String> {
+    stream: bool,
+) -> ChatCompletionRequest {
     let openai_config: OpenAIConfig = Environ::init();
     debug!("response_format: {:?}", response_format);
 
@@ -29,7 +38,8 @@ pub async fn post_chat_completion(
     let openai_request = ChatCompletionRequest {
         model: openai_config.model,
         messages,
-        stream: Some(false),
+        stream: Some(stream),
+        stream_options: if stream { Some(StreamOptions { include_usage: true }) } else { None },
         max_completion_tokens,
         user: user_id,
         response_format,
@@ -43,33 +53,78 @@ pub async fn post_chat_completion(
     };
 
     debug!("openai_request: {:?}", openai_request);
+    openai_request
+}
 
-    let client = HttpClient::new();
-    let response = client
-        .post("https://api.openai.com/v1/chat/completions")
-        .header("Content-Type", "application/json")
-        .header("Authorization", format!("Bearer {}", openai_config.api_key))
-        .json(&openai_request)
-        .send()
-        .await;
-
-    let response = match response {
-        Ok(r) => r,
-        Err(e) => {
-            log::error!("OpenAI request failed: {}", e);
-            return None;
+/// Sends a completion request and records its token usage against `user_id` as `operation`
+pub async fn post_chat_completion(
+    provider: &dyn LlmProvider,
+    messages: Vec<Message>,
+    response_format: Option<ResponseFormat>,
+    user_id: Option<String>,
+    operation: LlmOperation,
+) -> Result<String, CompletionError> {
+    let openai_request = build_request(messages, response_format, user_id.clone(), false);
+    let data = provider.chat_completion(&openai_request).await?;
+    let usage = data.usage;
+    let result = match data.choices.into_iter().next() {
+        Some(choice) => {
+            debug!("{} finish reason: {}", provider.name(), choice.finish_reason);
+            match (choice.finish_reason.as_str(), choice.message.content) {
+                ("length", _) => Err(CompletionError::Truncated {
+                    finish_reason: choice.finish_reason,
+                    usage: Some(usage.clone()),
+                    body: data.raw_body.clone(),
+                }),
+                ("content_filter", _) | (_, None) => Err(CompletionError::Refused {
+                    finish_reason: choice.finish_reason,
+                    refusal: choice.message.refusal,
+                    usage: Some(usage.clone()),
+                }),
+                (_, Some(content)) => Ok(content),
+            }
         }
+        None => Err(CompletionError::Empty {
+            usage: Some(usage.clone()),
+            body: data.raw_body.clone(),
+        }),
     };
+    LlmUsageEntity::new(user_id, operation, provider.name(), &data.model, &usage, result.is_ok()).record();
+    result
+}
+
+/// Streams the content of a completion as it is generated, usage is recorded from the final chunk
+pub async fn stream_chat_completion(
+    provider: &dyn LlmProvider,
+    messages: Vec<Message>,
+    response_format: Option<ResponseFormat>,
+    user_id: Option<String>,
+    operation: LlmOperation,
+) -> Result<DeltaStream, CompletionError> {
+    let openai_request = build_request(messages, response_format, user_id.clone(), true);
+    let provider_name = provider.name();
+    let chunks = provider.chat_completion_stream(&openai_request).await?;
+    let mut finish_reason: Option<String> = None;
+    let chunks = chunks
+        .inspect(move |chunk| {
+            if let Ok(chunk) = chunk {
+                if let Some(reason) = chunk.choices.iter().find_map(|c| c.finish_reason.clone()) {
+                    finish_reason = Some(reason);
+                }
+                if let Some(usage) = &chunk.usage {
+                    let succeeded = !matches!(finish_reason.as_deref(), Some("length") | Some("content_filter"));
+                    LlmUsageEntity::new(user_id.clone(), operation, provider_name, &chunk.model, usage, succeeded).record();
+                }
+            }
+        })
+        .boxed();
+    Ok(to_delta_stream(chunks))
+}
 
-    if response.status().is_success() {
-        let response_body = response.text().await.unwrap();
-        let data = serde_json::from_str::<ChatCompletionResponse>(&response_body).unwrap();
-        let choice = &data.choices[0];
-        debug!("Finish reason: {}", choice.finish_reason);
-        let content = &choice.message.content;
-        Some(content.to_string())
-    } else {
-        log::error!("OpenAI response NOT OK: {} Details: [{}]", response.status(), response.text().await.unwrap());
-        None
-    }
+/// Deserializes a structured completion into `T`, keeping the raw content on failure
+pub fn parse_completion<T: DeserializeOwned>(content: &str) -> Result<T, CompletionError> {
+    serde_json::from_str::<T>(content).map_err(|e| CompletionError::Schema {
+        message: e.to_string(),
+        body: content.to_string(),
+    })
 }
@@ -0,0 +1,531 @@
+use std::sync::{Arc, OnceLock};
+
+use async_trait::async_trait;
+use futures::{stream, StreamExt};
+use log::{debug, error};
+use reqwest::{header::HeaderMap, Client as HttpClient, RequestBuilder, Response};
+use serde_json::{json, Map, Value};
+
+use super::{
+    completion_error::CompletionError,
+    completion_request::{ChatCompletionRequest, ResponseFormat},
+    completion_response::{ChatCompletionChunk, ChatCompletionResponse, Choice, ChunkChoice, Delta, Message, Usage},
+    resilience::{ResilientProvider, RetryPolicy},
+    stream::{to_chunk_stream, ChunkStream},
+};
+use crate::shared::ops::{
+    date_ops,
+    environ_ops::{Environ, LlmConfig, LlmProviderKind, OpenAIConfig},
+};
+
+const OPENAI_BASE_URL: &str = "https://api.openai.com/v1";
+const OLLAMA_BASE_URL: &str = "http://localhost:11434/v1";
+const AZURE_API_VERSION: &str = "2024-08-01-preview";
+
+/// A backend that can answer an OpenAI-style chat completion request.
+#[async_trait]
+pub trait LlmProvider: Send + Sync {
+    /// Short name used in logs
+    fn name(&self) -> &'static str;
+
+    async fn chat_completion(
+        &self,
+        request: &ChatCompletionRequest,
+    ) -> Result<ChatCompletionResponse, CompletionError>;
+
+    /// Streams the completion as it is generated. Providers without streaming
+    /// support answer in full and yield a single chunk.
+    async fn chat_completion_stream(
+        &self,
+        request: &ChatCompletionRequest,
+    ) -> Result<ChunkStream, CompletionError> {
+        let response = self.chat_completion(request).await?;
+        let chunk = ChatCompletionChunk {
+            id: response.id,
+            model: response.model,
+            choices: response
+                .choices
+                .into_iter()
+                .map(|c| ChunkChoice {
+                    index: c.index,
+                    delta: Delta {
+                        content: c.message.content,
+                        refusal: c.message.refusal,
+                    },
+                    finish_reason: Some(c.finish_reason),
+                })
+                .collect(),
+            usage: Some(response.usage),
+        };
+        Ok(stream::iter(vec![Ok(chunk)]).boxed())
+    }
+}
+
+/// Builds the provider selected by `LLM_PROVIDER`, wrapped with the retry policy from `OpenAIConfig`.
+/// Called once at startup, a provider missing its settings stops the server or worker there.
+pub fn build_provider(
+    llm_config: LlmConfig,
+    openai_config: OpenAIConfig,
+) -> Result<Arc<dyn LlmProvider>, String> {
+    let policy = RetryPolicy::from(&openai_config);
+    let inner: Box<dyn LlmProvider> = match llm_config.llm_provider {
+        LlmProviderKind::OpenAI => Box::new(OpenAIProvider {
+            base_url: llm_config.llm_base_url.unwrap_or(OPENAI_BASE_URL.to_string()),
+            api_key: required_key(openai_config.api_key, "OpenAI")?,
+        }),
+        LlmProviderKind::AzureOpenAI => Box::new(AzureOpenAIProvider {
+            endpoint: llm_config.llm_base_url.ok_or("Missing LLM_BASE_URL for Azure OpenAI")?,
+            deployment: llm_config.azure_deployment.ok_or("Missing AZURE_OPENAI_DEPLOYMENT for Azure OpenAI")?,
+            api_version: llm_config.azure_api_version.unwrap_or(AZURE_API_VERSION.to_string()),
+            api_key: required_key(openai_config.api_key, "Azure OpenAI")?,
+        }),
+        LlmProviderKind::Ollama => Box::new(OllamaProvider {
+            base_url: llm_config.llm_base_url.unwrap_or(OLLAMA_BASE_URL.to_string()),
+        }),
+        LlmProviderKind::Fake => Box::new(FakeProvider::default()),
+    };
+    Ok(Arc::new(ResilientProvider::new(inner, policy)))
+}
+
+/// The key for a hosted provider, an empty `OPENAI_API_KEY` is as good as none
+fn required_key(
+    api_key: String,
+    provider: &str,
+) -> Result<String, String> {
+    if api_key.trim().is_empty() {
+        return Err(format!("Missing OPENAI_API_KEY for {}", provider));
+    }
+    Ok(api_key)
+}
+
+/// `build_provider` from the environment
+pub fn provider_from_env() -> Result<Arc<dyn LlmProvider>, String> {
+    build_provider(Environ::init(), Environ::init())
+}
+
+/// One HTTP client (and connection pool) shared by all providers
+fn http_client() -> &'static HttpClient {
+    static CLIENT: OnceLock<HttpClient> = OnceLock::new();
+    CLIENT.get_or_init(HttpClient::new)
+}
+
+/// Sends the request and turns transport failures and non-2xx answers into errors
+async fn execute(
+    provider: &str,
+    builder: RequestBuilder,
+    request: &ChatCompletionRequest,
+) -> Result<Response, CompletionError> {
+    let response = match builder.header("Content-Type", "application/json").json(request).send().await {
+        Ok(r) => r,
+        Err(e) => {
+            error!("{} request failed: {}", provider, e);
+            return Err(CompletionError::Network { message: e.to_string() });
+        }
+    };
+
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+    let retry_after_ms = retry_after_ms(response.headers());
+    let response_body = response.text().await.unwrap_or_default();
+    error!("{} response NOT OK: {} Details: [{}]", provider, status, response_body);
+    Err(CompletionError::Status {
+        status: status.as_u16(),
+        retry_after_ms,
+        body: response_body,
+    })
+}
+
+async fn send(
+    provider: &str,
+    builder: RequestBuilder,
+    request: &ChatCompletionRequest,
+) -> Result<ChatCompletionResponse, CompletionError> {
+    let response = execute(provider, builder, request).await?;
+    let response_body = match response.text().await {
+        Ok(b) => b,
+        Err(e) => {
+            error!("{} response body could not be read: {}", provider, e);
+            return Err(CompletionError::Network { message: e.to_string() });
+        }
+    };
+
+    match serde_json::from_str::<ChatCompletionResponse>(&response_body) {
+        Ok(mut r) => {
+            r.raw_body = response_body;
+            Ok(r)
+        }
+        Err(e) => {
+            error!("{} response could not be deserialized: {} Body: [{}]", provider, e, response_body);
+            Err(CompletionError::InvalidResponse {
+                message: e.to_string(),
+                body: response_body,
+            })
+        }
+    }
+}
+
+async fn send_stream(
+    provider: &str,
+    builder: RequestBuilder,
+    request: &ChatCompletionRequest,
+) -> Result<ChunkStream, CompletionError> {
+    let response = execute(provider, builder.header("Accept", "text/event-stream"), request).await?;
+    Ok(to_chunk_stream(response.bytes_stream()))
+}
+
+/// OpenAI sends `retry-after-ms`, everyone else sends `Retry-After` in seconds
+fn retry_after_ms(headers: &HeaderMap) -> Option<u64> {
+    let header = |name: &str| headers.get(name).and_then(|v| v.to_str().ok()).and_then(|v| v.trim().parse::<f64>().ok());
+    match header("retry-after-ms") {
+        Some(ms) => Some(ms as u64),
+        None => header("retry-after").map(|secs| (secs * 1000.0) as u64),
+    }
+}
+
+pub struct OpenAIProvider {
+    pub base_url: String,
+    pub api_key: String,
+}
+
+#[async_trait]
+impl LlmProvider for OpenAIProvider {
+    fn name(&self) -> &'static str {
+        "OpenAI"
+    }
+
+    async fn chat_completion(
+        &self,
+        request: &ChatCompletionRequest,
+    ) -> Result<ChatCompletionResponse, CompletionError> {
+        send(self.name(), self.post(), request).await
+    }
+
+    async fn chat_completion_stream(
+        &self,
+        request: &ChatCompletionRequest,
+    ) -> Result<ChunkStream, CompletionError> {
+        send_stream(self.name(), self.post(), request).await
+    }
+}
+
+impl OpenAIProvider {
+    fn post(&self) -> RequestBuilder {
+        let url = format!("{}/chat/completions", self.base_url.trim_end_matches('/'));
+        http_client().post(url).header("Authorization", format!("Bearer {}", self.api_key))
+    }
+}
+
+pub struct AzureOpenAIProvider {
+    pub endpoint: String,
+    pub deployment: String,
+    pub api_version: String,
+    pub api_key: String,
+}
+
+#[async_trait]
+impl LlmProvider for AzureOpenAIProvider {
+    fn name(&self) -> &'static str {
+        "Azure OpenAI"
+    }
+
+    async fn chat_completion(
+        &self,
+        request: &ChatCompletionRequest,
+    ) -> Result<ChatCompletionResponse, CompletionError> {
+        send(self.name(), self.post(), request).await
+    }
+
+    async fn chat_completion_stream(
+        &self,
+        request: &ChatCompletionRequest,
+    ) -> Result<ChunkStream, CompletionError> {
+        send_stream(self.name(), self.post(), request).await
+    }
+}
+
+impl AzureOpenAIProvider {
+    fn post(&self) -> RequestBuilder {
+        let url = format!(
+            "{}/openai/deployments/{}/chat/completions?api-version={}",
+            self.endpoint.trim_end_matches('/'),
+            self.deployment,
+            self.api_version
+        );
+        http_client().post(url).header("api-key", self.api_key.as_str())
+    }
+}
+
+/// Ollama and llama.cpp both expose an OpenAI-compatible `/v1/chat/completions`
+pub struct OllamaProvider {
+    pub base_url: String,
+}
+
+#[async_trait]
+impl LlmProvider for OllamaProvider {
+    fn name(&self) -> &'static str {
+        "Ollama"
+    }
+
+    async fn chat_completion(
+        &self,
+        request: &ChatCompletionRequest,
+    ) -> Result<ChatCompletionResponse, CompletionError> {
+        send(self.name(), self.post(), request).await
+    }
+
+    async fn chat_completion_stream(
+        &self,
+        request: &ChatCompletionRequest,
+    ) -> Result<ChunkStream, CompletionError> {
+        send_stream(self.name(), self.post(), request).await
+    }
+}
+
+impl OllamaProvider {
+    fn post(&self) -> RequestBuilder {
+        let url = format!("{}/chat/completions", self.base_url.trim_end_matches('/'));
+        http_client().post(url)
+    }
+}
+
+/// Answers without any network call. When `content` is set it is returned verbatim,
+/// otherwise a deterministic document is generated from the requested json schema.
+#[derive(Default)]
+pub struct FakeProvider {
+    pub content: Option<String>,
+}
+
+impl FakeProvider {
+    pub fn with_content(content: &str) -> Self {
+        FakeProvider { content: Some(content.to_string()) }
+    }
+}
+
+#[async_trait]
+impl LlmProvider for FakeProvider {
+    fn name(&self) -> &'static str {
+        "Fake"
+    }
+
+    async fn chat_completion(
+        &self,
+        request: &ChatCompletionRequest,
+    ) -> Result<ChatCompletionResponse, CompletionError> {
+        let content = match &self.content {
+            Some(c) => c.clone(),
+            None => fake_content(request.response_format.as_ref()),
+        };
+        debug!("Fake completion: {}", content);
+        let mut response = ChatCompletionResponse {
+            id: "fake-completion".to_string(),
+            object: "chat.completion".to_string(),
+            created: date_ops::to_timestamp() as u64,
+            model: request.model.clone(),
+            system_fingerprint: None,
+            choices: vec![Choice {
+                index: 0,
+                message: Message {
+                    role: "assistant".to_string(),
+                    content: Some(content),
+                    refusal: None,
+                },
+                logprobs: None,
+                finish_reason: "stop".to_string(),
+            }],
+            usage: Usage::default(),
+            raw_body: String::new(),
+        };
+        response.raw_body = serde_json::to_string(&response).unwrap_or_default();
+        Ok(response)
+    }
+}
+
+fn fake_content(response_format: Option<&ResponseFormat>) -> String {
+    let schema = response_format.and_then(|f| f.json_schema.as_ref()).and_then(|s| s.get("schema"));
+    match schema {
+        Some(schema) => {
+            let definitions = schema.get("definitions").cloned().unwrap_or(Value::Null);
+            fake_value(schema, &definitions, "value").to_string()
+        }
+        None => "fake completion".to_string(),
+    }
+}
+
+/// Walks a schemars json schema and produces the smallest document that satisfies it
+fn fake_value(
+    schema: &Value,
+    definitions: &Value,
+    name: &str,
+) -> Value {
+    if let Some(reference) = schema.get("$ref").and_then(Value::as_str) {
+        let key = reference.rsplit('/').next().unwrap_or_default();
+        return match definitions.get(key) {
+            Some(s) => fake_value(s, definitions, name),
+            None => Value::Null,
+        };
+    }
+    if let Some(first) = schema.get("enum").and_then(Value::as_array).and_then(|e| e.first()) {
+        return first.clone();
+    }
+    for key in ["allOf", "oneOf", "anyOf"] {
+        if let Some(first) = schema.get(key).and_then(Value::as_array).and_then(|e| e.first()) {
+            return fake_value(first, definitions, name);
+        }
+    }
+    // schemars renders Option<T> as ["T", "null"]
+    let kind = match schema.get("type") {
+        Some(Value::String(t)) => t.as_str(),
+        Some(Value::Array(types)) => types.iter().filter_map(Value::as_str).find(|t| *t != "null").unwrap_or("null"),
+        _ => "null",
+    };
+    match kind {
+        "object" => {
+            let mut object = Map::new();
+            if let Some(properties) = schema.get("properties").and_then(Value::as_object) {
+                for (key, property) in properties {
+                    object.insert(key.clone(), fake_value(property, definitions, key));
+                }
+            }
+            Value::Object(object)
+        }
+        "array" => match schema.get("items") {
+            Some(items) => json!([fake_value(items, definitions, name)]),
+            None => json!([]),
+        },
+        "string" => json!(name),
+        "integer" => json!(0),
+        "number" => json!(0.0),
+        "boolean" => json!(false),
+        _ => Value::Null,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::header::HeaderValue;
+
+    use super::*;
+    use crate::{
+        features::resumes::entities::resume::ParsedResume,
+        shared::ops::{
+            openai::{completion_request::ResponseFormatType, parse_completion},
+            schema_ops,
+        },
+    };
+
+    fn openai_config() -> OpenAIConfig {
+        OpenAIConfig {
+            api_key: "test-key".to_string(),
+            model: "test-model".to_string(),
+            temperature: 0.0,
+            max_retries: 0,
+            initial_backoff_ms: 1,
+            max_backoff_ms: 1,
+            request_timeout_secs: 1,
+            request_deadline_secs: 1,
+            breaker_failure_threshold: 5,
+            breaker_cooldown_secs: 1,
+        }
+    }
+
+    fn llm_config(kind: LlmProviderKind) -> LlmConfig {
+        LlmConfig {
+            llm_provider: kind,
+            llm_base_url: None,
+            azure_deployment: None,
+            azure_api_version: None,
+        }
+    }
+
+    fn resume_request() -> ChatCompletionRequest {
+        ChatCompletionRequest {
+            model: "test-model".to_string(),
+            response_format: Some(ResponseFormat {
+                format_type: ResponseFormatType::JsonSchema,
+                json_schema: Some(json!(schema_ops::to_openai_schema::<ParsedResume>().unwrap())),
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn fake_provider_answers_with_a_document_matching_the_schema() {
+        let response = FakeProvider::default().chat_completion(&resume_request()).await.unwrap();
+        let content = response.choices[0].message.content.clone().unwrap();
+        assert!(parse_completion::<ParsedResume>(&content).is_ok(), "not a ParsedResume: {}", content);
+        assert_eq!(response.model, "test-model");
+    }
+
+    #[tokio::test]
+    async fn fake_provider_answers_with_its_content_verbatim() {
+        let provider = FakeProvider::with_content("{\"score\": 3}");
+        let response = provider.chat_completion(&resume_request()).await.unwrap();
+        assert_eq!(response.choices[0].message.content.as_deref(), Some("{\"score\": 3}"));
+        assert_eq!(response.choices[0].finish_reason, "stop");
+    }
+
+    #[tokio::test]
+    async fn providers_without_streaming_stream_one_chunk() {
+        let provider = FakeProvider::with_content("whole answer");
+        let chunks: Vec<_> = provider.chat_completion_stream(&ChatCompletionRequest::default()).await.unwrap().collect().await;
+        assert_eq!(chunks.len(), 1);
+        let chunk = chunks.into_iter().next().unwrap().unwrap();
+        assert_eq!(chunk.choices[0].delta.content.as_deref(), Some("whole answer"));
+        assert_eq!(chunk.choices[0].finish_reason.as_deref(), Some("stop"));
+    }
+
+    #[test]
+    fn azure_without_its_settings_fails_to_build() {
+        let missing_endpoint = build_provider(llm_config(LlmProviderKind::AzureOpenAI), openai_config());
+        assert!(missing_endpoint.is_err_and(|e| e.contains("LLM_BASE_URL")));
+        let missing_deployment = build_provider(
+            LlmConfig {
+                llm_base_url: Some("https://example.openai.azure.com".to_string()),
+                ..llm_config(LlmProviderKind::AzureOpenAI)
+            },
+            openai_config(),
+        );
+        assert!(missing_deployment.is_err_and(|e| e.contains("AZURE_OPENAI_DEPLOYMENT")));
+    }
+
+    #[test]
+    fn hosted_providers_without_a_key_fail_to_build() {
+        let without_key = || OpenAIConfig {
+            api_key: " ".to_string(),
+            ..openai_config()
+        };
+        let openai = build_provider(llm_config(LlmProviderKind::OpenAI), without_key());
+        assert!(openai.is_err_and(|e| e == "Missing OPENAI_API_KEY for OpenAI"));
+        let azure = build_provider(
+            LlmConfig {
+                llm_base_url: Some("https://example.openai.azure.com".to_string()),
+                azure_deployment: Some("gpt".to_string()),
+                ..llm_config(LlmProviderKind::AzureOpenAI)
+            },
+            without_key(),
+        );
+        assert!(azure.is_err_and(|e| e == "Missing OPENAI_API_KEY for Azure OpenAI"));
+        for kind in [LlmProviderKind::Ollama, LlmProviderKind::Fake] {
+            assert!(build_provider(llm_config(kind), without_key()).is_ok(), "{:?}", kind);
+        }
+    }
+
+    #[test]
+    fn providers_with_defaults_build_without_settings() {
+        for kind in [LlmProviderKind::OpenAI, LlmProviderKind::Ollama, LlmProviderKind::Fake] {
+            assert!(build_provider(llm_config(kind), openai_config()).is_ok(), "{:?}", kind);
+        }
+    }
+
+    #[test]
+    fn retry_after_prefers_milliseconds() {
+        let mut headers = HeaderMap::new();
+        headers.insert("retry-after", HeaderValue::from_static("2"));
+        assert_eq!(retry_after_ms(&headers), Some(2000));
+        headers.insert("retry-after-ms", HeaderValue::from_static("150"));
+        assert_eq!(retry_after_ms(&headers), Some(150));
+        assert_eq!(retry_after_ms(&HeaderMap::new()), None);
+    }
+}
@@ -0,0 +1,432 @@
+use std::{
+    future::Future,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use log::{info, warn};
+use rand::Rng;
+
+use super::{completion_error::CompletionError, completion_request::ChatCompletionRequest, completion_response::ChatCompletionResponse, provider::LlmProvider, stream::ChunkStream};
+use crate::shared::ops::environ_ops::OpenAIConfig;
+
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    pub request_timeout: Duration,
+    pub request_deadline: Duration,
+    pub breaker_failure_threshold: u32,
+    pub breaker_cooldown: Duration,
+}
+
+impl From<&OpenAIConfig> for RetryPolicy {
+    fn from(config: &OpenAIConfig) -> Self {
+        RetryPolicy {
+            max_retries: config.max_retries,
+            initial_backoff: Duration::from_millis(config.initial_backoff_ms),
+            max_backoff: Duration::from_millis(config.max_backoff_ms),
+            request_timeout: Duration::from_secs(config.request_timeout_secs),
+            request_deadline: Duration::from_secs(config.request_deadline_secs),
+            breaker_failure_threshold: config.breaker_failure_threshold,
+            breaker_cooldown: Duration::from_secs(config.breaker_cooldown_secs),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Full-jitter exponential backoff, never shorter than what the provider asked for
+    /// and never longer than `max_backoff`, however long that was
+    pub fn backoff(
+        &self,
+        attempt: u32,
+        retry_after: Option<Duration>,
+    ) -> Duration {
+        let ceiling = self.initial_backoff.saturating_mul(2u32.saturating_pow(attempt)).min(self.max_backoff);
+        let jittered = Duration::from_millis(rand::thread_rng().gen_range(0..=ceiling.as_millis() as u64));
+        match retry_after {
+            Some(r) => jittered.max(r).min(self.max_backoff),
+            None => jittered,
+        }
+    }
+}
+
+/// Counts consecutive transient failures. Once the threshold is hit calls are rejected until
+/// the cooldown passes, then calls go through again and the first success closes it.
+#[derive(Debug, Default)]
+struct CircuitBreaker {
+    consecutive_failures: u32,
+    open_until: Option<Instant>,
+}
+
+/// Wraps any provider with per-attempt timeouts, retries and a circuit breaker.
+/// The provider is built once per process, so its breaker sees every call made through it.
+pub struct ResilientProvider {
+    pub inner: Box<dyn LlmProvider>,
+    pub policy: RetryPolicy,
+    breaker: Mutex<CircuitBreaker>,
+}
+
+impl ResilientProvider {
+    pub fn new(
+        inner: Box<dyn LlmProvider>,
+        policy: RetryPolicy,
+    ) -> Self {
+        ResilientProvider {
+            inner,
+            policy,
+            breaker: Mutex::new(CircuitBreaker::default()),
+        }
+    }
+
+    fn check_breaker(&self) -> Result<(), CompletionError> {
+        let breaker = self.breaker.lock().unwrap_or_else(|e| e.into_inner());
+        match breaker.open_until {
+            Some(until) if until > Instant::now() => Err(CompletionError::CircuitOpen {
+                retry_in_secs: until.saturating_duration_since(Instant::now()).as_secs().max(1),
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    fn record_success(&self) {
+        let mut breaker = self.breaker.lock().unwrap_or_else(|e| e.into_inner());
+        if breaker.open_until.is_some() {
+            info!("{} circuit closed", self.name());
+        }
+        *breaker = CircuitBreaker::default();
+    }
+
+    fn record_failure(&self) {
+        let mut breaker = self.breaker.lock().unwrap_or_else(|e| e.into_inner());
+        breaker.consecutive_failures += 1;
+        if breaker.consecutive_failures >= self.policy.breaker_failure_threshold {
+            warn!(
+                "{} circuit open for {:?} after {} consecutive failures",
+                self.name(),
+                self.policy.breaker_cooldown,
+                breaker.consecutive_failures
+            );
+            breaker.open_until = Some(Instant::now() + self.policy.breaker_cooldown);
+        }
+    }
+
+    /// Retries transient failures until `max_retries` or until the next attempt would start past
+    /// `request_deadline`, whichever comes first. An attempt never runs past the deadline either.
+    async fn with_retries<'a, T, F, Fut>(
+        &'a self,
+        call: F,
+    ) -> Result<T, CompletionError>
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = Result<T, CompletionError>> + 'a,
+    {
+        let name = self.name();
+        let deadline = Instant::now() + self.policy.request_deadline;
+        let mut attempt = 0;
+        loop {
+            self.check_breaker()?;
+            let timeout = self.policy.request_timeout.min(deadline.saturating_duration_since(Instant::now()));
+            let result = match tokio::time::timeout(timeout, call()).await {
+                Ok(r) => r,
+                Err(_) => Err(CompletionError::Timeout { seconds: timeout.as_secs().max(1) }),
+            };
+            match result {
+                Ok(r) => {
+                    self.record_success();
+                    return Ok(r);
+                }
+                Err(e) if e.is_transient() => {
+                    self.record_failure();
+                    if attempt >= self.policy.max_retries {
+                        return Err(e);
+                    }
+                    let delay = self.policy.backoff(attempt, e.retry_after_ms().map(Duration::from_millis));
+                    if Instant::now() + delay >= deadline {
+                        warn!("{} attempt {} failed: {}, not retrying past the request deadline", name, attempt + 1, e);
+                        return Err(e);
+                    }
+                    attempt += 1;
+                    warn!("{} attempt {} failed: {}, retrying in {:?}", name, attempt, e, delay);
+                    tokio::time::sleep(delay).await;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl LlmProvider for ResilientProvider {
+    fn name(&self) -> &'static str {
+        self.inner.name()
+    }
+
+    async fn chat_completion(
+        &self,
+        request: &ChatCompletionRequest,
+    ) -> Result<ChatCompletionResponse, CompletionError> {
+        self.with_retries(|| self.inner.chat_completion(request)).await
+    }
+
+    /// Only establishing the stream is retried, the timeout does not apply to reading it
+    async fn chat_completion_stream(
+        &self,
+        request: &ChatCompletionRequest,
+    ) -> Result<ChunkStream, CompletionError> {
+        self.with_retries(|| self.inner.chat_completion_stream(request)).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::{TcpListener, TcpStream},
+    };
+
+    use super::*;
+    use crate::shared::ops::openai::provider::OpenAIProvider;
+
+    const COMPLETION: &str =
+        r#"{"id":"mock","object":"chat.completion","created":0,"model":"mock-model","choices":[{"index":0,"message":{"role":"assistant","content":"ok"},"logprobs":null,"finish_reason":"stop"}]}"#;
+
+    /// One scripted answer of the mock server
+    struct Reply {
+        status: u16,
+        headers: Vec<(&'static str, &'static str)>,
+        body: &'static str,
+        delay: Duration,
+    }
+
+    fn reply(status: u16) -> Reply {
+        Reply {
+            status,
+            headers: vec![],
+            body: if status == 200 { COMPLETION } else { r#"{"error":{"message":"mock failure"}}"# },
+            delay: Duration::ZERO,
+        }
+    }
+
+    fn reply_after(
+        status: u16,
+        header: &'static str,
+        value: &'static str,
+    ) -> Reply {
+        Reply {
+            headers: vec![(header, value)],
+            ..reply(status)
+        }
+    }
+
+    /// Reads one request off the connection, headers and body
+    async fn read_request(stream: &mut TcpStream) {
+        let mut request = Vec::new();
+        let mut buffer = [0u8; 4096];
+        loop {
+            let read = stream.read(&mut buffer).await.unwrap_or(0);
+            if read == 0 {
+                return;
+            }
+            request.extend_from_slice(&buffer[..read]);
+            let text = String::from_utf8_lossy(&request).to_string();
+            if let Some(end) = text.find("\r\n\r\n") {
+                let length = text[..end]
+                    .lines()
+                    .find_map(|l| l.to_lowercase().strip_prefix("content-length:").map(|v| v.trim().parse::<usize>().unwrap_or(0)))
+                    .unwrap_or(0);
+                if request.len() >= end + 4 + length {
+                    return;
+                }
+            }
+        }
+    }
+
+    /// A local http server that answers with `replies` in order, repeating the last one.
+    /// Returns its base url and how many requests it got.
+    async fn mock_server(replies: Vec<Reply>) -> (String, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+        let hits = Arc::new(AtomicUsize::new(0));
+        let counter = hits.clone();
+        let replies = Arc::new(replies);
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let hit = counter.fetch_add(1, Ordering::SeqCst);
+                let replies = replies.clone();
+                tokio::spawn(async move {
+                    read_request(&mut stream).await;
+                    let reply = &replies[hit.min(replies.len() - 1)];
+                    tokio::time::sleep(reply.delay).await;
+                    let mut response = format!(
+                        "HTTP/1.1 {} Mock\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n",
+                        reply.status,
+                        reply.body.len()
+                    );
+                    for (name, value) in &reply.headers {
+                        response.push_str(&format!("{}: {}\r\n", name, value));
+                    }
+                    response.push_str("\r\n");
+                    response.push_str(reply.body);
+                    let _ = stream.write_all(response.as_bytes()).await;
+                    let _ = stream.shutdown().await;
+                });
+            }
+        });
+        (base_url, hits)
+    }
+
+    fn policy() -> RetryPolicy {
+        RetryPolicy {
+            max_retries: 3,
+            initial_backoff: Duration::from_millis(1),
+            max_backoff: Duration::from_millis(20),
+            request_timeout: Duration::from_secs(5),
+            request_deadline: Duration::from_secs(10),
+            breaker_failure_threshold: 100,
+            breaker_cooldown: Duration::from_secs(30),
+        }
+    }
+
+    fn provider(
+        base_url: String,
+        policy: RetryPolicy,
+    ) -> ResilientProvider {
+        let inner = OpenAIProvider {
+            base_url,
+            api_key: "test-key".to_string(),
+        };
+        ResilientProvider::new(Box::new(inner), policy)
+    }
+
+    fn request() -> ChatCompletionRequest {
+        ChatCompletionRequest {
+            model: "mock-model".to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn backoff_never_exceeds_max_backoff_even_when_asked_to_wait_longer() {
+        let policy = policy();
+        for attempt in 0..10 {
+            assert!(policy.backoff(attempt, None) <= policy.max_backoff);
+            assert_eq!(policy.backoff(attempt, Some(Duration::from_secs(3600))), policy.max_backoff);
+        }
+    }
+
+    #[tokio::test]
+    async fn server_errors_are_retried_until_one_succeeds() {
+        let (base_url, hits) = mock_server(vec![reply(500), reply(503), reply(200)]).await;
+        let response = provider(base_url, policy()).chat_completion(&request()).await.unwrap();
+        assert_eq!(response.choices[0].message.content.as_deref(), Some("ok"));
+        assert_eq!(hits.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn retries_stop_after_max_retries() {
+        let (base_url, hits) = mock_server(vec![reply(502)]).await;
+        let result = provider(base_url, policy()).chat_completion(&request()).await;
+        assert_eq!(result.unwrap_err().status(), Some(502));
+        assert_eq!(hits.load(Ordering::SeqCst), 4);
+    }
+
+    #[tokio::test]
+    async fn client_errors_are_not_retried() {
+        let (base_url, hits) = mock_server(vec![reply(400), reply(200)]).await;
+        let result = provider(base_url, policy()).chat_completion(&request()).await;
+        assert_eq!(result.unwrap_err().status(), Some(400));
+        assert_eq!(hits.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn rate_limits_wait_for_retry_after() {
+        let (base_url, hits) = mock_server(vec![reply_after(429, "retry-after-ms", "300"), reply(200)]).await;
+        let policy = RetryPolicy {
+            max_backoff: Duration::from_secs(1),
+            ..policy()
+        };
+        let started = Instant::now();
+        assert!(provider(base_url, policy).chat_completion(&request()).await.is_ok());
+        assert!(started.elapsed() >= Duration::from_millis(300), "retried after {:?}", started.elapsed());
+        assert_eq!(hits.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn a_long_retry_after_is_cut_to_max_backoff() {
+        let (base_url, hits) = mock_server(vec![reply_after(429, "retry-after", "3600"), reply(200)]).await;
+        let started = Instant::now();
+        assert!(provider(base_url, policy()).chat_completion(&request()).await.is_ok());
+        assert!(started.elapsed() < Duration::from_secs(5), "waited {:?}", started.elapsed());
+        assert_eq!(hits.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn no_retry_sleeps_past_the_deadline() {
+        let (base_url, hits) = mock_server(vec![reply_after(429, "retry-after-ms", "2000"), reply(200)]).await;
+        let policy = RetryPolicy {
+            max_backoff: Duration::from_secs(5),
+            request_deadline: Duration::from_millis(500),
+            ..policy()
+        };
+        let started = Instant::now();
+        let result = provider(base_url, policy).chat_completion(&request()).await;
+        assert_eq!(result.unwrap_err().status(), Some(429));
+        assert!(started.elapsed() < Duration::from_millis(500), "gave up after {:?}", started.elapsed());
+        assert_eq!(hits.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn slow_attempts_time_out_and_are_retried() {
+        let slow = Reply {
+            delay: Duration::from_secs(2),
+            ..reply(200)
+        };
+        let (base_url, hits) = mock_server(vec![slow]).await;
+        let policy = RetryPolicy {
+            max_retries: 1,
+            request_timeout: Duration::from_millis(100),
+            ..policy()
+        };
+        let result = provider(base_url, policy).chat_completion(&request()).await;
+        // a sub-second timeout is reported as a second, not 0
+        assert!(matches!(result, Err(CompletionError::Timeout { seconds: 1 })), "{:?}", result.err());
+        assert_eq!(hits.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn breaker_opens_then_lets_a_trial_call_through_after_the_cooldown() {
+        let (base_url, hits) = mock_server(vec![reply(500), reply(500), reply(500), reply(200)]).await;
+        let policy = RetryPolicy {
+            max_retries: 0,
+            breaker_failure_threshold: 2,
+            breaker_cooldown: Duration::from_millis(200),
+            ..policy()
+        };
+        let provider = provider(base_url, policy);
+        assert_eq!(provider.chat_completion(&request()).await.unwrap_err().status(), Some(500));
+        assert_eq!(provider.chat_completion(&request()).await.unwrap_err().status(), Some(500));
+        // open: rejected without a request
+        assert!(matches!(provider.chat_completion(&request()).await, Err(CompletionError::CircuitOpen { .. })));
+        assert_eq!(hits.load(Ordering::SeqCst), 2);
+
+        // half-open: the trial call fails and the circuit opens again right away
+        tokio::time::sleep(Duration::from_millis(250)).await;
+        assert_eq!(provider.chat_completion(&request()).await.unwrap_err().status(), Some(500));
+        assert!(matches!(provider.chat_completion(&request()).await, Err(CompletionError::CircuitOpen { .. })));
+        assert_eq!(hits.load(Ordering::SeqCst), 3);
+
+        // half-open: the trial call succeeds and closes it
+        tokio::time::sleep(Duration::from_millis(250)).await;
+        assert!(provider.chat_completion(&request()).await.is_ok());
+        assert!(provider.chat_completion(&request()).await.is_ok());
+        assert_eq!(hits.load(Ordering::SeqCst), 5);
+    }
+}
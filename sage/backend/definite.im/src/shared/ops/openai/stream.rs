@@ -0,0 +1,205 @@
+use std::collections::VecDeque;
+
+use futures::{
+    stream::{self, BoxStream},
+    Stream, StreamExt,
+};
+
+use super::{completion_error::CompletionError, completion_response::ChatCompletionChunk};
+
+/// Chunks of a streamed completion, in the order the provider sent them
+pub type ChunkStream = BoxStream<'static, Result<ChatCompletionChunk, CompletionError>>;
+
+/// Content deltas of a streamed completion
+pub type DeltaStream = BoxStream<'static, Result<String, CompletionError>>;
+
+/// Splits a server-sent event byte stream into the `data:` payload of each event.
+/// Bytes are buffered until a blank line so multi-byte characters split across reads survive.
+#[derive(Default)]
+pub struct SseBuffer {
+    buffer: Vec<u8>,
+}
+
+impl SseBuffer {
+    /// Appends `bytes` and returns the payloads of all events completed by them
+    pub fn push(
+        &mut self,
+        bytes: &[u8],
+    ) -> Vec<String> {
+        self.buffer.extend_from_slice(bytes);
+        let mut events = vec![];
+        while let Some((end, separator)) = find_event_end(&self.buffer) {
+            let block: Vec<u8> = self.buffer.drain(..end + separator).collect();
+            if let Some(data) = event_data(&block[..end]) {
+                events.push(data);
+            }
+        }
+        events
+    }
+
+    /// Flushes whatever is left once the connection closes
+    pub fn finish(&mut self) -> Vec<String> {
+        let block = std::mem::take(&mut self.buffer);
+        event_data(&block).into_iter().collect()
+    }
+}
+
+fn find_event_end(buffer: &[u8]) -> Option<(usize, usize)> {
+    let lf = buffer.windows(2).position(|w| w == b"\n\n").map(|i| (i, 2));
+    let crlf = buffer.windows(4).position(|w| w == b"\r\n\r\n").map(|i| (i, 4));
+    match (lf, crlf) {
+        (Some(a), Some(b)) => Some(if a.0 <= b.0 { a } else { b }),
+        (a, b) => a.or(b),
+    }
+}
+
+fn event_data(block: &[u8]) -> Option<String> {
+    let text = String::from_utf8_lossy(block);
+    let lines: Vec<&str> = text
+        .lines()
+        .map(|l| l.trim_end_matches('\r'))
+        .filter_map(|l| l.strip_prefix("data:"))
+        .map(|l| l.strip_prefix(' ').unwrap_or(l))
+        .collect();
+    if lines.is_empty() {
+        None
+    } else {
+        Some(lines.join("\n"))
+    }
+}
+
+/// Turns a raw SSE byte stream into completion chunks, ending at `[DONE]`
+pub fn to_chunk_stream<S, B, E>(bytes: S) -> ChunkStream
+where
+    S: Stream<Item = Result<B, E>> + Send + Unpin + 'static,
+    B: AsRef<[u8]>,
+    E: std::fmt::Display,
+{
+    let state = (bytes, SseBuffer::default(), VecDeque::<String>::new(), false);
+    stream::unfold(state, |(mut bytes, mut buffer, mut pending, mut closed)| async move {
+        loop {
+            if let Some(data) = pending.pop_front() {
+                if data.trim() == "[DONE]" {
+                    return None;
+                }
+                let chunk = serde_json::from_str::<ChatCompletionChunk>(&data).map_err(|e| CompletionError::InvalidResponse {
+                    message: e.to_string(),
+                    body: data.clone(),
+                });
+                return Some((chunk, (bytes, buffer, pending, closed)));
+            }
+            if closed {
+                return None;
+            }
+            match bytes.next().await {
+                Some(Ok(b)) => pending.extend(buffer.push(b.as_ref())),
+                Some(Err(e)) => return Some((Err(CompletionError::Network { message: e.to_string() }), (bytes, buffer, pending, true))),
+                None => {
+                    pending.extend(buffer.finish());
+                    closed = true;
+                }
+            }
+        }
+    })
+    .boxed()
+}
+
+/// Maps chunks to their content deltas, surfacing truncation and refusals as errors
+pub fn to_delta_stream(chunks: ChunkStream) -> DeltaStream {
+    chunks
+        .filter_map(|chunk| async move {
+            let chunk = match chunk {
+                Ok(c) => c,
+                Err(e) => return Some(Err(e)),
+            };
+            let usage = chunk.usage;
+            let choice = chunk.choices.into_iter().next()?;
+            match choice.finish_reason.as_deref() {
+                Some("length") => Some(Err(CompletionError::Truncated {
+                    finish_reason: "length".to_string(),
+                    usage,
+                    body: choice.delta.content.unwrap_or_default(),
+                })),
+                Some("content_filter") => Some(Err(CompletionError::Refused {
+                    finish_reason: "content_filter".to_string(),
+                    refusal: choice.delta.refusal,
+                    usage,
+                })),
+                _ => match (choice.delta.refusal, choice.delta.content) {
+                    (Some(refusal), _) => Some(Err(CompletionError::Refused {
+                        finish_reason: choice.finish_reason.unwrap_or_default(),
+                        refusal: Some(refusal),
+                        usage,
+                    })),
+                    (None, Some(content)) if !content.is_empty() => Some(Ok(content)),
+                    _ => None,
+                },
+            }
+        })
+        .boxed()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(content: &str) -> String {
+        format!(r#"{{"id":"c1","choices":[{{"index":0,"delta":{{"content":"{}"}},"finish_reason":null}}]}}"#, content)
+    }
+
+    #[test]
+    fn an_event_split_across_reads_is_returned_once_complete() {
+        let mut buffer = SseBuffer::default();
+        assert!(buffer.push(b"data: {\"a\"").is_empty());
+        assert!(buffer.push(b":1}\n").is_empty());
+        assert_eq!(buffer.push(b"\ndata: {\"b\":2}\n\n"), vec!["{\"a\":1}", "{\"b\":2}"]);
+        assert!(buffer.finish().is_empty());
+    }
+
+    #[test]
+    fn a_character_split_across_reads_survives() {
+        let mut buffer = SseBuffer::default();
+        let bytes = "data: résumé\n\n".as_bytes();
+        assert!(buffer.push(&bytes[..8]).is_empty());
+        assert_eq!(buffer.push(&bytes[8..]), vec!["résumé"]);
+    }
+
+    #[test]
+    fn data_lines_of_one_event_are_joined() {
+        let mut buffer = SseBuffer::default();
+        let events = buffer.push(b": keep-alive\nevent: message\ndata: first\ndata:second\nid: 7\n\n");
+        assert_eq!(events, vec!["first\nsecond"]);
+    }
+
+    #[test]
+    fn crlf_line_endings_separate_events() {
+        let mut buffer = SseBuffer::default();
+        assert_eq!(buffer.push(b"data: one\r\ndata: two\r\n\r\ndata: three\r\n\r"), vec!["one\ntwo"]);
+        assert_eq!(buffer.push(b"\n"), vec!["three"]);
+    }
+
+    #[test]
+    fn events_without_data_are_skipped_and_the_rest_is_flushed_on_close() {
+        let mut buffer = SseBuffer::default();
+        assert!(buffer.push(b": comment\n\n").is_empty());
+        assert!(buffer.push(b"data: [DONE]").is_empty());
+        assert_eq!(buffer.finish(), vec!["[DONE]"]);
+    }
+
+    #[tokio::test]
+    async fn chunks_end_at_done() {
+        let body = format!("data: {}\n\ndata: {}\n\ndata: [DONE]\n\ndata: {}\n\n", chunk("Hel"), chunk("lo"), chunk("ignored"));
+        let (head, tail) = body.split_at(20);
+        let reads = stream::iter(vec![Ok::<_, String>(head.as_bytes().to_vec()), Ok(tail.as_bytes().to_vec())]);
+        let deltas: Vec<_> = to_delta_stream(to_chunk_stream(reads)).collect().await;
+        let deltas: Vec<String> = deltas.into_iter().map(Result::unwrap).collect();
+        assert_eq!(deltas, vec!["Hel", "lo"]);
+    }
+
+    #[tokio::test]
+    async fn a_malformed_chunk_is_an_invalid_response() {
+        let reads = stream::iter(vec![Ok::<_, String>(b"data: {not json}\n\n".to_vec())]);
+        let chunks: Vec<_> = to_chunk_stream(reads).collect().await;
+        assert!(matches!(&chunks[..], [Err(CompletionError::InvalidResponse { body, .. })] if body == "{not json}"));
+    }
+}
@@ -1,26 +1,146 @@
-use std::{sync::Arc, thread, time::Duration};
+use std::{
+    future::Future,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
-use log::{debug, error};
-use redis::{Commands, RedisResult};
-use serde::de::DeserializeOwned;
+use chrono::Utc;
+use futures::{Stream, StreamExt};
+use log::{debug, error, info, warn};
+use redis::{aio::MultiplexedConnection, AsyncCommands, Direction, RedisResult};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use serde_json::Value;
 
+use crate::shared::ops::environ_ops::QueueConfig;
+
+/// How long a worker waits on an empty queue before it checks for expired leases again
+const BLOCK_SECS: f64 = 5.0;
+
+/// How often a worker looks for jobs whose worker went away
+const REAP_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Pause after a Redis error, so a lost connection does not turn into a busy loop
+const ERROR_BACKOFF: Duration = Duration::from_secs(1);
+
+/// A running job's lease is renewed this many times per visibility timeout
+const RENEWALS_PER_TIMEOUT: u64 = 3;
+
 pub struct RedisConnectionManager {
     pub client: redis::Client,
+    key_prefix: String,
 }
 
 impl RedisConnectionManager {
-    pub fn new(redis_url: &str) -> Self {
+    /// Queue names get `key_prefix` like every cache key, environments can share a Redis
+    pub fn new(
+        redis_url: &str,
+        key_prefix: &str,
+    ) -> Self {
         Self {
             client: redis::Client::open(redis_url).expect("Failed to create Redis client"),
+            key_prefix: key_prefix.to_string(),
         }
     }
 
     pub fn get_connection(&self) -> RedisResult<redis::Connection> {
         self.client.get_connection()
     }
+
+    pub async fn get_async_connection(&self) -> RedisResult<MultiplexedConnection> {
+        self.client.get_multiplexed_async_connection().await
+    }
+
+    fn key(
+        &self,
+        name: &str,
+    ) -> String {
+        format!("{}{}", self.key_prefix, name)
+    }
+}
+
+/// A job as it sits in a queue, `attempts` counts the attempts that failed
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Envelope<T> {
+    pub id: String,
+    pub attempts: u32,
+    pub last_error: Option<String>,
+    pub payload: T,
+}
+
+/// A job handed to a worker
+#[derive(Debug)]
+pub struct Delivery<T> {
+    pub id: String,
+    /// Starts at 1
+    pub attempt: u32,
+    /// A failure now moves the job to the dead-letter queue instead of back to the queue
+    pub last_attempt: bool,
+    pub payload: T,
+    pub lease: Lease,
+}
+
+/// A worker's hold on the job it is running. It is renewed while the handler runs, and lost when the
+/// handler stalls past the visibility timeout and `reap` hands the job to another worker.
+#[derive(Clone)]
+pub struct Lease {
+    con: MultiplexedConnection,
+    processing_key: String,
+    leases_key: String,
+    message: String,
+    visibility_timeout_secs: u64,
+}
+
+impl std::fmt::Debug for Lease {
+    fn fmt(
+        &self,
+        f: &mut std::fmt::Formatter<'_>,
+    ) -> std::fmt::Result {
+        f.debug_struct("Lease").field("processing_key", &self.processing_key).finish_non_exhaustive()
+    }
+}
+
+impl Lease {
+    /// Whether the job is still this worker's. A handler checks before it records a result,
+    /// once the lease is lost the job is another worker's to finish.
+    pub async fn is_held(&self) -> bool {
+        let mut con = self.con.clone();
+        match redis::cmd("LPOS").arg(&self.processing_key).arg(&self.message).query_async::<Option<i64>>(&mut con).await {
+            Ok(position) => position.is_some(),
+            Err(e) => {
+                // a worker that cannot tell keeps the result, the reaper is the one that gives leases away
+                error!("Error checking the lease on {}: {:?}", self.processing_key, e);
+                true
+            }
+        }
+    }
+
+    /// Pushes the deadline back a visibility timeout. `XX` leaves a lease `reap` already took alone.
+    async fn renew(&self) {
+        let mut con = self.con.clone();
+        let deadline = Utc::now().timestamp() + self.visibility_timeout_secs as i64;
+        if let Err(e) = redis::cmd("ZADD").arg(&self.leases_key).arg("XX").arg(deadline).arg(&self.message).query_async::<i64>(&mut con).await {
+            error!("Error renewing the lease on {}: {:?}", self.processing_key, e);
+        }
+    }
+
+    /// Runs `handled`, renewing the lease until it is done
+    async fn hold<Fut: Future>(
+        &self,
+        handled: Fut,
+    ) -> Fut::Output {
+        let period = Duration::from_secs((self.visibility_timeout_secs / RENEWALS_PER_TIMEOUT).max(1));
+        let mut renewals = tokio::time::interval_at(tokio::time::Instant::now() + period, period);
+        tokio::pin!(handled);
+        loop {
+            tokio::select! {
+                output = &mut handled => return output,
+                _ = renewals.tick() => self.renew().await,
+            }
+        }
+    }
 }
 
+#[derive(Clone)]
 pub struct Producer {
     pub connection_manager: Arc<RedisConnectionManager>,
 }
@@ -30,79 +150,309 @@ impl Producer {
         Self { connection_manager }
     }
 
-    // Now accepts serde_json::Value and serializes it to a string before pushing to Redis
-    pub fn produce(
+    /// Adds `payload` to the back of `queue` as job `id`
+    pub async fn produce<T: Serialize>(
         &self,
         queue: &str,
-        message: &Value,
+        id: &str,
+        payload: &T,
     ) -> RedisResult<()> {
-        let mut con = self.connection_manager.get_connection()?;
-        let message_str = serde_json::to_string(message).expect("Failed to serialize message");
-        match con.lpush::<&str, String, i32>(queue, message_str) {
+        let envelope = Envelope {
+            id: id.to_string(),
+            attempts: 0,
+            last_error: None,
+            payload,
+        };
+        let message = serde_json::to_string(&envelope).map_err(|e| redis::RedisError::from((redis::ErrorKind::TypeError, "Failed to serialize job", e.to_string())))?;
+        let mut con = self.connection_manager.get_async_connection().await?;
+        match con.lpush::<String, String, i64>(self.connection_manager.key(queue), message).await {
             Ok(_) => Ok(()),
             Err(e) => {
-                eprintln!("Failed to push message to queue: {:?}", e);
+                error!("Failed to push job {} to queue {}: {:?}", id, queue, e);
                 Err(e)
             }
         }
     }
 }
 
-pub struct Consumer {
+/// Output of a running job as it streams in, passed from the worker to the page over Redis pub/sub.
+/// Nothing is kept, a page that subscribes late only sees what arrives after it, and still gets the
+/// result from the job's record.
+#[derive(Clone)]
+pub struct Partials {
     connection_manager: Arc<RedisConnectionManager>,
 }
 
-impl Consumer {
+/// Publishes one job's partial output
+pub struct PartialPublisher {
+    con: MultiplexedConnection,
+    channel: String,
+}
+
+/// The partial output of one job as it is published
+pub type PartialStream = std::pin::Pin<Box<dyn Stream<Item = String> + Send>>;
+
+impl Partials {
     pub fn new(connection_manager: Arc<RedisConnectionManager>) -> Self {
         Self { connection_manager }
     }
 
-    // Consume messages from multiple queues, with a custom deserialization and processing function
-    pub fn consume_multiple_queues<T, F, G>(
+    fn channel(
+        &self,
+        job_id: &str,
+    ) -> String {
+        self.connection_manager.key(&format!("jobs:partial:{}", job_id))
+    }
+
+    pub async fn publisher(
+        &self,
+        job_id: &str,
+    ) -> RedisResult<PartialPublisher> {
+        Ok(PartialPublisher {
+            con: self.connection_manager.get_async_connection().await?,
+            channel: self.channel(job_id),
+        })
+    }
+
+    /// Everything published for `job_id` from now on
+    pub async fn subscribe(
+        &self,
+        job_id: &str,
+    ) -> RedisResult<PartialStream> {
+        let mut pubsub = self.connection_manager.client.get_async_pubsub().await?;
+        pubsub.subscribe(self.channel(job_id)).await?;
+        Ok(Box::pin(pubsub.into_on_message().filter_map(|message| async move {
+            message.get_payload::<String>().map_err(|e| debug!("Unreadable partial output: {:?}", e)).ok()
+        })))
+    }
+}
+
+impl PartialPublisher {
+    pub async fn publish(
+        &mut self,
+        partial: &Value,
+    ) -> RedisResult<()> {
+        self.con.publish::<&str, String, i64>(&self.channel, partial.to_string()).await.map(|_| ())
+    }
+}
+
+/// Takes jobs off one queue. A job is moved atomically to `<queue>:processing` and leased for the
+/// visibility timeout, renewed while it runs, so a job whose worker dies goes back to the queue instead of being lost.
+/// Jobs that fail `max_attempts` times end up in `<queue>:dead`.
+pub struct Consumer {
+    connection_manager: Arc<RedisConnectionManager>,
+    queue: String,
+    visibility_timeout_secs: u64,
+    max_attempts: u32,
+}
+
+impl Consumer {
+    pub fn new(
+        connection_manager: Arc<RedisConnectionManager>,
+        queue: &str,
+        queue_config: &QueueConfig,
+    ) -> Self {
+        Self {
+            queue: connection_manager.key(queue),
+            connection_manager,
+            visibility_timeout_secs: queue_config.queue_visibility_timeout_secs,
+            max_attempts: queue_config.queue_max_attempts.max(1),
+        }
+    }
+
+    fn processing_key(&self) -> String {
+        format!("{}:processing", self.queue)
+    }
+
+    fn leases_key(&self) -> String {
+        format!("{}:leases", self.queue)
+    }
+
+    fn dead_key(&self) -> String {
+        format!("{}:dead", self.queue)
+    }
+
+    fn lease_deadline(&self) -> i64 {
+        Utc::now().timestamp() + self.visibility_timeout_secs as i64
+    }
+
+    /// Waits up to `BLOCK_SECS` for a job, returns it as stored
+    async fn take(
+        &self,
+        con: &mut MultiplexedConnection,
+    ) -> RedisResult<Option<String>> {
+        let message: Option<String> = con.blmove(&self.queue, self.processing_key(), Direction::Right, Direction::Left, BLOCK_SECS).await?;
+        if let Some(message) = &message {
+            con.zadd::<String, i64, &str, i64>(self.leases_key(), message, self.lease_deadline()).await?;
+        }
+        Ok(message)
+    }
+
+    /// `false` when the lease was lost: `reap` already handed the job back, and it is not this worker's to finish
+    async fn ack(
+        &self,
+        con: &mut MultiplexedConnection,
+        message: &str,
+    ) -> RedisResult<bool> {
+        let held = con.lrem::<String, &str, i64>(self.processing_key(), 1, message).await? > 0;
+        con.zrem::<String, &str, i64>(self.leases_key(), message).await?;
+        Ok(held)
+    }
+
+    /// Counts the failed attempt and puts the job back on the queue, or in the dead-letter queue once
+    /// it is out of attempts or `retry` is false. The caller has already taken `message` off the processing list.
+    async fn retry_or_bury(
+        &self,
+        con: &mut MultiplexedConnection,
+        message: &str,
+        reason: &str,
+        retry: bool,
+    ) -> RedisResult<()> {
+        con.zrem::<String, &str, i64>(self.leases_key(), message).await?;
+        // the payload is kept as it is, a worker that cannot read it still must not lose it
+        let mut envelope = match serde_json::from_str::<Envelope<Value>>(message) {
+            Ok(e) => e,
+            Err(e) => {
+                error!("Unreadable job on {}, moving it to the dead-letter queue: {}", self.queue, e);
+                return con.lpush::<String, &str, i64>(self.dead_key(), message).await.map(|_| ());
+            }
+        };
+        envelope.attempts += 1;
+        envelope.last_error = Some(reason.to_string());
+        let message = serde_json::to_string(&envelope).unwrap_or(message.to_string());
+        if !retry || envelope.attempts >= self.max_attempts {
+            error!("Job {} failed {} times, moving it to {}: {}", envelope.id, envelope.attempts, self.dead_key(), reason);
+            con.lpush::<String, String, i64>(self.dead_key(), message).await?;
+        } else {
+            warn!("Job {} failed attempt {}, requeued: {}", envelope.id, envelope.attempts, reason);
+            con.lpush::<String, String, i64>(self.queue.clone(), message).await?;
+        }
+        Ok(())
+    }
+
+    /// Does nothing when the lease was lost, `reap` has already counted the attempt and requeued the job
+    async fn fail(
+        &self,
+        con: &mut MultiplexedConnection,
+        message: &str,
+        reason: &str,
+        retry: bool,
+    ) -> RedisResult<()> {
+        if con.lrem::<String, &str, i64>(self.processing_key(), 1, message).await? == 0 {
+            warn!("Lost the lease on a job of {} before it failed: {}", self.queue, reason);
+            return Ok(());
+        }
+        self.retry_or_bury(con, message, reason, retry).await
+    }
+
+    /// Hands jobs whose lease expired back to the queue. A job moved to processing by a worker
+    /// that died before leasing it gets a lease here, and is handed back once that expires.
+    async fn reap(
+        &self,
+        con: &mut MultiplexedConnection,
+    ) -> RedisResult<()> {
+        let processing: Vec<String> = con.lrange(self.processing_key(), 0, -1).await?;
+        for message in &processing {
+            redis::cmd("ZADD")
+                .arg(self.leases_key())
+                .arg("NX")
+                .arg(self.lease_deadline())
+                .arg(message)
+                .query_async::<i64>(con)
+                .await?;
+        }
+        let expired: Vec<String> = con.zrangebyscore(self.leases_key(), "-inf", Utc::now().timestamp()).await?;
+        for message in expired {
+            // only one worker gets to remove it, the others leave it alone
+            if con.lrem::<String, &str, i64>(self.processing_key(), 1, &message).await? > 0 {
+                self.retry_or_bury(con, &message, "Visibility timeout expired", true).await?;
+            } else {
+                con.zrem::<String, &str, i64>(self.leases_key(), &message).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Processes jobs until the process stops. `handler` returns `Err` for a failed attempt,
+    /// a job it gives up on for good is acknowledged with `Ok` after it has recorded why.
+    pub async fn run<T, F, Fut>(
         &self,
-        queues: &[&str],
-        deserialize_fn: F,
-        process_fn: G,
+        handler: F,
+    ) where
+        T: DeserializeOwned,
+        F: Fn(Delivery<T>) -> Fut,
+        Fut: Future<Output = Result<(), String>>,
+    {
+        info!("Consuming {}", self.queue);
+        let mut last_reap: Option<Instant> = None;
+        loop {
+            let mut con = match self.connection_manager.get_async_connection().await {
+                Ok(c) => c,
+                Err(e) => {
+                    error!("Error connecting to Redis for {}: {:?}", self.queue, e);
+                    tokio::time::sleep(ERROR_BACKOFF).await;
+                    continue;
+                }
+            };
+            if let Err(e) = self.consume(&mut con, &handler, &mut last_reap).await {
+                error!("Error consuming {}: {:?}", self.queue, e);
+                tokio::time::sleep(ERROR_BACKOFF).await;
+            }
+        }
+    }
+
+    async fn consume<T, F, Fut>(
+        &self,
+        con: &mut MultiplexedConnection,
+        handler: &F,
+        last_reap: &mut Option<Instant>,
     ) -> RedisResult<()>
     where
-        T: DeserializeOwned + 'static,
-        F: Fn(&str) -> Result<T, serde_json::Error> + Send + Sync + 'static,
-        G: Fn(T) -> bool + Send + Sync + 'static,
+        T: DeserializeOwned,
+        F: Fn(Delivery<T>) -> Fut,
+        Fut: Future<Output = Result<(), String>>,
     {
-        let mut con = self.connection_manager.get_connection()?;
         loop {
-            let msg: RedisResult<(String, String)> = con.brpop(queues, 0.0); // Block until a message is available
-            match msg {
-                Ok((queue, message_str)) => {
-                    println!("Received message from queue {}: {}", queue, message_str);
-                    match deserialize_fn(&message_str) {
-                        Ok(message) => {
-                            if !process_fn(message) {
-                                match con.lpush::<&str, &str, i32>(&queue, &message_str) {
-                                    Ok(_) => {
-                                        error!("Failed to process message, requeued: {}", message_str);
-                                    }
-                                    Err(e) => {
-                                        error!("Failed to requeue message: {:?}", e);
-                                    }
-                                }
-                            } else {
-                                match con.lrem::<&str, &str, i32>("processing_queue", 1, &message_str) {
-                                    Ok(_) => debug!("Message processed successfully: {}", message_str),
-                                    Err(e) => debug!("Failed to remove message from processing queue: {:?}", e),
-                                } // Remove successfully processed message
-                            }
-                        }
-                        Err(e) => {
-                            eprintln!("Failed to deserialize message: {:?}", e);
-                        }
-                    }
-                }
+            if last_reap.is_none_or(|t| t.elapsed() >= REAP_INTERVAL) {
+                self.reap(con).await?;
+                *last_reap = Some(Instant::now());
+            }
+            let message = match self.take(con).await? {
+                Some(m) => m,
+                None => continue,
+            };
+            let envelope = match serde_json::from_str::<Envelope<T>>(&message) {
+                Ok(e) => e,
                 Err(e) => {
-                    eprintln!("Error receiving message from queues: {:?}", e);
+                    // another attempt reads it the same way
+                    self.fail(con, &message, &format!("Unreadable job: {}", e), false).await?;
+                    continue;
+                }
+            };
+            debug!("Job {} taken from {}", envelope.id, self.queue);
+            let lease = Lease {
+                con: con.clone(),
+                processing_key: self.processing_key(),
+                leases_key: self.leases_key(),
+                message: message.clone(),
+                visibility_timeout_secs: self.visibility_timeout_secs,
+            };
+            let id = envelope.id.clone();
+            let delivery = Delivery {
+                id: envelope.id,
+                attempt: envelope.attempts + 1,
+                last_attempt: envelope.attempts + 1 >= self.max_attempts,
+                payload: envelope.payload,
+                lease: lease.clone(),
+            };
+            match lease.hold(handler(delivery)).await {
+                Ok(()) => {
+                    if !self.ack(con, &message).await? {
+                        warn!("Lost the lease on job {} before it was acknowledged, another worker has it", id);
+                    }
                 }
+                Err(reason) => self.fail(con, &message, &reason, true).await?,
             }
-            thread::sleep(Duration::from_secs(1)); // Simulate processing delay
         }
     }
 }
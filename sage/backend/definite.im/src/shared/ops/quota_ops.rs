@@ -0,0 +1,358 @@
+use std::{collections::HashMap, sync::OnceLock};
+
+use chrono::{DateTime, Datelike, Duration, TimeZone, Utc};
+use log::{debug, error, warn};
+use mongodb::Client;
+use serde::{Deserialize, Serialize};
+
+use crate::shared::{
+    entities::user::UserEntity,
+    ops::{
+        cache_ops::Cache,
+        environ_ops::{Environ, QuotaConfig},
+    },
+};
+
+/// Limits for one plan. `daily` and `monthly` of 0 mean unlimited.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PlanQuota {
+    /// Requests that can be made back to back before the bucket runs dry
+    pub burst: u32,
+    pub refill_per_minute: f64,
+    pub daily: u32,
+    pub monthly: u32,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct QuotaPlans {
+    pub default_plan: String,
+    pub plans: HashMap<String, PlanQuota>,
+}
+
+impl QuotaPlans {
+    pub fn get() -> &'static QuotaPlans {
+        static PLANS: OnceLock<QuotaPlans> = OnceLock::new();
+        PLANS.get_or_init(|| {
+            let quota_config: QuotaConfig = Environ::init();
+            let plans = std::fs::read_to_string(&quota_config.quota_plans).unwrap_or_else(|e| panic!("Error reading quota plans {}: {}", quota_config.quota_plans, e));
+            QuotaPlans::parse(&plans).unwrap_or_else(|e| panic!("Error in quota plans {}: {}", quota_config.quota_plans, e))
+        })
+    }
+
+    /// Plans from json, every plan must be able to serve a request and the default plan must exist
+    pub fn parse(json: &str) -> Result<QuotaPlans, String> {
+        let plans: QuotaPlans = serde_json::from_str(json).map_err(|e| e.to_string())?;
+        if !plans.plans.contains_key(&plans.default_plan) {
+            return Err(format!("Default quota plan {} is not defined", plans.default_plan));
+        }
+        if let Some((name, _)) = plans.plans.iter().find(|(_, p)| p.burst == 0 || p.refill_per_minute <= 0.0) {
+            return Err(format!("Quota plan {} must have a burst and refill_per_minute above 0", name));
+        }
+        Ok(plans)
+    }
+
+    /// Unknown plan names fall back to the default plan
+    pub fn plan(
+        &self,
+        name: Option<&str>,
+    ) -> (&str, &PlanQuota) {
+        match name.and_then(|n| self.plans.get_key_value(n)) {
+            Some((name, quota)) => (name, quota),
+            None => (&self.default_plan, &self.plans[&self.default_plan]),
+        }
+    }
+}
+
+/// Why a request was turned away
+#[derive(Debug, Clone, PartialEq)]
+pub enum QuotaExceeded {
+    /// Too many requests in a short time, the bucket refills in `retry_after_secs`
+    RateLimited {
+        retry_after_secs: u64,
+    },
+    DailyExceeded {
+        limit: u32,
+        retry_after_secs: u64,
+    },
+    MonthlyExceeded {
+        limit: u32,
+        retry_after_secs: u64,
+    },
+}
+
+impl QuotaExceeded {
+    pub fn message(&self) -> String {
+        match self {
+            QuotaExceeded::RateLimited { .. } => "You're going a little fast. Please wait a moment before trying again.".to_string(),
+            QuotaExceeded::DailyExceeded { limit, .. } => format!("You've used all {} of today's requests. Your quota resets tomorrow.", limit),
+            QuotaExceeded::MonthlyExceeded { limit, .. } => format!("You've used all {} of this month's requests. Your quota resets next month.", limit),
+        }
+    }
+
+    pub fn retry_after_secs(&self) -> u64 {
+        match self {
+            QuotaExceeded::RateLimited { retry_after_secs } | QuotaExceeded::DailyExceeded { retry_after_secs, .. } | QuotaExceeded::MonthlyExceeded { retry_after_secs, .. } => *retry_after_secs,
+        }
+    }
+}
+
+// Checks the daily and monthly counters, then takes a token from the bucket.
+// Nothing is consumed unless all three allow the request, and nothing at all when ARGV[8] is '0'.
+// Returns {status, wait_ms}, status 0 allowed, 1 rate limited, 2 daily, 3 monthly.
+const TOKEN_BUCKET_SCRIPT: &str = r"
+local capacity = tonumber(ARGV[1])
+local refill_per_ms = tonumber(ARGV[2])
+local now = tonumber(ARGV[3])
+local daily_limit = tonumber(ARGV[4])
+local monthly_limit = tonumber(ARGV[5])
+local daily = tonumber(redis.call('GET', KEYS[2]) or '0')
+if daily_limit > 0 and daily >= daily_limit then
+    return {2, 0}
+end
+local monthly = tonumber(redis.call('GET', KEYS[3]) or '0')
+if monthly_limit > 0 and monthly >= monthly_limit then
+    return {3, 0}
+end
+local bucket = redis.call('HMGET', KEYS[1], 'tokens', 'ts')
+local tokens = tonumber(bucket[1]) or capacity
+local ts = tonumber(bucket[2]) or now
+tokens = math.min(capacity, tokens + math.max(0, now - ts) * refill_per_ms)
+if tokens < 1 then
+    return {1, math.ceil((1 - tokens) / refill_per_ms)}
+end
+if ARGV[8] == '0' then
+    return {0, 0}
+end
+redis.call('HSET', KEYS[1], 'tokens', tostring(tokens - 1), 'ts', tostring(now))
+redis.call('PEXPIRE', KEYS[1], math.ceil(capacity / refill_per_ms))
+if redis.call('INCR', KEYS[2]) == 1 then
+    redis.call('EXPIRE', KEYS[2], ARGV[6])
+end
+if redis.call('INCR', KEYS[3]) == 1 then
+    redis.call('EXPIRE', KEYS[3], ARGV[7])
+end
+return {0, 0}
+";
+
+fn secs_until_tomorrow(now: DateTime<Utc>) -> u64 {
+    let tomorrow = (now.date_naive() + Duration::days(1)).and_hms_opt(0, 0, 0).unwrap();
+    (Utc.from_utc_datetime(&tomorrow) - now).num_seconds().max(1) as u64
+}
+
+fn secs_until_next_month(now: DateTime<Utc>) -> u64 {
+    let (year, month) = if now.month() == 12 { (now.year() + 1, 1) } else { (now.year(), now.month() + 1) };
+    let next_month = chrono::NaiveDate::from_ymd_opt(year, month, 1).unwrap().and_hms_opt(0, 0, 0).unwrap();
+    (Utc.from_utc_datetime(&next_month) - now).num_seconds().max(1) as u64
+}
+
+/// Bucket, daily and monthly counter keys. The counters are named after the day and month so they roll over on their own.
+fn script_keys(
+    user_id: &str,
+    now: DateTime<Utc>,
+) -> [String; 3] {
+    [
+        format!("quota:bucket:{}", user_id),
+        format!("quota:daily:{}:{}", user_id, now.format("%Y-%m-%d")),
+        format!("quota:monthly:{}:{}", user_id, now.format("%Y-%m")),
+    ]
+}
+
+/// `ARGV` of `TOKEN_BUCKET_SCRIPT`, the refill rate goes in tokens per millisecond
+fn script_args(
+    quota: &PlanQuota,
+    now: DateTime<Utc>,
+    spend: bool,
+) -> [String; 8] {
+    [
+        quota.burst.to_string(),
+        (quota.refill_per_minute / 60_000.0).to_string(),
+        now.timestamp_millis().to_string(),
+        quota.daily.to_string(),
+        quota.monthly.to_string(),
+        // the key names roll over, the ttl only cleans up
+        (2 * 24 * 60 * 60).to_string(),
+        (32 * 24 * 60 * 60).to_string(),
+        if spend { "1" } else { "0" }.to_string(),
+    ]
+}
+
+/// What a `{status, wait_ms}` answer of `TOKEN_BUCKET_SCRIPT` means for the request
+fn decision(
+    status: u8,
+    wait_ms: u64,
+    quota: &PlanQuota,
+    now: DateTime<Utc>,
+) -> Result<(), QuotaExceeded> {
+    match status {
+        1 => Err(QuotaExceeded::RateLimited {
+            retry_after_secs: wait_ms.div_ceil(1000).max(1),
+        }),
+        2 => Err(QuotaExceeded::DailyExceeded {
+            limit: quota.daily,
+            retry_after_secs: secs_until_tomorrow(now),
+        }),
+        3 => Err(QuotaExceeded::MonthlyExceeded {
+            limit: quota.monthly,
+            retry_after_secs: secs_until_next_month(now),
+        }),
+        0 => Ok(()),
+        status => {
+            error!("Unexpected quota status {}", status);
+            Ok(())
+        }
+    }
+}
+
+/// Whether `user_id` has a request left, without spending it. Checked before a request is taken on,
+/// so a user out of quota is told right away instead of after their job was queued.
+pub async fn check_quota(
+    cache: &Cache,
+    mongoc: &Client,
+    user_id: &str,
+) -> Result<(), QuotaExceeded> {
+    run_quota(cache, mongoc, user_id, false).await
+}
+
+/// Spends one request from `user_id`'s quota. Called right before the provider is, an answer from
+/// the completion cache costs nothing.
+pub async fn spend_quota(
+    cache: &Cache,
+    mongoc: &Client,
+    user_id: &str,
+) -> Result<(), QuotaExceeded> {
+    run_quota(cache, mongoc, user_id, true).await
+}
+
+/// If quotas are off or Redis is unavailable the request is let through
+async fn run_quota(
+    cache: &Cache,
+    mongoc: &Client,
+    user_id: &str,
+    spend: bool,
+) -> Result<(), QuotaExceeded> {
+    let quota_config: QuotaConfig = Environ::init();
+    if !quota_config.quota_enabled {
+        return Ok(());
+    }
+    let plan = UserEntity::find_plan(mongoc, user_id).await;
+    let (plan_name, quota) = QuotaPlans::get().plan(plan.as_deref());
+    let decision = run_script(cache, user_id, quota, Utc::now(), spend).await;
+    debug!("Quota for user {} on plan {} (spend {}): {:?}", user_id, plan_name, spend, decision);
+    decision
+}
+
+/// One run of `TOKEN_BUCKET_SCRIPT` for `quota` at `now`
+async fn run_script(
+    cache: &Cache,
+    user_id: &str,
+    quota: &PlanQuota,
+    now: DateTime<Utc>,
+    spend: bool,
+) -> Result<(), QuotaExceeded> {
+    static SCRIPT: OnceLock<redis::Script> = OnceLock::new();
+    let script = SCRIPT.get_or_init(|| redis::Script::new(TOKEN_BUCKET_SCRIPT));
+    match cache.eval_script::<(u8, u64)>(script, &script_keys(user_id, now), &script_args(quota, now, spend)).await {
+        Ok((status, wait_ms)) => decision(status, wait_ms, quota, now),
+        Err(e) => {
+            warn!("Quota check failed for user {}, allowing request: {}", user_id, e);
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shared::ops::environ_ops::RedisConfig;
+
+    fn at(rfc3339: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(rfc3339).unwrap().with_timezone(&Utc)
+    }
+
+    fn quota(
+        burst: u32,
+        refill_per_minute: f64,
+        daily: u32,
+        monthly: u32,
+    ) -> PlanQuota {
+        PlanQuota {
+            burst,
+            refill_per_minute,
+            daily,
+            monthly,
+        }
+    }
+
+    #[test]
+    fn the_bundled_plans_are_valid() {
+        let plans = QuotaPlans::parse(include_str!("../../../assets/data/quota_plans.json")).unwrap();
+        let (name, free) = plans.plan(None);
+        assert_eq!((name, free.burst, free.daily, free.monthly), ("free", 3, 10, 100));
+        assert_eq!(plans.plan(Some("pro")).0, "pro");
+        assert_eq!(plans.plan(Some("enterprise")).0, "free");
+    }
+
+    #[test]
+    fn plans_that_cannot_serve_a_request_are_rejected() {
+        let missing_default = r#"{"default_plan": "free", "plans": {"pro": {"burst": 1, "refill_per_minute": 1.0, "daily": 0, "monthly": 0}}}"#;
+        assert_eq!(QuotaPlans::parse(missing_default).unwrap_err(), "Default quota plan free is not defined");
+        let no_burst = r#"{"default_plan": "free", "plans": {"free": {"burst": 0, "refill_per_minute": 1.0, "daily": 0, "monthly": 0}}}"#;
+        assert!(QuotaPlans::parse(no_burst).unwrap_err().contains("free must have a burst"));
+        let no_refill = r#"{"default_plan": "free", "plans": {"free": {"burst": 1, "refill_per_minute": 0.0, "daily": 0, "monthly": 0}}}"#;
+        assert!(QuotaPlans::parse(no_refill).is_err());
+    }
+
+    #[test]
+    fn script_arguments_carry_the_plan_and_whether_to_spend() {
+        let now = at("2026-03-05T10:00:00Z");
+        let check = script_args(&quota(3, 6.0, 10, 100), now, false);
+        assert_eq!(check[..5], ["3", "0.0001", "1772704800000", "10", "100"]);
+        assert_eq!(check[7], "0");
+        assert_eq!(script_args(&quota(3, 6.0, 10, 100), now, true)[7], "1");
+        assert_eq!(script_keys("u1", now), ["quota:bucket:u1", "quota:daily:u1:2026-03-05", "quota:monthly:u1:2026-03"]);
+    }
+
+    #[test]
+    fn script_answers_become_decisions() {
+        let plan = quota(3, 1.0, 10, 100);
+        let now = at("2026-12-31T23:59:30Z");
+        assert_eq!(decision(0, 0, &plan, now), Ok(()));
+        assert_eq!(decision(1, 1_500, &plan, now), Err(QuotaExceeded::RateLimited { retry_after_secs: 2 }));
+        assert_eq!(decision(1, 10, &plan, now), Err(QuotaExceeded::RateLimited { retry_after_secs: 1 }));
+        assert_eq!(decision(2, 0, &plan, now), Err(QuotaExceeded::DailyExceeded { limit: 10, retry_after_secs: 30 }));
+        assert_eq!(decision(3, 0, &plan, now), Err(QuotaExceeded::MonthlyExceeded { limit: 100, retry_after_secs: 30 }));
+        assert_eq!(decision(9, 0, &plan, now), Ok(()));
+    }
+
+    #[test]
+    fn quotas_reset_at_midnight_and_on_the_first_of_the_month() {
+        let now = at("2026-02-14T12:00:00Z");
+        assert_eq!(secs_until_tomorrow(now), 12 * 60 * 60);
+        assert_eq!(secs_until_next_month(now), (14 * 24 + 12) * 60 * 60);
+        assert_eq!(secs_until_tomorrow(at("2026-02-14T23:59:59.900Z")), 1);
+    }
+
+    #[actix_web::test]
+    #[ignore = "needs Redis: set REDIS_SERVER, REDIS_TTL and REDIS_KEY_PREFIX"]
+    async fn checking_spends_nothing_and_the_bucket_refills() {
+        let cache = Cache::new(&Environ::init::<RedisConfig>()).unwrap();
+        let user_id = format!("quota-test-{}", Utc::now().timestamp_nanos_opt().unwrap());
+        let plan = quota(2, 1.0, 3, 0);
+        let start = Utc::now();
+        for _ in 0..5 {
+            assert_eq!(run_script(&cache, &user_id, &plan, start, false).await, Ok(()));
+        }
+        assert_eq!(run_script(&cache, &user_id, &plan, start, true).await, Ok(()));
+        assert_eq!(run_script(&cache, &user_id, &plan, start, true).await, Ok(()));
+        // one token a minute
+        let limited = run_script(&cache, &user_id, &plan, start, false).await.unwrap_err();
+        assert!(matches!(limited, QuotaExceeded::RateLimited { retry_after_secs: 60..=61 }), "{:?}", limited);
+        assert_eq!(run_script(&cache, &user_id, &plan, start, true).await, Err(limited));
+        assert_eq!(run_script(&cache, &user_id, &plan, start + Duration::seconds(61), true).await, Ok(()));
+        let daily = run_script(&cache, &user_id, &plan, start + Duration::minutes(10), false).await;
+        assert!(matches!(daily, Err(QuotaExceeded::DailyExceeded { limit: 3, .. })), "{:?}", daily);
+        for key in script_keys(&user_id, start) {
+            cache.remove(&key).await.unwrap();
+        }
+    }
+}
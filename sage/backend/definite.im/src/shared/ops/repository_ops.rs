@@ -0,0 +1,562 @@
+use std::fmt::{self, Debug};
+
+use async_trait::async_trait;
+use futures::stream::TryStreamExt;
+use log::{error, warn};
+use mongodb::{
+    bson::{self, doc, oid::ObjectId, Bson, Document},
+    Client, Collection,
+};
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::shared::ops::db_ops::Database;
+
+/// Why a repository call failed
+#[derive(Debug)]
+pub enum RepositoryError {
+    /// The id is not an `ObjectId`, e.g. a mistyped url
+    InvalidId {
+        id: String,
+    },
+    /// Not there, or owned by someone else
+    NotFound {
+        collection: &'static str,
+        id: String,
+    },
+    /// Saved by someone else since it was read, or created with a unique key someone else took first.
+    /// Read it again before saving.
+    Conflict {
+        collection: &'static str,
+        id: String,
+    },
+    Database {
+        collection: &'static str,
+        message: String,
+    },
+    /// The stored document could not be read or the entity could not be stored
+    Serialization {
+        collection: &'static str,
+        message: String,
+    },
+}
+
+impl fmt::Display for RepositoryError {
+    fn fmt(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+    ) -> fmt::Result {
+        match self {
+            RepositoryError::InvalidId { id } => write!(f, "{} is not a valid id", id),
+            RepositoryError::NotFound { collection, id } => write!(f, "no document {} in {}", id, collection),
+            RepositoryError::Conflict { collection, id } => write!(f, "document {} in {} was changed by someone else", id, collection),
+            RepositoryError::Database { collection, message } => write!(f, "database error in {}: {}", collection, message),
+            RepositoryError::Serialization { collection, message } => write!(f, "document in {} could not be converted: {}", collection, message),
+        }
+    }
+}
+
+impl std::error::Error for RepositoryError {}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SortOrder {
+    Ascending,
+    Descending,
+}
+
+/// One page of results, `number` starts at 0
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Page {
+    pub number: u64,
+    pub size: u64,
+}
+
+/// What to list: a filter on top of the owner, the order, and optionally one page of it
+#[derive(Debug, Clone, Default)]
+pub struct Query {
+    pub filter: Document,
+    pub sort: Document,
+    pub page: Option<Page>,
+    /// Fields `list` leaves unread, e.g. a large one the page does not show. `project` ignores it.
+    pub exclude: Vec<String>,
+}
+
+impl Query {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn filter(
+        mut self,
+        filter: Document,
+    ) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    /// Sorts by `field`, call again to break ties on another field
+    pub fn sort(
+        mut self,
+        field: &str,
+        order: SortOrder,
+    ) -> Self {
+        self.sort.insert(field, if order == SortOrder::Ascending { 1 } else { -1 });
+        self
+    }
+
+    pub fn page(
+        mut self,
+        number: u64,
+        size: u64,
+    ) -> Self {
+        self.page = Some(Page { number, size });
+        self
+    }
+
+    /// Leaves `field` out of what `list` reads, it decodes as missing so it must be an `Option` or have a default
+    pub fn exclude(
+        mut self,
+        field: &str,
+    ) -> Self {
+        self.exclude.push(field.to_string());
+        self
+    }
+}
+
+fn database_error(
+    collection: &'static str,
+    e: mongodb::error::Error,
+) -> RepositoryError {
+    error!("Database error in {}: {}", collection, e);
+    RepositoryError::Database { collection, message: e.to_string() }
+}
+
+fn serialization_error(
+    collection: &'static str,
+    message: String,
+) -> RepositoryError {
+    error!("Serialization error in {}: {}", collection, message);
+    RepositoryError::Serialization { collection, message }
+}
+
+/// Documents saved before revisions were counted have no `revision`, they are at revision 0
+fn revision_filter(revision: u32) -> Bson {
+    match revision {
+        0 => bson::bson!({ "$in": [0, Bson::Null] }),
+        r => Bson::Int64(r as i64),
+    }
+}
+
+/// Typed access to an entity's collection. Every read and write is scoped to the document's owner,
+/// so an id from another user's url is simply not found. Routes load documents through `access::fetch`.
+/// `save` only succeeds against the revision that was read, a concurrent save in between is reported
+/// as `RepositoryError::Conflict`.
+#[async_trait]
+pub trait Repository: Serialize + DeserializeOwned + Debug + Unpin + Send + Sync + Sized {
+    const COLLECTION: &'static str;
+    /// The field that holds the owning user's id
+    const OWNER_FIELD: &'static str = "user_id";
+
+    fn id(&self) -> ObjectId;
+
+    fn set_id(
+        &mut self,
+        id: ObjectId,
+    );
+
+    fn owner(&self) -> &str;
+
+    /// How many times the document has been saved, see `save`
+    fn revision(&self) -> u32;
+
+    fn set_revision(
+        &mut self,
+        revision: u32,
+    );
+
+    /// Reads a stored document, versioned entities upgrade it through their migrations here
+    fn decode(document: Document) -> Result<Self, String> {
+        bson::from_document(document).map_err(|e| e.to_string())
+    }
+
+    fn collection(mongoc: &Client) -> Collection<Document> {
+        Database::get_collection::<Document>(mongoc, Self::COLLECTION)
+    }
+
+    /// `filter` narrowed to `owner`'s documents
+    fn owned(
+        owner: &str,
+        mut filter: Document,
+    ) -> Document {
+        filter.insert(Self::OWNER_FIELD, owner);
+        filter
+    }
+
+    fn encode(&self) -> Result<Document, RepositoryError> {
+        bson::to_document(self).map_err(|e| serialization_error(Self::COLLECTION, e.to_string()))
+    }
+
+    async fn create(
+        &self,
+        mongoc: &Client,
+    ) -> Result<ObjectId, RepositoryError> {
+        let document = self.encode()?;
+        match Self::collection(mongoc).insert_one(document).await {
+            Ok(r) => r
+                .inserted_id
+                .as_object_id()
+                .ok_or_else(|| serialization_error(Self::COLLECTION, format!("inserted id {} is not an ObjectId", r.inserted_id))),
+            Err(e) if Database::is_duplicate_key(&e) => Err(RepositoryError::Conflict {
+                collection: Self::COLLECTION,
+                id: self.id().to_hex(),
+            }),
+            Err(e) => Err(database_error(Self::COLLECTION, e)),
+        }
+    }
+
+    /// `owner`'s document `id`
+    async fn find(
+        mongoc: &Client,
+        owner: &str,
+        id: &ObjectId,
+    ) -> Result<Self, RepositoryError> {
+        match Self::find_one(mongoc, owner, doc! {"_id": id}).await? {
+            Some(entity) => Ok(entity),
+            None => Err(RepositoryError::NotFound {
+                collection: Self::COLLECTION,
+                id: id.to_hex(),
+            }),
+        }
+    }
+
+    /// Same as `find` for an id taken from a url or a form
+    async fn find_hex(
+        mongoc: &Client,
+        owner: &str,
+        id: &str,
+    ) -> Result<Self, RepositoryError> {
+        let oid = ObjectId::parse_str(id).map_err(|_| RepositoryError::InvalidId { id: id.to_string() })?;
+        Self::find(mongoc, owner, &oid).await
+    }
+
+    /// Anyone's document `id`. Only for `access::fetch`, which asks the `AccessPolicy` before handing it out.
+    async fn find_unscoped(
+        mongoc: &Client,
+        id: &ObjectId,
+    ) -> Result<Self, RepositoryError> {
+        match Self::collection(mongoc).find_one(doc! {"_id": id}).await {
+            Ok(Some(document)) => Self::decode(document).map_err(|e| serialization_error(Self::COLLECTION, e)),
+            Ok(None) => Err(RepositoryError::NotFound {
+                collection: Self::COLLECTION,
+                id: id.to_hex(),
+            }),
+            Err(e) => Err(database_error(Self::COLLECTION, e)),
+        }
+    }
+
+    /// The first of `owner`'s documents matching `filter`
+    async fn find_one(
+        mongoc: &Client,
+        owner: &str,
+        filter: Document,
+    ) -> Result<Option<Self>, RepositoryError> {
+        match Self::collection(mongoc).find_one(Self::owned(owner, filter)).await {
+            Ok(Some(document)) => Self::decode(document).map(Some).map_err(|e| serialization_error(Self::COLLECTION, e)),
+            Ok(None) => Ok(None),
+            Err(e) => Err(database_error(Self::COLLECTION, e)),
+        }
+    }
+
+    /// `owner`'s documents matching the query. Documents that cannot be read are logged and skipped.
+    async fn list(
+        mongoc: &Client,
+        owner: &str,
+        query: &Query,
+    ) -> Result<Vec<Self>, RepositoryError> {
+        let documents = Self::scan(mongoc, owner, query, None).await?;
+        Ok(documents
+            .into_iter()
+            .filter_map(|document| match Self::decode(document) {
+                Ok(entity) => Some(entity),
+                Err(e) => {
+                    warn!("Skipping unreadable document in {}: {}", Self::COLLECTION, e);
+                    None
+                }
+            })
+            .collect())
+    }
+
+    /// Like `list` but only reads `fields` into `P`. Projections skip migrations, only project fields every schema version has.
+    async fn project<P: DeserializeOwned + Send>(
+        mongoc: &Client,
+        owner: &str,
+        query: &Query,
+        fields: &[&str],
+    ) -> Result<Vec<P>, RepositoryError> {
+        let mut projection = Document::new();
+        for field in fields {
+            projection.insert(*field, 1);
+        }
+        let documents = Self::scan(mongoc, owner, query, Some(projection)).await?;
+        documents
+            .into_iter()
+            .map(|document| bson::from_document::<P>(document).map_err(|e| serialization_error(Self::COLLECTION, e.to_string())))
+            .collect()
+    }
+
+    async fn count(
+        mongoc: &Client,
+        owner: &str,
+        filter: Document,
+    ) -> Result<u64, RepositoryError> {
+        Self::collection(mongoc)
+            .count_documents(Self::owned(owner, filter))
+            .await
+            .map_err(|e| database_error(Self::COLLECTION, e))
+    }
+
+    /// The raw documents for `list` and `project`
+    async fn scan(
+        mongoc: &Client,
+        owner: &str,
+        query: &Query,
+        projection: Option<Document>,
+    ) -> Result<Vec<Document>, RepositoryError> {
+        let collection = Self::collection(mongoc);
+        let mut find = collection.find(Self::owned(owner, query.filter.clone())).sort(query.sort.clone());
+        if let Some(page) = query.page {
+            find = find.skip(page.number * page.size).limit(page.size as i64);
+        }
+        let projection = projection.or_else(|| (!query.exclude.is_empty()).then(|| query.exclude.iter().map(|f| (f.clone(), Bson::Int32(0))).collect()));
+        if let Some(projection) = projection {
+            find = find.projection(projection);
+        }
+        let cursor = find.await.map_err(|e| database_error(Self::COLLECTION, e))?;
+        cursor.try_collect().await.map_err(|e| database_error(Self::COLLECTION, e))
+    }
+
+    /// Writes the whole entity over the revision it was read at and moves it to the next revision
+    async fn save(
+        &mut self,
+        mongoc: &Client,
+    ) -> Result<(), RepositoryError> {
+        let id = self.id();
+        let revision = self.revision();
+        self.set_revision(revision + 1);
+        let document = match self.encode() {
+            Ok(d) => d,
+            Err(e) => {
+                self.set_revision(revision);
+                return Err(e);
+            }
+        };
+        let collection = Self::collection(mongoc);
+        let filter = Self::owned(self.owner(), doc! {"_id": id, "revision": revision_filter(revision)});
+        let result = match collection.replace_one(filter, document).await {
+            Ok(r) => r,
+            Err(e) => {
+                self.set_revision(revision);
+                return Err(database_error(Self::COLLECTION, e));
+            }
+        };
+        if result.matched_count > 0 {
+            return Ok(());
+        }
+        self.set_revision(revision);
+        match collection.count_documents(Self::owned(self.owner(), doc! {"_id": id})).await {
+            Ok(0) => Err(RepositoryError::NotFound {
+                collection: Self::COLLECTION,
+                id: id.to_hex(),
+            }),
+            Ok(_) => {
+                warn!("Conflicting save of {} in {} at revision {}", id, Self::COLLECTION, revision);
+                Err(RepositoryError::Conflict {
+                    collection: Self::COLLECTION,
+                    id: id.to_hex(),
+                })
+            }
+            Err(e) => Err(database_error(Self::COLLECTION, e)),
+        }
+    }
+
+    /// Saves over the owner's document matching `key`, or creates one if there is none.
+    /// Returns the document it replaced.
+    async fn upsert(
+        &mut self,
+        mongoc: &Client,
+        key: Document,
+    ) -> Result<Option<Self>, RepositoryError> {
+        match Self::find_one(mongoc, self.owner(), key).await? {
+            Some(previous) => {
+                self.set_id(previous.id());
+                self.set_revision(previous.revision());
+                self.save(mongoc).await?;
+                Ok(Some(previous))
+            }
+            None => {
+                self.create(mongoc).await?;
+                Ok(None)
+            }
+        }
+    }
+
+    async fn delete(
+        &self,
+        mongoc: &Client,
+    ) -> Result<(), RepositoryError> {
+        let id = self.id();
+        match Self::collection(mongoc).delete_one(Self::owned(self.owner(), doc! {"_id": id})).await {
+            Ok(r) if r.deleted_count > 0 => Ok(()),
+            Ok(_) => Err(RepositoryError::NotFound {
+                collection: Self::COLLECTION,
+                id: id.to_hex(),
+            }),
+            Err(e) => Err(database_error(Self::COLLECTION, e)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+
+    use super::*;
+
+    const OWNER: &str = "repository-test-alice";
+    const VISITOR: &str = "repository-test-mallory";
+
+    #[derive(Debug, Serialize, Deserialize, Clone)]
+    struct Note {
+        _id: ObjectId,
+        user_id: String,
+        text: String,
+        #[serde(default)]
+        revision: u32,
+    }
+
+    impl Note {
+        fn new(text: &str) -> Self {
+            Note {
+                _id: ObjectId::new(),
+                user_id: OWNER.to_string(),
+                text: text.to_string(),
+                revision: 0,
+            }
+        }
+    }
+
+    impl Repository for Note {
+        const COLLECTION: &'static str = "repository_test_notes";
+
+        fn id(&self) -> ObjectId {
+            self._id
+        }
+
+        fn set_id(
+            &mut self,
+            id: ObjectId,
+        ) {
+            self._id = id;
+        }
+
+        fn owner(&self) -> &str {
+            &self.user_id
+        }
+
+        fn revision(&self) -> u32 {
+            self.revision
+        }
+
+        fn set_revision(
+            &mut self,
+            revision: u32,
+        ) {
+            self.revision = revision;
+        }
+    }
+
+    async fn clean_up(mongoc: &Client) {
+        Note::collection(mongoc).delete_many(doc! {"user_id": {"$in": [OWNER, VISITOR]}}).await.unwrap();
+    }
+
+    #[test]
+    fn every_filter_is_narrowed_to_the_owner() {
+        assert_eq!(Note::owned(OWNER, doc! {"text": "a"}), doc! {"text": "a", "user_id": OWNER});
+        // an owner smuggled into the filter is replaced, not widened
+        assert_eq!(Note::owned(OWNER, doc! {"user_id": VISITOR}), doc! {"user_id": OWNER});
+    }
+
+    #[test]
+    fn revision_zero_also_matches_documents_from_before_revisions() {
+        assert_eq!(revision_filter(0), bson::bson!({"$in": [0, Bson::Null]}));
+        assert_eq!(revision_filter(3), Bson::Int64(3));
+    }
+
+    #[test]
+    fn a_query_keeps_its_sort_order() {
+        let query = Query::new().sort("version", SortOrder::Descending).sort("_id", SortOrder::Ascending).page(2, 10).exclude("html");
+        assert_eq!(query.sort, doc! {"version": -1, "_id": 1});
+        assert_eq!(query.page, Some(Page { number: 2, size: 10 }));
+        assert_eq!(query.exclude, vec!["html"]);
+    }
+
+    #[actix_web::test]
+    #[ignore = "needs MongoDB: set DB_CONNECTION_STRING and DB_NAME"]
+    async fn a_save_over_a_stale_revision_is_a_conflict() {
+        let mongoc = Database::get_client().await;
+        let note = Note::new("first");
+        note.create(&mongoc).await.unwrap();
+        let mut first = Note::find(&mongoc, OWNER, &note._id).await.unwrap();
+        let mut second = first.clone();
+        first.text = "saved first".to_string();
+        let saved = first.save(&mongoc).await;
+        second.text = "saved second".to_string();
+        let conflict = second.save(&mongoc).await;
+        let stored = Note::find(&mongoc, OWNER, &note._id).await.unwrap();
+        clean_up(&mongoc).await;
+
+        assert!(saved.is_ok());
+        assert_eq!(first.revision, 1);
+        assert!(matches!(conflict, Err(RepositoryError::Conflict { .. })), "{:?}", conflict);
+        assert_eq!(second.revision, 0, "a failed save keeps the revision it was read at");
+        assert_eq!((stored.text.as_str(), stored.revision), ("saved first", 1));
+    }
+
+    #[actix_web::test]
+    #[ignore = "needs MongoDB: set DB_CONNECTION_STRING and DB_NAME"]
+    async fn a_document_from_before_revisions_can_be_saved() {
+        let mongoc = Database::get_client().await;
+        let id = ObjectId::new();
+        Note::collection(&mongoc).insert_one(doc! {"_id": id, "user_id": OWNER, "text": "legacy"}).await.unwrap();
+        let mut legacy = Note::find(&mongoc, OWNER, &id).await.unwrap();
+        legacy.text = "saved".to_string();
+        let saved = legacy.save(&mongoc).await;
+        clean_up(&mongoc).await;
+
+        assert!(saved.is_ok(), "{:?}", saved);
+        assert_eq!(legacy.revision, 1);
+    }
+
+    #[actix_web::test]
+    #[ignore = "needs MongoDB: set DB_CONNECTION_STRING and DB_NAME"]
+    async fn another_owner_cannot_read_save_or_delete() {
+        let mongoc = Database::get_client().await;
+        let note = Note::new("private");
+        note.create(&mongoc).await.unwrap();
+        let found = Note::find(&mongoc, VISITOR, &note._id).await;
+        let counted = Note::count(&mongoc, VISITOR, doc! {}).await.unwrap();
+        let mut taken = Note {
+            user_id: VISITOR.to_string(),
+            ..note.clone()
+        };
+        let saved = taken.save(&mongoc).await;
+        let deleted = taken.delete(&mongoc).await;
+        let stored = Note::find(&mongoc, OWNER, &note._id).await.unwrap();
+        clean_up(&mongoc).await;
+
+        assert!(matches!(found, Err(RepositoryError::NotFound { .. })), "{:?}", found);
+        assert_eq!(counted, 0);
+        assert!(matches!(saved, Err(RepositoryError::NotFound { .. })), "{:?}", saved);
+        assert!(matches!(deleted, Err(RepositoryError::NotFound { .. })), "{:?}", deleted);
+        assert_eq!((stored.user_id.as_str(), stored.text.as_str()), (OWNER, "private"));
+    }
+}
@@ -0,0 +1,16 @@
+use actix_web::web::Bytes;
+
+/// Formats one server-sent event, multi-line data is split over several `data:` lines
+pub fn sse_event(
+    event: &str,
+    data: &str,
+) -> Bytes {
+    let mut frame = format!("event: {}\n", event);
+    for line in data.lines() {
+        frame.push_str("data: ");
+        frame.push_str(line);
+        frame.push('\n');
+    }
+    frame.push('\n');
+    Bytes::from(frame)
+}
@@ -0,0 +1,51 @@
+use actix_web::{web, HttpResponse, Responder};
+use mongodb::{bson::doc, Client};
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::shared::entities::llm_usage::{LlmUsageEntity, PriceTable, UsageSummary};
+
+#[derive(Debug, Deserialize)]
+pub struct UsageQuery {
+    pub user_id: Option<String>,
+    /// Unix timestamp, only usage recorded at or after it is counted
+    pub since: Option<i64>,
+}
+
+/// LLM token usage and cost, overall, per operation and per user
+pub async fn get_llm_usage(
+    query: web::Query<UsageQuery>,
+    mongoc: web::Data<Client>,
+) -> impl Responder {
+    let mut filter = doc! {};
+    if let Some(user_id) = &query.user_id {
+        filter.insert("user_id", user_id);
+    }
+    if let Some(since) = query.since {
+        filter.insert("timestamp", doc! {"$gte": since});
+    }
+    let usage = match LlmUsageEntity::summarize(&mongoc, filter).await {
+        Some(u) => u,
+        None => return HttpResponse::InternalServerError().json(json!({"error": "Error fetching LLM usage"})),
+    };
+
+    let mut users: Vec<&str> = usage.iter().map(|u| u.user_id.as_str()).collect();
+    users.sort();
+    users.dedup();
+    let mut by_user: Vec<_> = users
+        .into_iter()
+        .map(|user_id| {
+            let (total, by_operation) = UsageSummary::from_groups(usage.iter().filter(|u| u.user_id == user_id));
+            json!({"user_id": user_id, "total": total, "by_operation": by_operation})
+        })
+        .collect();
+    by_user.sort_by(|a, b| b["total"]["cost"].as_f64().unwrap_or_default().total_cmp(&a["total"]["cost"].as_f64().unwrap_or_default()));
+
+    let (total, by_operation) = UsageSummary::from_groups(&usage);
+    HttpResponse::Ok().json(json!({
+        "currency": PriceTable::get().currency,
+        "total": total,
+        "by_operation": by_operation,
+        "by_user": by_user,
+    }))
+}